@@ -0,0 +1,175 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+use crate::{DownloadedFile, Error};
+
+/// How many times slower than the median of recent responses for files of a similar size a response has to be
+/// to count as a latency spike.
+const LATENCY_SPIKE_FACTOR: u32 = 3;
+
+/// How many recent response times are kept for each size bucket.
+const SAMPLES_PER_BUCKET: usize = 16;
+
+/// How many response times a size bucket needs before a response in it can count as a latency spike.
+const MIN_SAMPLES: usize = 4;
+
+/// Limits the number of simultaneous downloads of a run, letting more through while responses are fast
+/// and successful and fewer once they slow down or GitHub starts turning requests away.
+///
+/// The limit starts at 1 and grows by 1 each time as many downloads as the limit have succeeded in a row,
+/// up to the maximum it was created with. A latency spike or a 403 or 429 response halves it.
+///
+/// Larger files take longer to download, so response times are only compared with those of files of a similar
+/// size: files are put in buckets by size, each four times as large as the last, and a response is a latency
+/// spike if it's much slower than the median of the recent responses in its bucket.
+pub(crate) struct AdaptiveLimiter {
+    state: Mutex<LimiterState>,
+    released: Notify,
+    max: usize,
+}
+
+struct LimiterState {
+    limit: usize,
+    in_flight: usize,
+    successes: usize,
+    /// The most recent response times in each size bucket, oldest first.
+    samples: HashMap<u32, VecDeque<Duration>>,
+}
+
+/// How a single download went, as far as an [AdaptiveLimiter] is concerned.
+pub(crate) enum Outcome {
+    /// The download of a file of the given size in bytes succeeded after the given time.
+    Succeeded { elapsed: Duration, bytes: u64 },
+    /// GitHub rejected the request with a 403 or 429, usually because of a rate limit.
+    Throttled,
+    /// The download failed for some other reason, such as a missing blob.
+    Failed,
+}
+
+impl Outcome {
+    /// Classifies the result of a download that took `elapsed`.
+    pub fn of(result: &Result<DownloadedFile, Error>, elapsed: Duration) -> Outcome {
+        let error = match result {
+            Ok(file) => {
+                return Outcome::Succeeded {
+                    elapsed,
+                    bytes: file.bytes_written,
+                }
+            }
+            Err(e) => e,
+        };
+
+        let status = error.context().and_then(|c| c.status);
+        match error.root() {
+            Error::RateLimited { .. } => Outcome::Throttled,
+            _ if matches!(status, Some(403) | Some(429)) => Outcome::Throttled,
+            _ => Outcome::Failed,
+        }
+    }
+}
+
+impl AdaptiveLimiter {
+    /// Creates a limiter that never allows more than `max` downloads at once.
+    pub fn new(max: usize) -> AdaptiveLimiter {
+        AdaptiveLimiter {
+            state: Mutex::new(LimiterState {
+                limit: 1,
+                in_flight: 0,
+                successes: 0,
+                samples: HashMap::new(),
+            }),
+            released: Notify::new(),
+            max: max.max(1),
+        }
+    }
+
+    /// Waits until another download is allowed, returning a permit that must be held while it runs.
+    pub async fn acquire(&self) -> AdaptivePermit<'_> {
+        loop {
+            // created before checking, so a permit released in between still wakes us
+            let released = self.released.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return AdaptivePermit { limiter: self };
+                }
+            }
+
+            released.await;
+        }
+    }
+
+    /// Adjusts the limit according to how a download went.
+    pub fn record(&self, outcome: Outcome) {
+        let mut state = self.state.lock().unwrap();
+        match outcome {
+            Outcome::Succeeded { elapsed, bytes } => {
+                if state.is_spike(elapsed, bytes) {
+                    state.back_off();
+                    return;
+                }
+
+                state.successes += 1;
+                if state.successes >= state.limit && state.limit < self.max {
+                    state.limit += 1;
+                    state.successes = 0;
+                    drop(state);
+                    self.released.notify_waiters();
+                }
+            }
+            Outcome::Throttled => state.back_off(),
+            Outcome::Failed => state.successes = 0,
+        }
+    }
+}
+
+impl LimiterState {
+    /// Records how long a file of `bytes` bytes took, returning whether that's a latency spike. Spikes are
+    /// recorded too, so the median follows response times that stay high.
+    fn is_spike(&mut self, elapsed: Duration, bytes: u64) -> bool {
+        let samples = self.samples.entry(size_bucket(bytes)).or_default();
+        let is_spike =
+            samples.len() >= MIN_SAMPLES && elapsed > median(samples) * LATENCY_SPIKE_FACTOR;
+
+        samples.push_back(elapsed);
+        if samples.len() > SAMPLES_PER_BUCKET {
+            samples.pop_front();
+        }
+        is_spike
+    }
+
+    fn back_off(&mut self) {
+        self.limit = (self.limit / 2).max(1);
+        self.successes = 0;
+    }
+}
+
+/// Returns the bucket files of `bytes` bytes are compared in. Each bucket holds files up to four times as large
+/// as the one before it.
+fn size_bucket(bytes: u64) -> u32 {
+    (u64::BITS - bytes.leading_zeros()) / 2
+}
+
+fn median(samples: &VecDeque<Duration>) -> Duration {
+    let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
+
+/// Allows a single download through an [AdaptiveLimiter] until it's dropped.
+pub(crate) struct AdaptivePermit<'l> {
+    limiter: &'l AdaptiveLimiter,
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_flight -= 1;
+        self.limiter.released.notify_waiters();
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    download::partial_path, DownloadConfig, DownloadReporter, DownloadedFile, Downloader, Error,
+    FileMode,
+};
+
+/// The modification time given to every entry of a deterministic archive: 1980-01-01 00:00:00 UTC,
+/// the earliest time a zip file can hold.
+const DETERMINISTIC_MTIME: u64 = 315_532_800;
+
+/// The compression level used for `.tar.zst` archives when none is given.
+const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+/// The compression level used for `.zip` archives when none is given.
+const DEFAULT_DEFLATE_LEVEL: i32 = 6;
+
+/// The kind of archive written by [package](Downloader::package).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A tar archive compressed with zstd.
+    TarZst,
+    /// A zip archive compressed with deflate.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Picks the format from the extension of `path`: `.tar.zst` or `.tzst` for [ArchiveFormat::TarZst],
+    /// and `.zip` for [ArchiveFormat::Zip].
+    pub fn from_path(path: &Path) -> Option<ArchiveFormat> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Options for packaging downloaded files into an archive with [package](Downloader::package).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveOptions {
+    /// Whether packaging the same files always produces the same bytes, so that repeated grabs of a commit
+    /// can be compared or cached by their hash.
+    ///
+    /// Entries are sorted by path, every entry is given the same modification time, and permissions are
+    /// normalized to `644`, or `755` for files that are executable in the repository. Otherwise, entries are
+    /// written in the order given with the modification times and permissions of the files on disk.
+    /// The default is true.
+    pub deterministic: bool,
+    /// The compression level, from 1 to 22 for `.tar.zst` and from 0 to 9 for `.zip`.
+    /// The default is 3 for `.tar.zst` and 6 for `.zip`.
+    pub compression_level: Option<i32>,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        ArchiveOptions {
+            deterministic: true,
+            compression_level: None,
+        }
+    }
+}
+
+/// A single file to be added to an archive.
+struct ArchiveEntry<'f> {
+    name: String,
+    file: &'f DownloadedFile,
+}
+
+impl ArchiveEntry<'_> {
+    /// Returns the modification time and permissions to give this entry.
+    fn metadata(&self, options: &ArchiveOptions) -> io::Result<(u64, u32)> {
+        let normalized = match self.file.entry.mode {
+            FileMode::Executable => 0o755,
+            _ => 0o644,
+        };
+        if options.deterministic {
+            return Ok((DETERMINISTIC_MTIME, normalized));
+        }
+
+        let metadata = std::fs::metadata(&self.file.output_path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs();
+
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o777;
+        #[cfg(not(unix))]
+        let mode = normalized;
+
+        Ok((mtime, mode))
+    }
+}
+
+impl Downloader {
+    /// Packages downloaded files into a single archive at `archive_path`.
+    ///
+    /// Each file is read back from where it was written, and stored under its path relative to the
+    /// config's [output_path](DownloadConfig::output_path), or under its path in the repository if it was
+    /// written elsewhere. The archive is written to a partial file first, so `archive_path` never holds an
+    /// incomplete archive.
+    ///
+    /// This blocks while the archive is written, so use `spawn_blocking` or similar from an async context.
+    pub fn package<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        files: &[DownloadedFile],
+        archive_path: &Path,
+        format: ArchiveFormat,
+        options: &ArchiveOptions,
+    ) -> Result<(), Error> {
+        let mut names = HashSet::new();
+        let mut entries: Vec<ArchiveEntry> = files
+            .iter()
+            .map(|file| {
+                let name = match file.output_path.strip_prefix(config.output_path) {
+                    Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                    Err(_) => file.entry.path.to_string(),
+                };
+                ArchiveEntry { name, file }
+            })
+            // content-addressed layouts can write the same file more than once
+            .filter(|entry| names.insert(entry.name.clone()))
+            .collect();
+
+        if options.deterministic {
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        let partial = partial_path(archive_path, None);
+        let writer = BufWriter::new(File::create(&partial)?);
+        match format {
+            ArchiveFormat::TarZst => write_tar_zst(writer, &entries, options)?,
+            ArchiveFormat::Zip => write_zip(writer, &entries, options)?,
+        }
+        std::fs::rename(&partial, archive_path)?;
+
+        Ok(())
+    }
+}
+
+fn write_tar_zst<W: Write>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    options: &ArchiveOptions,
+) -> io::Result<()> {
+    let level = options.compression_level.unwrap_or(DEFAULT_ZSTD_LEVEL);
+    let mut builder = tar::Builder::new(zstd::Encoder::new(writer, level)?);
+
+    for entry in entries {
+        let (mtime, mode) = entry.metadata(options)?;
+        let file = File::open(&entry.file.output_path)?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(file.metadata()?.len());
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        builder.append_data(&mut header, &entry.name, file)?;
+    }
+
+    builder.into_inner()?.finish()?.flush()
+}
+
+fn write_zip<W: Write + io::Seek>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    options: &ArchiveOptions,
+) -> io::Result<()> {
+    let level = options.compression_level.unwrap_or(DEFAULT_DEFLATE_LEVEL);
+    let mut zip = zip::ZipWriter::new(writer);
+
+    for entry in entries {
+        let (mtime, mode) = entry.metadata(options)?;
+        let file_options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .compression_level(Some(level as i64))
+            .last_modified_time(zip_time(mtime))
+            .unix_permissions(mode);
+
+        zip.start_file(entry.name.as_str(), file_options)?;
+        io::copy(&mut File::open(&entry.file.output_path)?, &mut zip)?;
+    }
+
+    zip.finish()?.flush()
+}
+
+/// Converts seconds since the unix epoch into the MS-DOS time stored in zip files, in UTC.
+fn zip_time(seconds: u64) -> zip::DateTime {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // converts days since the epoch into a civil date, from Howard Hinnant's `civil_from_days`
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    zip::DateTime::from_date_and_time(
+        year.clamp(1980, 2107) as u16,
+        month as u8,
+        day as u8,
+        (time / 3600) as u8,
+        (time / 60 % 60) as u8,
+        (time % 60) as u8,
+    )
+    .unwrap_or_default()
+}
@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use futures::{stream, StreamExt, TryStreamExt};
+
+use crate::{
+    raw, DownloadConfig, DownloadReporter, Downloader, Error, Filter, GithubBranchPath, SourceTree,
+};
+
+/// How long the requests made by [bench_profile](Downloader::bench_profile) took, to compare
+/// [HttpOptions](crate::HttpOptions) and concurrency settings by.
+///
+/// Latencies are measured from sending a request to having read the whole response. Its
+/// [Display](fmt::Display) form is a one-line summary, like
+/// `50 requests at concurrency 8 in 1.20s (41.7/s): p50 180ms, p90 310ms, p99 420ms, max 450ms`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchProfile {
+    /// How long fetching the tree took, including resolving the reference.
+    pub tree_latency: Duration,
+    /// How many blobs were fetched.
+    pub requests: usize,
+    /// How many blobs were fetched at once.
+    pub concurrency: usize,
+    /// The total size of the blobs that were fetched, in bytes.
+    pub bytes: u64,
+    /// How long it took to fetch every blob.
+    pub elapsed: Duration,
+    /// The latency of each blob request, from fastest to slowest.
+    pub latencies: Vec<Duration>,
+}
+
+impl BenchProfile {
+    /// Returns the latency that `percentile` percent of blob requests were at least as fast as, such as
+    /// `percentile(50.0)` for the median, or zero if no blobs were fetched.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+
+        // the nearest rank, which is always one of the measured latencies
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.latencies.len() as f64).ceil();
+        self.latencies[(rank as usize).saturating_sub(1)]
+    }
+
+    /// Returns the average latency of the blob requests, or zero if no blobs were fetched.
+    pub fn mean(&self) -> Duration {
+        match self.latencies.len() {
+            0 => Duration::ZERO,
+            n => self.latencies.iter().sum::<Duration>() / n as u32,
+        }
+    }
+
+    /// Returns how many blob requests were completed per second.
+    pub fn requests_per_second(&self) -> f64 {
+        match self.elapsed.is_zero() {
+            true => 0.0,
+            false => self.requests as f64 / self.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+impl fmt::Display for BenchProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests at concurrency {} in {:.2}s ({:.1}/s)",
+            self.requests,
+            self.concurrency,
+            self.elapsed.as_secs_f64(),
+            self.requests_per_second()
+        )?;
+
+        if let Some(max) = self.latencies.last() {
+            write!(
+                f,
+                ": p50 {}ms, p90 {}ms, p99 {}ms, max {}ms",
+                self.percentile(50.0).as_millis(),
+                self.percentile(90.0).as_millis(),
+                self.percentile(99.0).as_millis(),
+                max.as_millis()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'p> Downloader {
+    /// Fetches the tree of `path` and up to `samples` of the blobs that pass `filter`, without writing anything
+    /// to disk, and reports how long the requests took.
+    ///
+    /// Requests are made like a download would make them, with the client,
+    /// [http_options](DownloadConfig::http_options) and
+    /// [max_simultaneous_downloads](DownloadConfig::max_simultaneous_downloads) of `config`, so running this with
+    /// different settings shows which suit a large download best. Each request counts against the rate
+    /// limit as usual. Blobs are served from the client's [TreeCache](crate::TreeCache) if it
+    /// [caches blobs](crate::CachePolicy::cache_blobs), which makes them look instant, so benchmark with a client
+    /// that doesn't.
+    pub async fn bench_profile<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        path: &'p GithubBranchPath<'p>,
+        filter: &Filter<'p>,
+        samples: usize,
+    ) -> Result<BenchProfile, Error> {
+        let client = config.client()?;
+
+        let start = Instant::now();
+        let (_, tree) = SourceTree::get_resolved_with_client(&client, path, filter).await?;
+        let tree_latency = start.elapsed();
+
+        let files = Downloader::select_files(config, &tree, filter)?;
+        let concurrency = config.max_simultaneous_downloads.max(1);
+        let start = Instant::now();
+        let mut samples: Vec<(Duration, u64)> = stream::iter(files.into_iter().take(samples))
+            .map(|entry| {
+                let client = &client;
+                async move {
+                    let start = Instant::now();
+                    let contents =
+                        raw::get_blob_contents(client, &entry.url, entry.size as u64).await?;
+                    Ok::<_, Error>((start.elapsed(), contents.len() as u64))
+                }
+            })
+            .buffer_unordered(concurrency)
+            .try_collect()
+            .await?;
+        let elapsed = start.elapsed();
+
+        samples.sort();
+        Ok(BenchProfile {
+            tree_latency,
+            requests: samples.len(),
+            concurrency,
+            bytes: samples.iter().map(|(_, bytes)| bytes).sum(),
+            elapsed,
+            latencies: samples.into_iter().map(|(latency, _)| latency).collect(),
+        })
+    }
+}
@@ -0,0 +1,229 @@
+use futures::future::{BoxFuture, FutureExt};
+use serde::Deserialize;
+
+use crate::{
+    forge::encode_component,
+    raw::{self, TreeEntryModel, TreeModel},
+    AuthScheme, Error, ErrorContext, FileMode, Filter, Forge, GithubBranchPath, GithubClient,
+    GithubRef, HttpOptions, ObjectId, ResolvedRef, SourceTree, TreeEntryType,
+};
+
+/// The URL of the Bitbucket Cloud REST API.
+pub const BITBUCKET_API_URL: &str = "https://api.bitbucket.org/2.0";
+
+/// How many entries are requested per page of a Bitbucket directory.
+const BITBUCKET_PAGE_LENGTH: usize = 100;
+
+#[derive(Deserialize)]
+struct BitbucketPageModel<T> {
+    values: Vec<T>,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitbucketCommitModel {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct BitbucketRefModel {
+    target: BitbucketCommitModel,
+}
+
+#[derive(Deserialize)]
+struct BitbucketSrcEntryModel {
+    #[serde(rename = "type")]
+    entry_type: String,
+    path: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    attributes: Vec<String>,
+}
+
+/// A [Forge] for Bitbucket Cloud, using its `refs` and `src` APIs.
+///
+/// Requests go through a [GithubClient] whose [api_url](GithubClient::api_url) is the Bitbucket API, so they're
+/// counted and observed like requests to GitHub. For Bitbucket, `user` in a [GithubBranchPath] is the workspace
+/// and `repo` is the repository slug.
+///
+/// Bitbucket lists one directory at a time, so a tree takes a request per directory, skipping those that can't
+/// hold files passing the filter. It doesn't report the hashes of blobs or trees, so entries and resolved
+/// references have an [ObjectId::NULL] hash and downloaded files are never
+/// [verified](crate::DownloadedFile::verified).
+#[derive(Debug, Clone)]
+pub struct BitbucketForge {
+    client: GithubClient,
+}
+
+impl BitbucketForge {
+    /// Creates a new [BitbucketForge] for Bitbucket Cloud, authenticating with the given repository, project or
+    /// workspace access token if provided.
+    pub fn new(access_token: Option<&str>) -> Result<BitbucketForge, Error> {
+        let options = HttpOptions {
+            auth_scheme: AuthScheme::Bearer,
+            ..HttpOptions::default()
+        };
+        let client =
+            GithubClient::with_options(access_token, &options)?.with_api_url(BITBUCKET_API_URL);
+        Ok(BitbucketForge { client })
+    }
+
+    /// Creates a new [BitbucketForge] making requests with `client`. Bitbucket only accepts access tokens sent
+    /// with [AuthScheme::Bearer].
+    pub fn with_client(client: GithubClient) -> BitbucketForge {
+        BitbucketForge { client }
+    }
+
+    /// Returns the client this forge makes requests with.
+    pub fn client(&self) -> &GithubClient {
+        &self.client
+    }
+
+    /// Returns the URL of the given API endpoint within the repository of `path`.
+    fn repo_url(&self, path: &GithubBranchPath<'_>, endpoint: &str) -> String {
+        format!(
+            "{}/repositories/{}/{}/{}",
+            self.client.api_url(),
+            encode_component(path.user),
+            encode_component(path.repo),
+            endpoint
+        )
+    }
+
+    /// Returns the URL of the `src` API for `file` at `commit`, with each component of `file` encoded.
+    fn src_url(&self, path: &GithubBranchPath<'_>, commit: &str, file: &str) -> String {
+        let file: Vec<String> = file.split('/').map(encode_component).collect();
+        let endpoint = format!("src/{}/{}", commit, file.join("/"));
+        self.repo_url(path, &endpoint)
+    }
+
+    /// Lists every directory below the commit of `path`, only keeping entries that pass `filter`.
+    async fn fetch_tree(
+        &self,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        let commit = path.reference.name();
+        let mut pending = vec![String::new()];
+        let mut entries = Vec::new();
+
+        while let Some(dir) = pending.pop() {
+            // directories are only listed with a trailing slash, which the root already has
+            let dir_url = match dir.is_empty() {
+                true => self.src_url(path, &commit, &dir),
+                false => format!("{}/", self.src_url(path, &commit, &dir)),
+            };
+            let mut next = Some(format!("{}?pagelen={}", dir_url, BITBUCKET_PAGE_LENGTH));
+
+            while let Some(url) = next.take() {
+                let page: BitbucketPageModel<BitbucketSrcEntryModel> =
+                    raw::get_with_accept(&self.client, &url, "application/json").await?;
+
+                for entry in page.values {
+                    let attribute = |name: &str| entry.attributes.iter().any(|a| a == name);
+                    let (entry_type, mode) = match entry.entry_type.as_str() {
+                        "commit_directory" => (TreeEntryType::Tree, FileMode::Directory),
+                        // submodules are listed as files, but aren't part of the tree
+                        "commit_file" if attribute("subrepository") => continue,
+                        "commit_file" if attribute("link") => {
+                            (TreeEntryType::Blob, FileMode::Symlink)
+                        }
+                        "commit_file" if attribute("executable") => {
+                            (TreeEntryType::Blob, FileMode::Executable)
+                        }
+                        "commit_file" => (TreeEntryType::Blob, FileMode::Regular),
+                        _ => continue,
+                    };
+
+                    let model = TreeEntryModel {
+                        url: self.src_url(path, &commit, &entry.path),
+                        path: entry.path,
+                        mode,
+                        entry_type,
+                        size: u32::try_from(entry.size).unwrap_or(u32::MAX),
+                        sha: ObjectId::NULL,
+                    };
+                    if !model.passes(filter) {
+                        continue;
+                    }
+
+                    if model.entry_type == TreeEntryType::Tree {
+                        pending.push(model.path.clone());
+                    }
+                    entries.push(model);
+                }
+
+                next = page.next;
+            }
+        }
+
+        Ok(TreeModel {
+            sha: ObjectId::NULL,
+            url: self.src_url(path, &commit, ""),
+            tree: entries,
+            truncated: false,
+        })
+    }
+}
+
+impl Forge for BitbucketForge {
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        async move {
+            let endpoint = match path.reference {
+                GithubRef::Branch(name) => format!("refs/branches/{}", encode_component(name)),
+                GithubRef::Tag(name) => format!("refs/tags/{}", encode_component(name)),
+                GithubRef::Commit(sha) => {
+                    return Ok(ResolvedRef {
+                        commit_sha: Some(sha.parse()?),
+                        tree_sha: ObjectId::NULL,
+                    })
+                }
+                reference => {
+                    return Err(Error::Other(format!(
+                        "{} can't be resolved on Bitbucket",
+                        reference
+                    )))
+                }
+            };
+
+            let url = self.repo_url(path, &endpoint);
+            let git_ref: BitbucketRefModel =
+                raw::get_with_accept(&self.client, &url, "application/json").await?;
+            Ok(ResolvedRef {
+                commit_sha: Some(git_ref.target.hash.parse()?),
+                tree_sha: ObjectId::NULL,
+            })
+        }
+        .boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move {
+            let tree = self.fetch_tree(path, filter).await.map_err(|e| {
+                e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                })
+            })?;
+            Ok(tree.into())
+        }
+        .boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        async move {
+            let request = self.client.http().get(&entry.url);
+            let (_, body) = raw::send(&self.client, request, "*/*").await?;
+            Ok(body)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{raw, Error, GithubClient, SourceTree, TreeEntryType};
+
+/// Contains methods for reading the contents of a blob directly, without writing it to disk.
+pub struct Blob {}
+
+impl Blob {
+    /// Fetches the contents of the blob `tree` points to.
+    ///
+    /// Returns an error if `tree` is not a [TreeEntryType::Blob].
+    pub async fn fetch(client: &GithubClient, tree: &SourceTree) -> Result<Vec<u8>, Error> {
+        if tree.entry_type != TreeEntryType::Blob {
+            return Err(Error::Other(format!("{} is not a blob", tree.path)));
+        }
+
+        raw::get_blob_contents(client, &tree.url, tree.size as u64).await
+    }
+
+    /// Fetches the contents of the blob `tree` points to as text.
+    ///
+    /// See [decode_text](Blob::decode_text) for how the encoding is determined.
+    pub async fn fetch_text(client: &GithubClient, tree: &SourceTree) -> Result<String, Error> {
+        let bytes = Blob::fetch(client, tree).await?;
+        Ok(Blob::decode_text(&bytes))
+    }
+
+    /// Decodes the contents of a text file.
+    ///
+    /// - If the bytes start with a UTF-8, UTF-16LE or UTF-16BE byte order mark, that encoding is used.
+    /// - Otherwise, if the bytes are valid UTF-8, they're decoded as UTF-8.
+    /// - Otherwise, the bytes are decoded as ISO-8859-1, which never fails.
+    pub fn decode_text(bytes: &[u8]) -> String {
+        if let Some(bytes) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+
+        if let Some(bytes) = bytes.strip_prefix(b"\xFF\xFE") {
+            return decode_utf16(bytes, u16::from_le_bytes);
+        }
+
+        if let Some(bytes) = bytes.strip_prefix(b"\xFE\xFF") {
+            return decode_utf16(bytes, u16::from_be_bytes);
+        }
+
+        match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_string(),
+            Err(_) => bytes.iter().map(|b| *b as char).collect(),
+        }
+    }
+}
+
+/// Decodes UTF-16 with the given byte order, replacing invalid sequences.
+fn decode_utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|c| to_u16([c[0], c[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
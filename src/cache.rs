@@ -0,0 +1,309 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "download")]
+use crate::Error;
+use crate::{raw::TreeModel, GithubBranchPath, GithubRef, ObjectId, ResolvedRef};
+
+/// How long a resolved branch, tag or pull request is reused for by default.
+const DEFAULT_REF_TTL: Duration = Duration::from_secs(60);
+
+/// How many trees are kept in memory by default.
+const DEFAULT_MAX_TREES: usize = 32;
+
+/// How many bytes of blobs are kept in memory by default.
+const DEFAULT_MAX_BLOB_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Settings for a [TreeCache].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachePolicy {
+    /// Whether trees are cached by their SHA. A tree never changes, so cached trees never expire.
+    /// The default is true.
+    pub cache_trees: bool,
+    /// How long the commit a branch, tag or pull request resolved to is reused for before it's looked up again.
+    /// Commits always resolve to the same tree, so they're reused for as long as they're cached.
+    /// The default is 60 seconds.
+    pub ref_ttl: Duration,
+    /// The most trees kept in memory at once. The trees cached longest are dropped first.
+    /// The default is 32.
+    pub max_trees: usize,
+    /// Whether the contents of blobs are cached by the URL they were fetched from. Blob URLs name the blob by
+    /// its SHA, so the same blob is never fetched twice, even when it's in more than one reference. Blobs that
+    /// don't exist are remembered too, so they aren't asked for again.
+    /// The default is false.
+    pub cache_blobs: bool,
+    /// The most bytes of blobs kept in memory at once. The blobs cached longest are dropped first.
+    /// The default is 64 MiB.
+    pub max_blob_bytes: u64,
+    /// A directory to also store trees in, as JSON files, and cached blobs in, so they're reused across runs.
+    /// The default is to only keep trees and blobs in memory.
+    pub disk_path: Option<PathBuf>,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        CachePolicy {
+            cache_trees: true,
+            ref_ttl: DEFAULT_REF_TTL,
+            max_trees: DEFAULT_MAX_TREES,
+            cache_blobs: false,
+            max_blob_bytes: DEFAULT_MAX_BLOB_BYTES,
+            disk_path: None,
+        }
+    }
+}
+
+/// Caches fetched trees and resolved references, so repeated operations on the same commit don't need
+/// to call the tree API again, and, if [cache_blobs](CachePolicy::cache_blobs) is set, fetched blobs.
+///
+/// Give a cache to [GithubClient::with_cache](crate::GithubClient::with_cache) to use it for every tree
+/// and blob fetched through that client and its clones. A cache can be shared between clients with different
+/// API URLs and access tokens: everything is cached separately for each API URL and token, in memory and on
+/// disk, so a tree fetched from a private repository is never served to a client without access to it.
+///
+/// Trees are cached whole, and filtered each time they're used. This means the first fetch of a tree
+/// through a cache fetches every entry, even if a [Filter](crate::Filter) would have let parts of a
+/// truncated tree be skipped.
+#[derive(Debug)]
+pub struct TreeCache {
+    policy: CachePolicy,
+    trees: Mutex<CachedTrees>,
+    refs: Mutex<HashMap<String, (ResolvedRef, Instant)>>,
+    #[cfg(feature = "download")]
+    blobs: Mutex<CachedBlobs>,
+}
+
+#[derive(Debug, Default)]
+struct CachedTrees {
+    trees: HashMap<String, TreeModel>,
+    order: VecDeque<String>,
+}
+
+#[cfg(feature = "download")]
+#[derive(Debug, Default)]
+struct CachedBlobs {
+    blobs: HashMap<String, Vec<u8>>,
+    order: VecDeque<String>,
+    bytes: u64,
+    missing: HashMap<String, Error>,
+}
+
+impl Default for TreeCache {
+    fn default() -> Self {
+        TreeCache::new(CachePolicy::default())
+    }
+}
+
+impl TreeCache {
+    /// Creates an empty cache with the given [CachePolicy].
+    pub fn new(policy: CachePolicy) -> TreeCache {
+        TreeCache {
+            policy,
+            trees: Mutex::new(CachedTrees::default()),
+            refs: Mutex::new(HashMap::new()),
+            #[cfg(feature = "download")]
+            blobs: Mutex::new(CachedBlobs::default()),
+        }
+    }
+
+    /// Returns the [CachePolicy] of this cache.
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    /// Forgets every tree, reference and blob held in memory. Trees and blobs stored on disk are kept.
+    pub fn clear(&self) {
+        *self.trees.lock().unwrap() = CachedTrees::default();
+        self.refs.lock().unwrap().clear();
+        #[cfg(feature = "download")]
+        {
+            *self.blobs.lock().unwrap() = CachedBlobs::default();
+        }
+    }
+
+    /// Returns what the reference of `path` resolved to, if it's cached and hasn't expired.
+    pub(crate) fn get_ref(&self, scope: &str, path: &GithubBranchPath<'_>) -> Option<ResolvedRef> {
+        let refs = self.refs.lock().unwrap();
+        let (resolved, resolved_at) = refs.get(&ref_key(scope, path))?;
+        let is_fresh = matches!(path.reference, GithubRef::Commit(_))
+            || resolved_at.elapsed() < self.policy.ref_ttl;
+        is_fresh.then(|| resolved.clone())
+    }
+
+    pub(crate) fn put_ref(&self, scope: &str, path: &GithubBranchPath<'_>, resolved: &ResolvedRef) {
+        self.refs
+            .lock()
+            .unwrap()
+            .insert(ref_key(scope, path), (resolved.clone(), Instant::now()));
+    }
+
+    /// Returns the cached tree that `path` refers to by its SHA, looking on disk if it isn't in memory.
+    pub(crate) fn get_tree(&self, scope: &str, path: &GithubBranchPath<'_>) -> Option<TreeModel> {
+        let key = tree_key(scope, path)?;
+        if let Some(tree) = self.trees.lock().unwrap().trees.get(&key) {
+            return Some(tree.clone());
+        }
+
+        let file = self.disk_file(scope, path)?;
+        let tree: TreeModel = serde_json::from_slice(&std::fs::read(file).ok()?).ok()?;
+        self.remember_tree(key, tree.clone());
+        Some(tree)
+    }
+
+    /// Caches `tree`, which `path` refers to by its SHA.
+    pub(crate) fn put_tree(&self, scope: &str, path: &GithubBranchPath<'_>, tree: &TreeModel) {
+        let Some(key) = tree_key(scope, path) else {
+            return;
+        };
+
+        // a cache that can't be written to is no worse than no cache, so failures are ignored
+        if let Some(file) = self.disk_file(scope, path) {
+            if let (Some(dir), Ok(json)) = (file.parent(), serde_json::to_vec(tree)) {
+                let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&file, json));
+            }
+        }
+
+        self.remember_tree(key, tree.clone());
+    }
+
+    fn remember_tree(&self, key: String, tree: TreeModel) {
+        let mut cached = self.trees.lock().unwrap();
+        if cached.trees.insert(key.clone(), tree).is_none() {
+            cached.order.push_back(key);
+        }
+
+        while cached.order.len() > self.policy.max_trees {
+            if let Some(oldest) = cached.order.pop_front() {
+                cached.trees.remove(&oldest);
+            }
+        }
+    }
+
+    #[cfg(feature = "download")]
+    /// Returns what fetching the blob at `url` returned before, looking on disk if it isn't in memory.
+    /// Only blobs that didn't exist are cached as errors.
+    pub(crate) fn get_blob(&self, scope: &str, url: &str) -> Option<Result<Vec<u8>, Error>> {
+        let key = blob_key(scope, url);
+        {
+            let cached = self.blobs.lock().unwrap();
+            if let Some(error) = cached.missing.get(&key) {
+                return Some(Err(error.clone()));
+            }
+            if let Some(contents) = cached.blobs.get(&key) {
+                return Some(Ok(contents.clone()));
+            }
+        }
+
+        let contents = std::fs::read(self.blob_file(scope, url)?).ok()?;
+        self.remember_blob(key, contents.clone());
+        Some(Ok(contents))
+    }
+
+    #[cfg(feature = "download")]
+    /// Caches the contents of the blob at `url`.
+    pub(crate) fn put_blob(&self, scope: &str, url: &str, contents: &[u8]) {
+        if let Some(file) = self.blob_file(scope, url) {
+            if let Some(dir) = file.parent() {
+                let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&file, contents));
+            }
+        }
+
+        self.remember_blob(blob_key(scope, url), contents.to_vec());
+    }
+
+    #[cfg(feature = "download")]
+    /// Caches `error` as the result of fetching the blob at `url`, if it says the blob doesn't exist.
+    /// Any other error might not happen again, so it isn't cached.
+    pub(crate) fn put_blob_error(&self, scope: &str, url: &str, error: &Error) {
+        let status = error.context().and_then(|c| c.status);
+        if matches!(status, Some(404) | Some(410)) {
+            let mut cached = self.blobs.lock().unwrap();
+            cached.missing.insert(blob_key(scope, url), error.clone());
+        }
+    }
+
+    #[cfg(feature = "download")]
+    fn remember_blob(&self, key: String, contents: Vec<u8>) {
+        let size = contents.len() as u64;
+        if size > self.policy.max_blob_bytes {
+            return;
+        }
+
+        let mut cached = self.blobs.lock().unwrap();
+        match cached.blobs.insert(key.clone(), contents) {
+            Some(previous) => cached.bytes -= previous.len() as u64,
+            None => cached.order.push_back(key),
+        }
+        cached.bytes += size;
+
+        while cached.bytes > self.policy.max_blob_bytes {
+            let Some(oldest) = cached.order.pop_front() else {
+                break;
+            };
+            if let Some(contents) = cached.blobs.remove(&oldest) {
+                cached.bytes -= contents.len() as u64;
+            }
+        }
+    }
+
+    #[cfg(feature = "download")]
+    /// Blobs are stored on disk by their SHA, which is the last segment of their URL, so that a blob is found
+    /// whichever repository on the same host it's fetched from with the same token.
+    fn blob_file(&self, scope: &str, url: &str) -> Option<PathBuf> {
+        let dir = self.policy.disk_path.as_ref()?;
+        let sha: ObjectId = url.rsplit('/').next()?.parse().ok()?;
+        Some(scope_dir(dir, scope).join("blobs").join(sha.to_string()))
+    }
+
+    fn disk_file(&self, scope: &str, path: &GithubBranchPath<'_>) -> Option<PathBuf> {
+        let sha = tree_sha(path)?;
+        let dir = self.policy.disk_path.as_ref()?;
+        Some(
+            scope_dir(dir, scope)
+                .join(path.user)
+                .join(path.repo)
+                .join(format!("{}.json", sha)),
+        )
+    }
+}
+
+fn ref_key(scope: &str, path: &GithubBranchPath<'_>) -> String {
+    format!("{}/{}/{}/{:?}", scope, path.user, path.repo, path.reference)
+}
+
+fn tree_key(scope: &str, path: &GithubBranchPath<'_>) -> Option<String> {
+    let sha = tree_sha(path)?;
+    Some(format!("{}/{}/{}/{}", scope, path.user, path.repo, sha))
+}
+
+#[cfg(feature = "download")]
+fn blob_key(scope: &str, url: &str) -> String {
+    format!("{}/{}", scope, url)
+}
+
+/// Returns the directory under `dir` that everything cached on disk for `scope` is stored in, named by a hash of
+/// the scope so that neither the API URL nor the token hash needs to be a valid file name.
+fn scope_dir(dir: &Path, scope: &str) -> PathBuf {
+    dir.join(format!("{:016x}", fingerprint(scope)))
+}
+
+/// Hashes `value` with 64-bit FNV-1a, which unlike the standard library's hasher gives the same result in
+/// every run, so it can name directories of the disk cache.
+pub(crate) fn fingerprint(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Only trees referred to by their SHA can be cached, since anything else can change. The SHA is parsed so
+/// that the same tree is found however its hash was written.
+fn tree_sha(path: &GithubBranchPath<'_>) -> Option<ObjectId> {
+    match path.reference {
+        GithubRef::Tree(sha) => sha.parse().ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::{Sha256, Sha512};
+
+use crate::{DownloadConfig, DownloadReporter, DownloadedFile, Downloader, Error};
+
+/// The hash used for the checksum manifest written with
+/// [checksum_manifest](crate::DownloadConfig::checksum_manifest).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChecksumAlgorithm {
+    /// SHA1, written to `SHA1SUMS`. Only for tooling that needs it, since SHA1 is no longer collision resistant.
+    Sha1,
+    /// SHA256, written to `SHA256SUMS`.
+    #[default]
+    Sha256,
+    /// SHA512, written to `SHA512SUMS`.
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// The name of the manifest file, like `SHA256SUMS`, which is written to the output directory.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha1 => "SHA1SUMS",
+            ChecksumAlgorithm::Sha256 => "SHA256SUMS",
+            ChecksumAlgorithm::Sha512 => "SHA512SUMS",
+        }
+    }
+
+    /// Hashes `bytes`, returning the digest as a lowercase hex string.
+    pub fn hex_digest(&self, bytes: &[u8]) -> String {
+        let digest = match self {
+            ChecksumAlgorithm::Sha1 => Sha1::digest(bytes).to_vec(),
+            ChecksumAlgorithm::Sha256 => Sha256::digest(bytes).to_vec(),
+            ChecksumAlgorithm::Sha512 => Sha512::digest(bytes).to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl Downloader {
+    /// Writes a checksum manifest of `files` in the format of `sha256sum` and similar tools, with a line like
+    /// `<hex digest>  <path>` for each file, to the [file_name](ChecksumAlgorithm::file_name) of `algorithm` in
+    /// the output directory. Files are hashed as they are on disk, and listed by their path relative to the
+    /// output directory, sorted, so the manifest can be checked with `sha256sum -c SHA256SUMS` from there.
+    ///
+    /// This is done automatically when [checksum_manifest](DownloadConfig::checksum_manifest) is set, except by
+    /// [download_stream](Downloader::download_stream), which leaves it to the caller once the stream is finished.
+    pub async fn write_checksums<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        files: &[DownloadedFile],
+        algorithm: ChecksumAlgorithm,
+    ) -> Result<(), Error> {
+        let mut lines = Vec::with_capacity(files.len());
+        for file in files {
+            let bytes = tokio::fs::read(&file.output_path).await?;
+            let path = file
+                .output_path
+                .strip_prefix(config.output_path)
+                .unwrap_or(&file.output_path);
+            let (path, escaped) = checksum_path(path);
+            lines.push((path, escaped, algorithm.hex_digest(&bytes)));
+        }
+        lines.sort();
+        lines.dedup();
+
+        let mut manifest = String::new();
+        for (path, escaped, digest) in lines {
+            // like the coreutils tools, a line whose path had to be escaped starts with a backslash
+            if escaped {
+                manifest.push('\\');
+            }
+            manifest.push_str(&format!("{}  {}\n", digest, path));
+        }
+
+        tokio::fs::create_dir_all(config.output_path).await?;
+        let manifest_path = config.output_path.join(algorithm.file_name());
+        Downloader::write_file(&manifest_path, manifest.as_bytes(), config.temp_dir).await
+    }
+}
+
+/// Returns `path` with forward slashes, escaping backslashes and newlines the way `sha256sum` does, and whether
+/// anything had to be escaped.
+fn checksum_path(path: &Path) -> (String, bool) {
+    let path: Vec<_> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    let path = path.join("/");
+
+    match path.contains(['\\', '\n', '\r']) {
+        true => (
+            path.replace('\\', "\\\\")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r"),
+            true,
+        ),
+        false => (path, false),
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use reqwest::{header::HeaderMap, Client};
+
+use crate::cache::fingerprint;
+use crate::{
+    raw::{self, CommitSummaryModel},
+    request::{HttpOptions, HttpRequest},
+    ApiObserver, Error, GithubBranchPath, RepoInfo, TreeCache, TreeFetchStrategy,
+};
+
+/// A client for the GitHub API, holding the credentials and connection pool shared between requests.
+///
+/// Cloning a [GithubClient] is cheap and shares the same connection pool.
+#[derive(Clone)]
+pub struct GithubClient {
+    client: Client,
+    budget: Arc<RequestBudget>,
+    observer: Option<Arc<dyn ApiObserver>>,
+    cache: Option<Arc<TreeCache>>,
+    authenticated: bool,
+    auth_fingerprint: Option<u64>,
+    api_url: Arc<str>,
+    tree_fetch_strategy: TreeFetchStrategy,
+}
+
+/// The URL of the GitHub REST API that clients make requests to unless given another with
+/// [with_api_url](GithubClient::with_api_url).
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// The number of requests per hour GitHub allows without an access token.
+#[cfg(feature = "download")]
+pub(crate) const UNAUTHENTICATED_RATE_LIMIT: u64 = 60;
+
+impl fmt::Debug for GithubClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GithubClient")
+            .field("client", &self.client)
+            .field("budget", &self.budget)
+            .field("has_observer", &self.observer.is_some())
+            .field("cache", &self.cache)
+            .field("authenticated", &self.authenticated)
+            .field("api_url", &self.api_url)
+            .field("tree_fetch_strategy", &self.tree_fetch_strategy)
+            .finish()
+    }
+}
+
+impl GithubClient {
+    /// Creates a new [GithubClient], authenticating with the given GitHub personal access token if provided.
+    pub fn new(access_token: Option<&str>) -> Result<GithubClient, Error> {
+        GithubClient::with_options(access_token, &HttpOptions::default())
+    }
+
+    /// Creates a new [GithubClient] like [new](GithubClient::new), sending requests with the given [HttpOptions].
+    pub fn with_options(
+        access_token: Option<&str>,
+        options: &HttpOptions<'_>,
+    ) -> Result<GithubClient, Error> {
+        let access_token = access_token.map(Cow::from);
+        Ok(GithubClient {
+            client: HttpRequest::client(&access_token, options)?,
+            budget: Arc::new(RequestBudget::default()),
+            observer: None,
+            cache: None,
+            authenticated: access_token.is_some(),
+            auth_fingerprint: access_token.as_deref().map(fingerprint),
+            api_url: Arc::from(DEFAULT_API_URL),
+            tree_fetch_strategy: TreeFetchStrategy::default(),
+        })
+    }
+
+    /// Returns this client with an [ApiObserver] that will be notified of every request made through it
+    /// and its clones.
+    pub fn with_observer(self, observer: Arc<dyn ApiObserver>) -> GithubClient {
+        GithubClient {
+            observer: Some(observer),
+            ..self
+        }
+    }
+
+    /// Returns this client with a [TreeCache] that trees and resolved references fetched through it and its
+    /// clones will be cached in.
+    pub fn with_cache(self, cache: Arc<TreeCache>) -> GithubClient {
+        GithubClient {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Returns this client making requests to the REST API at `api_url` instead of [DEFAULT_API_URL], such as
+    /// `https://github.example.com/api/v3` for GitHub Enterprise Server.
+    pub fn with_api_url(self, api_url: &str) -> GithubClient {
+        GithubClient {
+            api_url: Arc::from(api_url.trim_end_matches('/')),
+            ..self
+        }
+    }
+
+    /// Returns the URL of the REST API this client makes requests to, without a trailing slash.
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Returns the URL of the GraphQL API that goes with the REST API of this client.
+    ///
+    /// GitHub Enterprise Server serves the REST API at `/api/v3` and the GraphQL API at `/api/graphql`,
+    /// while github.com serves both from the root of `api.github.com`.
+    #[cfg(feature = "graphql")]
+    pub(crate) fn graphql_url(&self) -> String {
+        match self.api_url.strip_suffix("/api/v3") {
+            Some(host) => format!("{}/api/graphql", host),
+            None => format!("{}/graphql", self.api_url),
+        }
+    }
+
+    /// Returns this client obtaining trees with the given [TreeFetchStrategy] instead of
+    /// [TreeFetchStrategy::Rest].
+    pub fn with_tree_fetch_strategy(self, tree_fetch_strategy: TreeFetchStrategy) -> GithubClient {
+        GithubClient {
+            tree_fetch_strategy,
+            ..self
+        }
+    }
+
+    /// Returns the [TreeFetchStrategy] this client obtains trees with.
+    pub fn tree_fetch_strategy(&self) -> TreeFetchStrategy {
+        self.tree_fetch_strategy
+    }
+
+    /// Returns the [TreeCache] of this client, if it has one.
+    pub fn cache(&self) -> Option<&TreeCache> {
+        self.cache.as_deref()
+    }
+
+    /// Returns whether this client was created with an access token.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Returns the key that separates what this client caches in a shared [TreeCache] from what clients with
+    /// other API URLs or access tokens cache, so that nothing fetched with one token is served to another.
+    /// The token itself is only kept as a hash.
+    pub(crate) fn cache_scope(&self) -> String {
+        match self.auth_fingerprint {
+            Some(fingerprint) => format!("{}#{:016x}", self.api_url, fingerprint),
+            None => format!("{}#anonymous", self.api_url),
+        }
+    }
+
+    /// Returns the [RequestBudget] tracking the API requests made through this client and its clones.
+    pub fn budget(&self) -> &RequestBudget {
+        &self.budget
+    }
+
+    /// Fetches information about the repository of `repo`, such as its default branch and license.
+    /// The reference of `repo` is ignored.
+    pub async fn get_repo(&self, repo: &GithubBranchPath<'_>) -> Result<RepoInfo, Error> {
+        Ok(raw::get_repository_raw(self, repo).await?.into())
+    }
+
+    /// Lists the commits reachable from the reference of `repo`, newest first, one page at a time.
+    ///
+    /// Only commits touching `path` are listed if it's given, so a historical version of a file can be picked
+    /// and then fetched with [GithubRef::Commit](crate::GithubRef::Commit). `since` and `until` are
+    /// ISO 8601 timestamps, such as `2024-01-01T00:00:00Z`, limiting the commits to those made between them.
+    pub async fn list_commits(
+        &self,
+        repo: &GithubBranchPath<'_>,
+        path: Option<&str>,
+        since: Option<&str>,
+        until: Option<&str>,
+        pagination: Pagination,
+    ) -> Result<Vec<CommitSummaryModel>, Error> {
+        raw::list_commits_raw(self, repo, path, since, until, pagination).await
+    }
+
+    /// Returns the [ApiObserver] of this client, if it has one.
+    pub(crate) fn observer(&self) -> Option<&dyn ApiObserver> {
+        self.observer.as_deref()
+    }
+
+    /// Returns the underlying [reqwest::Client].
+    pub(crate) fn http(&self) -> &Client {
+        &self.client
+    }
+}
+
+/// The number of results per page GitHub returns by default.
+pub const DEFAULT_PER_PAGE: u32 = 30;
+
+/// Which page of results to request from an API that returns a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    /// The page to request, starting at 1.
+    pub page: u32,
+    /// How many results are on each page. GitHub allows at most 100.
+    pub per_page: u32,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Pagination {
+            page: 1,
+            per_page: DEFAULT_PER_PAGE,
+        }
+    }
+}
+
+impl Pagination {
+    /// Returns the pagination for the page after this one.
+    pub fn next(self) -> Pagination {
+        Pagination {
+            page: self.page + 1,
+            ..self
+        }
+    }
+}
+
+/// Sentinel for rate limit values that haven't been reported by GitHub yet.
+const UNKNOWN: u64 = u64::MAX;
+
+/// Tracks how many API requests have been made, and how many GitHub says are left.
+#[derive(Debug)]
+pub struct RequestBudget {
+    requests_made: AtomicU64,
+    bytes_received: AtomicU64,
+    limit: AtomicU64,
+    remaining: AtomicU64,
+}
+
+impl Default for RequestBudget {
+    fn default() -> Self {
+        RequestBudget {
+            requests_made: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            limit: AtomicU64::new(UNKNOWN),
+            remaining: AtomicU64::new(UNKNOWN),
+        }
+    }
+}
+
+impl RequestBudget {
+    /// The number of API requests that have been made so far.
+    pub fn requests_made(&self) -> u64 {
+        self.requests_made.load(Ordering::Relaxed)
+    }
+
+    /// The total size of the bodies of the responses received so far, in bytes, as they came over the wire. This
+    /// includes the JSON and base64 overhead of blobs fetched through the API, and the responses of requests
+    /// that failed.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests allowed per hour, as of the last response from GitHub.
+    pub fn limit(&self) -> Option<u64> {
+        known(self.limit.load(Ordering::Relaxed))
+    }
+
+    /// The number of requests left in the current hour, as of the last response from GitHub.
+    pub fn remaining(&self) -> Option<u64> {
+        known(self.remaining.load(Ordering::Relaxed))
+    }
+
+    /// Records the body of a response, which was `len` bytes long.
+    pub(crate) fn record_body(&self, len: usize) {
+        self.bytes_received.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Records a request, reading the rate limit headers from its response.
+    pub(crate) fn record(&self, headers: &HeaderMap) {
+        self.requests_made.fetch_add(1, Ordering::Relaxed);
+
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+        if let Some(limit) = header("x-ratelimit-limit") {
+            self.limit.store(limit, Ordering::Relaxed);
+        }
+        if let Some(remaining) = header("x-ratelimit-remaining") {
+            self.remaining.store(remaining, Ordering::Relaxed);
+        }
+    }
+}
+
+fn known(value: u64) -> Option<u64> {
+    match value {
+        UNKNOWN => None,
+        value => Some(value),
+    }
+}
@@ -0,0 +1,79 @@
+use std::{collections::HashMap, fmt, sync::Mutex};
+
+use sha2::{Digest, Sha256};
+
+use crate::{Error, GithubClient, HttpOptions};
+
+/// A [ClientPool] key: an API URL and the SHA-256 hash of an access token, so tokens aren't kept around as keys.
+type ClientKey = (String, Option<Vec<u8>>);
+
+/// Hands out a [GithubClient] for each pair of an API URL and access token, creating it the first time it's
+/// needed and reusing it afterwards.
+///
+/// This is for services that make requests on behalf of many users, possibly against several GitHub Enterprise
+/// Server hosts, which would otherwise create a client, and a connection pool, for every operation. Each client
+/// keeps its own [RequestBudget](crate::RequestBudget), so the rate limit of each token is tracked separately.
+/// Set [client_pool](crate::DownloadConfig::client_pool) to take clients from a pool.
+#[derive(Default)]
+pub struct ClientPool {
+    options: HttpOptions<'static>,
+    clients: Mutex<HashMap<ClientKey, GithubClient>>,
+}
+
+impl ClientPool {
+    /// Creates a new, empty [ClientPool] whose clients send requests with the default [HttpOptions].
+    pub fn new() -> ClientPool {
+        ClientPool::default()
+    }
+
+    /// Creates a new, empty [ClientPool] whose clients send requests with the given [HttpOptions].
+    pub fn with_options(options: HttpOptions<'static>) -> ClientPool {
+        ClientPool {
+            options,
+            clients: Mutex::default(),
+        }
+    }
+
+    /// Returns the client for the REST API at `api_url` authenticating with `access_token`, creating it if
+    /// this pool doesn't have one yet.
+    pub fn get(&self, api_url: &str, access_token: Option<&str>) -> Result<GithubClient, Error> {
+        let api_url = api_url.trim_end_matches('/');
+        let key = (
+            api_url.to_string(),
+            access_token.map(|token| Sha256::digest(token.as_bytes()).to_vec()),
+        );
+
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = GithubClient::with_options(access_token, &self.options)?.with_api_url(api_url);
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Returns the number of clients in this pool.
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    /// Returns true if this pool has no clients.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every client from this pool, such as when the tokens they were created with have been revoked.
+    pub fn clear(&self) {
+        self.clients.lock().unwrap().clear();
+    }
+}
+
+impl fmt::Debug for ClientPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientPool")
+            .field("options", &self.options)
+            .field("clients", &self.len())
+            .finish()
+    }
+}
@@ -0,0 +1,295 @@
+use std::{collections::HashMap, io::Read};
+
+use flate2::read::GzDecoder;
+use futures::future::{BoxFuture, FutureExt};
+use tar::EntryType;
+use tokio::sync::mpsc;
+
+use crate::{
+    git_hash, raw, DownloadConfig, DownloadReporter, Error, ErrorContext, FileMode, Filter, Forge,
+    GithubArchiveFormat, GithubBranchPath, GithubClient, ObjectFormat, ObjectId, ResolvedRef,
+    SourceTree, SourceTreeBuilder, TreeEntryType, DEFAULT_API_URL,
+};
+
+/// The contents of a repository at a reference, downloaded at once as a gzipped tarball and served as a
+/// [Forge], so a download can continue through the usual pipeline without making a request per file.
+///
+/// Only blobs that pass the filter it was fetched with are kept. Entries are hashed as they're read, so
+/// downloaded files are [verified](crate::DownloadedFile::verified) against the archive itself.
+pub(crate) struct CodeloadArchive {
+    resolved: ResolvedRef,
+    tree: SourceTree,
+    blobs: HashMap<ObjectId, Vec<u8>>,
+}
+
+/// The quotas of a [DownloadConfig] that are enforced while an archive is unpacked, so that a large archive,
+/// or one that decompresses to far more than it downloads, is given up on before it's held in memory.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct UnpackLimits {
+    /// Files over this size are listed in the tree, so they're reported as skipped, but aren't read.
+    pub(crate) max_file_bytes: Option<u64>,
+    /// Unpacking fails once the files kept add up to more than this.
+    pub(crate) max_total_bytes: Option<u64>,
+    /// Unpacking fails once more than this many files pass the filter.
+    pub(crate) max_files: Option<usize>,
+}
+
+impl UnpackLimits {
+    /// Returns the quotas of `config`.
+    pub(crate) fn of<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+    ) -> UnpackLimits {
+        UnpackLimits {
+            max_file_bytes: config.max_file_bytes,
+            max_total_bytes: config.max_total_bytes,
+            max_files: config.max_files,
+        }
+    }
+}
+
+/// How many chunks of the response can be waiting to be unpacked before reading more of it waits.
+const CHUNKS_IN_FLIGHT: usize = 16;
+
+impl CodeloadArchive {
+    /// Downloads and unpacks the archive of the reference of `path`, keeping the blobs that pass `filter`.
+    ///
+    /// Archives of repositories on github.com come from codeload.github.com, which doesn't count against the
+    /// API's rate limit. Other hosts, such as GitHub Enterprise Server, are asked for the archive through the
+    /// API, which redirects to wherever it's served from.
+    ///
+    /// The archive is unpacked as it's downloaded, and the download stops as soon as one of `limits` is
+    /// exceeded, failing with [Error::QuotaExceeded] or [Error::TooManyFiles].
+    pub(crate) async fn fetch(
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+        limits: UnpackLimits,
+    ) -> Result<CodeloadArchive, Error> {
+        let url = match client.api_url() == DEFAULT_API_URL {
+            true => path.archive_url(GithubArchiveFormat::TarGz)?,
+            false => {
+                let reference = path.reference.commit_expression()?;
+                path.to_api_url(client.api_url(), &format!("tarball/{}", reference))
+            }
+        };
+
+        let request = client.http().get(&url);
+        let mut response = raw::send_streaming(client, request, "*/*").await?;
+
+        // tar only reads synchronously, so the archive is unpacked on a blocking thread fed with the chunks
+        let (sender, receiver) = mpsc::channel(CHUNKS_IN_FLIGHT);
+        let unpacking = {
+            let url = url.clone();
+            let filter = filter.to_owned_filter();
+            tokio::task::spawn_blocking(move || {
+                let reader = ChunkReader {
+                    receiver,
+                    chunk: None,
+                    position: 0,
+                };
+                CodeloadArchive::unpack(reader, &url, &filter, limits)
+            })
+        };
+
+        let received = async {
+            while let Some(chunk) = response.chunk().await? {
+                client.budget().record_body(chunk.len());
+                // unpacking has stopped, having failed or reached the end of the archive
+                if sender.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        drop(sender);
+
+        let unpacked = unpacking
+            .await
+            .map_err(|e| Error::Other(format!("unpacking the archive failed: {}", e)))?;
+        let context = || ErrorContext {
+            url: Some(url.clone()),
+            ..ErrorContext::default()
+        };
+        match (received, unpacked) {
+            (Err(e), _) => Err(e.with_context(context())),
+            (Ok(()), unpacked) => unpacked,
+        }
+    }
+
+    /// Reads the gzipped tarball in `body`, which was downloaded from `url`.
+    fn unpack(
+        body: impl Read,
+        url: &str,
+        filter: &Filter<'_>,
+        limits: UnpackLimits,
+    ) -> Result<CodeloadArchive, Error> {
+        let mut archive = tar::Archive::new(GzDecoder::new(body));
+        let mut builder = SourceTreeBuilder::new();
+        let mut blobs = HashMap::new();
+        let mut commit_sha = None;
+        let mut files = 0;
+        let mut total_bytes = 0;
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+
+            // GitHub puts the hash of the commit in the comment of the archive's global header
+            if entry_type == EntryType::XGlobalHeader {
+                for extension in entry.pax_extensions()?.into_iter().flatten() {
+                    let extension = extension?;
+                    if extension.key() == Ok("comment") {
+                        commit_sha = extension.value().ok().and_then(|v| v.parse().ok());
+                    }
+                }
+                continue;
+            }
+
+            // every entry is inside a directory named after the repository and reference
+            let entry_path = entry.path()?.to_string_lossy().replace('\\', "/");
+            let path = match entry_path.split_once('/') {
+                Some((_, path)) if !path.is_empty() => path.trim_end_matches('/').to_string(),
+                _ => continue,
+            };
+
+            let (mode, contents) = match entry_type {
+                EntryType::Regular | EntryType::Continuous => {
+                    let mode = match entry.header().mode()? & 0o111 {
+                        0 => FileMode::Regular,
+                        _ => FileMode::Executable,
+                    };
+                    (mode, None)
+                }
+                EntryType::Symlink => {
+                    let target = entry.link_name()?.unwrap_or_default();
+                    let target = target.to_string_lossy().replace('\\', "/");
+                    (FileMode::Symlink, Some(target.into_bytes()))
+                }
+                _ => continue,
+            };
+            if !filter.check(&path) {
+                continue;
+            }
+
+            files += 1;
+            if let Some(limit) = limits.max_files.filter(|&limit| files > limit) {
+                return Err(Error::TooManyFiles { limit });
+            }
+
+            let size = match &contents {
+                Some(contents) => contents.len() as u64,
+                None => entry.header().size()?,
+            };
+            let mut node = SourceTree::new(TreeEntryType::Blob);
+            node.path = path.into();
+            node.mode = mode;
+            node.size = u32::try_from(size).unwrap_or(u32::MAX);
+            node.url = url.to_string();
+
+            // listed without its contents, so the download reports it as skipped
+            if limits.max_file_bytes.is_some_and(|limit| size > limit) {
+                builder.add_entry(node)?;
+                continue;
+            }
+
+            total_bytes += size;
+            if let Some(limit) = limits.max_total_bytes.filter(|&limit| total_bytes > limit) {
+                return Err(Error::QuotaExceeded {
+                    path: None,
+                    size: total_bytes,
+                    limit,
+                });
+            }
+
+            let contents = match contents {
+                Some(contents) => contents,
+                None => {
+                    let mut contents = Vec::new();
+                    entry.by_ref().take(size).read_to_end(&mut contents)?;
+                    contents
+                }
+            };
+
+            node.sha = git_hash::blob_id(&contents, ObjectFormat::Sha1);
+            builder.add_entry(node.clone())?;
+            blobs.insert(node.sha, contents);
+        }
+
+        Ok(CodeloadArchive {
+            resolved: ResolvedRef {
+                commit_sha,
+                tree_sha: ObjectId::NULL,
+            },
+            tree: builder.build(),
+            blobs,
+        })
+    }
+}
+
+/// Reads the chunks of a response sent from the task downloading it, blocking until each arrives.
+struct ChunkReader<T> {
+    receiver: mpsc::Receiver<T>,
+    chunk: Option<T>,
+    position: usize,
+}
+
+impl<T: AsRef<[u8]>> Read for ChunkReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(chunk) = &self.chunk {
+                let remaining = &chunk.as_ref()[self.position..];
+                if !remaining.is_empty() {
+                    let len = remaining.len().min(buf.len());
+                    buf[..len].copy_from_slice(&remaining[..len]);
+                    self.position += len;
+                    return Ok(len);
+                }
+            }
+
+            // the sender is dropped once the whole response has been received
+            match self.receiver.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = Some(chunk);
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+impl Forge for CodeloadArchive {
+    fn resolve_ref<'f>(
+        &'f self,
+        _path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        async move { Ok(self.resolved.clone()) }.boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        _path: &'f GithubBranchPath<'f>,
+        _filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move { Ok(self.tree.clone()) }.boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        async move {
+            self.blobs
+                .get(&entry.sha)
+                .cloned()
+                .ok_or_else(|| Error::Other(format!("{} isn't in the archive", entry.path)))
+        }
+        .boxed()
+    }
+
+    fn get_resolved<'f>(
+        &'f self,
+        _path: &'f GithubBranchPath<'f>,
+        _filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<(ResolvedRef, SourceTree), Error>> {
+        async move { Ok((self.resolved.clone(), self.tree.clone())) }.boxed()
+    }
+}
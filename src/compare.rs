@@ -0,0 +1,169 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    Error, FileMode, Filter, GithubBranchPath, GithubClient, GithubRef, ObjectId, ResolvedRef,
+    SourceTree, TreeEntryType,
+};
+
+/// How a file differs between the two trees of a [TreeComparison].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The file is only in the new tree.
+    Added,
+    /// The file is only in the old tree.
+    Removed,
+    /// The file is in both trees, with different contents or a different mode.
+    Modified,
+}
+
+/// A file that differs between the two trees of a [TreeComparison].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileChange {
+    /// The path of the file in the repository.
+    pub path: String,
+    /// How the file differs.
+    pub kind: ChangeKind,
+    /// The SHA of the file in the old tree, unless it was added.
+    pub old_sha: Option<ObjectId>,
+    /// The SHA of the file in the new tree, unless it was removed.
+    pub new_sha: Option<ObjectId>,
+    /// The mode of the file in the old tree, unless it was added.
+    pub old_mode: Option<FileMode>,
+    /// The mode of the file in the new tree, unless it was removed.
+    pub new_mode: Option<FileMode>,
+    /// How many bytes larger the file is in the new tree, counting a missing file as 0 bytes. Files whose size
+    /// isn't known, such as those from a reconstructed truncated tree, count as 0 bytes too.
+    pub size_delta: i64,
+}
+
+/// The files that differ between two references of a repository, as found by [compare](SourceTree::compare).
+///
+/// Its [Display](fmt::Display) form has a line for each change, like `M src/lib.rs (+120 bytes)`, with `A` for
+/// added, `D` for removed and `M` for modified files.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeComparison {
+    /// What the old reference resolved to.
+    pub old: ResolvedRef,
+    /// What the new reference resolved to.
+    pub new: ResolvedRef,
+    /// Every file that differs, sorted by path.
+    pub changes: Vec<FileChange>,
+}
+
+impl TreeComparison {
+    /// Returns true if every file that passed the filter is the same in both trees.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the changes of the given kind.
+    pub fn of_kind(&self, kind: ChangeKind) -> impl Iterator<Item = &FileChange> {
+        self.changes
+            .iter()
+            .filter(move |change| change.kind == kind)
+    }
+
+    /// How many bytes larger the files that passed the filter are in the new tree, in total.
+    pub fn size_delta(&self) -> i64 {
+        self.changes.iter().map(|change| change.size_delta).sum()
+    }
+}
+
+impl fmt::Display for TreeComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            let status = match change.kind {
+                ChangeKind::Added => 'A',
+                ChangeKind::Removed => 'D',
+                ChangeKind::Modified => 'M',
+            };
+            write!(f, "{} {}", status, change.path)?;
+
+            match (change.old_mode, change.new_mode) {
+                _ if change.size_delta != 0 => write!(f, " ({:+} bytes)", change.size_delta)?,
+                (Some(old), Some(new)) if old != new => write!(f, " (mode {} -> {})", old, new)?,
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SourceTree {
+    /// Compares the files of two references of the repository of `repo` that pass `filter`, fetching one tree for
+    /// each reference and without downloading any blobs. The reference of `repo` itself is ignored.
+    ///
+    /// Unlike GitHub's compare API, this works between any two references, including unrelated ones, and isn't
+    /// limited in how many files it returns.
+    pub async fn compare(
+        client: &GithubClient,
+        repo: &GithubBranchPath<'_>,
+        old: GithubRef<'_>,
+        new: GithubRef<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeComparison, Error> {
+        let old_path = repo.with_reference(old);
+        let new_path = repo.with_reference(new);
+        let (old, old_tree) =
+            SourceTree::get_resolved_with_client(client, &old_path, filter).await?;
+        let (new, new_tree) =
+            SourceTree::get_resolved_with_client(client, &new_path, filter).await?;
+
+        Ok(TreeComparison {
+            old,
+            new,
+            changes: old_tree.changes_to(&new_tree),
+        })
+    }
+
+    /// Returns the blobs that differ between this tree and `new`, sorted by path.
+    ///
+    /// Paths are taken relative to each tree, so two subtrees at different paths can be compared.
+    pub fn changes_to(&self, new: &SourceTree) -> Vec<FileChange> {
+        let blobs = |tree: &SourceTree| -> BTreeMap<String, (ObjectId, FileMode, i64)> {
+            tree.iter_relative()
+                .filter(|(_, node)| node.entry_type == TreeEntryType::Blob)
+                .map(|(path, node)| (path.to_string(), (node.sha, node.mode, node.size as i64)))
+                .collect()
+        };
+        let mut old_blobs = blobs(self);
+        let new_blobs = blobs(new);
+
+        let mut changes = Vec::new();
+        for (path, (new_sha, new_mode, new_size)) in new_blobs {
+            let (kind, old) = match old_blobs.remove(&path) {
+                None => (ChangeKind::Added, None),
+                Some(old) if old.0 == new_sha && old.1 == new_mode => continue,
+                Some(old) => (ChangeKind::Modified, Some(old)),
+            };
+            changes.push(FileChange {
+                path,
+                kind,
+                old_sha: old.map(|(sha, _, _)| sha),
+                new_sha: Some(new_sha),
+                old_mode: old.map(|(_, mode, _)| mode),
+                new_mode: Some(new_mode),
+                size_delta: new_size - old.map_or(0, |(_, _, size)| size),
+            });
+        }
+        for (path, (old_sha, old_mode, old_size)) in old_blobs {
+            changes.push(FileChange {
+                path,
+                kind: ChangeKind::Removed,
+                old_sha: Some(old_sha),
+                new_sha: None,
+                old_mode: Some(old_mode),
+                new_mode: None,
+                size_delta: -old_size,
+            });
+        }
+
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        changes
+    }
+}
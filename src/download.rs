@@ -1,20 +1,57 @@
-use base64::{prelude::BASE64_STANDARD, Engine};
 use futures::{
     future::{self, BoxFuture},
-    FutureExt,
+    stream, FutureExt, Stream, StreamExt, TryStreamExt,
 };
-use itertools::Itertools;
-use serde::Deserialize;
+use glob_match::glob_match_with_captures;
 use std::{
     borrow::Cow,
-    env,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    env, fmt,
+    hash::{Hash, Hasher},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use crate::{request::HttpRequest, Error, Filter, GithubBranchPath, SourceTree, TreeEntryType};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::{
+    adaptive::{AdaptiveLimiter, Outcome},
+    checksums::ChecksumAlgorithm,
+    client::{DEFAULT_API_URL, UNAUTHENTICATED_RATE_LIMIT},
+    client_pool::ClientPool,
+    error::serialize_duration_ms,
+    export::serialize_entry,
+    fetch_strategy::FetchStrategy,
+    forge::Forge,
+    git_hash,
+    handle::DownloadHandle,
+    inspect::{ContentInspector, InspectVerdict},
+    path_check,
+    permissions::{FilePermissions, PermissionsHook},
+    rate_limit::RateLimitGate,
+    raw,
+    raw::{ContentEncoding, LicenseModel},
+    source_tree::normalize_path,
+    spdx::{self, SpdxLicense},
+    telemetry,
+    transform::ContentTransform,
+    Error, ErrorContext, Filter, GithubBranchPath, GithubClient, GithubRef, HttpOptions, ObjectId,
+    RateLimitGuidance, ResolvedRef, SourceTree, TreeEntryType,
+};
+#[cfg(feature = "cli")]
+use crate::{Blob, GrabProfiles, PROFILE_FILE_NAME};
 
-/// An event involving a single download.
-#[derive(Debug)]
+/// An event involving a download.
+///
+/// Events serialize as an object whose `type` is the name of the variant in snake case, such as
+/// `{"type":"download_completed","path":"src/lib.rs"}`, with durations in milliseconds.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum DownloadEvent<'p> {
     /// A file has begun downloading.
     DownloadStarted {
@@ -25,6 +62,10 @@ pub enum DownloadEvent<'p> {
     DownloadCompleted {
         /// The path of the file relative to the root of the repository.
         path: &'p str,
+        /// See [DownloadedFile::bytes_received].
+        bytes_received: u64,
+        /// See [DownloadedFile::bytes_written].
+        bytes_written: u64,
     },
     /// A file has encountered an error and has failed to download.
     DownloadFailed {
@@ -33,6 +74,85 @@ pub enum DownloadEvent<'p> {
         /// The [Error] that was encountered while attempting to download the file.
         error: Error,
     },
+    /// A file hit a GitHub rate limit. All downloads are paused for `retry_after`,
+    /// after which this file will be retried.
+    RateLimited {
+        /// The path of the file relative to the root of the repository.
+        path: &'p str,
+        /// How long downloads are paused for.
+        #[serde(rename = "retry_after_ms", serialize_with = "serialize_duration_ms")]
+        retry_after: Duration,
+    },
+    /// A file would be written to the same path as another file, ignoring case if the filesystem does.
+    /// What happens next depends on the [CollisionPolicy].
+    PathCollision {
+        /// The path of the file relative to the root of the repository.
+        path: &'p str,
+        /// The path of the file that was going to be written to the same place first.
+        existing: &'p str,
+    },
+    /// Every file has been planned and downloading is about to begin. This is sent once per operation,
+    /// before any other event besides skipped files and collisions.
+    DownloadPlanned {
+        /// How many files will be downloaded.
+        files: usize,
+        /// The total size in bytes of the files that will be downloaded.
+        /// Files whose size isn't known count as 0; see [prefetch_sizes](DownloadConfig::prefetch_sizes).
+        total_bytes: u64,
+    },
+    /// No access token is set, and the download needs more requests than GitHub allows without one in an hour,
+    /// so it will likely be rate limited before it finishes. Setting an access token raises the limit, and
+    /// downloading the repository as an archive from github.com doesn't count against it.
+    UnauthenticatedRateLimit {
+        /// About how many requests the download needs.
+        requests: usize,
+        /// How many requests GitHub allows per hour without an access token.
+        limit: u64,
+    },
+    /// A file already on disk matched its blob during a [sync](Downloader::sync), so it wasn't downloaded.
+    FileUnchanged {
+        /// The path of the file relative to the root of the repository.
+        path: &'p str,
+    },
+    /// A file that isn't in the tree anymore was deleted during a [sync](Downloader::sync).
+    FileDeleted {
+        /// The path of the file relative to the output directory.
+        path: &'p str,
+    },
+    /// A [sync](Downloader::sync) has finished, with the number of files in each part of its [SyncSummary](crate::SyncSummary).
+    SyncFinished {
+        /// How many files were downloaded that weren't on disk before.
+        added: usize,
+        /// How many files on disk were replaced because their contents changed.
+        updated: usize,
+        /// How many files were deleted.
+        deleted: usize,
+        /// How many files on disk already matched.
+        unchanged: usize,
+    },
+    /// A file was left out because it didn't pass the filter. This is only sent with
+    /// [report_filtered](DownloadConfig::report_filtered) set, before the download is planned.
+    FilteredOut {
+        /// The path of the file relative to the root of the repository.
+        path: &'p str,
+        /// The first excluded glob that matched the file, or `None` if it was left out for not matching any
+        /// of the included globs.
+        matched_exclude: Option<&'p str>,
+    },
+    /// A download with [FetchStrategy::Auto] failed with one strategy, and is trying the next.
+    FetchFallback {
+        /// The strategy that failed.
+        from: FetchStrategy,
+        /// The strategy that will be tried next.
+        to: FetchStrategy,
+        /// The [Error] that `from` failed with.
+        error: Error,
+    },
+    /// A download with [FetchStrategy::Auto] has finished, having obtained its files with `strategy`.
+    FetchStrategyUsed {
+        /// The strategy that succeeded.
+        strategy: FetchStrategy,
+    },
 }
 
 /// Implement this trait to receive events on the status of each upload.
@@ -45,10 +165,169 @@ pub trait DownloadReporter: Sync {
 pub struct NullDownloadReporter {}
 
 impl DownloadReporter for NullDownloadReporter {
-    fn on_event<'p>(&'p self, _event: DownloadEvent<'p>) -> () {}
+    fn on_event<'p>(&'p self, _event: DownloadEvent<'p>) {}
+}
+
+/// Implement this trait to run something once files have been downloaded, such as changing their permissions,
+/// generating an index of them or starting a build.
+///
+/// Hooks are run by [download_resolved](Downloader::download_resolved) and
+/// [download_refs](Downloader::download_refs) after every file has been written. Errors they return don't fail
+/// the download, and are collected in [hook_failures](DownloadReport::hook_failures) instead.
+pub trait DownloadHooks: Sync {
+    /// Called for each downloaded file, one at a time, in the order of the report.
+    fn after_file<'h>(&'h self, _file: &'h DownloadedFile) -> BoxFuture<'h, Result<(), Error>> {
+        future::ready(Ok(())).boxed()
+    }
+
+    /// Called once [after_file](DownloadHooks::after_file) has been called for every file, with the report of
+    /// the download.
+    fn after_run<'h>(&'h self, _report: &'h DownloadReport) -> BoxFuture<'h, Result<(), Error>> {
+        future::ready(Ok(())).boxed()
+    }
+}
+
+/// What should happen to a file, as decided by a [DownloadInterceptor].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterceptDecision {
+    /// Download the file to the path it would normally be written to.
+    Download,
+    /// Don't download the file.
+    Skip,
+    /// Download the file to the given path instead.
+    Redirect(PathBuf),
+}
+
+/// Implement this trait to decide whether each file is downloaded, and where it is written to.
+pub trait DownloadInterceptor: Sync {
+    /// Called for each file before anything is downloaded, with the path it would be written to.
+    fn before_download(&self, entry: &SourceTree, output_path: &Path) -> InterceptDecision;
 }
 
 const DEFAULT_MAX_DOWNLOADS: usize = 5;
+const DEFAULT_MAX_RATE_LIMIT_RETRIES: usize = 3;
+const DEFAULT_WRITE_WORKERS: usize = 8;
+
+/// The file name of the manifest written by [OutputLayout::ContentAddressed] and [OutputLayout::ContentAddressedFlat].
+pub const CONTENT_MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// How downloaded files are arranged in the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputLayout {
+    /// Files are written to their path in the repository, after applying any rename rules.
+    #[default]
+    Paths,
+    /// Files are written to `<output>/<sha>/<file name>`, and a manifest mapping each path in the
+    /// repository to its SHA is written to [CONTENT_MANIFEST_FILE_NAME].
+    ContentAddressed,
+    /// Files are written to `<output>/<sha>`, with a manifest like [OutputLayout::ContentAddressed].
+    ContentAddressedFlat,
+}
+
+/// What to do when two files would be written to the same path.
+///
+/// When the output directory is on a case-insensitive filesystem, like the defaults on macOS and Windows, paths that
+/// only differ in case are the same path, since such files would silently overwrite each other. On case-sensitive
+/// filesystems they're written side by side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CollisionPolicy {
+    /// Fail with [Error::PathCollision] before anything is downloaded.
+    #[default]
+    Error,
+    /// Write the later file with `~1`, `~2`, etc. added to its name, such as `Readme~1.md`.
+    Rename,
+    /// Skip the later file.
+    FirstWins,
+}
+
+/// What to do with a file whose path can't be written to disk on this platform.
+///
+/// Paths that weren't valid UTF-8 in the repository, which GitHub reports with `\u{FFFD}` in place of the
+/// invalid bytes, and paths containing `..` or a NUL character can't be written anywhere. On Windows, names
+/// containing characters like `:` or `?`, ending with a dot or space, or reserved for devices like `CON`
+/// can't be written either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnrepresentablePathPolicy {
+    /// Skip the file, and report it as failed with [Error::UnrepresentablePath].
+    #[default]
+    Skip,
+    /// Write the file with each character that can't be written replaced by `%` and the hex of its
+    /// UTF-8 bytes, such as `a%3Ab.txt` for `a:b.txt`. `%` is encoded as `%25` in those names.
+    ///
+    /// The path is encoded before [rename_rules](DownloadConfig::rename_rules) are applied.
+    PercentEncode,
+    /// Fail with [Error::UnrepresentablePath] before anything is downloaded.
+    Error,
+}
+
+/// The order files are downloaded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DownloadOrdering {
+    /// Files are downloaded in the order they appear in the tree.
+    #[default]
+    Tree,
+    /// The smallest files are downloaded first, so progress is reported as early as possible.
+    SmallestFirst,
+    /// The largest files are downloaded first, so they don't hold up the end of the operation.
+    LargestFirst,
+    /// Files are downloaded in order of their path in the repository.
+    Path,
+}
+
+/// How [download_refs](Downloader::download_refs) arranges the references it downloads in the output directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefLayout {
+    /// Each reference is written to a directory named after it, such as `<output>/v1.0`. Slashes in the name are
+    /// replaced with `-`, so `release/1.x` is written to `<output>/release-1.x`.
+    #[default]
+    Names,
+    /// Each reference is written to a directory named after the commit it resolved to, or after its tree for a
+    /// [GithubRef::Tree](crate::GithubRef::Tree).
+    CommitShas,
+}
+
+/// The environment variable [TokenSource::Env] reads the access token from.
+pub const ACCESS_TOKEN_ENV_VAR: &str = "GITHUB_ACCESS_TOKEN";
+
+/// Where the [access_token](DownloadConfig::access_token) of a [DownloadConfig] comes from, as given to
+/// [with_token_source](DownloadConfig::with_token_source).
+///
+/// Formatting this with `{:?}` redacts the token.
+#[derive(Clone, PartialEq, Eq)]
+pub enum TokenSource<'t> {
+    /// No access token, so requests are unauthenticated.
+    None,
+    /// The given access token.
+    Token(Cow<'t, str>),
+    /// The environment variable [ACCESS_TOKEN_ENV_VAR], if it's set.
+    Env,
+    /// The given environment variable, if it's set.
+    EnvVar(Cow<'t, str>),
+}
+
+impl<'t> TokenSource<'t> {
+    /// Returns the access token from this source, reading the environment if it needs to.
+    pub fn resolve(&self) -> Option<Cow<'t, str>> {
+        match self {
+            TokenSource::None => None,
+            TokenSource::Token(token) => Some(token.clone()),
+            TokenSource::Env => env::var(ACCESS_TOKEN_ENV_VAR).ok().map(Cow::from),
+            TokenSource::EnvVar(name) => env::var(name.as_ref()).ok().map(Cow::from),
+        }
+    }
+}
+
+impl fmt::Debug for TokenSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenSource::None => f.write_str("None"),
+            TokenSource::Token(_) => f.write_str("Token(<redacted>)"),
+            TokenSource::Env => f.write_str("Env"),
+            TokenSource::EnvVar(name) => f.debug_tuple("EnvVar").field(name).finish(),
+        }
+    }
+}
 
 /// Contains the configuration for a downloading operation.
 pub struct DownloadConfig<'download, Reporter>
@@ -59,56 +338,501 @@ where
     pub output_path: &'download Path,
     /// If provided, the reporter will receive events on the status of each download.
     pub reporter: Option<&'download Reporter>,
-    /// The maximum number of simultaneous downloads allowed at once.
+    /// If provided, the interceptor can skip files or change where they're written to.
+    /// Files are checked for [collisions](DownloadConfig::collision_policy) after being intercepted.
+    pub interceptor: Option<&'download dyn DownloadInterceptor>,
+    /// If provided, the inspector checks the contents of each file before it's written, and can block it.
+    pub inspector: Option<&'download dyn ContentInspector>,
+    /// If provided, the transform changes the contents of each file before it's inspected and written.
+    pub transform: Option<&'download dyn ContentTransform>,
+    /// If provided, the hooks are run once every file has been written.
+    pub hooks: Option<&'download dyn DownloadHooks>,
+    /// If provided, picks the mode and ownership of each file, which are set before the file is moved into place,
+    /// so a file is never readable with the wrong permissions. These are only applied on Unix.
+    pub permissions_hook: Option<&'download PermissionsHook>,
+    /// The maximum number of simultaneous downloads allowed at once, across every host.
     /// The default is 5.
     pub max_simultaneous_downloads: usize,
+    /// Limits on the number of simultaneous downloads from particular hosts, as pairs of a host name
+    /// (such as `api.github.com`) and a limit.
+    ///
+    /// Files are downloaded from the host of their [url](SourceTree::url). Hosts without a limit here are only
+    /// limited by `max_simultaneous_downloads`.
+    pub host_limits: Vec<(Cow<'download, str>, usize)>,
+    /// Whether the number of simultaneous downloads adapts to how quickly GitHub is responding.
+    ///
+    /// Downloads start one at a time, and more are allowed as responses come back quickly and successfully,
+    /// up to `max_simultaneous_downloads`. Whenever response times spike, compared with recent responses for files
+    /// of a similar size, or GitHub responds with a 403 or 429, the number allowed is halved.
+    /// The default is false.
+    pub adaptive_concurrency: bool,
     /// Your GitHub personal access token, if you have one.
+    /// This is never read from the environment unless asked to with [TokenSource::Env].
     pub access_token: Option<Cow<'download, str>>,
+    /// Whether every operation fails with [Error::AuthenticationRequired] instead of making requests without
+    /// an access token, which GitHub limits to 60 an hour.
+    /// The default is false.
+    pub require_auth: bool,
+    /// The maximum number of files written to disk at once.
+    /// The default is 8.
+    pub write_workers: usize,
+    /// How many times a single file will be retried after hitting a rate limit before giving up.
+    /// The default is 3.
+    pub max_rate_limit_retries: usize,
+    /// Rules for changing where files are written, as pairs of a glob and a replacement path.
+    ///
+    /// The first rule whose glob matches a file's path is used. Each wildcard (`*`, `**`, `?`, `[...]` or `{...}`)
+    /// in the replacement is replaced with whatever the corresponding wildcard in the glob matched, in order,
+    /// so `("docs/**", "site/content/**")` writes `docs/guide/intro.md` to `site/content/guide/intro.md`.
+    /// Files that don't match any rule are written to their path in the repository.
+    pub rename_rules: Vec<(Cow<'download, str>, Cow<'download, str>)>,
+    /// How files are arranged in the output directory.
+    /// The default is [OutputLayout::Paths].
+    pub layout: OutputLayout,
+    /// What to do when two files would be written to the same path, ignoring case if the output directory is on
+    /// a case-insensitive filesystem.
+    /// The default is [CollisionPolicy::Error].
+    pub collision_policy: CollisionPolicy,
+    /// What to do with files whose paths can't be written to disk on this platform.
+    /// The default is [UnrepresentablePathPolicy::Skip].
+    pub unrepresentable_paths: UnrepresentablePathPolicy,
+    /// Files larger than this many bytes are skipped, and reported as failed with [Error::QuotaExceeded].
+    /// Files whose size isn't known up front, such as those from forges that don't report sizes, fail with
+    /// [Error::QuotaExceeded] as soon as more than this many bytes of them have been received.
+    /// The default is no limit.
+    pub max_file_bytes: Option<u64>,
+    /// If the files to download add up to more than this many bytes, nothing is downloaded and
+    /// [Error::QuotaExceeded] is returned. Files skipped because of `max_file_bytes` don't count towards this.
+    /// Bytes are also counted as they're received, so the download fails as soon as it goes over this, even
+    /// when the sizes of its files weren't known up front.
+    /// The default is no limit.
+    pub max_total_bytes: Option<u64>,
+    /// If more than this many files pass the filter, nothing is downloaded and [Error::TooManyFiles] is
+    /// returned. This bounds the number of writes a download of an untrusted repository can make.
+    /// The default is no limit.
+    pub max_files: Option<usize>,
+    /// If a file that passes the filter is nested in more than this many directories, nothing is downloaded and
+    /// [Error::TooDeep] is returned. `src/lib.rs` has a depth of 1, and files at the root have a depth of 0.
+    /// The default is no limit.
+    pub max_depth: Option<usize>,
+    /// Whether the files to download are checked against the space available on the disk holding `output_path`
+    /// before anything is downloaded, failing early with [Error::InsufficientSpace] if they won't fit.
+    /// Files that would be overwritten aren't taken into account, and the check is skipped if the available
+    /// space can't be found.
+    /// The default is true.
+    pub check_disk_space: bool,
+    /// The directory files are written to while they're being downloaded, before being moved to their place in
+    /// `output_path`. This must be on the same filesystem as `output_path`, since files are moved with a rename.
+    /// The default is to write each file next to where it will end up, with a `.part` extension.
+    pub temp_dir: Option<&'download Path>,
+    /// Whether files with a size of 0 are sized with a `HEAD` request before anything is planned, so that
+    /// [max_file_bytes](DownloadConfig::max_file_bytes), `max_total_bytes`, the ordering and the total in
+    /// [DownloadEvent::DownloadPlanned] are accurate. Sizes are missing from some trees, such as those
+    /// reconstructed after being truncated. Empty files are sized too, since they can't be told apart.
+    /// Files whose size still can't be found are downloaded as usual.
+    /// The default is false.
+    pub prefetch_sizes: bool,
+    /// Whether the reporter is sent a [DownloadEvent::FilteredOut] for each file the filter leaves out, for
+    /// finding out why a file wasn't downloaded. This can be a lot of events, so the default is false.
+    ///
+    /// With this set, the whole tree is fetched, rather than only the directories that can hold files passing
+    /// the filter, which takes more requests for trees too large for GitHub to return at once.
+    pub report_filtered: bool,
+    /// Whether the start of each downloaded file is searched for `SPDX-License-Identifier` tags, which are
+    /// collected into [spdx_licenses](DownloadReport::spdx_licenses), for checking the licenses of the files
+    /// that were vendored when they differ from the license of the repository as a whole.
+    /// The default is false.
+    pub collect_spdx: bool,
+    /// If provided, a checksum manifest of every file written is saved to the output directory, such as
+    /// `SHA256SUMS`, so the files can be checked with standard tools like `sha256sum -c` without knowing how git
+    /// hashes blobs. See [write_checksums](Downloader::write_checksums). The default is `None`.
+    pub checksum_manifest: Option<ChecksumAlgorithm>,
+    /// The order files are downloaded in, which is also the order they're returned in.
+    /// The default is [DownloadOrdering::Tree].
+    pub ordering: DownloadOrdering,
+    /// Options for the HTTP requests made while downloading, such as the `User-Agent` to send.
+    pub http_options: HttpOptions<'download>,
+    /// If provided, the handle can pause, resume or abort the download while it's running.
+    pub handle: Option<DownloadHandle>,
+    /// The URL of the GitHub REST API to make requests to, such as `https://github.example.com/api/v3` for
+    /// GitHub Enterprise Server.
+    /// The default is [DEFAULT_API_URL](crate::DEFAULT_API_URL).
+    pub api_url: Cow<'download, str>,
+    /// The client to make requests with.
+    /// If not set, a client is created from `api_url`, `access_token` and `http_options` for each operation and
+    /// shared between all of its requests.
+    pub client: Option<GithubClient>,
+    /// If provided and `client` isn't set, the client for `api_url` and `access_token` is taken from this pool
+    /// instead of being created for each operation. The pool's own [HttpOptions] are used instead of
+    /// `http_options`.
+    pub client_pool: Option<Arc<ClientPool>>,
+    /// If provided, trees and blobs are fetched from this [Forge] instead of from GitHub, such as a
+    /// [GitLabForge](crate::GitLabForge). Requests to GitHub's API made for other reasons, such as by
+    /// [preflight](Downloader::preflight), still use `client`.
+    pub forge: Option<&'download dyn Forge>,
+    /// How [download](Downloader::download) and [download_resolved](Downloader::download_resolved) obtain
+    /// files from GitHub, such as by falling back to downloading an archive when the API is rate limited.
+    /// This is ignored if `forge` is set.
+    /// The default is [FetchStrategy::Api].
+    pub fetch_strategy: FetchStrategy,
 }
 
 impl<'download, Reporter> DownloadConfig<'download, Reporter>
 where
     Reporter: DownloadReporter,
 {
-    /// Creates a new [DownloadConfig] with the given output path and default values.
+    /// Creates a new [DownloadConfig] with the given output path and default values, without an access token.
     ///
-    /// `access_token` will be read from the environment variable `GITHUB_ACCESS_TOKEN` if available.
+    /// To use the token in the environment variable [ACCESS_TOKEN_ENV_VAR], pass [TokenSource::Env] to
+    /// [with_token_source](DownloadConfig::with_token_source).
     pub fn new(output_path: &'download Path) -> DownloadConfig<'download, Reporter> {
-        let access_token = env::var("GITHUB_ACCESS_TOKEN")
-            .ok()
-            .and_then(|s| Some(Cow::from(s)));
-
         DownloadConfig {
             output_path,
             reporter: None,
+            interceptor: None,
+            inspector: None,
+            transform: None,
+            hooks: None,
+            permissions_hook: None,
             max_simultaneous_downloads: DEFAULT_MAX_DOWNLOADS,
-            access_token: access_token,
+            host_limits: Vec::new(),
+            adaptive_concurrency: false,
+            access_token: None,
+            require_auth: false,
+            write_workers: DEFAULT_WRITE_WORKERS,
+            max_rate_limit_retries: DEFAULT_MAX_RATE_LIMIT_RETRIES,
+            rename_rules: Vec::new(),
+            layout: OutputLayout::Paths,
+            collision_policy: CollisionPolicy::Error,
+            unrepresentable_paths: UnrepresentablePathPolicy::Skip,
+            max_file_bytes: None,
+            max_total_bytes: None,
+            max_files: None,
+            max_depth: None,
+            check_disk_space: true,
+            temp_dir: None,
+            prefetch_sizes: false,
+            report_filtered: false,
+            collect_spdx: false,
+            checksum_manifest: None,
+            ordering: DownloadOrdering::Tree,
+            http_options: HttpOptions::default(),
+            handle: None,
+            api_url: Cow::from(DEFAULT_API_URL),
+            client: None,
+            client_pool: None,
+            forge: None,
+            fetch_strategy: FetchStrategy::Api,
         }
     }
 
-    /// Creates a new [DownloadConfig] with the given output path, reporter, and default values.
-    ///
-    /// `access_token` will be read from the environment variable `GITHUB_ACCESS_TOKEN` if available.
+    /// Creates a new [DownloadConfig] with the given output path, reporter, and default values, without an
+    /// access token.
     pub fn new_with_reporter(
         output_path: &'download Path,
         reporter: &'download Reporter,
     ) -> DownloadConfig<'download, Reporter> {
-        let access_token = env::var("GITHUB_ACCESS_TOKEN")
-            .ok()
-            .and_then(|s| Some(Cow::from(s)));
-
         DownloadConfig {
-            output_path,
             reporter: Some(reporter),
-            max_simultaneous_downloads: DEFAULT_MAX_DOWNLOADS,
-            access_token,
+            ..DownloadConfig::new(output_path)
+        }
+    }
+
+    /// Creates a new [DownloadConfig] that makes requests without an access token.
+    ///
+    /// This is the same as [new](DownloadConfig::new), but says so where it matters, such as in a server that
+    /// must never pick up credentials from its environment.
+    pub fn unauthenticated(output_path: &'download Path) -> DownloadConfig<'download, Reporter> {
+        DownloadConfig::new(output_path)
+    }
+
+    /// Returns this config with its `access_token` taken from `source`, which is read immediately.
+    pub fn with_token_source(
+        self,
+        source: TokenSource<'download>,
+    ) -> DownloadConfig<'download, Reporter> {
+        DownloadConfig {
+            access_token: source.resolve(),
+            ..self
+        }
+    }
+
+    /// Returns the [client](DownloadConfig::client) if one was provided, takes one from the
+    /// [client_pool](DownloadConfig::client_pool), or creates a new one.
+    /// Fails with [Error::AuthenticationRequired] if [require_auth](DownloadConfig::require_auth) is set and
+    /// the client has no access token.
+    pub fn client(&self) -> Result<GithubClient, Error> {
+        let access_token = self.access_token.as_deref();
+        let client = match (&self.client, &self.client_pool) {
+            (Some(client), _) => client.clone(),
+            (None, Some(pool)) => pool.get(&self.api_url, access_token)?,
+            (None, None) => GithubClient::with_options(access_token, &self.http_options)?
+                .with_api_url(&self.api_url),
+        };
+
+        match self.require_auth && !client.is_authenticated() {
+            true => Err(Error::AuthenticationRequired),
+            false => Ok(client),
+        }
+    }
+
+    /// Returns the path a file at `path` in the repository will be written to, after applying
+    /// [rename_rules](DownloadConfig::rename_rules).
+    pub fn output_path_for(&self, path: &str) -> PathBuf {
+        let renamed = self
+            .rename_rules
+            .iter()
+            .find_map(|(glob, replacement)| rename(glob, replacement, path));
+
+        match renamed {
+            Some(renamed) => self.output_path.join(renamed),
+            None => self.output_path.join(path),
+        }
+    }
+
+    /// Returns the path `entry` will be written to, according to the [layout](DownloadConfig::layout).
+    ///
+    /// With [OutputLayout::ContentAddressed], the file name is taken from [output_path_for](DownloadConfig::output_path_for)
+    /// so that rename rules can still change it.
+    pub fn output_path_for_entry(&self, entry: &SourceTree) -> PathBuf {
+        self.output_path_for_entry_at(entry, entry.path.as_str())
+    }
+
+    /// Returns the path `entry` will be written to like [output_path_for_entry](DownloadConfig::output_path_for_entry),
+    /// as if it were at `path` in the repository.
+    fn output_path_for_entry_at(&self, entry: &SourceTree, path: &str) -> PathBuf {
+        match self.layout {
+            OutputLayout::Paths => self.output_path_for(path),
+            OutputLayout::ContentAddressed => {
+                let renamed = self.output_path_for(path);
+                let file_name = renamed.file_name().unwrap_or_default();
+                self.output_path.join(entry.sha.to_string()).join(file_name)
+            }
+            OutputLayout::ContentAddressedFlat => self.output_path.join(entry.sha.to_string()),
+        }
+    }
+}
+
+// not derived, since that would require the reporter to be Clone
+impl<'download, Reporter> Clone for DownloadConfig<'download, Reporter>
+where
+    Reporter: DownloadReporter,
+{
+    fn clone(&self) -> Self {
+        DownloadConfig {
+            output_path: self.output_path,
+            reporter: self.reporter,
+            interceptor: self.interceptor,
+            inspector: self.inspector,
+            transform: self.transform,
+            hooks: self.hooks,
+            permissions_hook: self.permissions_hook,
+            max_simultaneous_downloads: self.max_simultaneous_downloads,
+            host_limits: self.host_limits.clone(),
+            adaptive_concurrency: self.adaptive_concurrency,
+            access_token: self.access_token.clone(),
+            require_auth: self.require_auth,
+            write_workers: self.write_workers,
+            max_rate_limit_retries: self.max_rate_limit_retries,
+            rename_rules: self.rename_rules.clone(),
+            layout: self.layout,
+            collision_policy: self.collision_policy,
+            unrepresentable_paths: self.unrepresentable_paths,
+            max_file_bytes: self.max_file_bytes,
+            max_total_bytes: self.max_total_bytes,
+            max_files: self.max_files,
+            max_depth: self.max_depth,
+            check_disk_space: self.check_disk_space,
+            temp_dir: self.temp_dir,
+            prefetch_sizes: self.prefetch_sizes,
+            report_filtered: self.report_filtered,
+            collect_spdx: self.collect_spdx,
+            checksum_manifest: self.checksum_manifest,
+            ordering: self.ordering,
+            http_options: self.http_options.clone(),
+            handle: self.handle.clone(),
+            api_url: self.api_url.clone(),
+            client: self.client.clone(),
+            client_pool: self.client_pool.clone(),
+            forge: self.forge,
+            fetch_strategy: self.fetch_strategy,
         }
     }
 }
 
+/// Applies a single rename rule to `path`, returning the new path if `glob` matches.
+fn rename(glob: &str, replacement: &str, path: &str) -> Option<String> {
+    let mut captures = glob_match_with_captures(glob, path)?
+        .into_iter()
+        .map(|range| &path[range]);
+
+    let mut renamed = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        let closing = match c {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                }
+                None
+            }
+            '?' => None,
+            '[' => Some(']'),
+            '{' => Some('}'),
+            _ => {
+                renamed.push(c);
+                continue;
+            }
+        };
+
+        if let Some(closing) = closing {
+            chars.by_ref().find(|c| *c == closing);
+        }
+
+        renamed.push_str(captures.next().unwrap_or(""));
+    }
+
+    // an empty `**` capture would otherwise leave an empty path segment behind
+    let segments: Vec<&str> = renamed.split('/').filter(|s| !s.is_empty()).collect();
+    Some(segments.join("/"))
+}
+
 /// A convenience type for a download config with no reporter.
 pub type DownloadConfigNoReporting<'download> = DownloadConfig<'download, NullDownloadReporter>;
 
+/// A file that has been downloaded and written to disk.
+///
+/// `entry` is serialized like a line of [SourceTree::to_json_lines].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DownloadedFile {
+    /// The blob entry that was downloaded. It's shared rather than copied from the tree that was downloaded
+    /// when the download owned that tree, such as with [download](Downloader::download).
+    #[serde(serialize_with = "serialize_entry")]
+    pub entry: Arc<SourceTree>,
+    /// The path the file was written to, after applying any [rename_rules](DownloadConfig::rename_rules).
+    pub output_path: PathBuf,
+    /// The number of bytes written to `output_path`.
+    pub bytes_written: u64,
+    /// The size of the responses the contents were fetched with, in bytes, as they came over the wire. For a
+    /// blob fetched through the API this includes the overhead of its JSON and base64 encoding, so comparing it
+    /// with `bytes_written` shows what fetching the raw contents would save.
+    ///
+    /// This is 0 when the contents didn't have to be fetched from GitHub for this file.
+    pub bytes_received: u64,
+    /// Whether the git hash of the downloaded contents matched the SHA of `entry`.
+    pub verified: bool,
+}
+
+/// A license file downloaded by [download_license](Downloader::download_license).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DownloadedLicense {
+    /// The license file that was downloaded.
+    pub file: DownloadedFile,
+    /// The license GitHub detected in the file, including its SPDX identifier.
+    pub license: LicenseModel,
+}
+
+/// The result of downloading a GitHub tree with [download_resolved](Downloader::download_resolved).
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadReport {
+    /// The commit and tree that were downloaded.
+    pub resolved: ResolvedRef,
+    /// Every file that was downloaded, in tree order.
+    pub files: Vec<DownloadedFile>,
+    /// The size of every response received while downloading, in bytes, as they came over the wire. Unlike
+    /// the [bytes_received](DownloadedFile::bytes_received) of the files, this includes resolving the
+    /// reference and fetching the tree, as well as requests that failed. It's measured on the
+    /// [client](DownloadConfig::client), so it also counts responses to any other requests made with the same
+    /// client while downloading.
+    pub bytes_received: u64,
+    /// Files that didn't end up at the exact path they were written to, because the filesystem treats it as the
+    /// same as another path, such as on the case-insensitive filesystems macOS and Windows use by default.
+    /// When this isn't empty, the output directory isn't a faithful copy of the repository.
+    ///
+    /// [collision_policy](DownloadConfig::collision_policy) catches paths that only differ in case before
+    /// anything is written, but filesystems also merge paths in other ways, such as by their Unicode
+    /// normalization, which can only be found once the files are written.
+    pub merged_paths: Vec<MergedPaths>,
+    /// The license expressions the files were tagged with, sorted, if
+    /// [collect_spdx](DownloadConfig::collect_spdx) is set. Files without a tag aren't included.
+    pub spdx_licenses: Vec<SpdxLicense>,
+    /// The errors returned by the [hooks](DownloadConfig::hooks), in the order they were run.
+    pub hook_failures: Vec<HookFailure>,
+}
+
+/// Files that were written to paths the filesystem treated as the same path, or a file that ended up with a
+/// different name than it was written with. Only the contents of one of them is on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MergedPaths {
+    /// The path the files are at, as it's named on disk.
+    pub on_disk: PathBuf,
+    /// The paths in the repository of the files, in the order of the report.
+    pub paths: Vec<String>,
+    /// The paths the files were written to, in the same order as `paths`.
+    pub output_paths: Vec<PathBuf>,
+}
+
+/// An error returned by one of the [DownloadHooks] of a download.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookFailure {
+    /// The path in the repository of the file [after_file](DownloadHooks::after_file) failed for, or `None` if
+    /// [after_run](DownloadHooks::after_run) failed.
+    pub path: Option<String>,
+    /// The error the hook returned.
+    pub error: Error,
+}
+
+impl DownloadReport {
+    /// The total number of bytes written across all files.
+    pub fn bytes_written(&self) -> u64 {
+        self.files.iter().map(|f| f.bytes_written).sum()
+    }
+
+    /// Returns true if the contents of every file matched its SHA.
+    pub fn all_verified(&self) -> bool {
+        self.files.iter().all(|f| f.verified)
+    }
+
+    /// Creates a report of `files`, checking where they ended up on disk and running the
+    /// [hooks](DownloadConfig::hooks) of `config` on them.
+    async fn finish<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        resolved: ResolvedRef,
+        files: Vec<DownloadedFile>,
+        bytes_received: u64,
+    ) -> DownloadReport {
+        let spdx_licenses = match config.collect_spdx {
+            true => spdx::collect_licenses(&files).await,
+            false => Vec::new(),
+        };
+        let mut report = DownloadReport {
+            merged_paths: path_check::find_merged_paths(config.output_path, &files).await,
+            resolved,
+            files,
+            bytes_received,
+            spdx_licenses,
+            hook_failures: Vec::new(),
+        };
+        let Some(hooks) = config.hooks else {
+            return report;
+        };
+
+        for file in &report.files {
+            if let Err(error) = hooks.after_file(file).await {
+                report.hook_failures.push(HookFailure {
+                    path: Some(file.entry.path.to_string()),
+                    error,
+                });
+            }
+        }
+        if let Err(error) = hooks.after_run(&report).await {
+            report.hook_failures.push(HookFailure { path: None, error });
+        }
+
+        report
+    }
+}
+
 /// Contains methods for downloading a [SourceTree] into a directory of files.
 pub struct Downloader {}
 
@@ -118,85 +842,793 @@ impl<'p> Downloader {
         config: &'p DownloadConfig<'p, Reporter>,
         path: &GithubBranchPath<'p>,
         filter: &Filter<'p>,
-    ) -> Result<Vec<SourceTree>, Error> {
-        let tree = SourceTree::get(path).await?;
-        let files = Downloader::download_tree(config, &tree, filter).await?;
-        Ok(files.into_iter().map(|s| s.clone()).collect())
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        let report = Downloader::download_resolved(config, path, filter).await?;
+        Ok(report.files)
     }
 
-    /// Downloads an entire [SourceTree] to a directory.
-    pub async fn download_tree<Reporter: DownloadReporter>(
+    /// Downloads an entire GitHub tree specified by `path`, returning a [DownloadReport] that also
+    /// includes the commit and tree its reference resolved to.
+    ///
+    /// Files are obtained with the [fetch_strategy](DownloadConfig::fetch_strategy) of `config`.
+    pub async fn download_resolved<Reporter: DownloadReporter>(
         config: &'p DownloadConfig<'p, Reporter>,
-        tree: &'p SourceTree,
+        path: &GithubBranchPath<'p>,
         filter: &Filter<'p>,
-    ) -> Result<Vec<&'p SourceTree>, Error> {
-        Ok(Downloader::download_tree_iter(config, tree.iter(), filter).await?)
+    ) -> Result<DownloadReport, Error> {
+        let client = config.client()?;
+        let strategy = match config.forge {
+            Some(_) => FetchStrategy::Api,
+            None => config.fetch_strategy,
+        };
+        Downloader::download_with_strategy(config, &client, path, filter, strategy).await
     }
 
-    /// Downloads an iterator of [SourceTree] nodes to a directory.
-    pub async fn download_tree_iter<Reporter, Iter>(
+    /// Resolves the reference of `path` and downloads its tree using `client`.
+    pub(crate) async fn download_tree_of<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<DownloadReport, Error> {
+        let received_before = client.budget().bytes_received();
+        let (resolved, tree) = Downloader::get_resolved(config, client, path, filter).await?;
+        let files = Downloader::download_files(config, client, tree, filter)
+            .await
+            .map_err(|e| {
+                e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                })
+            })?;
+        let bytes_received = client.budget().bytes_received() - received_before;
+        Ok(DownloadReport::finish(config, resolved, files, bytes_received).await)
+    }
+
+    /// Downloads several references of the repository of `repo` side by side, each to its own directory in the
+    /// output directory as arranged by `layout`, such as to build documentation for several versions. The
+    /// reference of `repo` itself is ignored.
+    ///
+    /// Blobs with the same SHA are only downloaded once. Files of later references are hard links to the file
+    /// written for the first reference that had them, or copies where hard links aren't supported, so changing
+    /// one of them afterwards may change the others. Returns a [DownloadReport] for each reference, in the same
+    /// order as `refs`.
+    pub async fn download_refs<Reporter: DownloadReporter>(
         config: &'p DownloadConfig<'p, Reporter>,
-        iter: Iter,
+        repo: &GithubBranchPath<'p>,
+        refs: &[GithubRef<'p>],
+        layout: RefLayout,
         filter: &Filter<'p>,
-    ) -> Result<Vec<&'p SourceTree>, Error>
+    ) -> Result<Vec<DownloadReport>, Error> {
+        let client = config.client()?;
+        let mut downloaded: HashMap<ObjectId, DownloadedFile> = HashMap::new();
+        let mut reports = Vec::with_capacity(refs.len());
+
+        for reference in refs {
+            let received_before = client.budget().bytes_received();
+            let path = repo.with_reference(*reference);
+            let (resolved, tree) = Downloader::get_resolved(config, &client, &path, filter).await?;
+            let dir = match layout {
+                RefLayout::Names => reference.name().replace('/', "-"),
+                RefLayout::CommitShas => {
+                    resolved.commit_sha.unwrap_or(resolved.tree_sha).to_string()
+                }
+            };
+
+            let output_path = config.output_path.join(dir);
+            let mut ref_config = config.clone();
+            ref_config.output_path = &output_path;
+
+            let files =
+                Downloader::download_shared(&ref_config, &client, tree, filter, &downloaded)
+                    .await
+                    .map_err(|e| {
+                        e.with_context(ErrorContext {
+                            repo: Some(format!("{}/{}", path.user, path.repo)),
+                            ..ErrorContext::default()
+                        })
+                    })?;
+            for file in &files {
+                downloaded
+                    .entry(file.entry.sha)
+                    .or_insert_with(|| file.clone());
+            }
+
+            let bytes_received = client.budget().bytes_received() - received_before;
+            reports
+                .push(DownloadReport::finish(&ref_config, resolved, files, bytes_received).await);
+        }
+
+        Ok(reports)
+    }
+
+    /// Downloads the blobs of `tree` that pass `filter` like [download_files](Downloader::download_files),
+    /// except that those already in `downloaded` are linked to the file written for them instead.
+    async fn download_shared<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        tree: SourceTree,
+        filter: &Filter<'_>,
+        downloaded: &HashMap<ObjectId, DownloadedFile>,
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        let files = Downloader::select_files(config, tree, filter)?;
+        let files = Downloader::prefetch_sizes(config, client, files).await;
+        let planned = Downloader::plan(config, files)?;
+
+        let (shared, new): (Vec<_>, Vec<_>) = planned
+            .into_iter()
+            .enumerate()
+            .partition(|(_, p)| !p.entry.sha.is_null() && downloaded.contains_key(&p.entry.sha));
+        let (new_indices, new): (Vec<usize>, Vec<PlannedDownload>) = new.into_iter().unzip();
+
+        let mut files: Vec<(usize, DownloadedFile)> = new_indices
+            .into_iter()
+            .zip(Downloader::download_planned(config, client, new).await?)
+            .collect();
+        for (index, planned) in shared {
+            let source = &downloaded[&planned.entry.sha];
+            Downloader::link_or_copy(&source.output_path, &planned.output_path).await?;
+            files.push((
+                index,
+                DownloadedFile {
+                    entry: planned.entry,
+                    output_path: planned.output_path,
+                    bytes_written: source.bytes_written,
+                    bytes_received: 0,
+                    verified: source.verified,
+                },
+            ));
+        }
+
+        files.sort_by_key(|(index, _)| *index);
+        let files: Vec<DownloadedFile> = files.into_iter().map(|(_, file)| file).collect();
+        if config.layout != OutputLayout::Paths {
+            Downloader::write_manifest(config, &files).await?;
+        }
+        if let Some(algorithm) = config.checksum_manifest {
+            Downloader::write_checksums(config, &files, algorithm).await?;
+        }
+
+        Ok(files)
+    }
+
+    /// Downloads the files selected by the profile called `profile` in the repository's own
+    /// [PROFILE_FILE_NAME](PROFILE_FILE_NAME), letting maintainers define what consumers should grab.
+    ///
+    /// The profile's [strip_prefix](crate::GrabProfile::strip_prefix) is applied before any of the config's rename rules.
+    /// Returns [Error::ProfileError] if the file is missing or invalid, or has no such profile.
+    #[cfg(feature = "cli")]
+    pub async fn download_profile<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        path: &GithubBranchPath<'p>,
+        profile: &str,
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        let client = config.client()?;
+        let (_, tree) = SourceTree::get_resolved_with_client(&client, path, &Filter::all()).await?;
+
+        let profile_file = tree
+            .resolve_blob(Path::new(PROFILE_FILE_NAME))
+            .ok_or_else(|| Error::ProfileError(format!("{} not found", PROFILE_FILE_NAME)))?;
+        let profiles = GrabProfiles::parse(&Blob::fetch_text(&client, profile_file).await?)?;
+        let profile = profiles.get(profile)?;
+
+        let mut config = config.clone();
+        if let Some(rule) = profile.rename_rule() {
+            config.rename_rules.insert(0, rule);
+        }
+
+        let filter = profile.filter()?;
+        Downloader::download_files(&config, &client, tree, &filter).await
+    }
+
+    /// Downloads an entire [SourceTree] to a directory.
+    ///
+    /// If `tree` is a subtree, such as one found with [resolve_tree](SourceTree::resolve_tree), its files are
+    /// written relative to it rather than to the root of the repository, as if it had been
+    /// [rebased](SourceTree::rebase). `filter` and the returned entries use those relative paths too.
+    pub async fn download_tree<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        tree: &SourceTree,
+        filter: &Filter<'_>,
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        match tree.path.as_str().is_empty() {
+            true => Downloader::download_tree_iter(config, tree, filter).await,
+            false => Downloader::download_tree_iter(config, tree.rebase(&tree.path), filter).await,
+        }
+    }
+
+    /// Downloads an iterator of [SourceTree] nodes to a directory.
+    ///
+    /// The nodes can be borrowed or owned, so a tree made for a single download can be passed as is, such as
+    /// `download_tree_iter(&config, tree.prune(predicate), &filter)`.
+    /// The downloaded files are returned in the same order as `iter`.
+    pub async fn download_tree_iter<'t, Reporter, Iter>(
+        config: &DownloadConfig<'_, Reporter>,
+        iter: Iter,
+        filter: &Filter<'_>,
+    ) -> Result<Vec<DownloadedFile>, Error>
+    where
+        Reporter: DownloadReporter,
+        Iter: IntoIterator,
+        Iter::Item: Into<Cow<'t, SourceTree>>,
+    {
+        let client = config.client()?;
+        Downloader::download_files(config, &client, iter, filter).await
+    }
+
+    /// Downloads the blobs from `iter` that pass `filter` using `client`, in the same order as `iter`.
+    async fn download_files<'t, Reporter, Iter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        iter: Iter,
+        filter: &Filter<'_>,
+    ) -> Result<Vec<DownloadedFile>, Error>
     where
         Reporter: DownloadReporter,
-        Iter: IntoIterator<Item = &'p SourceTree>,
+        Iter: IntoIterator,
+        Iter::Item: Into<Cow<'t, SourceTree>>,
     {
-        let output_path = config.output_path;
-        let access_token = &config.access_token;
+        let files = Downloader::select_files(config, iter, filter)?;
+        let files = Downloader::prefetch_sizes(config, client, files).await;
+        let planned = Downloader::plan(config, files)?;
+        let files = Downloader::download_planned(config, client, planned).await?;
+
+        if config.layout != OutputLayout::Paths {
+            Downloader::write_manifest(config, &files).await?;
+        }
+        if let Some(algorithm) = config.checksum_manifest {
+            Downloader::write_checksums(config, &files, algorithm).await?;
+        }
+
+        Ok(files)
+    }
+
+    /// Downloads `planned` using `client`, in the same order as `planned`.
+    pub(crate) async fn download_planned<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        planned: Vec<PlannedDownload>,
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        Downloader::check_unauthenticated_limit(config, client, planned.len());
+        let run = DownloadRun::new(config, client.clone());
+
+        // collecting the futures first, rather than mapping the stream, keeps the returned future `Send`
+        let downloads: Vec<_> = planned
+            .iter()
+            .map(|p| Downloader::download_node_wrapper(config, &run, p))
+            .collect();
+
+        let result = stream::iter(downloads)
+            .buffered(config.max_simultaneous_downloads)
+            .try_collect()
+            .await;
+        run.remove_empty_dirs().await;
+        result.map_err(|e| run.guide(config, planned.len(), e))
+    }
+
+    /// Removes every empty directory below `path`, including directories left empty by removing the ones inside
+    /// them, and returns how many were removed. `path` itself is kept.
+    ///
+    /// Downloads only create a directory when the first file in it is written, and remove the directories they
+    /// created that are still empty once they finish or fail, such as when they're aborted. This is for output
+    /// directories that were left with empty directories some other way, such as by a
+    /// [download_stream](Downloader::download_stream) that was dropped before it finished.
+    pub async fn cleanup_empty_dirs(path: &Path) -> Result<usize, Error> {
+        let mut dirs = Vec::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    pending.push(entry.path());
+                    dirs.push(entry.path());
+                }
+            }
+        }
+
+        // a directory's descendants are removed before it, so it's empty by then if they were
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        let mut removed = 0;
+        for dir in dirs {
+            if tokio::fs::remove_dir(&dir).await.is_ok() {
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Writes a manifest mapping the path of each file in the repository to its SHA, as a JSON object, to
+    /// [CONTENT_MANIFEST_FILE_NAME] in the output directory.
+    ///
+    /// This is done automatically when downloading with a content-addressed [OutputLayout], except by
+    /// [download_stream](Downloader::download_stream), which leaves it to the caller once the stream is finished.
+    pub async fn write_manifest<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        files: &[DownloadedFile],
+    ) -> Result<(), Error> {
+        let manifest: BTreeMap<String, ObjectId> = files
+            .iter()
+            .map(|f| (f.entry.path.to_string(), f.entry.sha))
+            .collect();
+
+        tokio::fs::create_dir_all(config.output_path).await?;
+        let json = serde_json::to_vec_pretty(&manifest)?;
+        let manifest_path = config.output_path.join(CONTENT_MANIFEST_FILE_NAME);
+        Downloader::write_file(&manifest_path, &json, config.temp_dir).await
+    }
+
+    /// Downloads the license file of the repository of `path`, as detected by GitHub, along with the license
+    /// it contains. This doesn't depend on any filter, so it can be used alongside a download that excludes it.
+    ///
+    /// The file is written to its path in the repository, after applying the [layout](DownloadConfig::layout)
+    /// and any [rename_rules](DownloadConfig::rename_rules).
+    pub async fn download_license<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<DownloadedLicense, Error> {
+        let client = config.client()?;
+        let received_before = client.budget().bytes_received();
+        let model = raw::get_license_raw(&client, path).await?;
+        let bytes = match (&model.encoding, &model.git_url) {
+            (ContentEncoding::None, Some(git_url)) => {
+                raw::get_blob_bytes_raw(&client, git_url).await?
+            }
+            _ => model.decode()?,
+        };
+
+        let mut entry = SourceTree::new(TreeEntryType::Blob);
+        entry.path = normalize_path(&model.path);
+        entry.size = model.size;
+        entry.url = model.git_url.unwrap_or(model.url);
+        entry.sha = model.sha.parse()?;
+        let verified = git_hash::blob_id(&bytes, entry.sha.format()) == entry.sha;
+        let bytes = match config.transform {
+            Some(transform) => transform.transform(&entry, bytes),
+            None => bytes,
+        };
+        Downloader::inspect(config.inspector, &entry, &bytes)?;
+
+        let output_path = config.output_path_for_entry(&entry);
+        if let Some(parent) = output_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Downloader::write_file(&output_path, &bytes, config.temp_dir).await?;
+
+        Ok(DownloadedLicense {
+            file: DownloadedFile {
+                verified,
+                entry: Arc::new(entry),
+                output_path,
+                bytes_written: bytes.len() as u64,
+                bytes_received: client.budget().bytes_received() - received_before,
+            },
+            license: model.license,
+        })
+    }
+
+    /// Estimates how many API requests downloading `tree` with `filter` will make, not counting
+    /// the requests already made to fetch the tree itself.
+    ///
+    /// Each blob is downloaded with its own request, so grabbing a large repository this way can use
+    /// up a significant part of the hourly rate limit (see [RequestBudget](crate::RequestBudget)).
+    pub fn estimate_requests(tree: &SourceTree, filter: &Filter<'_>) -> usize {
+        Downloader::filter_files(tree.iter(), filter).len()
+    }
+
+    /// Downloads an entire GitHub tree specified by `path`, yielding each file as soon as it has been written.
+    ///
+    /// Unlike [download](Downloader::download), a failed file doesn't stop the remaining files from downloading.
+    pub fn download_stream<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        path: &'p GithubBranchPath<'p>,
+        filter: &'p Filter<'p>,
+    ) -> impl Stream<Item = Result<DownloadedFile, Error>> + 'p {
+        let planned = async move {
+            let client = config.client()?;
+            let (_, tree) = Downloader::get_resolved(config, &client, path, filter).await?;
+            let files = Downloader::select_files(config, tree, filter)?;
+            let files = Downloader::prefetch_sizes(config, &client, files).await;
+            let planned = Downloader::plan(config, files)?;
+            Downloader::check_unauthenticated_limit(config, &client, planned.len());
+            Ok((client, planned))
+        };
+
+        stream::once(planned)
+            .map(move |planned| match planned {
+                Ok((client, planned)) => {
+                    Downloader::download_files_stream(config, client, planned).left_stream()
+                }
+                Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+            })
+            .flatten()
+    }
+
+    /// Downloads each of the given blobs, yielding them as they complete.
+    fn download_files_stream<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        client: GithubClient,
+        planned: Vec<PlannedDownload>,
+    ) -> impl Stream<Item = Result<DownloadedFile, Error>> + 'p {
+        let run = Arc::new(DownloadRun::new(config, client));
+        let finished_run = run.clone();
+        let cleanup = stream::once(async move { finished_run.remove_empty_dirs().await })
+            .filter_map(|_| future::ready(None));
 
-        let files: Vec<&SourceTree> = iter
+        stream::iter(planned)
+            .map(move |planned| {
+                let run = run.clone();
+                async move { Downloader::download_node_wrapper(config, &run, &planned).await }
+            })
+            .buffer_unordered(config.max_simultaneous_downloads)
+            .chain(cleanup)
+    }
+
+    /// Fills in the size of each of `files` whose size is 0, if [prefetch_sizes](DownloadConfig::prefetch_sizes)
+    /// is set. Sizes that can't be found are left as 0.
+    pub(crate) async fn prefetch_sizes<'t, Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        files: Vec<Cow<'t, SourceTree>>,
+    ) -> Vec<Cow<'t, SourceTree>> {
+        if !config.prefetch_sizes {
+            return files;
+        }
+
+        // collecting the futures first, rather than mapping the stream, keeps the returned future `Send`
+        let sizes: Vec<_> = files
             .into_iter()
-            .filter(|n| {
-                n.entry_type == TreeEntryType::Blob && filter.check(n.path.to_str().unwrap_or(""))
+            .map(|mut entry| async move {
+                if entry.size != 0 {
+                    return entry;
+                }
+
+                if let Ok(Some(size)) = raw::get_blob_size_raw(client, &entry.url).await {
+                    entry.to_mut().size = u32::try_from(size).unwrap_or(u32::MAX);
+                }
+                entry
             })
             .collect();
 
-        let mut active: Vec<BoxFuture<'p, Result<(), Error>>> = Vec::new();
+        stream::iter(sizes)
+            .buffered(config.max_simultaneous_downloads.max(1))
+            .collect()
+            .await
+    }
+
+    /// Decides where each of `files` will be written, applying the [UnrepresentablePathPolicy], consulting the
+    /// [DownloadInterceptor] and applying the [CollisionPolicy] to any that would be written to the same path,
+    /// ignoring case if the output directory is on a case-insensitive filesystem.
+    /// Fails if the planned files are over [max_total_bytes](DownloadConfig::max_total_bytes).
+    pub(crate) fn plan<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        files: Vec<Cow<'_, SourceTree>>,
+    ) -> Result<Vec<PlannedDownload>, Error> {
+        let mut planned: Vec<PlannedDownload> = Vec::with_capacity(files.len());
+        let mut planned_for_path: HashMap<String, usize> = HashMap::new();
+        let ignore_case = match path_check::closest_existing_dir(config.output_path) {
+            Some(dir) => path_check::is_case_insensitive(dir),
+            None => path_check::CASE_INSENSITIVE_BY_DEFAULT,
+        };
+        let key = |path: &Path| match ignore_case {
+            true => path.to_string_lossy().to_lowercase(),
+            false => path.to_string_lossy().into_owned(),
+        };
+
+        for file in files {
+            let entry: &SourceTree = &file;
+            let size = entry.size as u64;
+            if let Some(limit) = config.max_file_bytes.filter(|&limit| size > limit) {
+                if let Some(reporter) = config.reporter {
+                    let path = entry.path.as_str();
+                    reporter.on_event(DownloadEvent::DownloadFailed {
+                        path,
+                        error: Error::QuotaExceeded {
+                            path: Some(path.to_string()),
+                            size,
+                            limit,
+                        },
+                    });
+                }
+                continue;
+            }
+
+            let mut output_path = match path_check::unrepresentable_reason(entry.path.as_str()) {
+                None => config.output_path_for_entry(entry),
+                Some(reason) => {
+                    let path = entry.path.as_str();
+                    let error = Error::UnrepresentablePath {
+                        path: path.to_string(),
+                        reason,
+                    };
+                    match config.unrepresentable_paths {
+                        UnrepresentablePathPolicy::Error => return Err(error),
+                        UnrepresentablePathPolicy::Skip => {
+                            if let Some(reporter) = config.reporter {
+                                reporter.on_event(DownloadEvent::DownloadFailed { path, error });
+                            }
+                            continue;
+                        }
+                        UnrepresentablePathPolicy::PercentEncode => config
+                            .output_path_for_entry_at(entry, &path_check::percent_encode(path)),
+                    }
+                }
+            };
+
+            if let Some(interceptor) = config.interceptor {
+                match interceptor.before_download(entry, &output_path) {
+                    InterceptDecision::Download => {}
+                    InterceptDecision::Skip => continue,
+                    InterceptDecision::Redirect(path) => output_path = path,
+                }
+            }
+
+            if let Some(&index) = planned_for_path.get(&key(&output_path)) {
+                let existing = &planned[index];
+
+                // identical files in the same place, as happens with content-addressed layouts
+                let is_duplicate =
+                    existing.output_path == output_path && existing.entry.sha == entry.sha;
+                if !is_duplicate {
+                    let path = entry.path.as_str();
+                    let existing = existing.entry.path.as_str();
+                    if let Some(reporter) = config.reporter {
+                        reporter.on_event(DownloadEvent::PathCollision { path, existing });
+                    }
+
+                    match config.collision_policy {
+                        CollisionPolicy::Error => {
+                            return Err(Error::PathCollision {
+                                path: path.to_string(),
+                                existing: existing.to_string(),
+                            })
+                        }
+                        CollisionPolicy::FirstWins => continue,
+                        CollisionPolicy::Rename => {
+                            output_path = (1..)
+                                .map(|n| numbered(&output_path, n))
+                                .find(|p| !planned_for_path.contains_key(&key(p)))
+                                .unwrap();
+                        }
+                    }
+                }
+            }
+
+            planned_for_path.insert(key(&output_path), planned.len());
+            planned.push(PlannedDownload {
+                entry: Arc::new(file.into_owned()),
+                output_path,
+            });
+        }
+
+        let total_bytes: u64 = planned.iter().map(|p| p.entry.size as u64).sum();
+        if let Some(limit) = config.max_total_bytes {
+            if total_bytes > limit {
+                return Err(Error::QuotaExceeded {
+                    path: None,
+                    size: total_bytes,
+                    limit,
+                });
+            }
+        }
+        Downloader::check_disk_space(config, total_bytes)?;
 
-        for f in &files {
-            if active.len() > config.max_simultaneous_downloads {
-                // make sure some active downloads complete before starting new ones
-                let (result, index, _) = future::select_all(&mut active).await;
-                result?;
-                let _future = active.remove(index);
+        // sorted after collisions are resolved, so which file comes first doesn't depend on the ordering
+        match config.ordering {
+            DownloadOrdering::Tree => {}
+            DownloadOrdering::SmallestFirst => planned.sort_by_key(|p| p.entry.size),
+            DownloadOrdering::LargestFirst => {
+                planned.sort_by_key(|p| std::cmp::Reverse(p.entry.size))
             }
+            DownloadOrdering::Path => planned.sort_by(|a, b| a.entry.path.cmp(&b.entry.path)),
+        }
+
+        if let Some(reporter) = config.reporter {
+            reporter.on_event(DownloadEvent::DownloadPlanned {
+                files: planned.len(),
+                total_bytes,
+            });
+        }
+
+        Ok(planned)
+    }
+
+    /// Fails with [Error::InsufficientSpace] if [check_disk_space](DownloadConfig::check_disk_space) is set and
+    /// the disk holding the output directory has less than `required` bytes available.
+    fn check_disk_space<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        required: u64,
+    ) -> Result<(), Error> {
+        if !config.check_disk_space || required == 0 {
+            return Ok(());
+        }
+
+        // the output directory might not exist yet, so look at the closest directory that does
+        let dir = path_check::closest_existing_dir(config.output_path);
+        let available = match dir.map(fs4::available_space) {
+            Some(Ok(available)) => available,
+            _ => return Ok(()),
+        };
+
+        match required > available {
+            true => Err(Error::InsufficientSpace {
+                required,
+                available,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// Warns the reporter if `client` has no access token and downloading `files` files will go over the
+    /// unauthenticated rate limit.
+    fn check_unauthenticated_limit<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        files: usize,
+    ) {
+        if client.is_authenticated() || files as u64 <= UNAUTHENTICATED_RATE_LIMIT {
+            return;
+        }
+
+        if let Some(reporter) = config.reporter {
+            reporter.on_event(DownloadEvent::UnauthenticatedRateLimit {
+                requests: files,
+                limit: UNAUTHENTICATED_RATE_LIMIT,
+            });
+        }
+    }
+
+    /// Resolves the reference of `path` and obtains its tree from the [forge](DownloadConfig::forge), or from
+    /// GitHub with `client` if there isn't one.
+    pub(crate) async fn get_resolved<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<(ResolvedRef, SourceTree), Error> {
+        // every file is needed to report the ones that are left out, so they're filtered by select_files alone
+        let all = Filter::all();
+        let filter = match config.report_filtered && config.reporter.is_some() {
+            true => &all,
+            false => filter,
+        };
 
-            let next = Downloader::download_node_wrapper(
-                &config.reporter,
-                &access_token,
-                output_path.to_path_buf(),
-                f,
-            );
-            active.push(next.boxed());
+        match config.forge {
+            Some(forge) => forge.get_resolved(path, filter).await,
+            None => SourceTree::get_resolved_with_client(client, path, filter).await,
         }
+    }
+
+    /// Returns the blobs from `iter` that pass `filter`, failing as soon as one of them breaks
+    /// [max_files](DownloadConfig::max_files) or [max_depth](DownloadConfig::max_depth).
+    pub(crate) fn select_files<'t, Reporter, Iter>(
+        config: &DownloadConfig<'_, Reporter>,
+        iter: Iter,
+        filter: &Filter<'_>,
+    ) -> Result<Vec<Cow<'t, SourceTree>>, Error>
+    where
+        Reporter: DownloadReporter,
+        Iter: IntoIterator,
+        Iter::Item: Into<Cow<'t, SourceTree>>,
+    {
+        let filtered_reporter = config.reporter.filter(|_| config.report_filtered);
+        let mut files = Vec::new();
+        for node in iter.into_iter().map(Into::into) {
+            if node.entry_type != TreeEntryType::Blob {
+                continue;
+            }
+            if !filter.check(node.path.as_str()) {
+                if let Some(reporter) = filtered_reporter {
+                    reporter.on_event(DownloadEvent::FilteredOut {
+                        path: node.path.as_str(),
+                        matched_exclude: filter.explain(node.path.as_str()).excluded_by,
+                    });
+                }
+                continue;
+            }
 
-        for r in future::join_all(active).await {
-            if let Err(e) = r {
-                return Err(e);
+            if let Some(limit) = config.max_depth {
+                let depth = node.path.components().count().saturating_sub(1);
+                if depth > limit {
+                    return Err(Error::TooDeep {
+                        path: node.path.to_string(),
+                        depth,
+                        limit,
+                    });
+                }
+            }
+            if let Some(limit) = config.max_files {
+                if files.len() == limit {
+                    return Err(Error::TooManyFiles { limit });
+                }
             }
+
+            files.push(node);
         }
 
         Ok(files)
     }
 
+    /// Returns the blobs from `iter` that pass `filter`.
+    fn filter_files<'t, Iter>(iter: Iter, filter: &Filter<'_>) -> Vec<Cow<'t, SourceTree>>
+    where
+        Iter: IntoIterator,
+        Iter::Item: Into<Cow<'t, SourceTree>>,
+    {
+        iter.into_iter()
+            .map(Into::into)
+            .filter(|n| n.entry_type == TreeEntryType::Blob && filter.check(n.path.as_str()))
+            .collect()
+    }
+
     async fn download_node_wrapper<Reporter: DownloadReporter>(
-        reporter: &'p Option<&'p Reporter>,
-        access_token: &'p Option<Cow<'p, str>>,
-        output_path: PathBuf,
-        tree: &'p SourceTree,
-    ) -> Result<(), Error> {
-        let path = tree.path.to_str().unwrap();
-        if let Some(reporter) = *reporter {
+        config: &DownloadConfig<'_, Reporter>,
+        run: &DownloadRun,
+        planned: &PlannedDownload,
+    ) -> Result<DownloadedFile, Error> {
+        let path = planned.entry.path.as_str();
+        let reporter = config.reporter;
+        if let Some(reporter) = reporter {
             reporter.on_event(DownloadEvent::DownloadStarted { path })
         }
 
-        let result = Downloader::download_node(&access_token, &output_path, &tree).await;
+        let mut retries = 0;
+        let result = loop {
+            let result = match &config.handle {
+                Some(handle) => {
+                    tokio::select! {
+                        result = Downloader::download_node_gated(config, run, planned, Some(handle)) => result,
+                        _ = handle.aborted() => {
+                            // a write that's already started keeps going once dropped, but removes its own
+                            // partial file when it finishes and sees the download was aborted
+                            let partial = partial_path(&planned.output_path, run.temp_dir.as_deref());
+                            let _ = tokio::fs::remove_file(partial).await;
+                            Err(Error::Aborted)
+                        }
+                    }
+                }
+                None => Downloader::download_node_gated(config, run, planned, None).await,
+            };
+
+            let rate_limited = match &result {
+                Err(e) => match e.root() {
+                    Error::RateLimited { retry_after, .. } => Some(*retry_after),
+                    _ => None,
+                },
+                Ok(_) => None,
+            };
 
-        if let Some(reporter) = *reporter {
+            match rate_limited {
+                Some(retry_after) if retries < config.max_rate_limit_retries => {
+                    retries += 1;
+                    run.gate.pause(retry_after);
+                    telemetry::record_retry();
+                    if let Some(reporter) = reporter {
+                        reporter.on_event(DownloadEvent::RateLimited { path, retry_after })
+                    }
+                }
+                _ => {
+                    break result.map_err(|e| {
+                        e.with_context(ErrorContext {
+                            path: Some(path.to_string()),
+                            url: Some(planned.entry.url.clone()),
+                            ..ErrorContext::default()
+                        })
+                    })
+                }
+            }
+        };
+
+        if result.is_ok() {
+            run.downloaded.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(reporter) = reporter {
             match result {
-                Ok(_) => reporter.on_event(DownloadEvent::DownloadCompleted { path }),
+                Ok(ref file) => reporter.on_event(DownloadEvent::DownloadCompleted {
+                    path,
+                    bytes_received: file.bytes_received,
+                    bytes_written: file.bytes_written,
+                }),
                 Err(ref e) => reporter.on_event(DownloadEvent::DownloadFailed {
                     path,
                     error: e.clone(),
@@ -207,65 +1639,365 @@ impl<'p> Downloader {
         result
     }
 
-    async fn download_node(
-        access_token: &'p Option<Cow<'p, str>>,
-        output_path: &'p Path,
-        tree: &'p SourceTree,
-    ) -> Result<(), Error> {
-        let client = HttpRequest::client(access_token)?;
-        let request = client.get(&tree.url).build()?;
-        let str = request
-            .headers()
-            .iter()
-            .map(|(name, val)| format!("{} = {:?}", name, val))
-            .join(", ");
-        println!("{}", str);
-        let response = client.execute(request).await?;
-        let body = response.text().await?;
-
-        #[derive(Deserialize)]
-        #[serde(untagged)]
-        enum BlobOrError {
-            Blob { content: String },
-            Error { message: String },
-        }
-
-        let model: BlobOrError = serde_json::from_str(&body)?;
-        match model {
-            BlobOrError::Error { message } => Err(Error::GithubError(message)),
-            BlobOrError::Blob { content } => {
-                let base64_str: String = content.chars().filter(|c| *c != '\n').collect();
-                let bytes = BASE64_STANDARD.decode(base64_str.as_bytes())?;
-
-                let output_path = output_path.to_path_buf().join(&tree.path);
-
-                Downloader::write_file(&output_path, &bytes).await?;
-                Ok(())
+    /// Downloads a single file once it's allowed to by the rate limit gate and the [DownloadHandle].
+    async fn download_node_gated<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        run: &DownloadRun,
+        planned: &PlannedDownload,
+        handle: Option<&DownloadHandle>,
+    ) -> Result<DownloadedFile, Error> {
+        if let Some(handle) = handle {
+            handle.wait_until_running().await?;
+        }
+
+        let host_permits = reqwest::Url::parse(&planned.entry.url)
+            .ok()
+            .and_then(|url| run.host_permits.get(&url.host_str()?.to_lowercase()));
+        let _host_permit = match host_permits {
+            Some(permits) => Some(permits.acquire().await),
+            None => None,
+        };
+
+        let _adaptive_permit = match &run.adaptive {
+            Some(limiter) => Some(limiter.acquire().await),
+            None => None,
+        };
+
+        let _guard = run.gate.acquire().await;
+        let started = Instant::now();
+        let result = Downloader::download_node(run, planned, config).await;
+        if let Some(limiter) = &run.adaptive {
+            limiter.record(Outcome::of(&result, started.elapsed()));
+        }
+
+        result
+    }
+
+    async fn download_node<Reporter: DownloadReporter>(
+        run: &DownloadRun,
+        planned: &PlannedDownload,
+        config: &DownloadConfig<'_, Reporter>,
+    ) -> Result<DownloadedFile, Error> {
+        let tree = &planned.entry;
+        let started = Instant::now();
+        let file_bytes = AtomicU64::new(0);
+        let counter = |len: u64| run.count_bytes(tree, &file_bytes, len);
+        let (bytes, bytes_received) = match config.forge {
+            Some(forge) => {
+                let bytes = forge.get_blob(tree).await?;
+                counter(bytes.len() as u64)?;
+                (bytes, 0)
+            }
+            None => {
+                let counter: raw::ChunkCounter = &counter;
+                let counter = run.has_byte_quota().then_some(counter);
+                raw::get_blob_contents_counted(&run.client, &tree.url, tree.size as u64, counter)
+                    .await?
             }
+        };
+        let verified = git_hash::blob_id(&bytes, tree.sha.format()) == tree.sha;
+        let bytes = match config.transform {
+            Some(transform) => transform.transform(tree, bytes),
+            None => bytes,
+        };
+        Downloader::inspect(config.inspector, tree, &bytes)?;
+
+        let output_path = planned.output_path.clone();
+        let permissions = config.permissions_hook.and_then(|hook| hook(tree));
+
+        let bytes_written = bytes.len() as u64;
+        let permit = run.write_permits.acquire().await;
+        run.create_parent(&output_path).await?;
+        Downloader::write_file_with(
+            &output_path,
+            bytes,
+            run.temp_dir.as_deref(),
+            permissions.as_ref(),
+            config.handle.as_ref(),
+        )
+        .await?;
+        drop(permit);
+
+        telemetry::record_download(bytes_written, started.elapsed());
+
+        Ok(DownloadedFile {
+            entry: planned.entry.clone(),
+            output_path,
+            bytes_written,
+            bytes_received,
+            verified,
+        })
+    }
+
+    /// Checks the contents of `entry` with the [ContentInspector], if there is one.
+    fn inspect(
+        inspector: Option<&dyn ContentInspector>,
+        entry: &SourceTree,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        match inspector.map(|i| i.inspect(entry, bytes)) {
+            Some(InspectVerdict::Block(reason)) => Err(Error::ContentBlocked {
+                path: entry.path.to_string(),
+                reason,
+            }),
+            Some(InspectVerdict::Allow) | None => Ok(()),
         }
     }
 
-    async fn write_file(path: &Path, bytes: &[u8]) -> Result<(), Error> {
-        Downloader::ensure_dir_exists(path).await?;
+    /// Writes `bytes` to a partial file first, in `temp_dir` if given, so that `path` never holds an incomplete file.
+    pub(crate) async fn write_file(
+        path: &Path,
+        bytes: &[u8],
+        temp_dir: Option<&Path>,
+    ) -> Result<(), Error> {
+        Downloader::write_file_with(path, bytes.to_vec(), temp_dir, None, None).await
+    }
 
-        tokio::fs::write(path, &bytes).await?;
+    /// Writes `bytes` like [write_file](Downloader::write_file), applying `permissions` to the partial file before
+    /// it's moved into place.
+    ///
+    /// The file is written and moved into place on a blocking thread, which carries on if the returned future is
+    /// dropped, such as when a download is aborted. If `handle` has been aborted by the time the partial file is
+    /// written, it's removed there rather than moved into place, so an aborted download doesn't leave it behind.
+    async fn write_file_with(
+        path: &Path,
+        bytes: Vec<u8>,
+        temp_dir: Option<&Path>,
+        permissions: Option<&FilePermissions>,
+        handle: Option<&DownloadHandle>,
+    ) -> Result<(), Error> {
+        if let Some(temp_dir) = temp_dir {
+            tokio::fs::create_dir_all(temp_dir).await?;
+        }
+
+        let partial = partial_path(path, temp_dir);
+        let (output_path, permissions, handle) =
+            (path.to_path_buf(), permissions.copied(), handle.cloned());
+        tokio::task::spawn_blocking(move || {
+            std::fs::write(&partial, bytes)?;
+            let applied = match permissions {
+                Some(permissions) => permissions.apply(&partial),
+                None => Ok(()),
+            };
+            let result = match handle.is_some_and(|handle| handle.is_aborted()) {
+                true => Err(Error::Aborted),
+                false => applied.and_then(|_| Ok(std::fs::rename(&partial, &output_path)?)),
+            };
+
+            if result.is_err() {
+                let _ = std::fs::remove_file(&partial);
+            }
+            result
+        })
+        .await
+        .map_err(|e| Error::Other(format!("writing {} panicked: {}", path.display(), e)))?
+    }
+
+    /// Hard links `path` to the file at `source`, or copies it where it can't be linked, such as across filesystems.
+    async fn link_or_copy(source: &Path, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // linking fails if a file from an earlier download is already there
+        let _ = tokio::fs::remove_file(path).await;
+        if tokio::fs::hard_link(source, path).await.is_err() {
+            tokio::fs::copy(source, path).await?;
+        }
 
         Ok(())
     }
+}
 
-    async fn ensure_dir_exists(path: &Path) -> Result<(), Error> {
-        let dirname = path.parent();
-        if dirname.is_none() {
-            return Ok(());
+/// A file that is going to be downloaded, and where it will be written.
+pub(crate) struct PlannedDownload {
+    pub(crate) entry: Arc<SourceTree>,
+    pub(crate) output_path: PathBuf,
+}
+
+/// The path a file is written to before it's complete: next to it with a `.part` extension, or in `temp_dir` if
+/// given, named after its file name and a hash of its full path so files with the same name don't clash.
+pub(crate) fn partial_path(path: &Path, temp_dir: Option<&Path>) -> PathBuf {
+    let temp_dir = match temp_dir {
+        Some(temp_dir) => temp_dir,
+        None => {
+            let mut partial = path.as_os_str().to_owned();
+            partial.push(".part");
+            return PathBuf::from(partial);
+        }
+    };
+
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    temp_dir.join(format!("{}.{:016x}.part", file_name, hasher.finish()))
+}
+
+/// Adds `~n` to the end of the file name of `path`, before its extension.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}~{}.{}", stem, n, extension.to_string_lossy()),
+        None => format!("{}~{}", stem, n),
+    };
+
+    path.with_file_name(file_name)
+}
+
+/// The state shared between every download in a single operation.
+struct DownloadRun {
+    client: GithubClient,
+    gate: RateLimitGate,
+    write_permits: Semaphore,
+    host_permits: HashMap<String, Semaphore>,
+    adaptive: Option<AdaptiveLimiter>,
+    temp_dir: Option<PathBuf>,
+    dirs: std::sync::Mutex<RunDirs>,
+    /// How many files have been downloaded so far.
+    downloaded: AtomicUsize,
+    max_file_bytes: Option<u64>,
+    max_total_bytes: Option<u64>,
+    /// How many bytes of files have been received so far, counted as they arrive.
+    bytes_received: AtomicU64,
+}
+
+/// The directories a [DownloadRun] has written files into.
+#[derive(Default)]
+struct RunDirs {
+    /// Directories that are known to exist, whether or not they were created by the run.
+    existing: HashSet<PathBuf>,
+    /// Directories created by the run, including the ancestors created along with them.
+    created: Vec<PathBuf>,
+}
+
+impl DownloadRun {
+    fn new<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: GithubClient,
+    ) -> DownloadRun {
+        DownloadRun {
+            client,
+            gate: RateLimitGate::new(),
+            write_permits: Semaphore::new(config.write_workers.max(1)),
+            host_permits: config
+                .host_limits
+                .iter()
+                .map(|(host, limit)| (host.to_lowercase(), Semaphore::new((*limit).max(1))))
+                .collect(),
+            adaptive: config
+                .adaptive_concurrency
+                .then(|| AdaptiveLimiter::new(config.max_simultaneous_downloads)),
+            temp_dir: config.temp_dir.map(Path::to_path_buf),
+            dirs: std::sync::Mutex::default(),
+            downloaded: AtomicUsize::new(0),
+            max_file_bytes: config.max_file_bytes,
+            max_total_bytes: config.max_total_bytes,
+            bytes_received: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns whether the run has a [max_file_bytes](DownloadConfig::max_file_bytes) or
+    /// [max_total_bytes](DownloadConfig::max_total_bytes) to count received bytes against.
+    fn has_byte_quota(&self) -> bool {
+        self.max_file_bytes.is_some() || self.max_total_bytes.is_some()
+    }
+
+    /// Counts `len` more bytes received for `entry`, of which `file_bytes` have been received before, failing
+    /// with [Error::QuotaExceeded] once the file or the run as a whole is over its quota. This catches files
+    /// whose sizes weren't known when they were planned, such as those from forges that don't report sizes.
+    fn count_bytes(
+        &self,
+        entry: &SourceTree,
+        file_bytes: &AtomicU64,
+        len: u64,
+    ) -> Result<(), Error> {
+        let file = file_bytes.fetch_add(len, Ordering::Relaxed) + len;
+        if let Some(limit) = self.max_file_bytes.filter(|&limit| file > limit) {
+            return Err(Error::QuotaExceeded {
+                path: Some(entry.path.to_string()),
+                size: file,
+                limit,
+            });
+        }
+
+        let total = self.bytes_received.fetch_add(len, Ordering::Relaxed) + len;
+        if let Some(limit) = self.max_total_bytes.filter(|&limit| total > limit) {
+            return Err(Error::QuotaExceeded {
+                path: None,
+                size: total,
+                limit,
+            });
         }
 
-        let dirname = dirname.unwrap();
-        if dirname.exists() {
+        Ok(())
+    }
+
+    /// Attaches a [RateLimitGuidance] to `error` if it's [Error::RateLimited], for a run of `files` files.
+    fn guide<Reporter: DownloadReporter>(
+        &self,
+        config: &DownloadConfig<'_, Reporter>,
+        files: usize,
+        error: Error,
+    ) -> Error {
+        let Error::RateLimited { retry_after, .. } = error.root() else {
+            return error;
+        };
+
+        let files_downloaded = self.downloaded.load(Ordering::Relaxed);
+        let reset_at = (SystemTime::now() + *retry_after)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let guidance = RateLimitGuidance {
+            requests_made: self.client.budget().requests_made(),
+            files_downloaded,
+            files_remaining: files.saturating_sub(files_downloaded),
+            reset_at,
+            archive_would_succeed: cfg!(feature = "archive")
+                && config.forge.is_none()
+                && self.client.api_url() == DEFAULT_API_URL,
+        };
+        error.with_rate_limit_guidance(guidance)
+    }
+
+    /// Creates the directory `path` is written into if it doesn't exist yet, remembering which directories
+    /// were created so they can be removed if they're left empty.
+    async fn create_parent(&self, path: &Path) -> Result<(), Error> {
+        let Some(dir) = path.parent() else {
+            return Ok(());
+        };
+        if self.dirs.lock().unwrap().existing.contains(dir) {
             return Ok(());
         }
 
-        tokio::fs::create_dir_all(dirname).await?;
+        let mut missing = Vec::new();
+        let mut ancestor = Some(dir);
+        while let Some(current) = ancestor.filter(|a| !a.as_os_str().is_empty()) {
+            if tokio::fs::try_exists(current).await.unwrap_or(false) {
+                break;
+            }
+            missing.push(current.to_path_buf());
+            ancestor = current.parent();
+        }
+        tokio::fs::create_dir_all(dir).await?;
 
+        let mut dirs = self.dirs.lock().unwrap();
+        dirs.existing.insert(dir.to_path_buf());
+        dirs.created.extend(missing);
         Ok(())
     }
+
+    /// Removes the directories created by [create_parent](DownloadRun::create_parent) that are empty, such as
+    /// those whose only file failed or was aborted. Directories that can't be removed are left alone.
+    async fn remove_empty_dirs(&self) {
+        let mut created = std::mem::take(&mut self.dirs.lock().unwrap().created);
+        created.sort();
+        created.dedup();
+
+        // a directory's descendants are removed before it, so it's empty by then if they were
+        created.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        for dir in created {
+            let _ = tokio::fs::remove_dir(&dir).await;
+        }
+    }
 }
@@ -0,0 +1,165 @@
+#[cfg(feature = "graphql")]
+use std::collections::HashMap;
+
+#[cfg(feature = "graphql")]
+use camino::Utf8PathBuf;
+
+#[cfg(feature = "graphql")]
+use serde::Deserialize;
+#[cfg(feature = "graphql")]
+use serde_json::json;
+
+use crate::ObjectId;
+#[cfg(feature = "graphql")]
+use crate::{raw, Error, GithubBranchPath, GithubClient, SourceTree, TreeEntryType};
+
+/// How many paths are looked up in a single GraphQL query.
+#[cfg(feature = "graphql")]
+const PATHS_PER_QUERY: usize = 50;
+
+/// The last commit to modify a blob, as filled in by `SourceTree::enrich_with_commits` when the `graphql`
+/// feature is enabled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntryCommitInfo {
+    /// The hash of the commit.
+    pub sha: ObjectId,
+    /// The name of the commit's author.
+    pub author_name: String,
+    /// The email address of the commit's author.
+    pub author_email: String,
+    /// When the commit was authored, as an ISO 8601 timestamp.
+    pub date: String,
+}
+
+#[cfg(feature = "graphql")]
+#[derive(Deserialize, Default)]
+struct GitActorModel {
+    name: Option<String>,
+    email: Option<String>,
+    date: Option<String>,
+}
+
+#[cfg(feature = "graphql")]
+#[derive(Deserialize)]
+struct HistoryCommitModel {
+    oid: ObjectId,
+    author: Option<GitActorModel>,
+}
+
+#[cfg(feature = "graphql")]
+#[derive(Deserialize)]
+struct HistoryModel {
+    nodes: Vec<HistoryCommitModel>,
+}
+
+#[cfg(feature = "graphql")]
+#[derive(Deserialize)]
+struct RepositoryHistoryModel {
+    // one history per path, keyed by the alias it was requested under
+    object: Option<HashMap<String, HistoryModel>>,
+}
+
+#[cfg(feature = "graphql")]
+#[derive(Deserialize)]
+struct HistoryQueryModel {
+    repository: Option<RepositoryHistoryModel>,
+}
+
+#[cfg(feature = "graphql")]
+impl From<HistoryCommitModel> for EntryCommitInfo {
+    fn from(value: HistoryCommitModel) -> Self {
+        let author = value.author.unwrap_or_default();
+
+        EntryCommitInfo {
+            sha: value.oid,
+            author_name: author.name.unwrap_or_default(),
+            author_email: author.email.unwrap_or_default(),
+            date: author.date.unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(feature = "graphql")]
+impl SourceTree {
+    /// Fills in [last_commit](SourceTree::last_commit) for every blob in this tree with the last commit to
+    /// modify it, as of the reference of `path`.
+    ///
+    /// The commits are looked up in batches through the GitHub GraphQL API, which requires the client to
+    /// have an access token. Paths are taken as they appear in the tree, so this should be called on the
+    /// root of the repository.
+    pub async fn enrich_with_commits(
+        &mut self,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<(), Error> {
+        let expression = path.reference.commit_expression()?;
+        let blobs: Vec<String> = self
+            .iter()
+            .filter(|n| n.entry_type == TreeEntryType::Blob)
+            .map(|n| n.path.to_string())
+            .collect();
+
+        let mut commits = HashMap::new();
+        for batch in blobs.chunks(PATHS_PER_QUERY) {
+            let histories = (0..batch.len())
+                .map(|i| {
+                    format!(
+                        "f{i}: history(first: 1, path: $p{i}) {{ nodes {{ oid author {{ name email date }} }} }}"
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let parameters: String = (0..batch.len())
+                .map(|i| format!(", $p{}: String!", i))
+                .collect();
+            let query = format!(
+                "query($owner: String!, $name: String!, $expression: String!{}) {{ \
+                    repository(owner: $owner, name: $name) {{ \
+                        object(expression: $expression) {{ ... on Commit {{ {} }} }} \
+                    }} \
+                }}",
+                parameters, histories
+            );
+
+            let mut variables = json!({
+                "owner": path.user,
+                "name": path.repo,
+                "expression": expression,
+            });
+            for (i, blob) in batch.iter().enumerate() {
+                variables[format!("p{}", i)] = json!(blob);
+            }
+
+            let result: HistoryQueryModel = raw::post_graphql(client, &query, variables).await?;
+            let mut histories = result.repository.and_then(|r| r.object).ok_or_else(|| {
+                Error::Other(format!(
+                    "{} isn't a commit in {}/{}",
+                    expression, path.user, path.repo
+                ))
+            })?;
+
+            for (i, blob) in batch.iter().enumerate() {
+                let commit = histories
+                    .remove(&format!("f{}", i))
+                    .and_then(|h| h.nodes.into_iter().next());
+                if let Some(commit) = commit {
+                    commits.insert(Utf8PathBuf::from(blob), EntryCommitInfo::from(commit));
+                }
+            }
+        }
+
+        apply_commits(self, &mut commits);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "graphql")]
+fn apply_commits(node: &mut SourceTree, commits: &mut HashMap<Utf8PathBuf, EntryCommitInfo>) {
+    if node.entry_type == TreeEntryType::Blob {
+        node.last_commit = commits.remove(&node.path);
+    }
+
+    for child in &mut node.children {
+        apply_commits(child, commits);
+    }
+}
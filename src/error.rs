@@ -1,6 +1,12 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize, Serializer};
 
 /// Encapsulates an error value from grab_github or one of its dependencies.
+///
+/// Errors serialize as an object whose `type` is the name of the variant in snake case, such as
+/// `{"type":"rate_limited","retry_after_ms":60000,"message":"..."}`. Errors from dependencies are serialized
+/// as their message.
 #[derive(Debug, Clone)]
 pub enum Error {
     /// An error occurred during an HTTP request.
@@ -10,11 +16,544 @@ pub enum Error {
     /// An error occured when trying to perform a filesystem operation.
     IOError(Arc<std::io::Error>),
     /// An error occurred while trying to decode base64 obtained from GitHub.
+    #[cfg(feature = "download")]
     Base64Error(Arc<base64::DecodeError>),
-    /// An error occurred with a GitHub API request (usually a rate limit error).
-    GithubError(String),
+    /// An error occurred while reading from a local git repository.
+    #[cfg(feature = "git2")]
+    GitError(Arc<git2::Error>),
+    /// GitHub responded to a request with an error.
+    GithubError(GithubApiError),
+    /// GitHub rejected a request because a rate limit was exceeded.
+    RateLimited {
+        /// How long GitHub asked us to wait before making another request.
+        retry_after: Duration,
+        /// The message GitHub returned with the response.
+        message: String,
+        /// How far the download had got when it was rate limited, for deciding how to carry on. This is only set
+        /// when downloading files fails, after any [retries](crate::DownloadConfig::max_rate_limit_retries).
+        guidance: Option<Box<RateLimitGuidance>>,
+    },
+    /// The repository has no commits, so there is no tree to fetch.
+    EmptyRepository,
+    /// A blob is too large for GitHub to return through the API.
+    ///
+    /// Files this large need to be fetched another way, such as by cloning the repository with git
+    /// or downloading it from a release.
+    BlobTooLargeForApi {
+        /// The API URL of the blob.
+        url: String,
+        /// The size of the blob in bytes.
+        size: u64,
+        /// The message GitHub returned with the response.
+        message: String,
+    },
+    /// GitHub returned the contents of a file in an encoding that can't be decoded, such as `none` for contents
+    /// it left out. See [ContentEncoding](crate::raw::ContentEncoding).
+    UnsupportedEncoding {
+        /// The encoding GitHub named.
+        encoding: String,
+    },
+    /// GitHub, or something between it and us, responded with something other than the JSON that was expected,
+    /// such as an HTML error page.
+    UnexpectedResponse {
+        /// The HTTP status code of the response.
+        status: u16,
+        /// The start of the body of the response.
+        body_preview: String,
+    },
+    /// A glob pattern given to a [Filter](crate::Filter) could not be interpreted.
+    InvalidPattern {
+        /// The pattern as it was given.
+        pattern: String,
+        /// A description of what is wrong with the pattern.
+        reason: String,
+    },
+    /// A repository spec such as `user/repo@ref`, or the URL of a repository, could not be interpreted as a
+    /// [GithubBranchPath](crate::GithubBranchPath).
+    InvalidSpec {
+        /// The spec or URL as it was given.
+        spec: String,
+        /// A description of what is wrong with the spec.
+        reason: String,
+    },
+    /// Two files would be written to the same path under [CollisionPolicy::Error](crate::CollisionPolicy::Error).
+    /// Paths that only differ in case are the same path on case-insensitive filesystems.
+    PathCollision {
+        /// The path in the repository of the file that couldn't be written.
+        path: String,
+        /// The path in the repository of the file that would have been overwritten.
+        existing: String,
+    },
+    /// A file's path can't be written to disk on this platform, such as a name that wasn't valid UTF-8 or one
+    /// that Windows doesn't allow. See [UnrepresentablePathPolicy](crate::UnrepresentablePathPolicy).
+    UnrepresentablePath {
+        /// The path in the repository of the file.
+        path: String,
+        /// Why the path can't be written.
+        reason: String,
+    },
+    /// A [ContentInspector](crate::ContentInspector) blocked a file from being written to disk.
+    ContentBlocked {
+        /// The path in the repository of the file.
+        path: String,
+        /// Why the file was blocked.
+        reason: String,
+    },
+    /// A download was larger than [max_file_bytes](crate::DownloadConfig::max_file_bytes) or
+    /// [max_total_bytes](crate::DownloadConfig::max_total_bytes) allow.
+    QuotaExceeded {
+        /// The path in the repository of the file that was too large, or `None` if the download as a whole was.
+        path: Option<String>,
+        /// The size in bytes of the file or download.
+        size: u64,
+        /// The limit in bytes that was exceeded.
+        limit: u64,
+    },
+    /// The files to download need more space than the disk holding the output directory has available. See
+    /// [check_disk_space](crate::DownloadConfig::check_disk_space).
+    InsufficientSpace {
+        /// The total size in bytes of the files to download.
+        required: u64,
+        /// The number of bytes available on the disk.
+        available: u64,
+    },
+    /// More files passed the filter than [max_files](crate::DownloadConfig::max_files) allows.
+    TooManyFiles {
+        /// The maximum number of files.
+        limit: usize,
+    },
+    /// A file that passed the filter is nested deeper than [max_depth](crate::DownloadConfig::max_depth) allows.
+    TooDeep {
+        /// The path in the repository of the file.
+        path: String,
+        /// The number of directories the file is nested in.
+        depth: usize,
+        /// The maximum depth.
+        limit: usize,
+    },
+    /// The download was aborted through its [DownloadHandle](crate::DownloadHandle).
+    Aborted,
+    /// [require_auth](crate::DownloadConfig::require_auth) is set, but there's no access token to make requests with.
+    AuthenticationRequired,
+    /// A sparse checkout profile couldn't be read, or the requested profile doesn't exist.
+    ProfileError(String),
+    /// The [TEMPLATE_FILE_NAME](crate::scaffold::TEMPLATE_FILE_NAME) of a template repository couldn't be
+    /// parsed.
+    InvalidTemplate(String),
+    /// A variable of a template was given no value and has no default.
+    MissingTemplateValue {
+        /// The name of the variable.
+        variable: String,
+    },
+    /// A file of a template would be generated outside of the output directory, because a value substituted
+    /// into its path made it absolute or gave it a `..` component.
+    InvalidTemplatePath {
+        /// The path of the file after substitution.
+        path: String,
+    },
+    /// A check made by [preflight](crate::Downloader::preflight) failed. The message says what went wrong
+    /// and how it can be fixed.
+    PreflightFailed(String),
     /// Some other error occurred.
     Other(String),
+    /// Another error, along with where it happened.
+    ///
+    /// Use [root](Error::root) to get the underlying error regardless of whether it has context attached.
+    WithContext {
+        /// Where the error happened.
+        context: ErrorContext,
+        /// The error that happened.
+        source: Box<Error>,
+    },
+}
+
+/// How far a download had got when it failed with [Error::RateLimited], so wrappers can say what to do next,
+/// or wait for the reset and resume.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateLimitGuidance {
+    /// How many API requests had been made with the download's client, including the one that was rate limited.
+    pub requests_made: u64,
+    /// How many files had been downloaded.
+    pub files_downloaded: usize,
+    /// How many files were left to download, including the one that was rate limited.
+    pub files_remaining: usize,
+    /// When the rate limit resets, in seconds since the unix epoch.
+    pub reset_at: u64,
+    /// Whether [FetchStrategy::Archive](crate::FetchStrategy) would have avoided the rate limit. This is true
+    /// when the `archive` feature is enabled and the repository is on github.com, whose archives don't count
+    /// against the API's rate limit.
+    pub archive_would_succeed: bool,
+}
+
+/// The error object GitHub returns in the body of an unsuccessful response.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GithubApiError {
+    /// A description of what went wrong.
+    pub message: String,
+    /// A link to the documentation of the API that was called.
+    #[serde(default)]
+    pub documentation_url: Option<String>,
+    /// Details of what went wrong, such as which fields of a request were invalid.
+    #[serde(default)]
+    pub errors: Vec<GithubApiErrorDetail>,
+}
+
+impl GithubApiError {
+    /// Creates a [GithubApiError] with only a message.
+    pub fn new(message: impl Into<String>) -> GithubApiError {
+        GithubApiError {
+            message: message.into(),
+            documentation_url: None,
+            errors: Vec::new(),
+        }
+    }
+}
+
+/// A single entry of [GithubApiError::errors].
+///
+/// Most APIs return objects with some of these fields, but some return plain strings, which are read
+/// into `message`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(from = "ErrorDetailRepr")]
+pub struct GithubApiErrorDetail {
+    /// The kind of resource the error is about, such as `Issue`.
+    pub resource: Option<String>,
+    /// The field of the resource the error is about.
+    pub field: Option<String>,
+    /// What was wrong, such as `missing_field` or `invalid`.
+    pub code: Option<String>,
+    /// A description of what went wrong.
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorDetailRepr {
+    Message(String),
+    Detail {
+        #[serde(default)]
+        resource: Option<String>,
+        #[serde(default)]
+        field: Option<String>,
+        #[serde(default)]
+        code: Option<String>,
+        #[serde(default)]
+        message: Option<String>,
+    },
+}
+
+impl From<ErrorDetailRepr> for GithubApiErrorDetail {
+    fn from(value: ErrorDetailRepr) -> Self {
+        match value {
+            ErrorDetailRepr::Message(message) => GithubApiErrorDetail {
+                message: Some(message),
+                ..GithubApiErrorDetail::default()
+            },
+            ErrorDetailRepr::Detail {
+                resource,
+                field,
+                code,
+                message,
+            } => GithubApiErrorDetail {
+                resource,
+                field,
+                code,
+                message,
+            },
+        }
+    }
+}
+
+/// Where an [Error] happened, as attached by [Error::WithContext].
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct ErrorContext {
+    /// The repository being fetched, such as `octocat/Hello-World`.
+    pub repo: Option<String>,
+    /// The path in the repository of the file being downloaded.
+    pub path: Option<String>,
+    /// The URL of the request that failed.
+    pub url: Option<String>,
+    /// The HTTP status code GitHub responded with.
+    pub status: Option<u16>,
+}
+
+impl Error {
+    /// Returns the underlying error, without any [context](Error::WithContext).
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::WithContext { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// Returns where the error happened, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the [RateLimitGuidance] of this error, if it's [Error::RateLimited] and has any.
+    pub fn rate_limit_guidance(&self) -> Option<&RateLimitGuidance> {
+        match self.root() {
+            Error::RateLimited { guidance, .. } => guidance.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Attaches `guidance` to this error if it's [Error::RateLimited], keeping any context.
+    #[cfg(feature = "download")]
+    pub(crate) fn with_rate_limit_guidance(self, guidance: RateLimitGuidance) -> Error {
+        match self {
+            Error::RateLimited {
+                retry_after,
+                message,
+                ..
+            } => Error::RateLimited {
+                retry_after,
+                message,
+                guidance: Some(Box::new(guidance)),
+            },
+            Error::WithContext { context, source } => Error::WithContext {
+                context,
+                source: Box::new(source.with_rate_limit_guidance(guidance)),
+            },
+            e => e,
+        }
+    }
+
+    /// Attaches `context` to this error. Fields that are already known are kept, since they were attached
+    /// closer to where the error happened.
+    pub(crate) fn with_context(self, context: ErrorContext) -> Error {
+        match self {
+            Error::WithContext {
+                context: existing,
+                source,
+            } => Error::WithContext {
+                context: ErrorContext {
+                    repo: existing.repo.or(context.repo),
+                    path: existing.path.or(context.path),
+                    url: existing.url.or(context.url),
+                    status: existing.status.or(context.status),
+                },
+                source,
+            },
+            e => Error::WithContext {
+                context,
+                source: Box::new(e),
+            },
+        }
+    }
+}
+
+/// The serialized form of an [Error], borrowing from it.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ErrorSummary<'e> {
+    RequestError {
+        message: String,
+    },
+    DeserializationError {
+        message: String,
+    },
+    #[serde(rename = "io_error")]
+    IOError {
+        message: String,
+    },
+    #[cfg(feature = "download")]
+    Base64Error {
+        message: String,
+    },
+    #[cfg(feature = "git2")]
+    GitError {
+        message: String,
+    },
+    GithubError {
+        #[serde(flatten)]
+        error: &'e GithubApiError,
+    },
+    RateLimited {
+        #[serde(serialize_with = "serialize_duration_ms")]
+        retry_after_ms: Duration,
+        message: &'e str,
+        guidance: Option<&'e RateLimitGuidance>,
+    },
+    EmptyRepository,
+    BlobTooLargeForApi {
+        url: &'e str,
+        size: u64,
+        message: &'e str,
+    },
+    UnsupportedEncoding {
+        encoding: &'e str,
+    },
+    UnexpectedResponse {
+        status: u16,
+        body_preview: &'e str,
+    },
+    InvalidPattern {
+        pattern: &'e str,
+        reason: &'e str,
+    },
+    InvalidSpec {
+        spec: &'e str,
+        reason: &'e str,
+    },
+    PathCollision {
+        path: &'e str,
+        existing: &'e str,
+    },
+    UnrepresentablePath {
+        path: &'e str,
+        reason: &'e str,
+    },
+    ContentBlocked {
+        path: &'e str,
+        reason: &'e str,
+    },
+    QuotaExceeded {
+        path: Option<&'e str>,
+        size: u64,
+        limit: u64,
+    },
+    InsufficientSpace {
+        required: u64,
+        available: u64,
+    },
+    TooManyFiles {
+        limit: usize,
+    },
+    TooDeep {
+        path: &'e str,
+        depth: usize,
+        limit: usize,
+    },
+    Aborted,
+    AuthenticationRequired,
+    ProfileError {
+        message: &'e str,
+    },
+    InvalidTemplate {
+        message: &'e str,
+    },
+    MissingTemplateValue {
+        variable: &'e str,
+    },
+    InvalidTemplatePath {
+        path: &'e str,
+    },
+    PreflightFailed {
+        message: &'e str,
+    },
+    Other {
+        message: &'e str,
+    },
+    WithContext {
+        context: &'e ErrorContext,
+        source: &'e Error,
+    },
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let summary = match self {
+            Error::RequestError(e) => ErrorSummary::RequestError {
+                message: e.to_string(),
+            },
+            Error::DeserializationError(e) => ErrorSummary::DeserializationError {
+                message: e.to_string(),
+            },
+            Error::IOError(e) => ErrorSummary::IOError {
+                message: e.to_string(),
+            },
+            #[cfg(feature = "download")]
+            Error::Base64Error(e) => ErrorSummary::Base64Error {
+                message: e.to_string(),
+            },
+            #[cfg(feature = "git2")]
+            Error::GitError(e) => ErrorSummary::GitError {
+                message: e.to_string(),
+            },
+            Error::GithubError(error) => ErrorSummary::GithubError { error },
+            Error::RateLimited {
+                retry_after,
+                message,
+                guidance,
+            } => ErrorSummary::RateLimited {
+                retry_after_ms: *retry_after,
+                message,
+                guidance: guidance.as_deref(),
+            },
+            Error::EmptyRepository => ErrorSummary::EmptyRepository,
+            Error::BlobTooLargeForApi { url, size, message } => ErrorSummary::BlobTooLargeForApi {
+                url,
+                size: *size,
+                message,
+            },
+            Error::UnsupportedEncoding { encoding } => {
+                ErrorSummary::UnsupportedEncoding { encoding }
+            }
+            Error::UnexpectedResponse {
+                status,
+                body_preview,
+            } => ErrorSummary::UnexpectedResponse {
+                status: *status,
+                body_preview,
+            },
+            Error::InvalidPattern { pattern, reason } => {
+                ErrorSummary::InvalidPattern { pattern, reason }
+            }
+            Error::InvalidSpec { spec, reason } => ErrorSummary::InvalidSpec { spec, reason },
+            Error::PathCollision { path, existing } => {
+                ErrorSummary::PathCollision { path, existing }
+            }
+            Error::UnrepresentablePath { path, reason } => {
+                ErrorSummary::UnrepresentablePath { path, reason }
+            }
+            Error::ContentBlocked { path, reason } => ErrorSummary::ContentBlocked { path, reason },
+            Error::QuotaExceeded { path, size, limit } => ErrorSummary::QuotaExceeded {
+                path: path.as_deref(),
+                size: *size,
+                limit: *limit,
+            },
+            Error::InsufficientSpace {
+                required,
+                available,
+            } => ErrorSummary::InsufficientSpace {
+                required: *required,
+                available: *available,
+            },
+            Error::TooManyFiles { limit } => ErrorSummary::TooManyFiles { limit: *limit },
+            Error::TooDeep { path, depth, limit } => ErrorSummary::TooDeep {
+                path,
+                depth: *depth,
+                limit: *limit,
+            },
+            Error::Aborted => ErrorSummary::Aborted,
+            Error::AuthenticationRequired => ErrorSummary::AuthenticationRequired,
+            Error::ProfileError(message) => ErrorSummary::ProfileError { message },
+            Error::InvalidTemplate(message) => ErrorSummary::InvalidTemplate { message },
+            Error::MissingTemplateValue { variable } => {
+                ErrorSummary::MissingTemplateValue { variable }
+            }
+            Error::InvalidTemplatePath { path } => ErrorSummary::InvalidTemplatePath { path },
+            Error::PreflightFailed(message) => ErrorSummary::PreflightFailed { message },
+            Error::Other(message) => ErrorSummary::Other { message },
+            Error::WithContext { context, source } => ErrorSummary::WithContext { context, source },
+        };
+
+        summary.serialize(serializer)
+    }
+}
+
+/// Serializes a [Duration] as a whole number of milliseconds, which is how durations are serialized
+/// throughout this crate.
+pub(crate) fn serialize_duration_ms<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis().try_into().unwrap_or(u64::MAX))
 }
 
 impl From<reqwest::Error> for Error {
@@ -35,8 +574,16 @@ impl From<std::io::Error> for Error {
     }
 }
 
+#[cfg(feature = "download")]
 impl From<base64::DecodeError> for Error {
     fn from(value: base64::DecodeError) -> Self {
         Error::Base64Error(value.into())
     }
 }
+
+#[cfg(feature = "git2")]
+impl From<git2::Error> for Error {
+    fn from(value: git2::Error) -> Self {
+        Error::GitError(value.into())
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::{Error, FileMode, ObjectId, SourceTree, TreeEntryType};
+
+/// A single line of [SourceTree::to_json_lines], which is also how entries are serialized as part of a
+/// [DownloadedFile](crate::DownloadedFile).
+#[derive(Serialize)]
+struct ListingEntry {
+    path: String,
+    #[serde(rename = "type")]
+    entry_type: TreeEntryType,
+    mode: FileMode,
+    size: u32,
+    sha: ObjectId,
+}
+
+impl From<&SourceTree> for ListingEntry {
+    fn from(node: &SourceTree) -> Self {
+        ListingEntry {
+            path: node.path.to_string(),
+            entry_type: node.entry_type.clone(),
+            mode: node.mode,
+            size: node.size,
+            sha: node.sha,
+        }
+    }
+}
+
+/// Serializes `entry` without its children, as an object with the fields `path`, `type`, `mode`, `size` and `sha`.
+#[cfg(feature = "download")]
+pub(crate) fn serialize_entry<S: serde::Serializer>(
+    entry: &std::sync::Arc<SourceTree>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    ListingEntry::from(entry.as_ref()).serialize(serializer)
+}
+
+impl SourceTree {
+    /// Writes every entry below this node as a JSON object per line, with the fields `path`, `type`, `mode`,
+    /// `size` and `sha`.
+    pub fn to_json_lines<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        for node in self.iter().skip(1) {
+            serde_json::to_writer(&mut writer, &ListingEntry::from(node))?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every entry below this node as CSV, with a header row and the columns `path`, `type`, `mode`,
+    /// `size` and `sha`.
+    pub fn to_csv<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writeln!(writer, "path,type,mode,size,sha")?;
+        for node in self.iter().skip(1) {
+            let entry = ListingEntry::from(node);
+            let entry_type = match entry.entry_type {
+                TreeEntryType::Blob => "blob",
+                TreeEntryType::Tree => "tree",
+            };
+
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                csv_field(&entry.path),
+                entry_type,
+                entry.mode,
+                entry.size,
+                entry.sha
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders this tree like the output of `tree(1)`, with the size of each file.
+    ///
+    /// ```text
+    /// .
+    /// ├── Cargo.toml (112 bytes)
+    /// └── src
+    ///     └── lib.rs (2048 bytes)
+    /// ```
+    pub fn to_text_tree(&self) -> String {
+        let mut text = match self.path.as_str().is_empty() {
+            true => String::from("."),
+            false => self.path.to_string(),
+        };
+        text.push('\n');
+
+        render_children(self, "", &mut text);
+        text
+    }
+}
+
+fn render_children(node: &SourceTree, prefix: &str, text: &mut String) {
+    for (i, child) in node.children.iter().enumerate() {
+        let is_last = i == node.children.len() - 1;
+        let name = child.path.file_name().unwrap_or(child.path.as_str());
+
+        text.push_str(prefix);
+        text.push_str(if is_last { "└── " } else { "├── " });
+        text.push_str(name);
+        if child.entry_type == TreeEntryType::Blob {
+            text.push_str(&format!(" ({} bytes)", child.size));
+        }
+        text.push('\n');
+
+        let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+        render_children(child, &child_prefix, text);
+    }
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
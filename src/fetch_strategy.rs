@@ -0,0 +1,398 @@
+#[cfg(any(feature = "git2", feature = "gix"))]
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+#[cfg(feature = "archive")]
+use crate::codeload::{CodeloadArchive, UnpackLimits};
+#[cfg(feature = "gix")]
+use crate::gix_clone::GixClone;
+#[cfg(all(feature = "git2", not(feature = "gix")))]
+use crate::LocalGitForge;
+use crate::{
+    DownloadConfig, DownloadEvent, DownloadReport, DownloadReporter, Downloader, Error, Filter,
+    GithubBranchPath, GithubClient,
+};
+#[cfg(any(feature = "git2", feature = "gix"))]
+use crate::{Forge, GithubRef, SourceTree, DEFAULT_API_URL};
+
+/// How [download_resolved](Downloader::download_resolved) obtains the files of a repository, set with
+/// [fetch_strategy](DownloadConfig::fetch_strategy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchStrategy {
+    /// Fetch the tree from the REST API, and then each blob that passes the filter.
+    #[default]
+    Api,
+    /// Download an archive of the whole repository at once, keeping the files that pass the filter. This takes
+    /// a single request however many files there are, and archives of repositories on github.com don't count
+    /// against the API's rate limit, but every file is downloaded even if few of them pass the filter.
+    ///
+    /// Files are verified against the hashes of the archive's contents. The resolved reference has the hash of
+    /// the commit, but not of its tree.
+    #[cfg(feature = "archive")]
+    Archive,
+    /// Make a shallow clone of only the commit being downloaded into a temporary directory, and read the files
+    /// that pass the filter out of it. The clone is deleted afterwards.
+    ///
+    /// With the `gix` feature, the clone is made in-process with gitoxide, which needs nothing else installed.
+    /// Otherwise, it's made with the `git` binary and read with libgit2.
+    ///
+    /// Partial clones aren't supported, so every file of the commit is fetched however few pass the filter;
+    /// only trees that can hold a file passing it are read. This suits repositories with a long history more
+    /// than ones with a large tree.
+    /// A [GithubRef::Tree](crate::GithubRef::Tree) can't be cloned.
+    #[cfg(any(feature = "git2", feature = "gix"))]
+    Clone,
+    /// Try [FetchStrategy::Api] first, and fall back to the other strategies that are enabled, in the order
+    /// they're listed here, whenever one fails in a way the next might not: by being rate limited, including
+    /// while rebuilding a truncated tree one directory at a time, by a blob being too large for the API, or by
+    /// a request failing outright.
+    ///
+    /// The reporter is sent a [DownloadEvent::FetchFallback] each time a strategy is given up on, and a
+    /// [DownloadEvent::FetchStrategyUsed] once the download has finished. Files written by a strategy that was
+    /// given up on are overwritten or left in place.
+    Auto,
+}
+
+impl FetchStrategy {
+    /// The strategies [FetchStrategy::Auto] tries, in order.
+    fn fallbacks() -> Vec<FetchStrategy> {
+        vec![
+            FetchStrategy::Api,
+            #[cfg(feature = "archive")]
+            FetchStrategy::Archive,
+            #[cfg(any(feature = "git2", feature = "gix"))]
+            FetchStrategy::Clone,
+        ]
+    }
+
+    /// Returns true if `error` might not happen with another strategy.
+    fn can_fall_back(error: &Error) -> bool {
+        matches!(
+            error.root(),
+            Error::RateLimited { .. }
+                | Error::BlobTooLargeForApi { .. }
+                | Error::RequestError(_)
+                | Error::UnexpectedResponse { .. }
+        )
+    }
+}
+
+impl Downloader {
+    /// Downloads the tree of `path` using `client` with the given strategy.
+    pub(crate) async fn download_with_strategy<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+        strategy: FetchStrategy,
+    ) -> Result<DownloadReport, Error> {
+        match strategy {
+            FetchStrategy::Auto => Downloader::download_auto(config, client, path, filter).await,
+            strategy => Downloader::download_once(config, client, path, filter, strategy).await,
+        }
+    }
+
+    /// Downloads the tree of `path` with a single strategy, without falling back to any other.
+    async fn download_once<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+        strategy: FetchStrategy,
+    ) -> Result<DownloadReport, Error> {
+        match strategy {
+            FetchStrategy::Api | FetchStrategy::Auto => {
+                Downloader::download_tree_of(config, client, path, filter).await
+            }
+            #[cfg(feature = "archive")]
+            FetchStrategy::Archive => {
+                let archive =
+                    CodeloadArchive::fetch(client, path, filter, UnpackLimits::of(config)).await?;
+                let config = DownloadConfig {
+                    forge: Some(&archive),
+                    ..config.clone()
+                };
+                Downloader::download_tree_of(&config, client, path, filter).await
+            }
+            #[cfg(feature = "gix")]
+            FetchStrategy::Clone => {
+                let clone = GixClone::fetch(config, client, path).await?;
+                let config = DownloadConfig {
+                    forge: Some(&clone),
+                    ..config.clone()
+                };
+                Downloader::download_tree_of(&config, client, path, filter).await
+            }
+            #[cfg(all(feature = "git2", not(feature = "gix")))]
+            FetchStrategy::Clone => {
+                let shallow = ShallowClone::fetch(config, client, path).await?;
+                let forge = LocalGitForge::open(&shallow.dir)?;
+                let config = DownloadConfig {
+                    forge: Some(&forge),
+                    ..config.clone()
+                };
+                Downloader::download_tree_of(&config, client, path, filter).await
+            }
+        }
+    }
+
+    /// Tries each strategy [FetchStrategy::Auto] falls back to in turn, until one succeeds or fails in a way
+    /// the next one wouldn't help with.
+    async fn download_auto<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<DownloadReport, Error> {
+        let mut strategies = FetchStrategy::fallbacks().into_iter().peekable();
+        while let Some(strategy) = strategies.next() {
+            let error =
+                match Downloader::download_once(config, client, path, filter, strategy).await {
+                    Ok(report) => {
+                        if let Some(reporter) = config.reporter {
+                            reporter.on_event(DownloadEvent::FetchStrategyUsed { strategy });
+                        }
+                        return Ok(report);
+                    }
+                    Err(e) => e,
+                };
+
+            match strategies.peek() {
+                Some(&next) if FetchStrategy::can_fall_back(&error) => {
+                    if let Some(reporter) = config.reporter {
+                        reporter.on_event(DownloadEvent::FetchFallback {
+                            from: strategy,
+                            to: next,
+                            error,
+                        });
+                    }
+                }
+                _ => return Err(error),
+            }
+        }
+
+        unreachable!("FetchStrategy::Api is always tried")
+    }
+}
+
+/// Where and how the commit of a reference is cloned from for [FetchStrategy::Clone].
+#[cfg(any(feature = "git2", feature = "gix"))]
+pub(crate) struct CloneTarget {
+    /// The URL of the repository.
+    pub(crate) url: String,
+    /// The refspec fetching the reference, and nothing else.
+    pub(crate) refspec: String,
+    /// The empty temporary directory to clone into.
+    pub(crate) dir: PathBuf,
+    /// The `Authorization` header to send with the access token of the config, if there is one.
+    pub(crate) auth_header: Option<String>,
+}
+
+#[cfg(any(feature = "git2", feature = "gix"))]
+impl CloneTarget {
+    /// Works out where to clone the reference of `path` from and to.
+    ///
+    /// The clone is made in [temp_dir](DownloadConfig::temp_dir) if it's set, or the system's temporary
+    /// directory otherwise.
+    pub(crate) fn new<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<CloneTarget, Error> {
+        let refspec = match path.reference {
+            GithubRef::Tree(sha) => {
+                return Err(Error::Other(format!(
+                    "tree {} isn't a commit and can't be cloned",
+                    sha
+                )))
+            }
+            GithubRef::Commit(sha) => {
+                // passed to git on its own, so anything else could be read as an option
+                if !matches!(sha.len(), 40 | 64) || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(Error::Other(format!("{} isn't a commit hash", sha)));
+                }
+                sha.to_string()
+            }
+            GithubRef::Branch(name) => format!("+refs/heads/{0}:refs/heads/{0}", name),
+            GithubRef::Tag(name) => format!("+refs/tags/{0}:refs/tags/{0}", name),
+            GithubRef::PullRequest(number) => {
+                format!("+refs/pull/{0}/head:refs/pull/{0}/head", number)
+            }
+        };
+
+        Ok(CloneTarget::with_refspec(
+            config, client, path.user, path.repo, refspec,
+        ))
+    }
+
+    /// Works out where to fetch `refspec` of the repository `user/repo` from and to.
+    pub(crate) fn with_refspec<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        user: &str,
+        repo: &str,
+        refspec: String,
+    ) -> CloneTarget {
+        use base64::prelude::*;
+
+        let web_url = match client.api_url() == DEFAULT_API_URL {
+            true => "https://github.com",
+            false => client.api_url().trim_end_matches("/api/v3"),
+        };
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir = config
+            .temp_dir
+            .map(Path::to_path_buf)
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!("grab_github-{}-{}.git", std::process::id(), nanos));
+
+        let auth_header = config.access_token.as_ref().map(|token| {
+            let credentials = BASE64_STANDARD.encode(format!("x-access-token:{}", token));
+            format!("Authorization: Basic {}", credentials)
+        });
+
+        CloneTarget {
+            url: format!("{}/{}/{}.git", web_url, user, repo),
+            refspec,
+            dir,
+            auth_header,
+        }
+    }
+
+    /// Asks the repository for the branch its `HEAD` points to, which is the branch a plain `git clone` checks
+    /// out. Nothing is cloned.
+    pub(crate) async fn default_branch(self) -> Result<String, Error> {
+        let url = self.url.clone();
+
+        #[cfg(feature = "gix")]
+        let head = GixClone::remote_head(self).await?;
+        #[cfg(all(feature = "git2", not(feature = "gix")))]
+        let head = ShallowClone::remote_head(self).await?;
+
+        head.strip_prefix("refs/heads/")
+            .map(str::to_string)
+            .ok_or_else(|| Error::Other(format!("HEAD of {} isn't a branch", url)))
+    }
+}
+
+#[cfg(any(feature = "git2", feature = "gix"))]
+impl Downloader {
+    /// Clones the commit of `path` like [FetchStrategy::Clone] does, and lists the entries of its tree that pass
+    /// `filter`. The clone is deleted before this returns.
+    pub(crate) async fn get_cloned_tree<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<SourceTree, Error> {
+        #[cfg(feature = "gix")]
+        let forge = GixClone::fetch(config, client, path).await?;
+        #[cfg(all(feature = "git2", not(feature = "gix")))]
+        let shallow = ShallowClone::fetch(config, client, path).await?;
+        #[cfg(all(feature = "git2", not(feature = "gix")))]
+        let forge = LocalGitForge::open(&shallow.dir)?;
+
+        forge.get_tree(path, filter).await
+    }
+}
+
+/// A bare, shallow clone of a repository in a temporary directory, which is deleted when this is dropped.
+#[cfg(all(feature = "git2", not(feature = "gix")))]
+struct ShallowClone {
+    dir: PathBuf,
+}
+
+#[cfg(all(feature = "git2", not(feature = "gix")))]
+impl ShallowClone {
+    /// Clones the commit the reference of `path` points to, and nothing else, with the `git` binary.
+    /// The access token of `config` is passed to git through its environment.
+    async fn fetch<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<ShallowClone, Error> {
+        let target = CloneTarget::new(config, client, path)?;
+
+        // deletes the directory if anything below fails
+        let shallow = ShallowClone { dir: target.dir };
+        let dir = shallow.dir.to_string_lossy();
+        let auth = target.auth_header.as_deref();
+        ShallowClone::git(auth, &["init", "--bare", "-q", &dir]).await?;
+        ShallowClone::git(
+            auth,
+            &[
+                "-C",
+                &dir,
+                "fetch",
+                "-q",
+                "--depth",
+                "1",
+                "--no-tags",
+                "--",
+                &target.url,
+                &target.refspec,
+            ],
+        )
+        .await?;
+        Ok(shallow)
+    }
+
+    /// Returns the full name of the reference the `HEAD` of the repository of `target` points to, such as
+    /// `refs/heads/main`, with `git ls-remote`.
+    async fn remote_head(target: CloneTarget) -> Result<String, Error> {
+        let auth = target.auth_header.as_deref();
+        let output = ShallowClone::git(
+            auth,
+            &["ls-remote", "--symref", "--", &target.url, &target.refspec],
+        )
+        .await?;
+
+        // the symbolic reference is listed as `ref: refs/heads/main<TAB>HEAD`
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix("ref: ")?.strip_suffix("\tHEAD"))
+            .map(str::to_string)
+            .ok_or_else(|| Error::Other(format!("{} has no HEAD", target.url)))
+    }
+
+    /// Runs git with `args`, returning what it writes to its standard output, and failing with its error
+    /// output if it doesn't succeed.
+    async fn git(auth_header: Option<&str>, args: &[&str]) -> Result<String, Error> {
+        let mut command = tokio::process::Command::new("git");
+        command.args(args).kill_on_drop(true);
+
+        // passed through the environment, since arguments can be seen by other users
+        if let Some(header) = auth_header {
+            command
+                .env("GIT_CONFIG_COUNT", "1")
+                .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+                .env("GIT_CONFIG_VALUE_0", header);
+        }
+
+        let output = command.output().await?;
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+            false => Err(Error::Other(format!(
+                "`git {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    }
+}
+
+#[cfg(all(feature = "git2", not(feature = "gix")))]
+impl Drop for ShallowClone {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
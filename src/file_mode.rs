@@ -0,0 +1,86 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Error;
+
+/// The mode of an entry in a git tree, which says what kind of entry it is.
+///
+/// Git only records whether a file is executable, so these are the only modes a tree can hold.
+/// This is parsed from and displayed as the octal string git uses, such as `100644`, which is also how
+/// it's serialized.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FileMode {
+    /// A regular file, `100644`.
+    #[default]
+    Regular,
+    /// An executable file, `100755`.
+    Executable,
+    /// A symbolic link, `120000`, whose blob holds the path it points to.
+    Symlink,
+    /// A submodule, `160000`, whose hash is the commit it points to in another repository.
+    Submodule,
+    /// A directory, `040000`.
+    Directory,
+}
+
+impl FileMode {
+    /// Returns the octal string git uses for this mode, such as `100644`.
+    pub fn raw(&self) -> &'static str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+            FileMode::Submodule => "160000",
+            FileMode::Directory => "040000",
+        }
+    }
+
+    /// Returns whether entries with this mode are stored as blobs: regular files, executables and symlinks.
+    pub fn is_blob(&self) -> bool {
+        matches!(
+            self,
+            FileMode::Regular | FileMode::Executable | FileMode::Symlink
+        )
+    }
+}
+
+impl FromStr for FileMode {
+    type Err = Error;
+
+    /// Parses an octal mode, classifying it by its type bits like git does. Any file with an executable bit set
+    /// is [FileMode::Executable], so old modes like `100664` are still understood.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Other(format!("{} is not a valid file mode", s));
+        let mode = u32::from_str_radix(s, 8).map_err(|_| invalid())?;
+
+        match mode & 0o170000 {
+            0o100000 if mode & 0o111 != 0 => Ok(FileMode::Executable),
+            0o100000 => Ok(FileMode::Regular),
+            0o120000 => Ok(FileMode::Symlink),
+            0o160000 => Ok(FileMode::Submodule),
+            0o040000 => Ok(FileMode::Directory),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.raw())
+    }
+}
+
+impl Serialize for FileMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.raw())
+    }
+}
+
+impl<'de> Deserialize<'de> for FileMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        raw.parse()
+            .map_err(|_| serde::de::Error::custom(format!("{} is not a valid file mode", raw)))
+    }
+}
@@ -1,13 +1,68 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, path::Path};
 
 use glob_match::glob_match;
 
-type FilterListType<'src> = Vec<Cow<'src, str>>;
+use crate::Error;
+
+/// The maximum brace nesting depth supported by `glob_match`.
+const MAX_BRACE_DEPTH: usize = 10;
+
+type FilterListType<'src> = Vec<Glob<'src>>;
+
+/// A glob as it was given, along with the form it's matched in under the filter's [FilterOptions].
+struct Glob<'src> {
+    source: Cow<'src, str>,
+    /// The normalized glob, if normalizing changed it.
+    normalized: Option<String>,
+}
+
+impl<'src> Glob<'src> {
+    fn new(source: Cow<'src, str>, options: &FilterOptions) -> Glob<'src> {
+        let normalized = match normalize(&source, options) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(_) => None,
+        };
+
+        Glob { source, normalized }
+    }
+
+    /// Returns the glob to match normalized paths against.
+    fn pattern(&self) -> &str {
+        self.normalized.as_deref().unwrap_or(&self.source)
+    }
+}
+
+/// Options controlling how a [Filter] matches globs against paths.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FilterOptions {
+    /// If true, globs match paths regardless of letter case.
+    pub case_insensitive: bool,
+    /// If true, backslashes in both globs and paths are treated as path separators, so that
+    /// Windows-style globs like `src\**` behave the same as `src/**`.
+    ///
+    /// This means backslashes can no longer be used to escape special characters.
+    pub match_backslashes: bool,
+    /// If true, a path component starting with `.` (such as `.github` or `.gitignore`) is only matched
+    /// by a glob segment that also starts with a literal `.`, so `*` and `**` no longer match dotfiles.
+    pub require_literal_leading_dot: bool,
+}
+
+/// Describes how a [Filter] reached its decision for a single path, as returned by [explain](Filter::explain).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterExplanation<'f> {
+    /// The first included glob that matched the path, if any.
+    pub included_by: Option<&'f str>,
+    /// The first excluded glob that matched the path, if any.
+    pub excluded_by: Option<&'f str>,
+    /// Whether the path passes the filter.
+    pub passes: bool,
+}
 
 /// Specifies a set of filters for which objects should be included and which should be excluded.
 pub struct Filter<'src> {
     included: FilterListType<'src>,
     excluded: FilterListType<'src>,
+    options: FilterOptions,
 }
 
 impl<'src> Filter<'src> {
@@ -17,45 +72,387 @@ impl<'src> Filter<'src> {
     /// - If only `included` is specified and `excluded` is empty, `included` acts as a whitelist.
     /// - If only `excluded` is specified and `included` is empty, `excluded` acts as a blacklist.
     /// - If both are empty, the filter passes for all paths (and you should call [all](Filter::all) instead).
+    ///
+    /// Globs are not validated, so a malformed glob will simply never match. Use [try_new](Filter::try_new)
+    /// to reject malformed globs up front.
     pub fn new<Iter, Str>(included: Iter, excluded: Iter) -> Filter<'src>
     where
         Iter: IntoIterator<Item = Str>,
         Str: Into<Cow<'src, str>>,
     {
+        let options = FilterOptions::default();
         Filter {
-            included: included.into_iter().map(|s| s.into()).collect(),
-            excluded: excluded.into_iter().map(|s| s.into()).collect(),
+            included: included
+                .into_iter()
+                .map(|s| Glob::new(s.into(), &options))
+                .collect(),
+            excluded: excluded
+                .into_iter()
+                .map(|s| Glob::new(s.into(), &options))
+                .collect(),
+            options,
         }
     }
 
+    /// Creates a new [Filter] like [new](Filter::new), but returns [Error::InvalidPattern] for the first
+    /// glob that can't be interpreted.
+    pub fn try_new<Iter, Str>(included: Iter, excluded: Iter) -> Result<Filter<'src>, Error>
+    where
+        Iter: IntoIterator<Item = Str>,
+        Str: Into<Cow<'src, str>>,
+    {
+        Filter::new(included, excluded).try_with_options(FilterOptions::default())
+    }
+
     /// Creates a new filter that passes for all paths.
     pub fn all() -> Filter<'src> {
         Filter {
             included: Vec::new(),
             excluded: Vec::new(),
+            options: FilterOptions::default(),
+        }
+    }
+
+    /// Returns this filter with the given [FilterOptions] applied to all of its globs.
+    pub fn with_options(mut self, options: FilterOptions) -> Filter<'src> {
+        self.options = options;
+        for glob in self.included.iter_mut().chain(self.excluded.iter_mut()) {
+            *glob = Glob::new(std::mem::take(&mut glob.source), &options);
+        }
+
+        self
+    }
+
+    /// Returns this filter with the given [FilterOptions] applied like [with_options](Filter::with_options),
+    /// but returns [Error::InvalidPattern] for the first glob that can't be interpreted under them.
+    ///
+    /// With [match_backslashes](FilterOptions::match_backslashes) set, backslashes are validated as path
+    /// separators rather than escapes.
+    pub fn try_with_options(self, options: FilterOptions) -> Result<Filter<'src>, Error> {
+        for glob in self.included.iter().chain(self.excluded.iter()) {
+            validate_pattern(&glob.source, &options)?;
+        }
+
+        Ok(self.with_options(options))
+    }
+
+    /// Parses a [Filter] from a comma-separated list of globs, such as one passed on the command line.
+    ///
+    /// Globs prefixed with `!` are excluded, all others are included.
+    /// Commas inside braces (`{a,b}`) are treated as part of the glob.
+    ///
+    /// ```
+    /// # use grab_github::Filter;
+    /// let filter = Filter::parse("src/**,*.{toml,md},!src/tests/**").unwrap();
+    /// assert!(filter.check("Cargo.toml"));
+    /// assert!(!filter.check("src/tests/filter.rs"));
+    /// ```
+    pub fn parse(spec: &'src str) -> Result<Filter<'src>, Error> {
+        let mut filter = Filter::all();
+        for pattern in split_top_level(spec) {
+            let (is_excluded, pattern) = strip_negation(pattern);
+            filter.push_pattern(is_excluded, Cow::Borrowed(pattern))?;
+        }
+
+        Ok(filter)
+    }
+
+    /// Reads a [Filter] from a file containing one glob per line.
+    ///
+    /// Lines prefixed with `!` are excluded, all others are included.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn from_patterns_file(path: &Path) -> Result<Filter<'src>, Error> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let mut filter = Filter::all();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (is_excluded, pattern) = strip_negation(line);
+            filter.push_pattern(is_excluded, Cow::Owned(pattern.to_string()))?;
+        }
+
+        Ok(filter)
+    }
+
+    /// Validates a glob and adds it to the excluded or included list.
+    fn push_pattern(&mut self, is_excluded: bool, pattern: Cow<'src, str>) -> Result<(), Error> {
+        validate_pattern(&pattern, &self.options)?;
+
+        let glob = Glob::new(pattern, &self.options);
+        match is_excluded {
+            true => self.excluded.push(glob),
+            false => self.included.push(glob),
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy of this filter that owns its globs, so it can be moved to another thread.
+    #[cfg(any(feature = "archive", feature = "gix"))]
+    pub(crate) fn to_owned_filter(&self) -> Filter<'static> {
+        let to_owned = |globs: &FilterListType<'src>| {
+            globs
+                .iter()
+                .map(|glob| Glob {
+                    source: Cow::Owned(glob.source.to_string()),
+                    normalized: glob.normalized.clone(),
+                })
+                .collect()
+        };
+
+        Filter {
+            included: to_owned(&self.included),
+            excluded: to_owned(&self.excluded),
+            options: self.options,
         }
     }
 
     /// Returns whether the given path matches this filter.
     pub fn check(&self, path: &str) -> bool {
-        let is_included = self.match_path(&self.included, path).unwrap_or(true);
-        let is_excluded = self.match_path(&self.excluded, path).unwrap_or(false);
+        self.explain(path).passes
+    }
 
-        return is_included && !is_excluded;
+    /// Returns whether any path inside the directory `dir` could pass this filter.
+    ///
+    /// This errs on the side of returning true, so a false result means the directory can be skipped entirely.
+    pub fn may_match_within(&self, dir: &str) -> bool {
+        let dir = normalize(dir.trim_end_matches('/'), &self.options);
+        if dir.is_empty() {
+            return true;
+        }
+
+        // an exclude like `node_modules/**` covers the entire directory, but only
+        // when dotfiles inside it can't escape the match
+        if !self.options.require_literal_leading_dot {
+            let is_excluded = self.excluded.iter().any(|glob| {
+                let glob = glob.pattern();
+                match glob.strip_suffix("/**") {
+                    Some(prefix) => dir
+                        .match_indices('/')
+                        .map(|(i, _)| &dir[..i])
+                        .chain(std::iter::once(dir.as_ref()))
+                        .any(|ancestor| glob_match(prefix, ancestor)),
+                    None => glob == "**",
+                }
+            });
+
+            if is_excluded {
+                return false;
+            }
+        }
+
+        self.included.is_empty()
+            || self
+                .included
+                .iter()
+                .any(|glob| glob_may_match_within(glob.pattern(), &dir))
     }
 
-    /// Returns whether a path matches the given glob array.
-    fn match_path(&self, globs: &FilterListType, path: &str) -> Option<bool> {
+    /// Returns which globs matched the given path, for debugging why a path was or wasn't included.
+    pub fn explain(&self, path: &str) -> FilterExplanation<'_> {
+        let included_by = self.match_path(&self.included, path);
+        let excluded_by = self.match_path(&self.excluded, path);
+        let is_included = self.included.is_empty() || included_by.is_some();
+
+        FilterExplanation {
+            included_by,
+            excluded_by,
+            passes: is_included && excluded_by.is_none(),
+        }
+    }
+
+    /// Returns the first glob in the given glob array that matches the path, if any.
+    fn match_path<'f>(&self, globs: &'f FilterListType, path: &str) -> Option<&'f str> {
         if globs.is_empty() {
             return None;
         }
 
-        for glob in globs {
-            if glob_match(glob, path) {
-                return Some(true);
+        let path = normalize(path, &self.options);
+        globs
+            .iter()
+            .find(|glob| self.match_glob(glob.pattern(), &path))
+            .map(|glob| glob.source.as_ref())
+    }
+
+    /// Returns whether a normalized glob matches a normalized path.
+    fn match_glob(&self, glob: &str, path: &str) -> bool {
+        if !glob_match(glob, path) {
+            return false;
+        }
+
+        if !self.options.require_literal_leading_dot {
+            return true;
+        }
+
+        // every dot-prefixed component needs a dot-prefixed glob segment that matches it
+        path.split('/')
+            .filter(|component| component.starts_with('.'))
+            .all(|component| {
+                glob.split('/')
+                    .any(|segment| segment.starts_with('.') && glob_match(segment, component))
+            })
+    }
+}
+
+/// Applies the case and separator options to a glob or path.
+fn normalize<'a>(s: &'a str, options: &FilterOptions) -> Cow<'a, str> {
+    let mut s = Cow::Borrowed(s);
+    if options.match_backslashes && s.contains('\\') {
+        s = Cow::Owned(s.replace('\\', "/"));
+    }
+
+    if options.case_insensitive {
+        s = Cow::Owned(s.to_lowercase());
+    }
+
+    s
+}
+
+/// Splits `spec` on commas that aren't inside braces, trimming whitespace and skipping empty globs.
+fn split_top_level(spec: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in spec.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&spec[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&spec[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Returns whether `glob` could match some path below `dir`, comparing one path segment at a time.
+fn glob_may_match_within(glob: &str, dir: &str) -> bool {
+    // braces can contain separators, so segments can't be compared reliably
+    if glob.contains('{') {
+        return true;
+    }
+
+    let segments: Vec<&str> = glob.split('/').collect();
+    let dir_segments: Vec<&str> = dir.split('/').collect();
+    for (i, dir_segment) in dir_segments.iter().enumerate() {
+        match segments.get(i) {
+            None => return false,
+            Some(&"**") => return true,
+            Some(segment) if !glob_match(segment, dir_segment) => return false,
+            Some(_) => {}
+        }
+    }
+
+    // the glob needs at least one more segment to match something inside the directory
+    segments.len() > dir_segments.len()
+}
+
+/// Splits a leading `!` off of a glob, returning whether it was present.
+fn strip_negation(pattern: &str) -> (bool, &str) {
+    match pattern.strip_prefix('!') {
+        Some(pattern) => (true, pattern.trim_start()),
+        None => (false, pattern),
+    }
+}
+
+/// Checks that `pattern` is a glob that `glob_match` can interpret the way it was written, under `options`.
+fn validate_pattern(pattern: &str, options: &FilterOptions) -> Result<(), Error> {
+    let invalid = |reason: &str| {
+        Err(Error::InvalidPattern {
+            pattern: pattern.to_string(),
+            reason: reason.to_string(),
+        })
+    };
+
+    if pattern.is_empty() {
+        return invalid("pattern is empty");
+    }
+
+    // backslashes are matched as separators, so they can't escape anything
+    let normalized = normalize(pattern, options);
+    let mut chars = normalized.chars().peekable();
+    let mut brace_depth = 0usize;
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            // the guard also consumes the escaped character
+            '\\' if chars.next().is_none() => {
+                return invalid("pattern ends with an unfinished escape");
+            }
+            '[' => {
+                // a `]` directly after the opening bracket (or its negation) is a literal
+                if matches!(chars.peek(), Some('!') | Some('^')) {
+                    chars.next();
+                }
+
+                let mut closed = false;
+                let mut empty = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        ']' if !empty => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                    empty = false;
+                }
+
+                if !closed {
+                    return invalid("unclosed character class `[`");
+                }
+            }
+            '{' => {
+                brace_depth += 1;
+                if brace_depth > MAX_BRACE_DEPTH {
+                    return invalid("braces are nested more than 10 levels deep");
+                }
+            }
+            '}' => {
+                if brace_depth == 0 {
+                    return invalid("unmatched closing brace `}`");
+                }
+                brace_depth -= 1;
+            }
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let starts_segment = matches!(prev, None | Some('/') | Some('{') | Some(','));
+                let ends_segment = matches!(chars.peek(), None | Some('/') | Some('}') | Some(','));
+                if !starts_segment || !ends_segment {
+                    return invalid("`**` must be a complete path segment");
+                }
+                prev = Some('*');
+                continue;
             }
+            _ => {}
         }
 
-        return Some(false);
+        prev = Some(c);
     }
+
+    if brace_depth > 0 {
+        return invalid("unclosed brace `{`");
+    }
+
+    Ok(())
 }
@@ -0,0 +1,289 @@
+use futures::future::{BoxFuture, FutureExt};
+use serde::Deserialize;
+
+use crate::{
+    raw::{self, TreeEntryModel, TreeModel},
+    AuthScheme, Error, ErrorContext, FileMode, Filter, GithubBranchPath, GithubClient, GithubRef,
+    HttpOptions, ObjectId, ResolvedRef, SourceTree, TreeEntryType,
+};
+
+/// A service hosting git repositories that trees and blobs can be fetched from, so that the same
+/// [Downloader](crate::Downloader), [Filter] and reporters work with repositories outside of GitHub.
+///
+/// A [GithubBranchPath] names a repository on any forge, with `user` as the owner or namespace it belongs to.
+/// [GithubClient] is the forge for GitHub, and the one used unless [forge](crate::DownloadConfig::forge) is set.
+pub trait Forge: Send + Sync {
+    /// Resolves the reference of `path` to a commit and tree.
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>>;
+
+    /// Obtains the tree that the reference of `path` names, only keeping blobs that pass `filter`.
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>>;
+
+    /// Fetches the contents of `entry`, a blob in a tree obtained with [get_tree](Forge::get_tree).
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>>;
+
+    /// Resolves the reference of `path` and obtains the tree of the commit it resolved to, so the tree is
+    /// consistent even if a branch moves in between.
+    fn get_resolved<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<(ResolvedRef, SourceTree), Error>> {
+        async move {
+            let resolved = self.resolve_ref(path).await?;
+            let sha = resolved.commit_sha.unwrap_or(resolved.tree_sha).to_string();
+            let reference = match resolved.commit_sha {
+                Some(_) => GithubRef::Commit(&sha),
+                None => GithubRef::Tree(&sha),
+            };
+
+            let tree = self
+                .get_tree(&path.with_reference(reference), filter)
+                .await?;
+            Ok((resolved, tree))
+        }
+        .boxed()
+    }
+}
+
+impl Forge for GithubClient {
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        ResolvedRef::resolve(self, path).boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move { Ok(TreeModel::get_tree(self, path, filter).await?.into()) }.boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        raw::get_blob_contents(self, &entry.url, entry.size as u64).boxed()
+    }
+
+    fn get_resolved<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<(ResolvedRef, SourceTree), Error>> {
+        SourceTree::get_resolved_with_client(self, path, filter).boxed()
+    }
+}
+
+/// The URL of the GitLab REST API on gitlab.com.
+pub const GITLAB_API_URL: &str = "https://gitlab.com/api/v4";
+
+/// How many entries are requested per page of a GitLab tree.
+const GITLAB_PER_PAGE: usize = 100;
+
+#[derive(Deserialize)]
+struct GitLabCommitModel {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabTreeEntryModel {
+    id: ObjectId,
+    #[serde(rename = "type")]
+    entry_type: String,
+    path: String,
+    mode: FileMode,
+}
+
+/// A [Forge] for GitLab, using its repository tree and blob APIs.
+///
+/// Requests go through a [GithubClient] whose [api_url](GithubClient::api_url) is the GitLab API, so they're
+/// counted, observed and cached like requests to GitHub. For GitLab, `user` in a [GithubBranchPath] is the
+/// namespace of the project, such as `gitlab-org` or `group/subgroup`.
+///
+/// GitLab doesn't report the root tree of a commit or the sizes of blobs in a tree, so resolved references have
+/// an [ObjectId::NULL] tree and blobs have a size of 0 until
+/// [prefetch_sizes](crate::DownloadConfig::prefetch_sizes) fills them in.
+#[derive(Debug, Clone)]
+pub struct GitLabForge {
+    client: GithubClient,
+}
+
+impl GitLabForge {
+    /// Creates a new [GitLabForge] for gitlab.com, authenticating with the given GitLab access token if provided.
+    pub fn new(access_token: Option<&str>) -> Result<GitLabForge, Error> {
+        let options = HttpOptions {
+            auth_scheme: AuthScheme::Bearer,
+            ..HttpOptions::default()
+        };
+        let client =
+            GithubClient::with_options(access_token, &options)?.with_api_url(GITLAB_API_URL);
+        Ok(GitLabForge { client })
+    }
+
+    /// Creates a new [GitLabForge] making requests with `client`, such as one for a self-managed instance at
+    /// `https://gitlab.example.com/api/v4`. GitLab only accepts access tokens sent with [AuthScheme::Bearer].
+    pub fn with_client(client: GithubClient) -> GitLabForge {
+        GitLabForge { client }
+    }
+
+    /// Returns the client this forge makes requests with.
+    pub fn client(&self) -> &GithubClient {
+        &self.client
+    }
+
+    /// Returns the URL of the given API endpoint within the project of `path`.
+    fn project_url(&self, path: &GithubBranchPath<'_>, endpoint: &str) -> String {
+        let project = format!("{}/{}", path.user, path.repo);
+        format!(
+            "{}/projects/{}/{}",
+            self.client.api_url(),
+            encode_component(&project),
+            endpoint
+        )
+    }
+
+    /// Fetches every page of the tree of `path`, only keeping entries that pass `filter`.
+    async fn fetch_tree(
+        &self,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        let reference = path.reference.name();
+        let mut next = Some(format!(
+            "{}?ref={}&recursive=true&per_page={}&pagination=keyset",
+            self.project_url(path, "repository/tree"),
+            encode_component(&reference),
+            GITLAB_PER_PAGE
+        ));
+
+        let mut entries = Vec::new();
+        while let Some(url) = next.take() {
+            let request = self.client.http().get(&url);
+            let (status, headers, body) =
+                raw::send_with_headers(&self.client, request, "application/json").await?;
+            let page: Vec<GitLabTreeEntryModel> = serde_json::from_slice(&body)
+                .map_err(|e| raw::deserialization_error(status, &body, e))?;
+
+            for entry in page {
+                // submodules have the type `commit` and aren't part of the tree
+                let entry_type = match entry.entry_type.as_str() {
+                    "blob" => TreeEntryType::Blob,
+                    "tree" => TreeEntryType::Tree,
+                    _ => continue,
+                };
+
+                let endpoint = format!("repository/blobs/{}/raw", entry.id);
+                let model = TreeEntryModel {
+                    url: self.project_url(path, &endpoint),
+                    path: entry.path,
+                    mode: entry.mode,
+                    entry_type,
+                    size: 0,
+                    sha: entry.id,
+                };
+                if model.passes(filter) {
+                    entries.push(model);
+                }
+            }
+
+            next = headers
+                .get(reqwest::header::LINK)
+                .and_then(|v| v.to_str().ok())
+                .and_then(next_link);
+        }
+
+        Ok(TreeModel {
+            sha: ObjectId::NULL,
+            url: self.project_url(path, "repository/tree"),
+            tree: entries,
+            truncated: false,
+        })
+    }
+}
+
+impl Forge for GitLabForge {
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        async move {
+            let reference = path.reference.commit_expression()?;
+            let endpoint = format!("repository/commits/{}", encode_component(&reference));
+            let url = self.project_url(path, &endpoint);
+            let commit: GitLabCommitModel =
+                raw::get_with_accept(&self.client, &url, "application/json").await?;
+
+            Ok(ResolvedRef {
+                commit_sha: Some(commit.id.parse()?),
+                tree_sha: ObjectId::NULL,
+            })
+        }
+        .boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move {
+            let tree = self.fetch_tree(path, filter).await.map_err(|e| {
+                e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                })
+            })?;
+            Ok(tree.into())
+        }
+        .boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        async move {
+            let request = self.client.http().get(&entry.url);
+            let (_, body) = raw::send(&self.client, request, "*/*").await?;
+            Ok(body)
+        }
+        .boxed()
+    }
+}
+
+/// Returns the URL of the `next` relation in a `Link` header, if there is one.
+pub(crate) fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        let is_next = params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#);
+        match is_next {
+            true => Some(
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string(),
+            ),
+            false => None,
+        }
+    })
+}
+
+/// Percent-encodes `component` for use as a single segment or query value of a URL.
+pub(crate) fn encode_component(component: &str) -> String {
+    component
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,159 @@
+//! Computes the hashes git uses to identify objects, so local data can be compared against
+//! [SourceTree::sha](crate::SourceTree::sha) without a copy of the repository. Both the SHA1 and SHA256
+//! [object formats](ObjectFormat) are supported.
+//!
+//! ```
+//! use grab_github::git_hash;
+//!
+//! assert_eq!(git_hash::blob_sha1(b"hello\n"), "ce013625030ba8dba906f756967f9e9ca394464a");
+//! ```
+
+use std::io::{self, Read};
+
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{Error, FileMode, ObjectFormat, ObjectId, SourceTree};
+
+/// Computes the SHA1 hash git uses to identify a blob with the given contents, as a hex string.
+pub fn blob_sha1(bytes: &[u8]) -> String {
+    blob_id(bytes, ObjectFormat::Sha1).to_string()
+}
+
+/// Computes the SHA256 hash git uses to identify a blob with the given contents in a repository using the
+/// SHA256 object format, as a hex string.
+pub fn blob_sha256(bytes: &[u8]) -> String {
+    blob_id(bytes, ObjectFormat::Sha256).to_string()
+}
+
+/// Computes the hash git uses to identify a blob with the given contents, in the given [ObjectFormat].
+///
+/// To check downloaded contents against a [SourceTree], pass the [format](ObjectId::format) of its hash.
+pub fn blob_id(bytes: &[u8], format: ObjectFormat) -> ObjectId {
+    hash_object("blob", bytes, format)
+}
+
+/// Computes the SHA1 hash git uses to identify a blob like [blob_sha1], reading its `size` bytes of contents
+/// from `reader`, such as a file, without holding them in memory.
+///
+/// Fails if `reader` doesn't contain exactly `size` bytes.
+pub fn blob_sha1_reader<R: Read>(mut reader: R, size: u64) -> io::Result<String> {
+    let mut hash = Sha1::new();
+    hash.update(format!("blob {}\0", size).as_bytes());
+
+    let mut buf = [0u8; 8192];
+    let mut read = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hash.update(&buf[..n]);
+        read += n as u64;
+    }
+
+    if read != size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("expected {} bytes, but read {}", size, read),
+        ));
+    }
+
+    Ok(hex(&hash.finalize()))
+}
+
+/// A single entry of a tree, as hashed by [tree_id].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeHashEntry<'e> {
+    /// The mode of the entry.
+    pub mode: FileMode,
+    /// The name of the entry within the tree, without any directories.
+    pub name: &'e str,
+    /// The hash of the entry.
+    pub sha: ObjectId,
+}
+
+/// Computes the hash git uses to identify a tree with the given entries, in the given [ObjectFormat].
+///
+/// Entries can be in any order; they're sorted the way git sorts them, where directories are compared as if
+/// their names ended with `/`. Fails with [Error::Other] if the hash of an entry is in a different format.
+pub fn tree_id(entries: &[TreeHashEntry<'_>], format: ObjectFormat) -> Result<ObjectId, Error> {
+    let sort_key = |entry: &TreeHashEntry| {
+        let mut key = entry.name.as_bytes().to_vec();
+        if entry.mode == FileMode::Directory {
+            key.push(b'/');
+        }
+        key
+    };
+
+    let mut entries = entries.to_vec();
+    entries.sort_by_cached_key(sort_key);
+
+    let mut contents = Vec::new();
+    for entry in &entries {
+        if entry.sha.format() != format {
+            return Err(Error::Other(format!(
+                "{} is a {:?} hash, but the tree is hashed with {:?}",
+                entry.name,
+                entry.sha.format(),
+                format
+            )));
+        }
+
+        // git writes modes without leading zeros, so directories are `40000`
+        contents.extend_from_slice(entry.mode.raw().trim_start_matches('0').as_bytes());
+        contents.push(b' ');
+        contents.extend_from_slice(entry.name.as_bytes());
+        contents.push(0);
+        contents.extend_from_slice(entry.sha.as_bytes());
+    }
+
+    Ok(hash_object("tree", &contents, format))
+}
+
+/// Computes the SHA1 hash git uses to identify a tree with the given entries like [tree_id], as a hex string.
+pub fn tree_sha1(entries: &[TreeHashEntry<'_>]) -> Result<String, Error> {
+    tree_id(entries, ObjectFormat::Sha1).map(|id| id.to_string())
+}
+
+/// Computes the hash of a [SourceTree] directory from the hashes of its children, like [tree_id], in the
+/// same format as the directory's own [sha](SourceTree::sha).
+///
+/// This only matches the [sha](SourceTree::sha) GitHub reports if `tree` holds every child, so a tree
+/// fetched with a [Filter](crate::Filter) that left anything out will hash differently.
+pub fn source_tree_id(tree: &SourceTree) -> Result<ObjectId, Error> {
+    let entries: Vec<TreeHashEntry> = tree
+        .children
+        .iter()
+        .map(|child| TreeHashEntry {
+            mode: child.mode,
+            name: child.name(),
+            sha: child.sha,
+        })
+        .collect();
+
+    tree_id(&entries, tree.sha.format())
+}
+
+/// Hashes an object the way git does, with a header giving its kind and size.
+fn hash_object(kind: &str, contents: &[u8], format: ObjectFormat) -> ObjectId {
+    let header = format!("{} {}\0", kind, contents.len());
+    let digest = match format {
+        ObjectFormat::Sha1 => Sha1::new()
+            .chain_update(&header)
+            .chain_update(contents)
+            .finalize()
+            .to_vec(),
+        ObjectFormat::Sha256 => Sha256::new()
+            .chain_update(&header)
+            .chain_update(contents)
+            .finalize()
+            .to_vec(),
+    };
+
+    ObjectId::from_bytes(&digest).expect("SHA1 and SHA256 digests are 20 and 32 bytes")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
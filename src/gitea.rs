@@ -0,0 +1,213 @@
+use futures::future::{BoxFuture, FutureExt};
+use serde::Deserialize;
+
+use crate::{
+    raw::{self, TreeModel},
+    Error, ErrorContext, Filter, Forge, GithubBranchPath, GithubClient, GithubRef, HttpOptions,
+    ResolvedRef, SourceTree,
+};
+
+/// The URL of Codeberg, the largest public Gitea-compatible host.
+pub const CODEBERG_URL: &str = "https://codeberg.org";
+
+/// How many entries are requested per page of a Gitea tree.
+const GITEA_PER_PAGE: usize = 1000;
+
+#[derive(Deserialize)]
+struct GiteaBranchCommitModel {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaBranchModel {
+    commit: GiteaBranchCommitModel,
+}
+
+#[derive(Deserialize)]
+struct GiteaShaModel {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct GiteaTagModel {
+    commit: GiteaShaModel,
+}
+
+#[derive(Deserialize)]
+struct GiteaPullRequestModel {
+    head: GiteaShaModel,
+}
+
+#[derive(Deserialize)]
+struct GiteaCommitDetailsModel {
+    tree: GiteaShaModel,
+}
+
+#[derive(Deserialize)]
+struct GiteaCommitModel {
+    commit: GiteaCommitDetailsModel,
+}
+
+/// A [Forge] for Gitea and the hosts compatible with it, such as Codeberg and Forgejo instances.
+///
+/// Gitea serves trees and blobs the way GitHub does, so trees have the hashes and sizes of their entries and
+/// downloaded files are verified. Requests go through a [GithubClient] whose [api_url](GithubClient::api_url)
+/// is the Gitea API, so they're counted and observed like requests to GitHub.
+#[derive(Debug, Clone)]
+pub struct GiteaHost {
+    client: GithubClient,
+}
+
+impl GiteaHost {
+    /// Creates a new [GiteaHost] for the instance at `base_url`, such as [CODEBERG_URL], authenticating with
+    /// the given access token if provided.
+    pub fn new(base_url: &str, access_token: Option<&str>) -> Result<GiteaHost, Error> {
+        GiteaHost::with_options(base_url, access_token, &HttpOptions::default())
+    }
+
+    /// Creates a new [GiteaHost] like [new](GiteaHost::new), sending requests with the given [HttpOptions].
+    ///
+    /// Gitea accepts access tokens with either [AuthScheme](crate::AuthScheme), and the default sends them with
+    /// [AuthScheme::Token](crate::AuthScheme::Token). Instances behind a proxy that expects the token in another
+    /// header can have it sent in [extra_headers](HttpOptions::extra_headers) instead.
+    pub fn with_options(
+        base_url: &str,
+        access_token: Option<&str>,
+        options: &HttpOptions<'_>,
+    ) -> Result<GiteaHost, Error> {
+        let api_url = format!("{}/api/v1", base_url.trim_end_matches('/'));
+        let client = GithubClient::with_options(access_token, options)?.with_api_url(&api_url);
+        Ok(GiteaHost { client })
+    }
+
+    /// Creates a new [GiteaHost] making requests with `client`, whose API URL must end with `/api/v1`.
+    pub fn with_client(client: GithubClient) -> GiteaHost {
+        GiteaHost { client }
+    }
+
+    /// Returns the client this host makes requests with.
+    pub fn client(&self) -> &GithubClient {
+        &self.client
+    }
+
+    /// Makes a GET request to the given API endpoint within the repository of `path`.
+    async fn get<T>(&self, path: &GithubBranchPath<'_>, endpoint: &str) -> Result<T, Error>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let url = path.to_api_url(self.client.api_url(), endpoint);
+        raw::get_with_accept(&self.client, &url, "application/json").await
+    }
+
+    /// Fetches every page of the tree of `path`, only keeping entries that pass `filter`.
+    async fn fetch_tree(
+        &self,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        let mut entries = Vec::new();
+        let mut page = 1;
+
+        // Gitea pages recursive trees, marking every page but the last as truncated
+        loop {
+            let endpoint = format!(
+                "git/trees/{}?recursive=true&page={}&per_page={}",
+                path.reference.name(),
+                page,
+                GITEA_PER_PAGE
+            );
+            let model: TreeModel = self.get(path, &endpoint).await?;
+            let done = !model.truncated || model.tree.is_empty();
+            entries.extend(model.tree.into_iter().filter(|entry| entry.passes(filter)));
+
+            if done {
+                return Ok(TreeModel {
+                    sha: model.sha,
+                    url: model.url,
+                    tree: entries,
+                    truncated: false,
+                });
+            }
+            page += 1;
+        }
+    }
+}
+
+impl Forge for GiteaHost {
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        async move {
+            let commit_sha = match path.reference {
+                GithubRef::Tree(sha) => {
+                    return Ok(ResolvedRef {
+                        commit_sha: None,
+                        tree_sha: sha.parse()?,
+                    })
+                }
+                GithubRef::Commit(sha) => sha.to_string(),
+                GithubRef::Branch(name) => {
+                    let branch: GiteaBranchModel =
+                        self.get(path, &format!("branches/{}", name)).await?;
+                    branch.commit.id
+                }
+                GithubRef::Tag(name) => {
+                    let tag: GiteaTagModel = self.get(path, &format!("tags/{}", name)).await?;
+                    tag.commit.sha
+                }
+                GithubRef::PullRequest(number) => {
+                    let pull: GiteaPullRequestModel =
+                        self.get(path, &format!("pulls/{}", number)).await?;
+                    pull.head.sha
+                }
+            };
+
+            let commit: GiteaCommitModel = self
+                .get(path, &format!("git/commits/{}", commit_sha))
+                .await?;
+            Ok(ResolvedRef {
+                commit_sha: Some(commit_sha.parse()?),
+                tree_sha: commit.commit.tree.sha.parse()?,
+            })
+        }
+        .boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move {
+            let tree = self.fetch_tree(path, filter).await.map_err(|e| {
+                e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                })
+            })?;
+            Ok(tree.into())
+        }
+        .boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        async move { raw::get_blob_raw(&self.client, &entry.url).await?.decode() }.boxed()
+    }
+
+    fn get_resolved<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<(ResolvedRef, SourceTree), Error>> {
+        // trees are fetched by their hash, like from GitHub
+        async move {
+            let resolved = self.resolve_ref(path).await?;
+            let tree_sha = resolved.tree_sha.to_string();
+            let tree_path = path.with_reference(GithubRef::Tree(&tree_sha));
+            let tree = self.get_tree(&tree_path, filter).await?;
+            Ok((resolved, tree))
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,285 @@
+use std::{num::NonZeroU32, path::PathBuf, sync::atomic::AtomicBool};
+
+use futures::future::{BoxFuture, FutureExt};
+use gix::{
+    bstr::ByteSlice, objs::tree::EntryKind, protocol::handshake::Ref, remote::fetch::Shallow,
+    remote::Direction,
+};
+
+use crate::{
+    fetch_strategy::CloneTarget,
+    raw::{TreeEntryModel, TreeModel},
+    DownloadConfig, DownloadReporter, Error, FileMode, Filter, Forge, GithubBranchPath,
+    GithubClient, GithubRef, ObjectId, ResolvedRef, SourceTree, TreeEntryType,
+};
+
+/// A bare clone of a single commit made with gitoxide for [FetchStrategy::Clone](crate::FetchStrategy::Clone),
+/// served as a [Forge] so its files go through the usual pipeline. The clone is deleted when this is dropped.
+///
+/// Only the commit the reference points to is fetched, with a depth of 1. The whole commit is fetched, since
+/// GitHub's partial clones aren't supported by gitoxide yet, but trees that can't hold any file passing the
+/// filter are never read, so only the paths the filter selects are ever materialized.
+pub(crate) struct GixClone {
+    dir: PathBuf,
+    repo: gix::ThreadSafeRepository,
+}
+
+impl GixClone {
+    /// Clones the commit the reference of `path` points to into a temporary directory.
+    pub(crate) async fn fetch<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<GixClone, Error> {
+        let target = CloneTarget::new(config, client, path)?;
+
+        // gitoxide's network client blocks, so it's kept off of the runtime's threads
+        let dir = target.dir.clone();
+        let repo = tokio::task::spawn_blocking(move || GixClone::clone_blocking(&target))
+            .await
+            .map_err(|e| Error::Other(format!("cloning panicked: {}", e)))?;
+        match repo {
+            Ok(repo) => Ok(GixClone { dir, repo }),
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the full name of the reference the `HEAD` of the repository of `target` points to, such as
+    /// `refs/heads/main`. Only the references of the repository are listed, using a temporary repository in the
+    /// directory of `target` that's deleted afterwards.
+    pub(crate) async fn remote_head(target: CloneTarget) -> Result<String, Error> {
+        let dir = target.dir.clone();
+        let head = tokio::task::spawn_blocking(move || GixClone::remote_head_blocking(&target))
+            .await
+            .map_err(|e| Error::Other(format!("listing references panicked: {}", e)));
+        let _ = std::fs::remove_dir_all(&dir);
+        head?
+    }
+
+    fn remote_head_blocking(target: &CloneTarget) -> Result<String, Error> {
+        let repo = GixClone::init(target)?.to_thread_local();
+        let ref_map = repo
+            .remote_at(target.url.as_str())
+            .map_err(gix_error)?
+            .with_refspecs([target.refspec.as_str()], Direction::Fetch)
+            .map_err(gix_error)?
+            .connect(Direction::Fetch)
+            .map_err(gix_error)?
+            .ref_map(gix::progress::Discard, Default::default())
+            .map_err(gix_error)?;
+
+        ref_map
+            .remote_refs
+            .iter()
+            .find_map(|remote_ref| match remote_ref {
+                Ref::Symbolic {
+                    full_ref_name,
+                    target,
+                    ..
+                }
+                | Ref::Unborn {
+                    full_ref_name,
+                    target,
+                } if full_ref_name == "HEAD" => Some(target.to_str_lossy().into_owned()),
+                _ => None,
+            })
+            .ok_or_else(|| Error::Other(format!("{} has no HEAD", target.url)))
+    }
+
+    /// Initializes a bare repository in the directory of `target`, which sends its access token.
+    fn init(target: &CloneTarget) -> Result<gix::ThreadSafeRepository, Error> {
+        let overrides = target
+            .auth_header
+            .iter()
+            .map(|header| format!("http.extraHeader={}", header));
+        let options = gix::open::Options::isolated().config_overrides(overrides);
+        gix::ThreadSafeRepository::init_opts(
+            &target.dir,
+            gix::create::Kind::Bare,
+            gix::create::Options::default(),
+            options,
+        )
+        .map_err(gix_error)
+    }
+
+    /// Initializes a bare repository in the directory of `target` and fetches its refspec into it.
+    fn clone_blocking(target: &CloneTarget) -> Result<gix::ThreadSafeRepository, Error> {
+        let repo = GixClone::init(target)?;
+        let interrupt = AtomicBool::new(false);
+        let local = repo.to_thread_local();
+        local
+            .remote_at(target.url.as_str())
+            .map_err(gix_error)?
+            .with_refspecs([target.refspec.as_str()], Direction::Fetch)
+            .map_err(gix_error)?
+            .connect(Direction::Fetch)
+            .map_err(gix_error)?
+            .prepare_fetch(gix::progress::Discard, Default::default())
+            .map_err(gix_error)?
+            .with_shallow(Shallow::DepthAtRemote(NonZeroU32::MIN))
+            .receive(gix::progress::Discard, &interrupt)
+            .map_err(gix_error)?;
+
+        Ok(repo)
+    }
+
+    /// Resolves `reference` to a commit and tree among the objects that were fetched.
+    fn resolve(&self, reference: GithubRef<'_>) -> Result<ResolvedRef, Error> {
+        let repo = self.repo.to_thread_local();
+        let spec = match reference {
+            GithubRef::Tree(sha) => {
+                return Err(Error::Other(format!(
+                    "tree {} isn't a commit and can't be cloned",
+                    sha
+                )))
+            }
+            GithubRef::Branch(name) => format!("refs/heads/{}", name),
+            GithubRef::Tag(name) => format!("refs/tags/{}", name),
+            GithubRef::Commit(sha) => sha.to_string(),
+            GithubRef::PullRequest(number) => format!("refs/pull/{}/head", number),
+        };
+
+        let commit = repo
+            .rev_parse_single(spec.as_str())
+            .map_err(gix_error)?
+            .object()
+            .map_err(gix_error)?
+            .peel_to_kind(gix::object::Kind::Commit)
+            .map_err(gix_error)?
+            .into_commit();
+        Ok(ResolvedRef {
+            commit_sha: Some(commit.id.to_string().parse()?),
+            tree_sha: commit.tree_id().map_err(gix_error)?.to_string().parse()?,
+        })
+    }
+
+    /// Lists the entries of the tree with the given hash that pass `filter`, below `prefix`.
+    fn list_tree(
+        repo: &gix::Repository,
+        tree_id: gix::ObjectId,
+        prefix: &str,
+        filter: &Filter<'_>,
+        entries: &mut Vec<TreeEntryModel>,
+    ) -> Result<(), Error> {
+        let tree = repo
+            .find_object(tree_id)
+            .map_err(gix_error)?
+            .try_into_tree()
+            .map_err(gix_error)?;
+
+        for entry in tree.iter() {
+            let entry = entry.map_err(gix_error)?;
+            let (entry_type, mode) = match entry.mode().kind() {
+                EntryKind::Tree => (TreeEntryType::Tree, FileMode::Directory),
+                EntryKind::Blob => (TreeEntryType::Blob, FileMode::Regular),
+                EntryKind::BlobExecutable => (TreeEntryType::Blob, FileMode::Executable),
+                EntryKind::Link => (TreeEntryType::Blob, FileMode::Symlink),
+                // submodules are commits in another repository
+                EntryKind::Commit => continue,
+            };
+
+            let path = format!("{}{}", prefix, entry.filename().to_str_lossy());
+            let id = entry.oid().to_owned();
+            let size = match entry_type {
+                TreeEntryType::Blob => repo.find_header(id).map_err(gix_error)?.size(),
+                TreeEntryType::Tree => 0,
+            };
+            let model = TreeEntryModel {
+                path,
+                mode,
+                entry_type,
+                size: u32::try_from(size).unwrap_or(u32::MAX),
+                sha: id.to_string().parse()?,
+                url: id.to_string(),
+            };
+            if !model.passes(filter) {
+                continue;
+            }
+
+            if model.entry_type == TreeEntryType::Tree {
+                let prefix = format!("{}/", model.path);
+                entries.push(model);
+                GixClone::list_tree(repo, id, &prefix, filter, entries)?;
+            } else {
+                entries.push(model);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the contents of the blob with the given hash.
+    fn read_blob(repo: &gix::ThreadSafeRepository, sha: &ObjectId) -> Result<Vec<u8>, Error> {
+        let repo = repo.to_thread_local();
+        let id = gix::ObjectId::from_hex(sha.to_string().as_bytes()).map_err(gix_error)?;
+        let blob = repo.find_object(id).map_err(gix_error)?.detach();
+        Ok(blob.data)
+    }
+}
+
+impl Drop for GixClone {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+impl Forge for GixClone {
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        async move { self.resolve(path.reference) }.boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move {
+            let tree_sha = self.resolve(path.reference)?.tree_sha;
+            let tree_id =
+                gix::ObjectId::from_hex(tree_sha.to_string().as_bytes()).map_err(gix_error)?;
+
+            // walking the tree reads the header of every blob that passes the filter, so it's kept off of
+            // the runtime's threads too
+            let repo = self.repo.clone();
+            let filter = filter.to_owned_filter();
+            let entries = tokio::task::spawn_blocking(move || {
+                let mut entries = Vec::new();
+                let repo = repo.to_thread_local();
+                GixClone::list_tree(&repo, tree_id, "", &filter, &mut entries)?;
+                Ok::<_, Error>(entries)
+            })
+            .await
+            .map_err(|e| Error::Other(format!("listing the tree panicked: {}", e)))??;
+            let tree = TreeModel {
+                sha: tree_sha,
+                url: String::new(),
+                tree: entries,
+                truncated: false,
+            };
+            Ok(tree.into())
+        }
+        .boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        let repo = self.repo.clone();
+        let sha = entry.sha;
+        async move {
+            tokio::task::spawn_blocking(move || GixClone::read_blob(&repo, &sha))
+                .await
+                .map_err(|e| Error::Other(format!("reading a blob panicked: {}", e)))?
+        }
+        .boxed()
+    }
+}
+
+/// Converts an error from gitoxide, which has a type for each operation, into an [Error].
+fn gix_error(error: impl std::error::Error) -> Error {
+    Error::Other(format!("git: {}", error))
+}
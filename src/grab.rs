@@ -0,0 +1,57 @@
+use std::path::Path;
+
+use crate::{
+    raw, DownloadConfigNoReporting, DownloadReport, Downloader, Error, Filter, GithubBranchPath,
+    GithubClient, GithubRef, TokenSource,
+};
+
+/// Downloads every file of a GitHub repository into `dest` with the default settings.
+///
+/// `spec` is `user/repo`, optionally followed by `@` and a reference as understood by [GithubRef::parse],
+/// such as `azrogers/grab_github@main`. Without a reference, the repository's default branch is used.
+/// The access token is read from the environment variable `GITHUB_ACCESS_TOKEN` if available.
+///
+/// For anything more involved, use [Downloader] with a [DownloadConfig](crate::DownloadConfig).
+pub async fn grab(spec: &str, dest: impl AsRef<Path>) -> Result<DownloadReport, Error> {
+    let (repo, reference) = match spec.split_once('@') {
+        Some((repo, reference)) => (repo, Some(reference)),
+        None => (spec, None),
+    };
+
+    let (user, repo) = split_repo(repo)
+        .map_err(|_| Error::Other(format!("{} is not of the form user/repo[@ref]", spec)))?;
+
+    let mut config =
+        DownloadConfigNoReporting::new(dest.as_ref()).with_token_source(TokenSource::Env);
+    let client = config.client()?;
+    config.client = Some(client.clone());
+
+    let branch;
+    let reference = match reference {
+        Some(reference) => GithubRef::parse(reference),
+        None => {
+            branch = default_branch(&client, user, repo).await?;
+            GithubRef::Branch(&branch)
+        }
+    };
+
+    let path = GithubBranchPath::with_ref(user, repo, reference);
+    Downloader::download_resolved(&config, &path, &Filter::all()).await
+}
+
+/// Splits `user/repo` into its user and repository.
+pub(crate) fn split_repo(repo: &str) -> Result<(&str, &str), Error> {
+    repo.split_once('/')
+        .filter(|(user, repo)| !user.is_empty() && !repo.is_empty() && !repo.contains('/'))
+        .ok_or_else(|| Error::Other(format!("{} is not of the form user/repo", repo)))
+}
+
+/// Looks up the name of the default branch of `user/repo`.
+pub(crate) async fn default_branch(
+    client: &GithubClient,
+    user: &str,
+    repo: &str,
+) -> Result<String, Error> {
+    let path = GithubBranchPath::new(user, repo, "");
+    Ok(raw::get_repository_raw(client, &path).await?.default_branch)
+}
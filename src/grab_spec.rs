@@ -0,0 +1,362 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grab::{default_branch, split_repo},
+    CachePolicy, ClientPool, DownloadConfig, DownloadConfigNoReporting, DownloadReport,
+    DownloadReporter, Downloader, Error, Filter, GithubBranchPath, GithubClient, GithubRef,
+    OutputLayout, TokenSource, Transform, TreeCache,
+};
+
+/// A declarative description of a single grab, read from a TOML file that can be checked into a repository
+/// and run again with [run_spec](Downloader::run_spec), like a tiny vendoring manifest.
+///
+/// ```toml
+/// repo = "azrogers/grab_github"
+/// ref = "v0.1.0"
+/// include = ["src/**", "LICENSE"]
+/// exclude = ["**/*.snap"]
+/// output = "vendor/grab_github"
+/// strip_prefix = "src"
+/// layout = "paths"
+/// auth = { env_var = "VENDOR_TOKEN" }
+///
+/// [[rename]]
+/// from = "LICENSE"
+/// to = "LICENSE.grab_github"
+///
+/// [[transform]]
+/// type = "line_endings"
+/// to = "lf"
+/// ```
+///
+/// Access tokens can't be written in the file itself, only the environment variable to read one from.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GrabSpec {
+    /// The repository to grab, as `user/repo`.
+    pub repo: String,
+    /// The reference to grab, as understood by [GithubRef::parse].
+    /// The default is the repository's default branch.
+    #[serde(default, rename = "ref")]
+    pub reference: Option<String>,
+    /// Globs of the files to include, as in [Filter::new]. The default is every file.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Globs of the files to exclude, as in [Filter::new].
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// The directory to download into. A relative path is relative to the directory the spec was read from.
+    pub output: PathBuf,
+    /// How files are arranged in the output directory.
+    /// The default is [OutputLayout::Paths].
+    #[serde(default)]
+    pub layout: OutputLayout,
+    /// A directory whose contents are written to the root of the output directory instead of inside it.
+    #[serde(default)]
+    pub strip_prefix: Option<String>,
+    /// [Rename rules](DownloadConfig::rename_rules). The first rule matching a file is used before `strip_prefix`.
+    #[serde(default)]
+    pub rename: Vec<RenameRule>,
+    /// [Transforms](Transform) applied to the contents of each file in order, before it's written.
+    #[serde(default, rename = "transform")]
+    pub transforms: Vec<Transform>,
+    /// Where the access token comes from.
+    /// The default is [SpecAuth::Env].
+    #[serde(default)]
+    pub auth: SpecAuth,
+    /// The URL of the GitHub REST API, such as for GitHub Enterprise Server.
+    /// The default is [DEFAULT_API_URL](crate::DEFAULT_API_URL).
+    #[serde(default)]
+    pub api_url: Option<String>,
+    /// The directory relative paths are resolved against, which is where the spec was read from.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+/// A workspace of many [GrabSpec]s, such as different repositories vendored into different directories, read
+/// from a TOML file and run together with [run_workspace](Downloader::run_workspace).
+///
+/// ```toml
+/// max_requests = 500
+/// cache_dir = ".grab-cache"
+///
+/// [[grab]]
+/// repo = "azrogers/grab_github"
+/// output = "vendor/grab_github"
+///
+/// [[grab]]
+/// repo = "githubtraining/hellogitworld"
+/// include = ["src/**"]
+/// output = "vendor/hellogitworld"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GrabWorkspace {
+    /// Each grab, in the order they're run.
+    #[serde(default, rename = "grab")]
+    pub grabs: Vec<GrabSpec>,
+    /// The most requests made across every grab. Once they've been made, no more grabs are started, though
+    /// the grab that used them up is finished.
+    /// The default is no limit.
+    #[serde(default)]
+    pub max_requests: Option<u64>,
+    /// A directory to store fetched trees and blobs in, so they're reused across runs. A relative path is
+    /// relative to the directory the workspace was read from.
+    /// The default is to only share them between the grabs of a single run.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// The directory relative paths are resolved against, which is where the workspace was read from.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl GrabWorkspace {
+    /// Reads a workspace from the TOML file at `path`. Relative paths in it, including those of its grabs, are
+    /// resolved against the file's directory.
+    pub fn from_toml(path: impl AsRef<Path>) -> Result<GrabWorkspace, Error> {
+        let path = path.as_ref();
+        let mut workspace = GrabWorkspace::parse(&std::fs::read_to_string(path)?)?;
+        workspace.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for spec in &mut workspace.grabs {
+            spec.base_dir = workspace.base_dir.clone();
+        }
+        Ok(workspace)
+    }
+
+    /// Parses a workspace from TOML. Relative paths in it are resolved against the current directory.
+    pub fn parse(text: &str) -> Result<GrabWorkspace, Error> {
+        let workspace: GrabWorkspace = toml::from_str(text)
+            .map_err(|e| Error::Other(format!("invalid grab workspace: {}", e)))?;
+        for spec in &workspace.grabs {
+            split_repo(&spec.repo)?;
+        }
+        Ok(workspace)
+    }
+
+    /// Returns the policy of the [TreeCache] shared by the grabs of this workspace, which also caches blobs.
+    pub fn cache_policy(&self) -> CachePolicy {
+        CachePolicy {
+            cache_blobs: true,
+            disk_path: self.cache_dir.as_ref().map(|dir| self.base_dir.join(dir)),
+            ..CachePolicy::default()
+        }
+    }
+}
+
+/// The result of running a [GrabWorkspace] with [run_workspace](Downloader::run_workspace).
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceReport {
+    /// The result of each grab, in the order they were run.
+    pub grabs: Vec<WorkspaceGrab>,
+    /// The number of requests made across every grab.
+    pub requests_made: u64,
+}
+
+/// The result of a single grab in a [WorkspaceReport].
+#[derive(Debug, Clone)]
+pub struct WorkspaceGrab {
+    /// The repository that was grabbed, as `user/repo`.
+    pub repo: String,
+    /// The directory it was downloaded into.
+    pub output_path: PathBuf,
+    /// The report of the download, or why it failed.
+    pub result: Result<DownloadReport, Error>,
+}
+
+impl WorkspaceReport {
+    /// Returns true if every grab succeeded.
+    pub fn is_success(&self) -> bool {
+        self.grabs.iter().all(|grab| grab.result.is_ok())
+    }
+
+    /// Returns the grabs that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &WorkspaceGrab> {
+        self.grabs.iter().filter(|grab| grab.result.is_err())
+    }
+
+    /// The total number of bytes written across every grab that succeeded.
+    pub fn bytes_written(&self) -> u64 {
+        self.grabs
+            .iter()
+            .filter_map(|grab| grab.result.as_ref().ok())
+            .map(DownloadReport::bytes_written)
+            .sum()
+    }
+}
+
+/// A [rename rule](DownloadConfig::rename_rules) in a [GrabSpec].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RenameRule {
+    /// The glob matching the paths to rename.
+    pub from: String,
+    /// The replacement path.
+    pub to: String,
+}
+
+/// Where the access token of a [GrabSpec] comes from.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SpecAuth {
+    /// No access token, so requests are unauthenticated.
+    None,
+    /// [TokenSource::Env].
+    #[default]
+    Env,
+    /// [TokenSource::EnvVar] with the given name.
+    EnvVar(String),
+}
+
+impl GrabSpec {
+    /// Reads a spec from the TOML file at `path`. Relative paths in it are resolved against the file's directory.
+    pub fn from_toml(path: impl AsRef<Path>) -> Result<GrabSpec, Error> {
+        let path = path.as_ref();
+        let mut spec = GrabSpec::parse(&std::fs::read_to_string(path)?)?;
+        spec.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(spec)
+    }
+
+    /// Parses a spec from TOML. Relative paths in it are resolved against the current directory.
+    pub fn parse(text: &str) -> Result<GrabSpec, Error> {
+        let spec: GrabSpec =
+            toml::from_str(text).map_err(|e| Error::Other(format!("invalid grab spec: {}", e)))?;
+        split_repo(&spec.repo)?;
+        Ok(spec)
+    }
+
+    /// Returns the directory the spec downloads into.
+    pub fn output_path(&self) -> PathBuf {
+        self.base_dir.join(&self.output)
+    }
+
+    /// Creates a [Filter] from the include and exclude globs of this spec.
+    pub fn filter(&self) -> Result<Filter<'_>, Error> {
+        Filter::try_new(
+            self.include.iter().map(String::as_str),
+            self.exclude.iter().map(String::as_str),
+        )
+    }
+
+    /// Returns `config` with the layout, rename rules, transforms, access token and API URL of this spec.
+    /// Rename rules already in `config` are applied after the spec's own, and the spec's transforms replace any
+    /// [transform](DownloadConfig::transform) already in `config`.
+    pub fn configure<'s, Reporter: DownloadReporter>(
+        &'s self,
+        config: DownloadConfig<'s, Reporter>,
+    ) -> DownloadConfig<'s, Reporter> {
+        let source = match &self.auth {
+            SpecAuth::None => TokenSource::None,
+            SpecAuth::Env => TokenSource::Env,
+            SpecAuth::EnvVar(name) => TokenSource::EnvVar(Cow::from(name.as_str())),
+        };
+        let mut config = config.with_token_source(source);
+
+        let strip = self.strip_prefix.as_deref().map(|prefix| {
+            let prefix = prefix.trim_matches('/');
+            (format!("{}/**", prefix).into(), "**".into())
+        });
+        let renames = self
+            .rename
+            .iter()
+            .map(|rule| (rule.from.as_str().into(), rule.to.as_str().into()));
+        config.rename_rules.splice(0..0, renames.chain(strip));
+
+        if !self.transforms.is_empty() {
+            config.transform = Some(&self.transforms);
+        }
+
+        config.layout = self.layout;
+        if let Some(api_url) = &self.api_url {
+            config.api_url = Cow::from(api_url.as_str());
+        }
+        config
+    }
+}
+
+impl Downloader {
+    /// Downloads the files described by `spec` into its [output_path](GrabSpec::output_path).
+    ///
+    /// To report progress or change anything else about the download, build a config with
+    /// [configure](GrabSpec::configure) and pass it to [download_resolved](Downloader::download_resolved).
+    pub async fn run_spec(spec: &GrabSpec) -> Result<DownloadReport, Error> {
+        let client = spec
+            .configure(DownloadConfigNoReporting::new(&spec.output_path()))
+            .client()?;
+        Downloader::run_spec_with_client(spec, &client).await
+    }
+
+    /// Downloads the files described by `spec` like [run_spec](Downloader::run_spec), making requests with
+    /// `client`. The [auth](GrabSpec::auth) and [api_url](GrabSpec::api_url) of the spec are ignored.
+    pub async fn run_spec_with_client(
+        spec: &GrabSpec,
+        client: &GithubClient,
+    ) -> Result<DownloadReport, Error> {
+        let output_path = spec.output_path();
+        let mut config = spec.configure(DownloadConfigNoReporting::new(&output_path));
+        config.client = Some(client.clone());
+        let filter = spec.filter()?;
+
+        let (user, repo) = split_repo(&spec.repo)?;
+        let branch;
+        let reference = match &spec.reference {
+            Some(reference) => GithubRef::parse(reference),
+            None => {
+                branch = default_branch(client, user, repo).await?;
+                GithubRef::Branch(&branch)
+            }
+        };
+
+        let path = GithubBranchPath::with_ref(user, repo, reference);
+        Downloader::download_resolved(&config, &path, &filter).await
+    }
+
+    /// Runs every grab of `workspace` in order, sharing clients and a [TreeCache] between them.
+    ///
+    /// Grabs that use the same API URL and access token share a client, and so a connection pool and a
+    /// [RequestBudget](crate::RequestBudget). The cache keeps what each API URL and access token fetched apart,
+    /// so grabs only reuse trees and blobs fetched with the same credentials. A grab that fails doesn't stop the
+    /// ones after it, and once [max_requests](GrabWorkspace::max_requests) have been made, the remaining grabs
+    /// fail without making any.
+    pub async fn run_workspace(workspace: &GrabWorkspace) -> WorkspaceReport {
+        let pool = Arc::new(ClientPool::new());
+        let cache = Arc::new(TreeCache::new(workspace.cache_policy()));
+
+        let mut report = WorkspaceReport::default();
+        for spec in &workspace.grabs {
+            let result = match workspace.max_requests {
+                Some(limit) if report.requests_made >= limit => Err(Error::Other(format!(
+                    "the workspace's budget of {} requests has been used up",
+                    limit
+                ))),
+                _ => {
+                    let output_path = spec.output_path();
+                    let mut config = spec.configure(DownloadConfigNoReporting::new(&output_path));
+                    config.client_pool = Some(pool.clone());
+                    match config.client() {
+                        Ok(client) => {
+                            let client = client.with_cache(cache.clone());
+                            let before = client.budget().requests_made();
+                            let result = Downloader::run_spec_with_client(spec, &client).await;
+                            report.requests_made += client.budget().requests_made() - before;
+                            result
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+
+            report.grabs.push(WorkspaceGrab {
+                repo: spec.repo.clone(),
+                output_path: spec.output_path(),
+                result,
+            });
+        }
+
+        report
+    }
+}
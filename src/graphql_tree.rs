@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    raw::{self, TreeEntryModel, TreeModel},
+    Error, FileMode, Filter, GithubBranchPath, GithubClient, ObjectId, TreeEntryType,
+};
+
+/// How many trees are requested in a single GraphQL query.
+const TREES_PER_QUERY: usize = 20;
+
+/// How many levels of each tree are requested in a single GraphQL query. Directories deeper than this are
+/// requested in a later query.
+const LEVELS_PER_QUERY: usize = 4;
+
+#[derive(Deserialize)]
+struct GraphQlObjectModel {
+    // set for trees
+    entries: Option<Vec<GraphQlTreeEntryModel>>,
+    // set for blobs
+    #[serde(rename = "byteSize")]
+    byte_size: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlTreeEntryModel {
+    name: String,
+    mode: u32,
+    #[serde(rename = "type")]
+    entry_type: String,
+    oid: ObjectId,
+    object: Option<GraphQlObjectModel>,
+}
+
+#[derive(Deserialize)]
+struct TreesQueryModel {
+    // one tree per requested object, keyed by the alias it was requested under
+    repository: Option<HashMap<String, Option<GraphQlObjectModel>>>,
+}
+
+/// A directory whose entries still have to be requested.
+struct PendingTree {
+    path: String,
+    oid: ObjectId,
+}
+
+/// Returns the selection of the entries of a tree, nested `depth` levels deep.
+fn entries_selection(depth: usize) -> String {
+    let nested = match depth > 1 {
+        true => format!("... on Tree {{ {} }}", entries_selection(depth - 1)),
+        false => String::new(),
+    };
+
+    format!(
+        "entries {{ name mode type oid object {{ ... on Blob {{ byteSize }} {} }} }}",
+        nested
+    )
+}
+
+/// Obtains the tree that the reference of `path` names through the GitHub GraphQL API, only keeping entries
+/// that pass `filter`.
+///
+/// Trees are requested by their hash in batches, each nested several levels deep, so deep repositories take
+/// few round trips and nothing is ever truncated.
+pub(crate) async fn fetch_tree_graphql(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    filter: &Filter<'_>,
+) -> Result<TreeModel, Error> {
+    let root_sha: ObjectId = path.reference.name().parse()?;
+    let mut pending = vec![PendingTree {
+        path: String::new(),
+        oid: root_sha,
+    }];
+    let mut entries = Vec::new();
+
+    let selection = entries_selection(LEVELS_PER_QUERY);
+    while !pending.is_empty() {
+        let batch: Vec<PendingTree> = pending
+            .drain(..pending.len().min(TREES_PER_QUERY))
+            .collect();
+
+        let trees = (0..batch.len())
+            .map(|i| format!("t{i}: object(oid: $o{i}) {{ ... on Tree {{ {selection} }} }}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let parameters: String = (0..batch.len())
+            .map(|i| format!(", $o{}: GitObjectID!", i))
+            .collect();
+        let query = format!(
+            "query($owner: String!, $name: String!{}) {{ \
+                repository(owner: $owner, name: $name) {{ {} }} \
+            }}",
+            parameters, trees
+        );
+
+        let mut variables = json!({
+            "owner": path.user,
+            "name": path.repo,
+        });
+        for (i, tree) in batch.iter().enumerate() {
+            variables[format!("o{}", i)] = json!(tree.oid);
+        }
+
+        let result: TreesQueryModel = raw::post_graphql(client, &query, variables).await?;
+        let mut objects = result
+            .repository
+            .ok_or_else(|| Error::Other(format!("{}/{} wasn't found", path.user, path.repo)))?;
+
+        for (i, tree) in batch.into_iter().enumerate() {
+            let tree_entries = objects
+                .remove(&format!("t{}", i))
+                .flatten()
+                .and_then(|o| o.entries)
+                .ok_or_else(|| {
+                    Error::Other(format!(
+                        "{} isn't a tree in {}/{}",
+                        tree.oid, path.user, path.repo
+                    ))
+                })?;
+
+            collect_entries(
+                client,
+                path,
+                &tree.path,
+                tree_entries,
+                filter,
+                &mut entries,
+                &mut pending,
+            )?;
+        }
+    }
+
+    Ok(TreeModel {
+        sha: root_sha,
+        url: path.to_tree_url(client.api_url()),
+        tree: entries,
+        truncated: false,
+    })
+}
+
+/// Turns the entries of the tree at `dir` into [TreeEntryModel]s in `entries`, descending into the
+/// subtrees that came with them and queueing the rest in `pending`.
+fn collect_entries(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    dir: &str,
+    tree_entries: Vec<GraphQlTreeEntryModel>,
+    filter: &Filter<'_>,
+    entries: &mut Vec<TreeEntryModel>,
+    pending: &mut Vec<PendingTree>,
+) -> Result<(), Error> {
+    for entry in tree_entries {
+        // submodules have the type `commit` and aren't part of the tree
+        let entry_type = match entry.entry_type.as_str() {
+            "blob" => TreeEntryType::Blob,
+            "tree" => TreeEntryType::Tree,
+            _ => continue,
+        };
+
+        let entry_path = match dir.is_empty() {
+            true => entry.name,
+            false => format!("{}/{}", dir, entry.name),
+        };
+        let (size, children) = match entry.object {
+            Some(object) => (object.byte_size.unwrap_or(0), object.entries),
+            None => (0, None),
+        };
+        let endpoint = match entry_type {
+            TreeEntryType::Blob => format!("git/blobs/{}", entry.oid),
+            TreeEntryType::Tree => format!("git/trees/{}", entry.oid),
+        };
+
+        let model = TreeEntryModel {
+            path: entry_path,
+            mode: format!("{:o}", entry.mode).parse::<FileMode>()?,
+            entry_type,
+            size,
+            sha: entry.oid,
+            url: path.to_api_url(client.api_url(), &endpoint),
+        };
+        if !model.passes(filter) {
+            continue;
+        }
+
+        if model.entry_type == TreeEntryType::Tree {
+            match children {
+                Some(children) => collect_entries(
+                    client,
+                    path,
+                    &model.path,
+                    children,
+                    filter,
+                    entries,
+                    pending,
+                )?,
+                None => pending.push(PendingTree {
+                    path: model.path.clone(),
+                    oid: model.sha,
+                }),
+            }
+        }
+
+        entries.push(model);
+    }
+
+    Ok(())
+}
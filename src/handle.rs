@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DownloadState {
+    Running,
+    Paused,
+    Aborted,
+}
+
+/// Controls a download operation while it's running.
+///
+/// Give a handle to [DownloadConfig::handle](crate::DownloadConfig::handle) and keep a clone of it to pause,
+/// resume or abort the download from elsewhere, such as a UI thread.
+#[derive(Debug, Clone)]
+pub struct DownloadHandle {
+    state: Arc<watch::Sender<DownloadState>>,
+}
+
+impl Default for DownloadHandle {
+    fn default() -> Self {
+        DownloadHandle::new()
+    }
+}
+
+impl DownloadHandle {
+    /// Creates a new handle for a running download.
+    pub fn new() -> DownloadHandle {
+        DownloadHandle {
+            state: Arc::new(watch::Sender::new(DownloadState::Running)),
+        }
+    }
+
+    /// Stops new files from being downloaded until [resume](DownloadHandle::resume) is called.
+    /// Files that are already downloading will finish.
+    pub fn pause(&self) {
+        self.state.send_if_modified(|state| match state {
+            DownloadState::Running => {
+                *state = DownloadState::Paused;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Resumes a paused download.
+    pub fn resume(&self) {
+        self.state.send_if_modified(|state| match state {
+            DownloadState::Paused => {
+                *state = DownloadState::Running;
+                true
+            }
+            _ => false,
+        });
+    }
+
+    /// Stops the download, including any files that are downloading, and removes partially written files.
+    /// The download will fail with [Error::Aborted]. An aborted download can't be resumed.
+    pub fn abort(&self) {
+        self.state.send_replace(DownloadState::Aborted);
+    }
+
+    /// Returns true if the download is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.state.borrow() == DownloadState::Paused
+    }
+
+    /// Returns true if the download has been aborted.
+    pub fn is_aborted(&self) -> bool {
+        *self.state.borrow() == DownloadState::Aborted
+    }
+
+    /// Waits while the download is paused, failing if it's been aborted.
+    pub(crate) async fn wait_until_running(&self) -> Result<(), Error> {
+        let mut state = self.state.subscribe();
+        // the sender lives as long as self, so this can't fail
+        let state = *state
+            .wait_for(|state| *state != DownloadState::Paused)
+            .await
+            .unwrap();
+
+        match state {
+            DownloadState::Aborted => Err(Error::Aborted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Completes once the download has been aborted.
+    pub(crate) async fn aborted(&self) {
+        let mut state = self.state.subscribe();
+        let _ = state
+            .wait_for(|state| *state == DownloadState::Aborted)
+            .await;
+    }
+}
@@ -0,0 +1,189 @@
+use crate::SourceTree;
+
+/// What should happen to a file once its contents have been checked by a [ContentInspector].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InspectVerdict {
+    /// Write the file to disk.
+    Allow,
+    /// Don't write the file, for the given reason.
+    Block(String),
+}
+
+/// Implement this trait to check the contents of each file after it's downloaded, before it's written to disk.
+///
+/// A blocked file is never written, and fails with [Error::ContentBlocked](crate::Error::ContentBlocked).
+pub trait ContentInspector: Sync {
+    /// Called with the decoded contents of each file.
+    fn inspect(&self, entry: &SourceTree, bytes: &[u8]) -> InspectVerdict;
+}
+
+/// An inspector that blocks files with high Shannon entropy, which usually means they're compressed,
+/// encrypted or packed, and can't be reviewed as source.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntropyInspector {
+    /// The highest entropy allowed, in bits per byte, from 0 to 8.
+    /// The default is 7.5.
+    pub max_entropy: f64,
+    /// Files smaller than this many bytes are always allowed, since the entropy of a few bytes means little.
+    /// The default is 1024.
+    pub min_bytes: usize,
+}
+
+impl EntropyInspector {
+    /// Creates a new [EntropyInspector] that blocks files over `max_entropy` bits per byte.
+    pub fn new(max_entropy: f64) -> EntropyInspector {
+        EntropyInspector {
+            max_entropy,
+            ..EntropyInspector::default()
+        }
+    }
+
+    /// Returns the Shannon entropy of `bytes`, in bits per byte.
+    pub fn entropy(bytes: &[u8]) -> f64 {
+        let mut counts = [0usize; 256];
+        for &byte in bytes {
+            counts[byte as usize] += 1;
+        }
+
+        let len = bytes.len() as f64;
+        counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / len;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+impl Default for EntropyInspector {
+    fn default() -> Self {
+        EntropyInspector {
+            max_entropy: 7.5,
+            min_bytes: 1024,
+        }
+    }
+}
+
+impl ContentInspector for EntropyInspector {
+    fn inspect(&self, _entry: &SourceTree, bytes: &[u8]) -> InspectVerdict {
+        if bytes.len() < self.min_bytes {
+            return InspectVerdict::Allow;
+        }
+
+        let entropy = EntropyInspector::entropy(bytes);
+        match entropy > self.max_entropy {
+            true => InspectVerdict::Block(format!(
+                "its entropy of {:.2} bits per byte is over the limit of {:.2}",
+                entropy, self.max_entropy
+            )),
+            false => InspectVerdict::Allow,
+        }
+    }
+}
+
+/// A credential that [SecretPatternInspector] looks for: a fixed prefix followed by a run of token characters.
+struct SecretPattern {
+    name: &'static str,
+    prefix: &'static [u8],
+    min_len: usize,
+    is_token_char: fn(u8) -> bool,
+}
+
+const SECRET_PATTERNS: &[SecretPattern] = &[
+    SecretPattern {
+        name: "an AWS access key ID",
+        prefix: b"AKIA",
+        min_len: 16,
+        is_token_char: |c| c.is_ascii_uppercase() || c.is_ascii_digit(),
+    },
+    SecretPattern {
+        name: "a GitHub personal access token",
+        prefix: b"ghp_",
+        min_len: 36,
+        is_token_char: |c| c.is_ascii_alphanumeric(),
+    },
+    SecretPattern {
+        name: "a GitHub fine-grained personal access token",
+        prefix: b"github_pat_",
+        min_len: 82,
+        is_token_char: |c| c.is_ascii_alphanumeric() || c == b'_',
+    },
+    SecretPattern {
+        name: "a GitHub OAuth token",
+        prefix: b"gho_",
+        min_len: 36,
+        is_token_char: |c| c.is_ascii_alphanumeric(),
+    },
+    SecretPattern {
+        name: "a GitHub App token",
+        prefix: b"ghs_",
+        min_len: 36,
+        is_token_char: |c| c.is_ascii_alphanumeric(),
+    },
+    SecretPattern {
+        name: "a Slack bot token",
+        prefix: b"xoxb-",
+        min_len: 10,
+        is_token_char: |c| c.is_ascii_alphanumeric() || c == b'-',
+    },
+];
+
+/// An inspector that blocks files that look like they contain credentials: private keys, AWS access key IDs,
+/// GitHub tokens and Slack bot tokens.
+///
+/// This only recognizes the fixed formats of a few well-known credentials, so it's a last line of defense
+/// rather than a replacement for a dedicated secret scanner.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SecretPatternInspector {}
+
+impl SecretPatternInspector {
+    /// Creates a new [SecretPatternInspector].
+    pub fn new() -> SecretPatternInspector {
+        SecretPatternInspector {}
+    }
+
+    /// Returns a description of the first credential found in `bytes`, if any.
+    pub fn find_secret(bytes: &[u8]) -> Option<&'static str> {
+        if find(bytes, b"-----BEGIN ").any(|start| {
+            let line = &bytes[start..];
+            let line = &line[..line.iter().position(|&c| c == b'\n').unwrap_or(line.len())];
+            line.ends_with(b"PRIVATE KEY-----") || line.ends_with(b"PRIVATE KEY-----\r")
+        }) {
+            return Some("a private key");
+        }
+
+        SECRET_PATTERNS
+            .iter()
+            .find(|pattern| {
+                find(bytes, pattern.prefix).any(|start| {
+                    let token = &bytes[start + pattern.prefix.len()..];
+                    let len = token
+                        .iter()
+                        .take_while(|&&c| (pattern.is_token_char)(c))
+                        .count();
+                    len >= pattern.min_len
+                })
+            })
+            .map(|pattern| pattern.name)
+    }
+}
+
+impl ContentInspector for SecretPatternInspector {
+    fn inspect(&self, _entry: &SourceTree, bytes: &[u8]) -> InspectVerdict {
+        match SecretPatternInspector::find_secret(bytes) {
+            Some(secret) => InspectVerdict::Block(format!("it looks like it contains {}", secret)),
+            None => InspectVerdict::Allow,
+        }
+    }
+}
+
+/// Returns the start of each occurrence of `needle` in `haystack`.
+fn find<'h>(haystack: &'h [u8], needle: &'h [u8]) -> impl Iterator<Item = usize> + 'h {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(i, _)| i)
+}
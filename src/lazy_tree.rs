@@ -0,0 +1,167 @@
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::{
+    raw, Error, ErrorContext, GithubBranchPath, GithubClient, GithubRef, ResolvedRef, SourceTree,
+    TreeEntryType,
+};
+
+/// A [SourceTree] whose directories fetch their children the first time they're
+/// [expanded](LazySourceTree::expand), for tools like repository browsers that only ever look at a few
+/// directories of a large repository.
+///
+/// Each expansion is a single request for one directory. Use [materialize](LazySourceTree::materialize) to
+/// fetch everything that hasn't been expanded yet and get a regular [SourceTree].
+#[derive(Debug, Clone)]
+pub struct LazySourceTree {
+    client: GithubClient,
+    user: String,
+    repo: String,
+    entry: SourceTree,
+    children: Option<Vec<LazySourceTree>>,
+}
+
+impl LazySourceTree {
+    /// Resolves the reference of `path` and returns the root of its tree, without fetching any of its entries.
+    ///
+    /// An empty repository has an already expanded root with no children.
+    pub async fn root(
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<LazySourceTree, Error> {
+        let mut entry = SourceTree::new(TreeEntryType::Tree);
+        let mut children = None;
+
+        match ResolvedRef::resolve(client, path).await {
+            Ok(resolved) => {
+                entry.sha = resolved.tree_sha;
+                entry.url = path
+                    .with_reference(GithubRef::Tree(&resolved.tree_sha.to_string()))
+                    .to_tree_url(client.api_url());
+            }
+            Err(e) if matches!(e.root(), Error::EmptyRepository) => children = Some(Vec::new()),
+            Err(e) => {
+                return Err(e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                }))
+            }
+        }
+
+        Ok(LazySourceTree {
+            client: client.clone(),
+            user: path.user.to_string(),
+            repo: path.repo.to_string(),
+            entry,
+            children,
+        })
+    }
+
+    /// Returns this entry, without any children.
+    pub fn entry(&self) -> &SourceTree {
+        &self.entry
+    }
+
+    /// Returns whether the children of this entry have been fetched. Blobs are always expanded.
+    pub fn is_expanded(&self) -> bool {
+        self.entry.entry_type == TreeEntryType::Blob || self.children.is_some()
+    }
+
+    /// Returns the children of this entry, or [None] if it's a directory that hasn't been expanded yet.
+    pub fn children(&self) -> Option<&[LazySourceTree]> {
+        match self.entry.entry_type {
+            TreeEntryType::Blob => Some(&[]),
+            TreeEntryType::Tree => self.children.as_deref(),
+        }
+    }
+
+    /// Returns the children of this entry, fetching them first if this is a directory that hasn't been expanded
+    /// yet.
+    pub async fn expand(&mut self) -> Result<&mut [LazySourceTree], Error> {
+        if self.entry.entry_type == TreeEntryType::Blob {
+            return Ok(&mut []);
+        }
+
+        if self.children.is_none() {
+            let sha = self.entry.sha.to_string();
+            let path = GithubBranchPath::with_ref(&self.user, &self.repo, GithubRef::Tree(&sha));
+            let model = raw::get_tree_raw(&self.client, &path, false)
+                .await
+                .map_err(|e| {
+                    e.with_context(ErrorContext {
+                        repo: Some(format!("{}/{}", self.user, self.repo)),
+                        path: Some(self.entry.path.to_string()),
+                        ..ErrorContext::default()
+                    })
+                })?;
+
+            let children = model
+                .tree
+                .into_iter()
+                .map(|entry| {
+                    let mut entry = SourceTree::from(entry);
+                    entry.path = self.entry.path.join(&entry.path);
+                    LazySourceTree {
+                        client: self.client.clone(),
+                        user: self.user.clone(),
+                        repo: self.repo.clone(),
+                        entry,
+                        children: None,
+                    }
+                })
+                .collect();
+            self.children = Some(children);
+        }
+
+        Ok(self.children.as_deref_mut().unwrap_or_default())
+    }
+
+    /// Walks down from this entry to the directory or blob at `path`, relative to this entry, expanding each
+    /// directory along the way. Returns [None] if there's nothing at `path`.
+    pub async fn expand_path(&mut self, path: &str) -> Result<Option<&mut LazySourceTree>, Error> {
+        let mut node = self;
+        for name in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            let children = node.expand().await?;
+            node = match children.iter_mut().find(|c| c.entry.name() == name) {
+                Some(child) => child,
+                None => return Ok(None),
+            };
+        }
+
+        Ok(Some(node))
+    }
+
+    /// Expands this entry and every directory below it.
+    pub fn expand_all(&mut self) -> BoxFuture<'_, Result<(), Error>> {
+        // have to use boxed async here because we're calling an async recursively
+        async move {
+            for child in self.expand().await? {
+                child.expand_all().await?;
+            }
+
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Fetches every directory that hasn't been expanded yet and returns the whole tree as a [SourceTree].
+    pub async fn materialize(mut self) -> Result<SourceTree, Error> {
+        self.expand_all().await?;
+        Ok(self.into_source_tree())
+    }
+
+    /// Returns the entries fetched so far as a [SourceTree], where directories that haven't been expanded have
+    /// no children.
+    pub fn into_source_tree(self) -> SourceTree {
+        let children = self
+            .children
+            .unwrap_or_default()
+            .into_iter()
+            .map(LazySourceTree::into_source_tree)
+            .collect();
+
+        SourceTree {
+            children,
+            ..self.entry
+        }
+    }
+}
@@ -1,10 +1,157 @@
+#[cfg(feature = "download")]
+mod adaptive;
+#[cfg(feature = "archive")]
+mod archive;
+#[cfg(feature = "download")]
+mod bench;
+#[cfg(feature = "bitbucket")]
+mod bitbucket;
+#[cfg(feature = "download")]
+mod blob;
+mod cache;
+#[cfg(feature = "download")]
+mod checksums;
+mod client;
+#[cfg(feature = "download")]
+mod client_pool;
+#[cfg(feature = "archive")]
+mod codeload;
+mod compare;
+#[cfg(feature = "download")]
 mod download;
+mod enrich;
 mod error;
+mod export;
+#[cfg(feature = "download")]
+mod fetch_strategy;
+mod file_mode;
 mod filter;
+#[cfg(feature = "download")]
+mod forge;
+#[cfg(feature = "download")]
+pub mod git_hash;
+#[cfg(feature = "download")]
+mod gitea;
+#[cfg(feature = "gix")]
+mod gix_clone;
+#[cfg(feature = "cli")]
+mod grab;
+#[cfg(feature = "cli")]
+mod grab_spec;
+#[cfg(feature = "graphql")]
+mod graphql_tree;
+#[cfg(feature = "download")]
+mod handle;
+#[cfg(feature = "download")]
+mod inspect;
+mod lazy_tree;
+#[cfg(feature = "download")]
+mod local_diff;
+#[cfg(feature = "git2")]
+mod local_git;
+mod object_id;
+mod observer;
+#[cfg(feature = "download")]
+mod path_check;
+#[cfg(feature = "download")]
+mod permissions;
+#[cfg(feature = "download")]
+mod preflight;
+#[cfg(feature = "cli")]
+mod profile;
+#[cfg(feature = "download")]
+mod rate_limit;
+pub mod raw;
+mod reference;
+#[cfg(feature = "download")]
+mod reporters;
+mod repository;
 mod request;
+#[cfg(feature = "cli")]
+pub mod scaffold;
 mod source_tree;
+#[cfg(feature = "download")]
+mod spdx;
+mod stats;
+#[cfg(feature = "download")]
+mod sync;
+mod telemetry;
+#[cfg(feature = "download")]
+mod temp_grab;
+#[cfg(feature = "download")]
+mod transform;
+mod tree_builder;
+#[cfg(feature = "download")]
+mod watch;
+#[cfg(any(feature = "git2", feature = "gix"))]
+pub mod wiki;
 
+#[cfg(feature = "archive")]
+pub use archive::{ArchiveFormat, ArchiveOptions};
+#[cfg(feature = "download")]
+pub use bench::BenchProfile;
+#[cfg(feature = "bitbucket")]
+pub use bitbucket::{BitbucketForge, BITBUCKET_API_URL};
+#[cfg(feature = "download")]
+pub use blob::Blob;
+pub use cache::{CachePolicy, TreeCache};
+pub use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "download")]
+pub use checksums::ChecksumAlgorithm;
+pub use client::{GithubClient, Pagination, RequestBudget, DEFAULT_API_URL, DEFAULT_PER_PAGE};
+#[cfg(feature = "download")]
+pub use client_pool::ClientPool;
+pub use compare::{ChangeKind, FileChange, TreeComparison};
+#[cfg(feature = "download")]
 pub use download::*;
-pub use error::Error;
-pub use filter::Filter;
+pub use enrich::EntryCommitInfo;
+pub use error::{Error, ErrorContext, GithubApiError, GithubApiErrorDetail, RateLimitGuidance};
+#[cfg(feature = "download")]
+pub use fetch_strategy::FetchStrategy;
+pub use file_mode::FileMode;
+pub use filter::{Filter, FilterExplanation, FilterOptions};
+#[cfg(feature = "download")]
+pub use forge::{Forge, GitLabForge, GITLAB_API_URL};
+#[cfg(feature = "download")]
+pub use gitea::{GiteaHost, CODEBERG_URL};
+#[cfg(feature = "cli")]
+pub use grab::grab;
+#[cfg(feature = "cli")]
+pub use grab_spec::{
+    GrabSpec, GrabWorkspace, RenameRule, SpecAuth, WorkspaceGrab, WorkspaceReport,
+};
+#[cfg(feature = "download")]
+pub use handle::DownloadHandle;
+#[cfg(feature = "download")]
+pub use inspect::{ContentInspector, EntropyInspector, InspectVerdict, SecretPatternInspector};
+pub use lazy_tree::LazySourceTree;
+#[cfg(feature = "download")]
+pub use local_diff::LocalDiff;
+#[cfg(feature = "git2")]
+pub use local_git::LocalGitForge;
+pub use object_id::{ObjectFormat, ObjectId};
+pub use observer::{ApiObserver, ApiRequest, ApiResponse};
+#[cfg(feature = "download")]
+pub use permissions::{FilePermissions, PermissionsHook};
+#[cfg(feature = "download")]
+pub use preflight::PreflightReport;
+#[cfg(feature = "cli")]
+pub use profile::{GrabProfile, GrabProfiles, PROFILE_FILE_NAME};
+pub use reference::{GithubRef, ResolvedRef, EMPTY_TREE_SHA, EMPTY_TREE_SHA256};
+#[cfg(feature = "download")]
+pub use reporters::*;
+pub use repository::RepoInfo;
+pub use request::{AuthScheme, HttpOptions, SocksProxy, DEFAULT_USER_AGENT};
 pub use source_tree::*;
+#[cfg(feature = "download")]
+pub use spdx::SpdxLicense;
+pub use stats::{ExtensionStats, LargeFile, SizeBucket, TreeStats, DEFAULT_LARGEST_FILES};
+#[cfg(feature = "download")]
+pub use sync::SyncSummary;
+#[cfg(feature = "download")]
+pub use temp_grab::TempGrab;
+#[cfg(feature = "download")]
+pub use transform::{ContentTransform, LineEnding, Transform};
+pub use tree_builder::SourceTreeBuilder;
+#[cfg(feature = "download")]
+pub use watch::Watcher;
@@ -0,0 +1,82 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{git_hash, Error, SourceTree, TreeEntryType};
+
+/// The differences between a local directory and a [SourceTree], as found by [SourceTree::diff_local].
+///
+/// Every path is relative to the directory and the tree, and each list is sorted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LocalDiff {
+    /// Files that exist in both, but whose contents differ.
+    pub modified: Vec<PathBuf>,
+    /// Files in the tree that don't exist in the directory.
+    pub missing: Vec<PathBuf>,
+    /// Files in the directory that don't exist in the tree.
+    pub extra: Vec<PathBuf>,
+}
+
+impl LocalDiff {
+    /// Returns true if the directory matches the tree exactly.
+    pub fn is_empty(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+impl SourceTree {
+    /// Compares the files in `dir` against the blobs in this tree without downloading anything, by hashing
+    /// each local file the way git does and comparing it to the blob's SHA.
+    ///
+    /// Paths in the tree are taken relative to this node, so a subtree can be compared against a directory
+    /// holding just its contents.
+    pub fn diff_local(&self, dir: &Path) -> Result<LocalDiff, Error> {
+        let mut local = BTreeSet::new();
+        collect_files(dir, Path::new(""), &mut local)?;
+
+        let mut diff = LocalDiff::default();
+        for node in self.iter() {
+            if node.entry_type != TreeEntryType::Blob {
+                continue;
+            }
+
+            let path = node
+                .path
+                .strip_prefix(&self.path)
+                .unwrap_or(&node.path)
+                .as_std_path()
+                .to_path_buf();
+            if !local.remove(&path) {
+                diff.missing.push(path);
+                continue;
+            }
+
+            let bytes = fs::read(dir.join(&path))?;
+            if git_hash::blob_id(&bytes, node.sha.format()) != node.sha {
+                diff.modified.push(path);
+            }
+        }
+
+        diff.extra = local.into_iter().collect();
+        diff.modified.sort();
+        diff.missing.sort();
+        Ok(diff)
+    }
+}
+
+/// Adds the path of every file below `root.join(relative)` to `files`, relative to `root`.
+fn collect_files(root: &Path, relative: &Path, files: &mut BTreeSet<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.insert(path);
+        }
+    }
+
+    Ok(())
+}
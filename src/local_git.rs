@@ -0,0 +1,187 @@
+use std::{fmt, path::Path, str::FromStr, sync::Mutex};
+
+use futures::future::{BoxFuture, FutureExt};
+use git2::{ObjectType, Oid, Repository, TreeWalkMode, TreeWalkResult};
+
+use crate::{
+    raw::{TreeEntryModel, TreeModel},
+    Error, ErrorContext, FileMode, Filter, Forge, GithubBranchPath, GithubRef, ObjectId,
+    ResolvedRef, SourceTree, TreeEntryType,
+};
+
+/// A [Forge] that reads trees and blobs out of a local git repository, so the same filtering, layouts and
+/// reporters work without any network access, such as in air-gapped CI.
+///
+/// `user` and `repo` in a [GithubBranchPath] are ignored, since the repository is the one this forge was
+/// opened with. Branches are looked up among local branches first and then among the branches of `origin`,
+/// and pull requests among the `refs/pull/{number}/head` references fetched from GitHub.
+///
+/// Entries have the hashes and sizes from the object database, so downloaded files are
+/// [verified](crate::DownloadedFile::verified) like files from GitHub. Submodules aren't part of the tree.
+pub struct LocalGitForge {
+    repo: Mutex<Repository>,
+}
+
+impl fmt::Debug for LocalGitForge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let repo = self.repo.lock().unwrap();
+        f.debug_struct("LocalGitForge")
+            .field("path", &repo.path())
+            .finish()
+    }
+}
+
+impl LocalGitForge {
+    /// Opens the repository at `path`, which is either a working directory or a bare repository.
+    pub fn open(path: impl AsRef<Path>) -> Result<LocalGitForge, Error> {
+        let repo = Repository::open(path)?;
+        Ok(LocalGitForge {
+            repo: Mutex::new(repo),
+        })
+    }
+
+    /// Resolves `reference` to a commit and tree in the repository.
+    fn resolve(&self, reference: GithubRef<'_>) -> Result<ResolvedRef, Error> {
+        let repo = self.repo.lock().unwrap();
+        let candidates = match reference {
+            GithubRef::Tree(sha) => {
+                let tree = repo.find_tree(Oid::from_str(sha)?)?;
+                return Ok(ResolvedRef {
+                    commit_sha: None,
+                    tree_sha: tree.id().to_string().parse()?,
+                });
+            }
+            GithubRef::Branch(name) => vec![
+                format!("refs/heads/{}", name),
+                format!("refs/remotes/origin/{}", name),
+            ],
+            GithubRef::Tag(name) => vec![format!("refs/tags/{}", name)],
+            GithubRef::Commit(sha) => vec![sha.to_string()],
+            GithubRef::PullRequest(number) => vec![
+                format!("refs/pull/{}/head", number),
+                format!("refs/remotes/origin/pull/{}/head", number),
+            ],
+        };
+
+        let object = candidates
+            .iter()
+            .find_map(|spec| repo.revparse_single(spec).ok())
+            .ok_or_else(|| Error::Other(format!("{} isn't in the local repository", reference)))?;
+        let commit = object.peel_to_commit()?;
+        Ok(ResolvedRef {
+            commit_sha: Some(commit.id().to_string().parse()?),
+            tree_sha: commit.tree_id().to_string().parse()?,
+        })
+    }
+
+    /// Lists every entry below the tree or commit named by the reference of `path`, only keeping entries that
+    /// pass `filter` and skipping directories that can't hold any.
+    fn list_tree(
+        &self,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        let tree_sha = self.resolve(path.reference)?.tree_sha;
+        let repo = self.repo.lock().unwrap();
+        let odb = repo.odb()?;
+        let tree = repo.find_tree(Oid::from_str(&tree_sha.to_string())?)?;
+
+        let mut entries = Vec::new();
+        let mut error = None;
+        let walked = tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            let entry_type = match entry.kind() {
+                Some(ObjectType::Tree) => TreeEntryType::Tree,
+                Some(ObjectType::Blob) => TreeEntryType::Blob,
+                // submodules are commits in another repository
+                _ => return TreeWalkResult::Skip,
+            };
+
+            let sha = match entry.id().to_string().parse() {
+                Ok(sha) => sha,
+                Err(e) => {
+                    error = Some(e);
+                    return TreeWalkResult::Abort;
+                }
+            };
+            let model = TreeEntryModel {
+                path: format!("{}{}", dir, entry.name().unwrap_or_default()),
+                mode: FileMode::from_str(&format!("{:o}", entry.filemode())).unwrap_or_default(),
+                entry_type,
+                size: 0,
+                sha,
+                url: entry.id().to_string(),
+            };
+            if !model.passes(filter) {
+                return TreeWalkResult::Skip;
+            }
+
+            let size = match model.entry_type {
+                TreeEntryType::Blob => odb.read_header(entry.id()).map(|(size, _)| size),
+                TreeEntryType::Tree => Ok(0),
+            };
+            match size {
+                Ok(size) => {
+                    entries.push(TreeEntryModel {
+                        size: u32::try_from(size).unwrap_or(u32::MAX),
+                        ..model
+                    });
+                    TreeWalkResult::Ok
+                }
+                Err(e) => {
+                    error = Some(e.into());
+                    TreeWalkResult::Abort
+                }
+            }
+        });
+
+        // aborting the walk makes it fail too, so the error that aborted it takes precedence
+        if let Some(e) = error {
+            return Err(e);
+        }
+        walked?;
+
+        Ok(TreeModel {
+            sha: tree_sha,
+            url: String::new(),
+            tree: entries,
+            truncated: false,
+        })
+    }
+
+    /// Reads the contents of the blob with the given hash out of the object database.
+    fn read_blob(&self, sha: &ObjectId) -> Result<Vec<u8>, Error> {
+        let repo = self.repo.lock().unwrap();
+        let blob = repo.find_blob(Oid::from_str(&sha.to_string())?)?;
+        Ok(blob.content().to_vec())
+    }
+}
+
+impl Forge for LocalGitForge {
+    fn resolve_ref<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+    ) -> BoxFuture<'f, Result<ResolvedRef, Error>> {
+        async move { self.resolve(path.reference) }.boxed()
+    }
+
+    fn get_tree<'f>(
+        &'f self,
+        path: &'f GithubBranchPath<'f>,
+        filter: &'f Filter<'f>,
+    ) -> BoxFuture<'f, Result<SourceTree, Error>> {
+        async move {
+            let tree = self.list_tree(path, filter).map_err(|e| {
+                e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                })
+            })?;
+            Ok(tree.into())
+        }
+        .boxed()
+    }
+
+    fn get_blob<'f>(&'f self, entry: &'f SourceTree) -> BoxFuture<'f, Result<Vec<u8>, Error>> {
+        async move { self.read_blob(&entry.sha) }.boxed()
+    }
+}
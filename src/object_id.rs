@@ -0,0 +1,127 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::Error;
+
+/// The hash function a repository uses to identify its objects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ObjectFormat {
+    /// SHA1, used by almost every repository.
+    Sha1,
+    /// SHA256, used by repositories created with `git init --object-format=sha256`.
+    Sha256,
+}
+
+/// The hash identifying a git object, such as a blob, tree or commit.
+///
+/// This is parsed from and displayed as a lowercase hex string, which is also how it's serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ObjectId {
+    /// A SHA1 hash of 20 bytes.
+    Sha1([u8; 20]),
+    /// A SHA256 hash of 32 bytes.
+    Sha256([u8; 32]),
+}
+
+impl Default for ObjectId {
+    fn default() -> Self {
+        ObjectId::NULL
+    }
+}
+
+impl ObjectId {
+    /// The SHA1 hash made of all zeros, which git uses to mean "no object".
+    pub const NULL: ObjectId = ObjectId::Sha1([0; 20]);
+
+    /// Returns the hash of the empty tree in the given format, which is the same in every repository.
+    pub fn empty_tree(format: ObjectFormat) -> ObjectId {
+        match format {
+            ObjectFormat::Sha1 => crate::EMPTY_TREE_SHA.parse().unwrap(),
+            ObjectFormat::Sha256 => crate::EMPTY_TREE_SHA256.parse().unwrap(),
+        }
+    }
+
+    /// Creates an [ObjectId] from the raw bytes of a hash, picking the format from their length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<ObjectId> {
+        match bytes.len() {
+            20 => Some(ObjectId::Sha1(bytes.try_into().ok()?)),
+            32 => Some(ObjectId::Sha256(bytes.try_into().ok()?)),
+            _ => None,
+        }
+    }
+
+    /// Returns the hash function this hash was made with.
+    pub fn format(&self) -> ObjectFormat {
+        match self {
+            ObjectId::Sha1(_) => ObjectFormat::Sha1,
+            ObjectId::Sha256(_) => ObjectFormat::Sha256,
+        }
+    }
+
+    /// Returns the raw bytes of this hash.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            ObjectId::Sha1(bytes) => bytes,
+            ObjectId::Sha256(bytes) => bytes,
+        }
+    }
+
+    /// Returns whether every byte of this hash is zero, such as for [ObjectId::NULL].
+    pub fn is_null(&self) -> bool {
+        self.as_bytes().iter().all(|b| *b == 0)
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    /// Parses a hex string of 40 characters as a SHA1 hash, or 64 characters as a SHA256 hash.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Other(format!("{} is not a valid object hash", s));
+        if !s.is_ascii() || !(s.len() == 40 || s.len() == 64) {
+            return Err(invalid());
+        }
+
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| invalid()))
+            .collect::<Result<Vec<u8>, Error>>()?;
+        ObjectId::from_bytes(&bytes).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.as_bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq<str> for ObjectId {
+    fn eq(&self, other: &str) -> bool {
+        other.parse::<ObjectId>().is_ok_and(|other| *self == other)
+    }
+}
+
+impl PartialEq<&str> for ObjectId {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl Serialize for ObjectId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ObjectId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse()
+            .map_err(|_| serde::de::Error::custom(format!("{} is not a valid object hash", hex)))
+    }
+}
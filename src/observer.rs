@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+
+/// Implement this trait to observe every call this crate makes to the GitHub API.
+///
+/// Unlike [DownloadReporter](crate::DownloadReporter), which reports on each file, an observer sees the
+/// individual HTTP requests, including those made while fetching trees and resolving references.
+/// Set one with [GithubClient::with_observer](crate::GithubClient::with_observer).
+pub trait ApiObserver: Send + Sync {
+    /// Called just before a request is sent.
+    fn on_request(&self, _request: &ApiRequest<'_>) {}
+
+    /// Called when the response to a request has been received, before its body is read.
+    fn on_response(&self, _response: &ApiResponse<'_>) {}
+
+    /// Called when GitHub rejects a request because a rate limit was exceeded.
+    fn on_rate_limit(&self, _url: &str, _retry_after: Duration) {}
+}
+
+/// A request about to be made to the GitHub API.
+#[derive(Debug)]
+pub struct ApiRequest<'r> {
+    /// The HTTP method of the request.
+    pub method: &'r str,
+    /// The full URL of the request, including the query string.
+    pub url: &'r str,
+}
+
+/// A response received from the GitHub API.
+#[derive(Debug)]
+pub struct ApiResponse<'r> {
+    /// The HTTP method of the request.
+    pub method: &'r str,
+    /// The full URL of the request, including the query string.
+    pub url: &'r str,
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// The headers of the response.
+    pub headers: &'r HeaderMap,
+    /// How long it took from sending the request to receiving the response headers.
+    pub duration: Duration,
+}
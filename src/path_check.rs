@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::{DownloadedFile, MergedPaths};
+
+/// Characters that can't be used in file names on Windows, besides control characters.
+const WINDOWS_RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// Names that refer to devices on Windows, with or without an extension.
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Returns why the repository path `path` can't be written to disk on this platform, if it can't.
+pub(crate) fn unrepresentable_reason(path: &str) -> Option<String> {
+    path.split('/').find_map(component_reason)
+}
+
+/// Replaces each character of `path` that keeps it from being written to disk with `%` and the hex of its
+/// UTF-8 bytes. `%` is encoded too in the components that need encoding, so they can be decoded again.
+pub(crate) fn percent_encode(path: &str) -> String {
+    let components: Vec<String> = path
+        .split('/')
+        .map(|component| match component_reason(component) {
+            Some(_) => encode_component(component),
+            None => component.to_string(),
+        })
+        .collect();
+    components.join("/")
+}
+
+fn component_reason(component: &str) -> Option<String> {
+    if component == ".." {
+        return Some(String::from(
+            "`..` would be written outside of its directory",
+        ));
+    }
+    if component.contains('\u{FFFD}') {
+        return Some(String::from("it wasn't valid UTF-8 in the repository"));
+    }
+    if component.contains('\0') {
+        return Some(String::from("it contains a NUL character"));
+    }
+    if !cfg!(windows) {
+        return None;
+    }
+
+    if let Some(c) = component
+        .chars()
+        .find(|c| WINDOWS_RESERVED_CHARS.contains(c))
+    {
+        return Some(format!("`{}` can't be used in file names on Windows", c));
+    }
+    if component.chars().any(|c| c.is_ascii_control()) {
+        return Some(String::from(
+            "control characters can't be used in file names on Windows",
+        ));
+    }
+    if component.ends_with(['.', ' ']) {
+        return Some(String::from(
+            "file names on Windows can't end with a dot or a space",
+        ));
+    }
+    if is_reserved_name(component) {
+        return Some(format!("{} is a reserved name on Windows", component));
+    }
+
+    None
+}
+
+fn is_reserved_name(component: &str) -> bool {
+    let stem = component.split('.').next().unwrap_or_default();
+    WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name))
+}
+
+fn encode_component(component: &str) -> String {
+    let must_encode = |i: usize, c: char| {
+        let is_first = i == 0;
+        let is_last = i + c.len_utf8() == component.len();
+        match c {
+            '%' | '\0' | '\u{FFFD}' => true,
+            '.' if component == ".." => true,
+            _ if !cfg!(windows) => false,
+            _ if WINDOWS_RESERVED_CHARS.contains(&c) || c.is_ascii_control() => true,
+            '.' | ' ' => is_last,
+            _ => is_first && is_reserved_name(component),
+        }
+    };
+
+    let mut encoded = String::with_capacity(component.len());
+    for (i, c) in component.char_indices() {
+        if must_encode(i, c) {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+
+    encoded
+}
+
+/// Returns the closest directory to `path` that exists, which is `path` itself if it's a directory. The last
+/// ancestor of a relative path is the current directory.
+pub(crate) fn closest_existing_dir(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .map(|dir| match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        })
+        .find(|dir| dir.is_dir())
+}
+
+/// Whether filesystems are case-insensitive by default on this platform, as they are on Windows and macOS.
+pub(crate) const CASE_INSENSITIVE_BY_DEFAULT: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Returns whether the filesystem `dir` is on treats names that only differ in case as the same name, by creating
+/// a file with an upper case name and looking for it by its lower case name. If that can't be checked, such as in
+/// a read-only directory, the filesystem is assumed to be case-insensitive on the platforms where that's the default.
+pub(crate) fn is_case_insensitive(dir: &Path) -> bool {
+    let name = format!(".GRAB_GITHUB_CASE_CHECK_{}", std::process::id());
+    let probe = dir.join(&name);
+    let created = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe);
+    if created.is_err() {
+        return CASE_INSENSITIVE_BY_DEFAULT;
+    }
+
+    let is_insensitive = dir.join(name.to_lowercase()).exists();
+    let _ = std::fs::remove_file(&probe);
+    is_insensitive
+}
+
+/// Finds the files in `files` that the filesystem didn't write to the exact path they were written to, such as
+/// `readme.md` overwriting `README.md` on a case-insensitive filesystem, or two paths that only differ in their
+/// Unicode normalization on macOS.
+///
+/// This is checked after writing, by looking for each component of each path in the listing of its directory,
+/// since the rules filesystems merge names by differ too much to check every one of them up front.
+pub(crate) async fn find_merged_paths(root: &Path, files: &[DownloadedFile]) -> Vec<MergedPaths> {
+    let mut listings = HashMap::new();
+    let mut merged: BTreeMap<PathBuf, (bool, MergedPaths)> = BTreeMap::new();
+    for file in files {
+        let Some((on_disk, exact)) = on_disk_path(root, &file.output_path, &mut listings).await
+        else {
+            continue;
+        };
+
+        let (any_inexact, group) = merged.entry(on_disk.clone()).or_insert_with(|| {
+            (
+                false,
+                MergedPaths {
+                    on_disk,
+                    paths: Vec::new(),
+                    output_paths: Vec::new(),
+                },
+            )
+        });
+        *any_inexact |= !exact;
+        group.paths.push(file.entry.path.to_string());
+        group.output_paths.push(file.output_path.clone());
+    }
+
+    merged
+        .into_values()
+        .filter_map(|(any_inexact, group)| any_inexact.then_some(group))
+        .collect()
+}
+
+/// Works out the path the file written to `path` is at on disk, going through each component below `root`.
+/// Returns the path, and whether every component was found exactly as written, or `None` if it's not found.
+async fn on_disk_path(
+    root: &Path,
+    path: &Path,
+    listings: &mut HashMap<PathBuf, Vec<OsString>>,
+) -> Option<(PathBuf, bool)> {
+    // files redirected outside of the output directory are checked from their own directory
+    let (mut current, relative) = match path.strip_prefix(root) {
+        Ok(relative) => (root.to_path_buf(), relative),
+        Err(_) => (path.parent()?.to_path_buf(), Path::new(path.file_name()?)),
+    };
+
+    let mut exact = true;
+    for component in relative.components() {
+        let name = component.as_os_str();
+        if !listings.contains_key(&current) {
+            let mut names = Vec::new();
+            let mut entries = tokio::fs::read_dir(&current).await.ok()?;
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                names.push(entry.file_name());
+            }
+            listings.insert(current.clone(), names);
+        }
+        let names = &listings[&current];
+
+        if names.iter().any(|n| n == name) {
+            current.push(name);
+            continue;
+        }
+
+        // the name was merged into one of the names that is there
+        exact = false;
+        let written = file_identity(&current.join(name)).await?;
+        let mut found = None;
+        for candidate in names {
+            if file_identity(&current.join(candidate)).await.as_ref() == Some(&written) {
+                found = Some(candidate.clone());
+                break;
+            }
+        }
+        current.push(found?);
+    }
+
+    Some((current, exact))
+}
+
+/// Identifies the file or directory at `path`, so that two paths to the same one have the same identity.
+async fn file_identity(path: &Path) -> Option<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = tokio::fs::metadata(path).await.ok()?;
+        Some(format!("{}:{}", metadata.dev(), metadata.ino()))
+    }
+
+    // Windows canonicalizes paths to the names they have on disk
+    #[cfg(not(unix))]
+    {
+        let canonical = tokio::fs::canonicalize(path).await.ok()?;
+        Some(canonical.to_string_lossy().into_owned())
+    }
+}
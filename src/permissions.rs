@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use crate::{Error, SourceTree};
+
+/// A function that picks the [FilePermissions] of each downloaded file, set with
+/// [permissions_hook](crate::DownloadConfig::permissions_hook). Files it returns `None` for keep the permissions
+/// they were created with.
+pub type PermissionsHook = dyn Fn(&SourceTree) -> Option<FilePermissions> + Sync;
+
+/// The mode and ownership to give a downloaded file as it's written.
+///
+/// These are only applied on Unix, and are ignored elsewhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilePermissions {
+    /// The permission bits of the file, such as `0o640`, or `None` to keep those it was created with.
+    pub mode: Option<u32>,
+    /// The user ID to own the file, or `None` to keep its owner. Changing it usually takes root.
+    pub uid: Option<u32>,
+    /// The group ID to own the file, or `None` to keep its group.
+    pub gid: Option<u32>,
+}
+
+impl FilePermissions {
+    /// Creates a new [FilePermissions] that only sets the permission bits of the file.
+    pub fn mode(mode: u32) -> FilePermissions {
+        FilePermissions {
+            mode: Some(mode),
+            ..FilePermissions::default()
+        }
+    }
+
+    /// Changes the owner of the file, keeping its mode.
+    pub fn with_owner(self, uid: Option<u32>, gid: Option<u32>) -> FilePermissions {
+        FilePermissions { uid, gid, ..self }
+    }
+
+    /// Applies these permissions to the file at `path`. This blocks, so it's called from a blocking thread.
+    #[cfg(unix)]
+    pub(crate) fn apply(&self, path: &Path) -> Result<(), Error> {
+        use std::os::unix::fs::PermissionsExt;
+
+        // changing the owner can clear the setuid and setgid bits, so it's done before setting the mode
+        if self.uid.is_some() || self.gid.is_some() {
+            std::os::unix::fs::chown(path, self.uid, self.gid)?;
+        }
+        if let Some(mode) = self.mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies these permissions to the file at `path`.
+    #[cfg(not(unix))]
+    pub(crate) fn apply(&self, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+}
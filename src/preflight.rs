@@ -0,0 +1,203 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    client::UNAUTHENTICATED_RATE_LIMIT,
+    raw::{self, RateLimitResourceModel},
+    DownloadConfig, DownloadReporter, Downloader, Error, Filter, GithubBranchPath, GithubRef,
+    RepoInfo, ResolvedRef, SourceTree,
+};
+
+/// What [preflight](Downloader::preflight) found out about a download before starting it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreflightReport {
+    /// The repository that will be downloaded from.
+    pub repo: RepoInfo,
+    /// The commit and tree the reference resolved to.
+    pub resolved: ResolvedRef,
+    /// Whether requests are being made with an access token, judging by the rate limit GitHub applies to them.
+    pub authenticated: bool,
+    /// The OAuth scopes of the access token.
+    ///
+    /// This is `None` for unauthenticated requests and for fine-grained tokens, which GitHub doesn't report scopes for.
+    pub token_scopes: Option<Vec<String>>,
+    /// The number of files that will be downloaded.
+    pub files: usize,
+    /// About how many API requests the download will make, including resolving the reference and fetching the tree.
+    pub estimated_requests: u64,
+    /// The number of API requests left in the current hour.
+    pub rate_limit_remaining: u64,
+    /// When the rate limit resets, in seconds since the unix epoch.
+    pub rate_limit_reset: u64,
+}
+
+impl<'p> Downloader {
+    /// Checks that downloading `path` with `filter` can succeed, without writing anything to disk.
+    ///
+    /// This verifies that the access token (if any) is accepted, that the repository exists and can be seen with
+    /// it, that the reference resolves, and that enough of the rate limit is left for the number of requests the
+    /// download is estimated to make. The first check that fails is returned as [Error::PreflightFailed], with
+    /// a message saying how to fix it. A tree that breaks [max_files](DownloadConfig::max_files) or
+    /// [max_depth](DownloadConfig::max_depth) fails with [Error::TooManyFiles] or [Error::TooDeep].
+    ///
+    /// The tree is fetched to estimate the number of requests, so this costs as many requests as fetching it does.
+    pub async fn preflight<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        path: &'p GithubBranchPath<'p>,
+        filter: &Filter<'p>,
+    ) -> Result<PreflightReport, Error> {
+        let client = config.client()?;
+        let repo_name = format!("{}/{}", path.user, path.repo);
+
+        let (rate_limit, token_scopes) = raw::get_rate_limit_with_scopes(&client)
+            .await
+            .map_err(|e| match status(&e) {
+                Some(401) => Error::PreflightFailed(String::from(
+                    "GitHub rejected the access token; check that it's correct and hasn't expired or been revoked",
+                )),
+                _ => e,
+            })?;
+        let authenticated = rate_limit.rate.limit > UNAUTHENTICATED_RATE_LIMIT;
+        if rate_limit.rate.remaining == 0 {
+            return Err(rate_limit_error(&repo_name, None, &rate_limit.rate));
+        }
+
+        let repo = client.get_repo(path).await.map_err(|e| match status(&e) {
+            Some(404) => Error::PreflightFailed(not_found_message(
+                &repo_name,
+                authenticated,
+                token_scopes.as_deref(),
+            )),
+            Some(403) if !matches!(e.root(), Error::RateLimited { .. }) => Error::PreflightFailed(
+                format!("access to {} was denied: {}", repo_name, message(&e)),
+            ),
+            _ => e,
+        })?;
+        if repo.disabled {
+            return Err(Error::PreflightFailed(format!(
+                "{} has been disabled by GitHub, so its files can't be downloaded",
+                repo_name
+            )));
+        }
+
+        let requests_before = client.budget().requests_made();
+        let (resolved, tree) = SourceTree::get_resolved_with_client(&client, path, filter)
+            .await
+            .map_err(|e| match status(&e) {
+                Some(404) | Some(422) => Error::PreflightFailed(format!(
+                    "{} {} wasn't found in {}; its default branch is {}",
+                    reference_kind(path),
+                    path.reference,
+                    repo_name,
+                    repo.default_branch
+                )),
+                _ => e,
+            })?;
+        let files = Downloader::select_files(config, &tree, filter)?.len();
+        let estimated_requests = client.budget().requests_made() - requests_before + files as u64;
+
+        let rate_limit_remaining = client
+            .budget()
+            .remaining()
+            .unwrap_or(rate_limit.rate.remaining);
+        if rate_limit_remaining < estimated_requests {
+            let rate = RateLimitResourceModel {
+                remaining: rate_limit_remaining,
+                ..rate_limit.rate
+            };
+            return Err(rate_limit_error(
+                &repo_name,
+                Some(estimated_requests),
+                &rate,
+            ));
+        }
+
+        Ok(PreflightReport {
+            repo,
+            resolved,
+            authenticated,
+            token_scopes,
+            files,
+            estimated_requests,
+            rate_limit_remaining,
+            rate_limit_reset: rate_limit.rate.reset,
+        })
+    }
+}
+
+fn status(error: &Error) -> Option<u16> {
+    error.context().and_then(|c| c.status)
+}
+
+fn message(error: &Error) -> String {
+    match error.root() {
+        Error::GithubError(error) => error.message.clone(),
+        error => format!("{:?}", error),
+    }
+}
+
+/// Explains why a repository might not have been found, which GitHub also reports for private repositories
+/// that can't be seen.
+fn not_found_message(repo_name: &str, authenticated: bool, scopes: Option<&[String]>) -> String {
+    match (authenticated, scopes) {
+        (false, _) => format!(
+            "{} wasn't found; if it's private, set an access token that can read it",
+            repo_name
+        ),
+        (true, Some(scopes)) if !scopes.iter().any(|s| s == "repo") => format!(
+            "{} wasn't found; if it's private, the access token needs the repo scope, but it only has [{}]",
+            repo_name,
+            scopes.join(", ")
+        ),
+        (true, _) => format!(
+            "{} wasn't found, or the access token hasn't been given access to it",
+            repo_name
+        ),
+    }
+}
+
+fn reference_kind(path: &GithubBranchPath<'_>) -> &'static str {
+    match path.reference {
+        GithubRef::Branch(_) => "branch",
+        GithubRef::Tag(_) => "tag",
+        GithubRef::Commit(_) => "commit",
+        GithubRef::Tree(_) => "tree",
+        GithubRef::PullRequest(_) => "pull request",
+    }
+}
+
+/// Explains that there isn't enough of the rate limit left for a download needing about `needed` requests,
+/// or any requests at all if it's not known yet.
+fn rate_limit_error(repo_name: &str, needed: Option<u64>, rate: &RateLimitResourceModel) -> Error {
+    let advice = match rate.limit > UNAUTHENTICATED_RATE_LIMIT {
+        true => format!("wait {} for it to reset", until(rate.reset)),
+        false => String::from(
+            "set an access token to raise the limit, or download the repository as an archive from github.com, \
+            which doesn't count against it",
+        ),
+    };
+
+    Error::PreflightFailed(match needed {
+        Some(needed) => format!(
+            "downloading {} needs about {} API requests, but only {} are left this hour; {}",
+            repo_name, needed, rate.remaining, advice
+        ),
+        None => format!(
+            "the API rate limit has been used up for this hour; {}",
+            advice
+        ),
+    })
+}
+
+/// Describes how long it is until `reset`, in seconds since the unix epoch.
+fn until(reset: u64) -> String {
+    let reset = UNIX_EPOCH + Duration::from_secs(reset);
+    let minutes = reset
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO)
+        .as_secs()
+        .div_ceil(60);
+    match minutes {
+        1 => String::from("1 minute"),
+        minutes => format!("{} minutes", minutes),
+    }
+}
@@ -0,0 +1,66 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, Filter};
+
+/// The name of the file, at the root of a repository, that [GrabProfiles] are read from.
+pub const PROFILE_FILE_NAME: &str = ".grabprofile.toml";
+
+/// A named set of files that maintainers of a repository suggest consumers grab, read from [PROFILE_FILE_NAME].
+///
+/// ```toml
+/// [profiles.minimal]
+/// include = ["template/**"]
+/// exclude = ["**/*.md"]
+/// strip_prefix = "template"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct GrabProfile {
+    /// Globs of the files to include, as in [Filter::new].
+    pub include: Vec<String>,
+    /// Globs of the files to exclude, as in [Filter::new].
+    pub exclude: Vec<String>,
+    /// A directory whose contents are written to the root of the output directory instead of inside it.
+    pub strip_prefix: Option<String>,
+}
+
+/// The contents of a [PROFILE_FILE_NAME] file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct GrabProfiles {
+    /// Each profile, keyed by name.
+    pub profiles: BTreeMap<String, GrabProfile>,
+}
+
+impl GrabProfiles {
+    /// Parses the contents of a [PROFILE_FILE_NAME] file.
+    pub fn parse(text: &str) -> Result<GrabProfiles, Error> {
+        toml::from_str(text).map_err(|e| Error::ProfileError(e.to_string()))
+    }
+
+    /// Returns the profile with the given name, or [Error::ProfileError] if there isn't one.
+    pub fn get(&self, name: &str) -> Result<&GrabProfile, Error> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| Error::ProfileError(format!("no profile named {}", name)))
+    }
+}
+
+impl GrabProfile {
+    /// Creates a [Filter] from the include and exclude globs of this profile.
+    pub fn filter(&self) -> Result<Filter<'_>, Error> {
+        Filter::try_new(
+            self.include.iter().map(String::as_str),
+            self.exclude.iter().map(String::as_str),
+        )
+    }
+
+    /// Returns the [rename rule](crate::DownloadConfig::rename_rules) that strips
+    /// [strip_prefix](GrabProfile::strip_prefix), if there is one.
+    pub fn rename_rule(&self) -> Option<(Cow<'static, str>, Cow<'static, str>)> {
+        let prefix = self.strip_prefix.as_deref()?.trim_matches('/');
+        Some((format!("{}/**", prefix).into(), "**".into()))
+    }
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{Mutex as AsyncMutex, MutexGuard},
+    time::{self, Instant},
+};
+
+/// Coordinates every request of a download run around GitHub's secondary rate limits.
+///
+/// Once a rate limit is hit, all requests wait until it has passed and then run one at a time,
+/// as GitHub recommends for clients that have been limited.
+pub(crate) struct RateLimitGate {
+    paused_until: Mutex<Option<Instant>>,
+    is_serialized: AtomicBool,
+    serial: AsyncMutex<()>,
+}
+
+impl RateLimitGate {
+    pub fn new() -> RateLimitGate {
+        RateLimitGate {
+            paused_until: Mutex::new(None),
+            is_serialized: AtomicBool::new(false),
+            serial: AsyncMutex::new(()),
+        }
+    }
+
+    /// Waits until requests are allowed, returning a guard that must be held for the duration of the request.
+    pub async fn acquire(&self) -> Option<MutexGuard<'_, ()>> {
+        // the pause can be extended while we're sleeping, so check again after waking
+        loop {
+            let paused_until = *self.paused_until.lock().unwrap();
+            match paused_until {
+                Some(until) if until > Instant::now() => time::sleep_until(until).await,
+                _ => break,
+            }
+        }
+
+        match self.is_serialized.load(Ordering::Acquire) {
+            true => Some(self.serial.lock().await),
+            false => None,
+        }
+    }
+
+    /// Pauses all requests for `duration` and makes them run one at a time afterwards.
+    pub fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|current| current < until) {
+            *paused_until = Some(until);
+        }
+
+        self.is_serialized.store(true, Ordering::Release);
+    }
+}
@@ -0,0 +1,1117 @@
+//! Typed models of the GitHub API responses used by this crate, and functions to fetch them unmodified.
+//!
+//! [SourceTree](crate::SourceTree) and [Downloader](crate::Downloader) are built on top of this layer.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "download")]
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::error::Category;
+
+#[cfg(feature = "graphql")]
+use crate::GithubApiErrorDetail;
+use crate::{
+    telemetry, ApiRequest, ApiResponse, Error, ErrorContext, FileMode, GithubApiError,
+    GithubBranchPath, GithubClient, ObjectId, Pagination, TreeEntryType,
+};
+
+/// A single entry of a [TreeModel].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TreeEntryModel {
+    /// The path of the entry, relative to the tree that was requested.
+    pub path: String,
+    /// The mode of the entry, which says whether it's a file, executable, symlink, submodule or directory.
+    pub mode: FileMode,
+    /// The type of the entry.
+    #[serde(rename = "type")]
+    pub entry_type: TreeEntryType,
+    /// The size of the entry in bytes, or 0 for tree entries.
+    #[serde(default)]
+    pub size: u32,
+    /// The SHA1 hash identifying this blob or tree.
+    pub sha: ObjectId,
+    /// The API URL to call to get more information on this object.
+    pub url: String,
+}
+
+/// The result of a call to the GitHub `Get a tree` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TreeModel {
+    /// The SHA1 hash identifying this tree.
+    pub sha: ObjectId,
+    /// The API URL of this tree.
+    pub url: String,
+    /// The entries of this tree.
+    pub tree: Vec<TreeEntryModel>,
+    /// Whether GitHub left out entries because the tree was too large to return at once.
+    pub truncated: bool,
+}
+
+/// The result of a call to the GitHub `Get a blob` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct BlobModel {
+    /// The contents of the blob, encoded as described by `encoding`.
+    pub content: String,
+    /// The encoding of `content`, usually [base64](ContentEncoding::Base64).
+    pub encoding: ContentEncoding,
+    /// The API URL of this blob.
+    pub url: String,
+    /// The SHA1 hash identifying this blob.
+    pub sha: String,
+    /// The size of the blob in bytes.
+    #[serde(default)]
+    pub size: u32,
+}
+
+#[cfg(feature = "download")]
+impl BlobModel {
+    /// Decodes `content` into the bytes of the blob.
+    ///
+    /// Fails with [Error::UnsupportedEncoding] if GitHub left the content out, which [get_blob_contents]
+    /// handles by fetching the blob again with the [MEDIA_TYPE_RAW] media type.
+    pub fn decode(&self) -> Result<Vec<u8>, Error> {
+        self.encoding.decode(&self.content)
+    }
+}
+
+/// How the `content` of a [BlobModel] or [LicenseContentModel] is encoded.
+///
+/// This is deserialized from and serialized as the string GitHub uses, such as `base64`. Encodings this crate
+/// doesn't know are kept in [ContentEncoding::Other], so that they fail when decoded rather than when parsed.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
+pub enum ContentEncoding {
+    /// The content is base64, wrapped in lines of 60 characters.
+    Base64,
+    /// The content is the text of the file as-is.
+    Utf8,
+    /// GitHub left the content out, as the contents API does for files between 1 and 100 MB. The content is
+    /// empty, and the file has to be fetched with the [MEDIA_TYPE_RAW] media type instead.
+    None,
+    /// An encoding this crate doesn't know how to decode.
+    Other(String),
+}
+
+/// Decodes base64 whatever its padding, since the padding carries nothing a decoder needs.
+#[cfg(feature = "download")]
+const BASE64_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+impl ContentEncoding {
+    /// Returns the name GitHub uses for this encoding.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ContentEncoding::Base64 => "base64",
+            ContentEncoding::Utf8 => "utf-8",
+            ContentEncoding::None => "none",
+            ContentEncoding::Other(name) => name,
+        }
+    }
+
+    /// Decodes `content` in this encoding, failing with [Error::UnsupportedEncoding] for
+    /// [ContentEncoding::None] and [ContentEncoding::Other].
+    ///
+    /// Base64 is decoded ignoring any whitespace in it, such as the line breaks GitHub wraps it with or the
+    /// carriage returns a proxy may add to them, and whether or not it's padded.
+    #[cfg(feature = "download")]
+    pub fn decode(&self, content: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            ContentEncoding::Base64 => {
+                let base64: Vec<u8> = content
+                    .bytes()
+                    .filter(|b| !b.is_ascii_whitespace())
+                    .collect();
+                Ok(BASE64_LENIENT.decode(base64)?)
+            }
+            ContentEncoding::Utf8 => Ok(content.as_bytes().to_vec()),
+            ContentEncoding::None | ContentEncoding::Other(_) => Err(Error::UnsupportedEncoding {
+                encoding: self.as_str().to_string(),
+            }),
+        }
+    }
+}
+
+impl From<String> for ContentEncoding {
+    fn from(value: String) -> Self {
+        match value.to_ascii_lowercase().as_str() {
+            "base64" => ContentEncoding::Base64,
+            "utf-8" | "utf8" => ContentEncoding::Utf8,
+            "none" => ContentEncoding::None,
+            _ => ContentEncoding::Other(value),
+        }
+    }
+}
+
+impl From<ContentEncoding> for String {
+    fn from(value: ContentEncoding) -> Self {
+        value.as_str().to_string()
+    }
+}
+
+/// A git object referred to by a [RefModel] or [TagModel].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct GitObjectModel {
+    /// The SHA1 hash identifying the object.
+    pub sha: String,
+    /// The type of the object: `commit`, `tag`, `tree` or `blob`.
+    #[serde(rename = "type")]
+    pub object_type: String,
+    /// The API URL of the object.
+    pub url: String,
+}
+
+/// The result of a call to the GitHub `Get a reference` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RefModel {
+    /// The fully qualified name of the reference, such as `refs/heads/main`.
+    #[serde(rename = "ref")]
+    pub name: String,
+    /// The API URL of the reference.
+    pub url: String,
+    /// The object the reference points to.
+    pub object: GitObjectModel,
+}
+
+/// The result of a call to the GitHub `Get a tag` API, for annotated tags.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TagModel {
+    /// The SHA1 hash identifying this tag object.
+    pub sha: String,
+    /// The name of the tag.
+    pub tag: String,
+    /// The object the tag points to.
+    pub object: GitObjectModel,
+}
+
+/// The tree of a [CommitModel].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitTreeModel {
+    /// The SHA1 hash identifying the tree.
+    pub sha: String,
+    /// The API URL of the tree.
+    pub url: String,
+}
+
+/// The result of a call to the GitHub `Get a commit object` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitModel {
+    /// The SHA1 hash identifying this commit.
+    pub sha: String,
+    /// The API URL of this commit.
+    pub url: String,
+    /// The commit message.
+    pub message: String,
+    /// The root tree of this commit.
+    pub tree: CommitTreeModel,
+}
+
+/// How long to wait after a secondary rate limit that doesn't say when to retry, as recommended by GitHub.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// An error returned by the GraphQL API.
+#[cfg(feature = "graphql")]
+#[derive(Deserialize)]
+struct GraphQlErrorModel {
+    message: String,
+    #[serde(rename = "type", default)]
+    error_type: Option<String>,
+}
+
+/// The body of a response from the GraphQL API, which can hold errors even when the status is successful.
+#[cfg(feature = "graphql")]
+#[derive(Deserialize)]
+struct GraphQlResponseModel<T> {
+    data: Option<T>,
+    errors: Option<Vec<GraphQlErrorModel>>,
+}
+
+/// Splits the body of a `Get a tree` response into its entries as it arrives, parsing each entry as soon as it's
+/// complete and dropping those that the function returns false for, so the body is never held in memory at once.
+///
+/// Everything but the entries is kept as it arrives, with an empty list in place of the entries, and parsed once
+/// the body has been read. Modes and types are parsed into [FileMode] and [TreeEntryType], so only paths, hashes
+/// and URLs are allocated for each entry that's kept.
+struct TreeBodyParser<F> {
+    keep: F,
+    /// How many objects and arrays the next byte is inside of.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// The last string read directly inside the top-level object, which is the key of the value that follows it.
+    key: Vec<u8>,
+    /// Whether the next byte is inside of the list of entries.
+    in_entries: bool,
+    /// The body read so far, without the entries.
+    skeleton: Vec<u8>,
+    /// The entry being read.
+    entry: Vec<u8>,
+    entries: Vec<TreeEntryModel>,
+}
+
+impl<F> TreeBodyParser<F>
+where
+    F: Fn(&TreeEntryModel) -> bool,
+{
+    fn new(keep: F) -> TreeBodyParser<F> {
+        TreeBodyParser {
+            keep,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            key: Vec::new(),
+            in_entries: false,
+            skeleton: Vec::new(),
+            entry: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Reads the next part of the body, parsing each entry it completes.
+    fn push(&mut self, status: StatusCode, chunk: &[u8]) -> Result<(), Error> {
+        for &byte in chunk {
+            let depth = self.depth;
+            if self.in_string {
+                match (self.escaped, byte) {
+                    (true, _) => self.escaped = false,
+                    (false, b'\\') => self.escaped = true,
+                    (false, b'"') => self.in_string = false,
+                    _ => {}
+                }
+                if depth == 1 && self.in_string {
+                    self.key.push(byte);
+                }
+            } else {
+                match byte {
+                    b'"' => {
+                        self.in_string = true;
+                        if depth == 1 {
+                            self.key.clear();
+                        }
+                    }
+                    b'[' if depth == 1 && self.key == b"tree" => {
+                        self.depth += 1;
+                        self.in_entries = true;
+                        self.skeleton.extend_from_slice(b"[]");
+                        continue;
+                    }
+                    b'{' | b'[' => self.depth += 1,
+                    b'}' | b']' => self.depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+            }
+
+            if !self.in_entries {
+                self.skeleton.push(byte);
+            } else if depth > 2 || self.depth > 2 {
+                self.entry.push(byte);
+                if depth == 3 && self.depth == 2 {
+                    self.finish_entry(status)?;
+                }
+            } else if self.depth == 1 {
+                // the end of the list of entries
+                self.in_entries = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish_entry(&mut self, status: StatusCode) -> Result<(), Error> {
+        let entry: TreeEntryModel = serde_json::from_slice(&self.entry)
+            .map_err(|e| deserialization_error(status, &self.entry, e))?;
+        if (self.keep)(&entry) {
+            self.entries.push(entry);
+        }
+        self.entry.clear();
+        Ok(())
+    }
+
+    /// Parses everything but the entries once the whole body has been read.
+    fn finish(self, status: StatusCode) -> Result<TreeModel, Error> {
+        let mut model: TreeModel = serde_json::from_slice(&self.skeleton)
+            .map_err(|e| deserialization_error(status, &self.skeleton, e))?;
+        model.tree = self.entries;
+        Ok(model)
+    }
+}
+
+/// Makes a request to the `Get a tree` API for the given branch path.
+///
+/// The reference of `path` is passed to GitHub as-is, which accepts tree hashes as well as anything
+/// that names a commit, except that pull requests are passed as `refs/pull/{number}/head`. Use
+/// [ResolvedRef](crate::ResolvedRef) to find the tree of a reference explicitly.
+///
+/// If `recursive` is true, GitHub will return every entry below the tree instead of only its direct children,
+/// unless there are too many entries (in which case `truncated` will be set).
+pub async fn get_tree_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    recursive: bool,
+) -> Result<TreeModel, Error> {
+    get_tree_filtered_raw(client, path, recursive, |_| true).await
+}
+
+/// Makes a request to the `Get a tree` API like [get_tree_raw], keeping only the entries that `keep` returns true for.
+///
+/// The response is parsed as it arrives, and entries are discarded as soon as they've been read, so neither the
+/// body of a large tree nor the entries that are going to be filtered out anyway are ever held in memory at once.
+pub(crate) async fn get_tree_filtered_raw<F>(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    recursive: bool,
+    keep: F,
+) -> Result<TreeModel, Error>
+where
+    F: Fn(&TreeEntryModel) -> bool,
+{
+    let url = path.to_tree_url(client.api_url());
+
+    let request = match recursive {
+        true => client.http().get(url).query(&[("recursive", true)]),
+        false => client.http().get(url),
+    };
+
+    let mut response = send_streaming(client, request, MEDIA_TYPE_JSON).await?;
+    let status = response.status();
+    let mut parser = TreeBodyParser::new(keep);
+    while let Some(chunk) = response.chunk().await? {
+        client.budget().record_body(chunk.len());
+        parser.push(status, &chunk)?;
+    }
+    parser.finish(status)
+}
+
+/// Makes a request to the `Get a repository` API for the repository of `path`.
+pub async fn get_repository_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+) -> Result<RepositoryModel, Error> {
+    get_model(
+        client,
+        client.http().get(path.to_repo_url(client.api_url())),
+        MEDIA_TYPE_JSON,
+    )
+    .await
+}
+
+/// Makes a request to the `Get a reference` API, where `name` is the reference without the `refs/` prefix,
+/// such as `heads/main` or `tags/v1.0.0`.
+pub async fn get_ref_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    name: &str,
+) -> Result<RefModel, Error> {
+    let url = path.to_api_url(client.api_url(), &format!("git/ref/{}", name));
+    get_model(client, client.http().get(url), MEDIA_TYPE_JSON).await
+}
+
+/// Makes a request to the `Get a reference` API like [get_ref_raw], sending `etag` from an earlier response in
+/// `If-None-Match`. Returns `None` if the reference hasn't changed since, which GitHub doesn't count against the
+/// rate limit, or else the reference and the `ETag` of the response.
+pub async fn get_ref_if_changed_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    name: &str,
+    etag: Option<&str>,
+) -> Result<Option<(RefModel, Option<String>)>, Error> {
+    let url = path.to_api_url(client.api_url(), &format!("git/ref/{}", name));
+    let mut request = client.http().get(url);
+    if let Some(etag) = etag {
+        request = request.header("If-None-Match", etag);
+    }
+
+    let (status, headers, body) = send_with_headers(client, request, MEDIA_TYPE_JSON).await?;
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+
+    let etag = headers
+        .get("etag")
+        .and_then(|etag| etag.to_str().ok())
+        .map(String::from);
+    let git_ref =
+        serde_json::from_slice(&body).map_err(|e| deserialization_error(status, &body, e))?;
+    Ok(Some((git_ref, etag)))
+}
+
+/// Makes a request to the `Get a tag` API for the annotated tag object with the given SHA.
+pub async fn get_tag_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    sha: &str,
+) -> Result<TagModel, Error> {
+    let url = path.to_api_url(client.api_url(), &format!("git/tags/{}", sha));
+    get_model(client, client.http().get(url), MEDIA_TYPE_JSON).await
+}
+
+/// Makes a request to the `Get the license for a repository` API for the repository and reference of `path`.
+pub async fn get_license_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+) -> Result<LicenseContentModel, Error> {
+    let query = [("ref", path.reference.commit_expression()?)];
+    let request = client
+        .http()
+        .get(path.to_api_url(client.api_url(), "license"))
+        .query(&query);
+    get_model(client, request, MEDIA_TYPE_JSON).await
+}
+
+/// Makes a request to the `Get a pull request` API for pull request `number` of the repository of `path`.
+pub async fn get_pull_request_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    number: u64,
+) -> Result<PullRequestModel, Error> {
+    let url = path.to_api_url(client.api_url(), &format!("pulls/{}", number));
+    get_model(client, client.http().get(url), MEDIA_TYPE_JSON).await
+}
+
+/// Makes a request to the `List commits` API for the repository of `path`, starting from its reference.
+///
+/// Only commits touching `file_path` are returned if it's given. `since` and `until` are ISO 8601 timestamps
+/// limiting the commits to those made after or before them.
+pub async fn list_commits_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    file_path: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    pagination: Pagination,
+) -> Result<Vec<CommitSummaryModel>, Error> {
+    let mut query = vec![
+        ("sha", path.reference.commit_expression()?.into_owned()),
+        ("page", pagination.page.to_string()),
+        ("per_page", pagination.per_page.to_string()),
+    ];
+    let optional = [("path", file_path), ("since", since), ("until", until)];
+    query.extend(
+        optional
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?.to_string()))),
+    );
+
+    let request = client
+        .http()
+        .get(path.to_api_url(client.api_url(), "commits"))
+        .query(&query);
+    get_model(client, request, MEDIA_TYPE_JSON).await
+}
+
+/// Makes a request to the `Get a commit object` API for the commit with the given SHA.
+pub async fn get_commit_raw(
+    client: &GithubClient,
+    path: &GithubBranchPath<'_>,
+    sha: &str,
+) -> Result<CommitModel, Error> {
+    let url = path.to_api_url(client.api_url(), &format!("git/commits/{}", sha));
+    get_model(client, client.http().get(url), MEDIA_TYPE_JSON).await
+}
+
+/// The result of a call to the GitHub `Get a repository` API, with only the fields used by this crate.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RepositoryModel {
+    /// The name of the repository including its owner, such as `octocat/Hello-World`.
+    pub full_name: String,
+    /// The API URL of the repository.
+    pub url: String,
+    /// The name of the branch checked out by default.
+    pub default_branch: String,
+    /// The description of the repository.
+    pub description: Option<String>,
+    /// The license GitHub detected in the repository, if any.
+    pub license: Option<LicenseModel>,
+    /// The topics the repository is tagged with.
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Who can see the repository: `public`, `private` or `internal`.
+    pub visibility: Option<String>,
+    /// Whether the repository has been archived and is read-only.
+    #[serde(default)]
+    pub archived: bool,
+    /// Whether the repository has been disabled.
+    #[serde(default)]
+    pub disabled: bool,
+    /// The size of the repository in kilobytes.
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// A license as returned by the GitHub API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct LicenseModel {
+    /// GitHub's identifier for the license, such as `mit`.
+    pub key: String,
+    /// The name of the license, such as `MIT License`.
+    pub name: String,
+    /// The SPDX identifier of the license, such as `MIT`, or `NOASSERTION` if GitHub couldn't identify it.
+    pub spdx_id: Option<String>,
+}
+
+/// The result of a call to the GitHub `Get the license for a repository` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct LicenseContentModel {
+    /// The file name of the license file.
+    pub name: String,
+    /// The path of the license file relative to the root of the repository.
+    pub path: String,
+    /// The SHA1 hash identifying the blob of the license file.
+    pub sha: String,
+    /// The size of the license file in bytes.
+    pub size: u32,
+    /// The API URL of the license file's contents.
+    pub url: String,
+    /// The API URL of the license file's blob.
+    pub git_url: Option<String>,
+    /// The contents of the license file, encoded as described by `encoding`.
+    pub content: String,
+    /// The encoding of `content`, usually [base64](ContentEncoding::Base64).
+    pub encoding: ContentEncoding,
+    /// The license GitHub detected in the file.
+    pub license: LicenseModel,
+}
+
+#[cfg(feature = "download")]
+impl LicenseContentModel {
+    /// Decodes `content` into the bytes of the license file.
+    pub fn decode(&self) -> Result<Vec<u8>, Error> {
+        self.encoding.decode(&self.content)
+    }
+}
+
+/// The author or committer of a [CommitDetailsModel].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitSignatureModel {
+    /// The name of the author or committer.
+    pub name: String,
+    /// The email address of the author or committer.
+    pub email: String,
+    /// When the commit was authored or committed, as an ISO 8601 timestamp.
+    pub date: String,
+}
+
+/// The git commit of a [CommitSummaryModel].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitDetailsModel {
+    /// The commit message.
+    pub message: String,
+    /// Who wrote the commit.
+    pub author: CommitSignatureModel,
+    /// Who committed the commit, which may differ from the author after a rebase or cherry-pick.
+    pub committer: CommitSignatureModel,
+    /// The root tree of this commit.
+    pub tree: CommitTreeModel,
+}
+
+/// A single commit returned by the GitHub `List commits` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct CommitSummaryModel {
+    /// The SHA1 hash identifying this commit.
+    pub sha: String,
+    /// The API URL of this commit.
+    pub url: String,
+    /// The contents of the commit.
+    pub commit: CommitDetailsModel,
+}
+
+/// The head of a [PullRequestModel].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PullRequestHeadModel {
+    /// The SHA1 hash of the commit at the head of the pull request.
+    pub sha: String,
+    /// The name of the branch the pull request is from, which may be in a fork.
+    #[serde(rename = "ref")]
+    pub name: String,
+    /// The branch and owner of the head, such as `octocat:new-topic`.
+    pub label: String,
+}
+
+/// The result of a call to the GitHub `Get a pull request` API, with only the fields used by this crate.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PullRequestModel {
+    /// The number of the pull request.
+    pub number: u64,
+    /// The API URL of the pull request.
+    pub url: String,
+    /// The branch and commit the pull request is from.
+    pub head: PullRequestHeadModel,
+}
+
+/// The limit of a single rate limit resource, as returned by the `Get rate limit status` API.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RateLimitResourceModel {
+    /// The number of requests allowed per hour.
+    pub limit: u64,
+    /// The number of requests left in the current hour.
+    pub remaining: u64,
+    /// The number of requests made in the current hour.
+    pub used: u64,
+    /// When the current hour ends and `remaining` is reset, in seconds since the unix epoch.
+    pub reset: u64,
+}
+
+/// The result of a call to the GitHub `Get rate limit status` API, with only the fields used by this crate.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RateLimitModel {
+    /// The limit of the core REST API, which the tree and blob APIs count towards.
+    pub rate: RateLimitResourceModel,
+}
+
+/// The URL of the GitHub `Get rate limit status` API on github.com. Other hosts serve it at `rate_limit` under
+/// their [api_url](GithubClient::api_url).
+pub const RATE_LIMIT_URL: &str = "https://api.github.com/rate_limit";
+
+/// Makes a request to the `Get rate limit status` API, which doesn't count towards the rate limit itself.
+pub async fn get_rate_limit_raw(client: &GithubClient) -> Result<RateLimitModel, Error> {
+    let (model, _) = get_rate_limit_with_scopes(client).await?;
+    Ok(model)
+}
+
+/// Makes a request to the `Get rate limit status` API like [get_rate_limit_raw], also returning the OAuth scopes
+/// of the access token from the `X-OAuth-Scopes` header.
+///
+/// The scopes are `None` if the header is missing, as it is for unauthenticated requests and fine-grained tokens.
+pub(crate) async fn get_rate_limit_with_scopes(
+    client: &GithubClient,
+) -> Result<(RateLimitModel, Option<Vec<String>>), Error> {
+    let request = client
+        .http()
+        .get(format!("{}/rate_limit", client.api_url()));
+    let (status, headers, body) = send_with_headers(client, request, MEDIA_TYPE_JSON).await?;
+    let model =
+        serde_json::from_slice(&body).map_err(|e| deserialization_error(status, &body, e))?;
+
+    let scopes = headers
+        .get("x-oauth-scopes")
+        .and_then(|v| v.to_str().ok())
+        .map(|scopes| {
+            scopes
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect()
+        });
+
+    Ok((model, scopes))
+}
+
+/// The media type requesting JSON responses, which every function in this module uses by default.
+pub const MEDIA_TYPE_JSON: &str = "application/vnd.github+json";
+
+/// The media type requesting the raw contents of a blob instead of a JSON [BlobModel].
+pub const MEDIA_TYPE_RAW: &str = "application/vnd.github.raw+json";
+
+/// The largest blob, in bytes, that the `Get a blob` API will return as a [BlobModel].
+pub const BLOB_API_SIZE_LIMIT: u64 = 100 * 1024 * 1024;
+
+/// Makes a request to the `Get a blob` API at the given URL, such as [SourceTree::url](crate::SourceTree::url).
+pub async fn get_blob_raw(client: &GithubClient, url: &str) -> Result<BlobModel, Error> {
+    get_model(client, client.http().get(url), MEDIA_TYPE_JSON).await
+}
+
+/// Makes a request to the `Get a blob` API with the [MEDIA_TYPE_RAW] media type, returning the
+/// contents of the blob as-is.
+pub async fn get_blob_bytes_raw(client: &GithubClient, url: &str) -> Result<Vec<u8>, Error> {
+    let (_, body) = send(client, client.http().get(url), MEDIA_TYPE_RAW).await?;
+    Ok(body)
+}
+
+/// Makes a `HEAD` request to the `Get a blob` API with the [MEDIA_TYPE_RAW] media type, returning the size
+/// of the blob in bytes from the `Content-Length` of the response, if GitHub sent one. The raw media type
+/// matters here, since the `Content-Length` of the JSON media type is the size of the JSON body instead.
+/// A length that doesn't describe the contents as they are, because the response is compressed, is ignored.
+pub async fn get_blob_size_raw(client: &GithubClient, url: &str) -> Result<Option<u64>, Error> {
+    let (_, headers, _) =
+        send_with_headers(client, client.http().head(url), MEDIA_TYPE_RAW).await?;
+    if headers.contains_key(reqwest::header::CONTENT_ENCODING) {
+        return Ok(None);
+    }
+
+    Ok(headers
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok()))
+}
+
+/// Fetches the contents of the blob at `url`, which is `size` bytes long.
+///
+/// Blobs up to [BLOB_API_SIZE_LIMIT] are fetched as a [BlobModel] and decoded, and larger blobs are
+/// fetched with the [MEDIA_TYPE_RAW] media type, as are blobs GitHub returns with
+/// [ContentEncoding::None]. If GitHub refuses to return the blob because it is too large,
+/// [Error::BlobTooLargeForApi] is returned.
+///
+/// If the client has a [TreeCache](crate::TreeCache) that [caches blobs](crate::CachePolicy::cache_blobs),
+/// a blob that was fetched before, or found not to exist, is returned from the cache without a request.
+#[cfg(feature = "download")]
+pub async fn get_blob_contents(
+    client: &GithubClient,
+    url: &str,
+    size: u64,
+) -> Result<Vec<u8>, Error> {
+    let (contents, _) = get_blob_contents_counted(client, url, size, None).await?;
+    Ok(contents)
+}
+
+/// Called with the length of each part of a blob's contents as it's received, failing to stop the blob from
+/// being received any further.
+#[cfg(feature = "download")]
+pub(crate) type ChunkCounter<'c> = &'c (dyn Fn(u64) -> Result<(), Error> + Send + Sync);
+
+/// Fetches the contents of the blob at `url` like [get_blob_contents], also returning the size of the body of
+/// the response, as it came over the wire. The size is 0 for blobs returned from the cache.
+///
+/// If `counter` is given, the blob is always fetched with the [MEDIA_TYPE_RAW] media type, so that its
+/// contents can be counted as they arrive, and the fetch fails as soon as `counter` does.
+#[cfg(feature = "download")]
+pub(crate) async fn get_blob_contents_counted(
+    client: &GithubClient,
+    url: &str,
+    size: u64,
+    counter: Option<ChunkCounter<'_>>,
+) -> Result<(Vec<u8>, u64), Error> {
+    let cache = client.cache().filter(|c| c.policy().cache_blobs);
+    let scope = client.cache_scope();
+    if let Some(cached) = cache.and_then(|c| c.get_blob(&scope, url)) {
+        let contents = cached?;
+        if let Some(counter) = counter {
+            counter(contents.len() as u64)?;
+        }
+        return Ok((contents, 0));
+    }
+
+    let result = async {
+        if let Some(counter) = counter {
+            let contents = get_blob_bytes_streamed(client, url, counter).await?;
+            let received = contents.len() as u64;
+            return Ok((contents, received));
+        }
+
+        let mut received = 0;
+        if size <= BLOB_API_SIZE_LIMIT {
+            let (status, body) = send(client, client.http().get(url), MEDIA_TYPE_JSON).await?;
+            received += body.len() as u64;
+            let blob: BlobModel = serde_json::from_slice(&body)
+                .map_err(|e| deserialization_error(status, &body, e))?;
+            if blob.encoding != ContentEncoding::None {
+                return Ok((blob.decode()?, received));
+            }
+        }
+
+        let contents = get_blob_bytes_raw(client, url).await?;
+        received += contents.len() as u64;
+        Ok((contents, received))
+    }
+    .await;
+
+    let result = result.map_err(|e: Error| match e.root() {
+        Error::GithubError(error) if error.message.to_lowercase().contains("too large") => {
+            let too_large = Error::BlobTooLargeForApi {
+                url: url.to_string(),
+                size,
+                message: error.message.clone(),
+            };
+
+            match e.context() {
+                Some(context) => too_large.with_context(context.clone()),
+                None => too_large,
+            }
+        }
+        _ => e,
+    });
+
+    if let Some(cache) = cache {
+        match &result {
+            Ok((contents, _)) => cache.put_blob(&scope, url, contents),
+            Err(e) => cache.put_blob_error(&scope, url, e),
+        }
+    }
+    result
+}
+
+/// Fetches the contents of the blob at `url` with the [MEDIA_TYPE_RAW] media type, passing the length of each
+/// chunk to `counter` as it arrives and stopping as soon as it fails.
+#[cfg(feature = "download")]
+async fn get_blob_bytes_streamed(
+    client: &GithubClient,
+    url: &str,
+    counter: ChunkCounter<'_>,
+) -> Result<Vec<u8>, Error> {
+    let mut response = send_streaming(client, client.http().get(url), MEDIA_TYPE_RAW).await?;
+    let mut contents = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        client.budget().record_body(chunk.len());
+        counter(chunk.len() as u64)?;
+        contents.extend_from_slice(&chunk);
+    }
+    Ok(contents)
+}
+
+/// The URL of the GitHub GraphQL API on github.com. Requests are sent to the GraphQL API of the client's
+/// [api_url](GithubClient::api_url), which is this for the default.
+#[cfg(feature = "graphql")]
+pub const GRAPHQL_URL: &str = "https://api.github.com/graphql";
+
+/// Makes a request to the GitHub GraphQL API, deserializing the `data` of the response as `T`.
+///
+/// The GraphQL API requires the client to have an access token.
+#[cfg(feature = "graphql")]
+pub async fn post_graphql<T>(
+    client: &GithubClient,
+    query: &str,
+    variables: serde_json::Value,
+) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let body = serde_json::to_vec(&serde_json::json!({ "query": query, "variables": variables }))?;
+    let request = client
+        .http()
+        .post(client.graphql_url())
+        .header("Content-Type", "application/json")
+        .body(body);
+
+    let response: GraphQlResponseModel<T> = get_model(client, request, MEDIA_TYPE_JSON).await?;
+    match (response.data, response.errors) {
+        (Some(data), None) => Ok(data),
+        (_, Some(errors)) if !errors.is_empty() => {
+            let message = errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<_>>()
+                .join("; ");
+            let errors = errors
+                .into_iter()
+                .map(|e| GithubApiErrorDetail {
+                    code: e.error_type,
+                    message: Some(e.message),
+                    ..GithubApiErrorDetail::default()
+                })
+                .collect();
+
+            Err(Error::GithubError(GithubApiError {
+                errors,
+                ..GithubApiError::new(message)
+            }))
+        }
+        (data, _) => data.ok_or_else(|| Error::Other(String::from("GraphQL response had no data"))),
+    }
+}
+
+/// Makes a GET request to any GitHub API `url` with the given `Accept` media type, deserializing
+/// the response as `T`.
+///
+/// This can be used to opt into API previews or alternative media types not otherwise supported by
+/// this module.
+pub async fn get_with_accept<T>(client: &GithubClient, url: &str, accept: &str) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    get_model(client, client.http().get(url), accept).await
+}
+
+/// Executes a request with the given `Accept` header, returning the status and body of the response if it
+/// was successful.
+pub(crate) async fn send(
+    client: &GithubClient,
+    request: reqwest::RequestBuilder,
+    accept: &str,
+) -> Result<(StatusCode, Vec<u8>), Error> {
+    let (status, _, body) = send_with_headers(client, request, accept).await?;
+    Ok((status, body))
+}
+
+/// Executes a request like [send], also returning the headers of the response.
+pub(crate) async fn send_with_headers(
+    client: &GithubClient,
+    request: reqwest::RequestBuilder,
+    accept: &str,
+) -> Result<(StatusCode, HeaderMap, Vec<u8>), Error> {
+    let response = send_streaming(client, request, accept).await?;
+    let status = response.status();
+    let headers = response.headers().clone();
+    let url = response.url().to_string();
+    let body = response.bytes().await.map_err(|e| {
+        Error::from(e).with_context(ErrorContext {
+            url: Some(url),
+            ..ErrorContext::default()
+        })
+    })?;
+    client.budget().record_body(body.len());
+    Ok((status, headers, body.into()))
+}
+
+/// Executes a request like [send], returning the response of a successful request before its body is read,
+/// so that the body can be processed as it arrives. The caller records the size of the body in the client's
+/// [RequestBudget](crate::RequestBudget) as it reads it.
+pub(crate) async fn send_streaming(
+    client: &GithubClient,
+    request: reqwest::RequestBuilder,
+    accept: &str,
+) -> Result<reqwest::Response, Error> {
+    let request = request.header("Accept", accept).build()?;
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let observer = client.observer();
+
+    if let Some(observer) = observer {
+        observer.on_request(&ApiRequest {
+            method: method.as_str(),
+            url: &url,
+        });
+    }
+
+    let started = Instant::now();
+    let url_context = || ErrorContext {
+        url: Some(url.clone()),
+        ..ErrorContext::default()
+    };
+    let response = client
+        .http()
+        .execute(request)
+        .await
+        .map_err(|e| Error::from(e).with_context(url_context()))?;
+    client.budget().record(response.headers());
+    let status = response.status();
+    let headers = response.headers().clone();
+    let retry_after = rate_limit_retry_after(&headers);
+    telemetry::record_response(&url, status, client.budget());
+
+    if let Some(observer) = observer {
+        observer.on_response(&ApiResponse {
+            method: method.as_str(),
+            url: &url,
+            status: status.as_u16(),
+            headers: &headers,
+            duration: started.elapsed(),
+        });
+    }
+
+    // only conditional requests are answered with 304, and their callers check for it
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| Error::from(e).with_context(url_context()))?;
+    client.budget().record_body(body.len());
+
+    let error = error_from_body(status, retry_after, &body);
+    if let (Some(observer), Error::RateLimited { retry_after, .. }) = (observer, &error) {
+        observer.on_rate_limit(&url, *retry_after);
+    }
+
+    Err(error.with_context(ErrorContext {
+        status: Some(status.as_u16()),
+        ..url_context()
+    }))
+}
+
+/// Executes a request and deserializes the response as either `T` or a GitHub error message.
+async fn get_model<T>(
+    client: &GithubClient,
+    request: reqwest::RequestBuilder,
+    accept: &str,
+) -> Result<T, Error>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let (status, body) = send(client, request, accept).await?;
+    serde_json::from_slice(&body).map_err(|e| deserialization_error(status, &body, e))
+}
+
+/// How many characters of an unexpected response body are kept in [Error::UnexpectedResponse].
+const BODY_PREVIEW_LENGTH: usize = 512;
+
+/// Returns the start of `body` as text, for showing in an [Error::UnexpectedResponse].
+fn body_preview(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    match body.char_indices().nth(BODY_PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.into_owned(),
+    }
+}
+
+/// Converts an error deserializing a successful response into an [Error]. Bodies that aren't JSON at all,
+/// such as HTML error pages from a proxy, are returned as [Error::UnexpectedResponse].
+pub(crate) fn deserialization_error(
+    status: StatusCode,
+    body: &[u8],
+    error: serde_json::Error,
+) -> Error {
+    match error.classify() {
+        Category::Syntax | Category::Eof => Error::UnexpectedResponse {
+            status: status.as_u16(),
+            body_preview: body_preview(body),
+        },
+        _ => error.into(),
+    }
+}
+
+/// Converts the body of an unsuccessful response into an [Error], using GitHub's error object if there is one.
+fn error_from_body(status: StatusCode, retry_after: Option<Duration>, body: &[u8]) -> Error {
+    match serde_json::from_slice::<GithubApiError>(body) {
+        Ok(error) => github_error(status, retry_after, error),
+        // rate limits are still recognised by their status and headers
+        Err(_) => {
+            match github_error(status, retry_after, GithubApiError::new(body_preview(body))) {
+                Error::GithubError(error) => Error::UnexpectedResponse {
+                    status: status.as_u16(),
+                    body_preview: error.message,
+                },
+                error => error,
+            }
+        }
+    }
+}
+
+/// Converts an error object returned by GitHub into an [Error].
+fn github_error(status: StatusCode, retry_after: Option<Duration>, error: GithubApiError) -> Error {
+    if status == StatusCode::CONFLICT && error.message.contains("Repository is empty") {
+        return Error::EmptyRepository;
+    }
+
+    let is_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && (retry_after.is_some() || error.message.contains("rate limit")));
+
+    match is_limited {
+        true => Error::RateLimited {
+            retry_after: retry_after.unwrap_or(DEFAULT_RETRY_AFTER),
+            message: error.message,
+            guidance: None,
+        },
+        false => Error::GithubError(error),
+    }
+}
+
+/// Reads how long to wait before retrying from the `Retry-After` header, or from the
+/// `X-RateLimit-Reset` header if the primary rate limit has been used up.
+fn rate_limit_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok()?.parse::<u64>().ok();
+
+    if let Some(seconds) = header("retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    if header("x-ratelimit-remaining") != Some(0) {
+        return None;
+    }
+
+    let reset = UNIX_EPOCH + Duration::from_secs(header("x-ratelimit-reset")?);
+    Some(
+        reset
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -0,0 +1,178 @@
+use std::{borrow::Cow, fmt};
+
+use serde::Serialize;
+
+use crate::{raw, Error, GithubBranchPath, GithubClient, ObjectId};
+
+/// A reference to a point in a repository's history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GithubRef<'g> {
+    /// The head of a branch, such as `main`.
+    Branch(&'g str),
+    /// A lightweight or annotated tag, such as `v1.0.0`.
+    Tag(&'g str),
+    /// The SHA1 or SHA256 hash of a commit.
+    Commit(&'g str),
+    /// The SHA1 or SHA256 hash of a tree, which is fetched directly and has no associated commit.
+    Tree(&'g str),
+    /// The head commit of a pull request, by number. This works for pull requests from forks too.
+    PullRequest(u64),
+}
+
+impl<'g> GithubRef<'g> {
+    /// Interprets a string naming a reference.
+    ///
+    /// - `tags/{name}` or `refs/tags/{name}` is a [GithubRef::Tag].
+    /// - `pull/{number}` or `refs/pull/{number}/head` is a [GithubRef::PullRequest].
+    /// - A full 40 character SHA1 or 64 character SHA256 hexadecimal hash is a [GithubRef::Commit].
+    /// - Anything else, optionally prefixed with `heads/` or `refs/heads/`, is a [GithubRef::Branch].
+    pub fn parse(reference: &'g str) -> GithubRef<'g> {
+        let reference = reference.strip_prefix("refs/").unwrap_or(reference);
+        if let Some(tag) = reference.strip_prefix("tags/") {
+            return GithubRef::Tag(tag);
+        }
+
+        let pull_request = reference.strip_prefix("pull/").map(|number| {
+            let number = number.strip_suffix("/head").unwrap_or(number);
+            number.parse()
+        });
+        if let Some(Ok(number)) = pull_request {
+            return GithubRef::PullRequest(number);
+        }
+
+        if matches!(reference.len(), 40 | 64) && reference.chars().all(|c| c.is_ascii_hexdigit()) {
+            return GithubRef::Commit(reference);
+        }
+
+        GithubRef::Branch(reference.strip_prefix("heads/").unwrap_or(reference))
+    }
+
+    /// Returns the branch name, tag name, hash or pull request number this reference points to.
+    ///
+    /// This returns a [Cow] rather than a `&str`, since a pull request's number has to be formatted. Use
+    /// [to_string](ToString::to_string) for a string that [parse](GithubRef::parse) reads back as the same
+    /// pull request.
+    pub fn name(&self) -> Cow<'g, str> {
+        match *self {
+            GithubRef::Branch(name)
+            | GithubRef::Tag(name)
+            | GithubRef::Commit(name)
+            | GithubRef::Tree(name) => Cow::Borrowed(name),
+            GithubRef::PullRequest(number) => Cow::Owned(number.to_string()),
+        }
+    }
+
+    /// Returns a revision expression naming the commit of this reference, as accepted by the commits
+    /// and GraphQL APIs.
+    pub(crate) fn commit_expression(self) -> Result<Cow<'g, str>, Error> {
+        match self {
+            GithubRef::Tree(sha) => Err(Error::Other(format!(
+                "tree {} isn't a commit and has no history",
+                sha
+            ))),
+            GithubRef::PullRequest(number) => Ok(Cow::Owned(format!("refs/pull/{}/head", number))),
+            reference => Ok(reference.name()),
+        }
+    }
+}
+
+impl fmt::Display for GithubRef<'_> {
+    /// Writes the [name](GithubRef::name) of the reference, or `pull/{number}/head` for a pull request, so that
+    /// [parse](GithubRef::parse) reads it back as a pull request rather than a branch.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GithubRef::PullRequest(number) => write!(f, "pull/{}/head", number),
+            reference => f.write_str(&reference.name()),
+        }
+    }
+}
+
+/// The commit and root tree a [GithubRef] pointed to when it was resolved.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResolvedRef {
+    /// The hash of the commit, or `None` if a [GithubRef::Tree] was resolved.
+    pub commit_sha: Option<ObjectId>,
+    /// The hash of the commit's root tree.
+    pub tree_sha: ObjectId,
+}
+
+/// The SHA1 hash of the empty tree, which is the same in every git repository.
+pub const EMPTY_TREE_SHA: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// The SHA256 hash of the empty tree, for repositories using the SHA256 [object format](crate::ObjectFormat).
+pub const EMPTY_TREE_SHA256: &str =
+    "6ef19b41225c5369f1c104d45d8d85efa9b057b53b14b4b9b939dd74decc5321";
+
+impl ResolvedRef {
+    /// Resolves the reference of `path` to a commit and tree.
+    ///
+    /// - Branches are looked up under `refs/heads`, and tags under `refs/tags`, following annotated tags to their commit.
+    /// - Pull requests are looked up to find their head commit.
+    /// - Commits are looked up directly to find their tree.
+    /// - Trees are returned as-is without making any requests.
+    ///
+    /// Fails with [Error::EmptyRepository] as the [root](Error::root) error if the repository has no commits.
+    ///
+    /// If the client has a [TreeCache](crate::TreeCache), references it has resolved recently are reused.
+    pub async fn resolve(
+        client: &GithubClient,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<ResolvedRef, Error> {
+        if let Some(resolved) = client
+            .cache()
+            .and_then(|c| c.get_ref(&client.cache_scope(), path))
+        {
+            return Ok(resolved);
+        }
+
+        let commit_sha = match path.reference {
+            GithubRef::Tree(sha) => {
+                return Ok(ResolvedRef {
+                    commit_sha: None,
+                    tree_sha: sha.parse()?,
+                })
+            }
+            GithubRef::Commit(sha) => sha.to_string(),
+            GithubRef::PullRequest(number) => {
+                raw::get_pull_request_raw(client, path, number)
+                    .await?
+                    .head
+                    .sha
+            }
+            GithubRef::Branch(name) => {
+                let git_ref = raw::get_ref_raw(client, path, &format!("heads/{}", name)).await?;
+                git_ref.object.sha
+            }
+            GithubRef::Tag(name) => {
+                let git_ref = raw::get_ref_raw(client, path, &format!("tags/{}", name)).await?;
+                let mut object = git_ref.object;
+
+                // annotated tags point at a tag object, which may itself point at another tag
+                while object.object_type == "tag" {
+                    object = raw::get_tag_raw(client, path, &object.sha).await?.object;
+                }
+
+                if object.object_type != "commit" {
+                    return Err(Error::Other(format!(
+                        "tag {} points to a {}, not a commit",
+                        name, object.object_type
+                    )));
+                }
+
+                object.sha
+            }
+        };
+
+        let commit = raw::get_commit_raw(client, path, &commit_sha).await?;
+        let resolved = ResolvedRef {
+            commit_sha: Some(commit.sha.parse()?),
+            tree_sha: commit.tree.sha.parse()?,
+        };
+
+        if let Some(cache) = client.cache() {
+            cache.put_ref(&client.cache_scope(), path, &resolved);
+        }
+
+        Ok(resolved)
+    }
+}
@@ -0,0 +1,364 @@
+use std::{sync::Mutex, time::Duration};
+
+use serde::Serialize;
+
+use crate::{error::serialize_duration_ms, DownloadEvent, DownloadReporter, Error, FetchStrategy};
+
+/// An owned copy of a [DownloadEvent], as stored by [CollectingReporter].
+///
+/// This serializes the same way as the [DownloadEvent] it was made from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CollectedEvent {
+    /// See [DownloadEvent::DownloadStarted].
+    DownloadStarted {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+    },
+    /// See [DownloadEvent::DownloadCompleted].
+    DownloadCompleted {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+        /// The size of the responses the file was fetched with, in bytes.
+        bytes_received: u64,
+        /// The number of bytes written.
+        bytes_written: u64,
+    },
+    /// See [DownloadEvent::DownloadFailed].
+    DownloadFailed {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+        /// The [Error] that was encountered while attempting to download the file.
+        error: Error,
+    },
+    /// See [DownloadEvent::RateLimited].
+    RateLimited {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+        /// How long downloads are paused for.
+        #[serde(rename = "retry_after_ms", serialize_with = "serialize_duration_ms")]
+        retry_after: Duration,
+    },
+    /// See [DownloadEvent::PathCollision].
+    PathCollision {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+        /// The path of the file that was going to be written to the same place first.
+        existing: String,
+    },
+    /// See [DownloadEvent::DownloadPlanned].
+    DownloadPlanned {
+        /// How many files will be downloaded.
+        files: usize,
+        /// The total size in bytes of the files that will be downloaded.
+        total_bytes: u64,
+    },
+    /// See [DownloadEvent::UnauthenticatedRateLimit].
+    UnauthenticatedRateLimit {
+        /// About how many requests the download needs.
+        requests: usize,
+        /// How many requests GitHub allows per hour without an access token.
+        limit: u64,
+    },
+    /// See [DownloadEvent::FileUnchanged].
+    FileUnchanged {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+    },
+    /// See [DownloadEvent::FileDeleted].
+    FileDeleted {
+        /// The path of the file relative to the output directory.
+        path: String,
+    },
+    /// See [DownloadEvent::SyncFinished].
+    SyncFinished {
+        /// How many files were downloaded that weren't on disk before.
+        added: usize,
+        /// How many files on disk were replaced because their contents changed.
+        updated: usize,
+        /// How many files were deleted.
+        deleted: usize,
+        /// How many files on disk already matched.
+        unchanged: usize,
+    },
+    /// See [DownloadEvent::FilteredOut].
+    FilteredOut {
+        /// The path of the file relative to the root of the repository.
+        path: String,
+        /// The first excluded glob that matched the file, if any.
+        matched_exclude: Option<String>,
+    },
+    /// See [DownloadEvent::FetchFallback].
+    FetchFallback {
+        /// The strategy that failed.
+        from: FetchStrategy,
+        /// The strategy that will be tried next.
+        to: FetchStrategy,
+        /// The [Error] that `from` failed with.
+        error: Error,
+    },
+    /// See [DownloadEvent::FetchStrategyUsed].
+    FetchStrategyUsed {
+        /// The strategy that succeeded.
+        strategy: FetchStrategy,
+    },
+}
+
+impl CollectedEvent {
+    /// Returns the path of the file the event is about, if it's about a single file.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            CollectedEvent::DownloadStarted { path }
+            | CollectedEvent::DownloadCompleted { path, .. }
+            | CollectedEvent::DownloadFailed { path, .. }
+            | CollectedEvent::RateLimited { path, .. }
+            | CollectedEvent::PathCollision { path, .. }
+            | CollectedEvent::FileUnchanged { path }
+            | CollectedEvent::FileDeleted { path }
+            | CollectedEvent::FilteredOut { path, .. } => Some(path),
+            CollectedEvent::DownloadPlanned { .. }
+            | CollectedEvent::UnauthenticatedRateLimit { .. }
+            | CollectedEvent::SyncFinished { .. }
+            | CollectedEvent::FetchFallback { .. }
+            | CollectedEvent::FetchStrategyUsed { .. } => None,
+        }
+    }
+}
+
+impl From<DownloadEvent<'_>> for CollectedEvent {
+    fn from(value: DownloadEvent<'_>) -> Self {
+        match value {
+            DownloadEvent::DownloadStarted { path } => CollectedEvent::DownloadStarted {
+                path: path.to_string(),
+            },
+            DownloadEvent::DownloadCompleted {
+                path,
+                bytes_received,
+                bytes_written,
+            } => CollectedEvent::DownloadCompleted {
+                path: path.to_string(),
+                bytes_received,
+                bytes_written,
+            },
+            DownloadEvent::DownloadFailed { path, error } => CollectedEvent::DownloadFailed {
+                path: path.to_string(),
+                error,
+            },
+            DownloadEvent::RateLimited { path, retry_after } => CollectedEvent::RateLimited {
+                path: path.to_string(),
+                retry_after,
+            },
+            DownloadEvent::PathCollision { path, existing } => CollectedEvent::PathCollision {
+                path: path.to_string(),
+                existing: existing.to_string(),
+            },
+            DownloadEvent::DownloadPlanned { files, total_bytes } => {
+                CollectedEvent::DownloadPlanned { files, total_bytes }
+            }
+            DownloadEvent::UnauthenticatedRateLimit { requests, limit } => {
+                CollectedEvent::UnauthenticatedRateLimit { requests, limit }
+            }
+            DownloadEvent::FileUnchanged { path } => CollectedEvent::FileUnchanged {
+                path: path.to_string(),
+            },
+            DownloadEvent::FileDeleted { path } => CollectedEvent::FileDeleted {
+                path: path.to_string(),
+            },
+            DownloadEvent::SyncFinished {
+                added,
+                updated,
+                deleted,
+                unchanged,
+            } => CollectedEvent::SyncFinished {
+                added,
+                updated,
+                deleted,
+                unchanged,
+            },
+            DownloadEvent::FilteredOut {
+                path,
+                matched_exclude,
+            } => CollectedEvent::FilteredOut {
+                path: path.to_string(),
+                matched_exclude: matched_exclude.map(str::to_string),
+            },
+            DownloadEvent::FetchFallback { from, to, error } => {
+                CollectedEvent::FetchFallback { from, to, error }
+            }
+            DownloadEvent::FetchStrategyUsed { strategy } => {
+                CollectedEvent::FetchStrategyUsed { strategy }
+            }
+        }
+    }
+}
+
+/// A reporter that keeps every event it receives, such as for making assertions in tests.
+#[derive(Debug, Default)]
+pub struct CollectingReporter {
+    events: Mutex<Vec<CollectedEvent>>,
+}
+
+impl CollectingReporter {
+    /// Creates a new [CollectingReporter] with no events.
+    pub fn new() -> CollectingReporter {
+        CollectingReporter::default()
+    }
+
+    /// Returns every event received so far, in the order they were received.
+    pub fn events(&self) -> Vec<CollectedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl DownloadReporter for CollectingReporter {
+    fn on_event<'p>(&'p self, event: DownloadEvent<'p>) {
+        self.events.lock().unwrap().push(event.into());
+    }
+}
+
+/// A reporter that logs every event through the [log](https://crates.io/crates/log) crate.
+///
+/// Planned, started, completed and filtered out downloads are logged at the debug level, and everything else
+/// as a warning.
+#[cfg(feature = "log")]
+#[derive(Debug, Default)]
+pub struct LogReporter {}
+
+#[cfg(feature = "log")]
+impl DownloadReporter for LogReporter {
+    fn on_event<'p>(&'p self, event: DownloadEvent<'p>) {
+        match event {
+            DownloadEvent::DownloadStarted { path } => log::debug!("downloading {}", path),
+            DownloadEvent::DownloadCompleted {
+                path,
+                bytes_received,
+                bytes_written,
+            } => log::debug!(
+                "downloaded {} ({} bytes, {} received)",
+                path,
+                bytes_written,
+                bytes_received
+            ),
+            DownloadEvent::DownloadFailed { path, error } => {
+                log::warn!("failed to download {}: {:?}", path, error)
+            }
+            DownloadEvent::RateLimited { path, retry_after } => log::warn!(
+                "rate limited while downloading {}, retrying in {:?}",
+                path,
+                retry_after
+            ),
+            DownloadEvent::PathCollision { path, existing } => {
+                log::warn!("{} would be written to the same path as {}", path, existing)
+            }
+            DownloadEvent::DownloadPlanned { files, total_bytes } => {
+                log::debug!(
+                    "downloading {} files, {} bytes in total",
+                    files,
+                    total_bytes
+                )
+            }
+            DownloadEvent::UnauthenticatedRateLimit { requests, limit } => {
+                log::warn!("{}", unauthenticated_warning(requests, limit))
+            }
+            DownloadEvent::FileUnchanged { path } => log::debug!("{} is unchanged", path),
+            DownloadEvent::FileDeleted { path } => log::info!("deleted {}", path),
+            DownloadEvent::SyncFinished {
+                added,
+                updated,
+                deleted,
+                unchanged,
+            } => log::info!(
+                "{} added, {} updated, {} deleted, {} unchanged",
+                added,
+                updated,
+                deleted,
+                unchanged
+            ),
+            DownloadEvent::FilteredOut {
+                path,
+                matched_exclude: Some(glob),
+            } => log::debug!("skipped {}, which matches the excluded glob {}", path, glob),
+            DownloadEvent::FilteredOut {
+                path,
+                matched_exclude: None,
+            } => log::debug!("skipped {}, which matches none of the included globs", path),
+            DownloadEvent::FetchFallback { from, to, error } => {
+                log::warn!("{:?} failed, trying {:?} instead: {:?}", from, to, error)
+            }
+            DownloadEvent::FetchStrategyUsed { strategy } => {
+                log::debug!("downloaded with {:?}", strategy)
+            }
+        }
+    }
+}
+
+/// A reporter that shows the progress of a download with an [indicatif](https://crates.io/crates/indicatif)
+/// progress bar, counting finished files.
+#[cfg(feature = "progress")]
+#[derive(Debug, Clone)]
+pub struct ProgressBarReporter {
+    bar: indicatif::ProgressBar,
+}
+
+#[cfg(feature = "progress")]
+impl ProgressBarReporter {
+    /// Creates a new [ProgressBarReporter] with a bar for downloading `files` files, such as the number returned
+    /// by [estimate_requests](crate::Downloader::estimate_requests).
+    /// The length of the bar is corrected once the download has been planned.
+    pub fn new(files: u64) -> ProgressBarReporter {
+        ProgressBarReporter::with_bar(indicatif::ProgressBar::new(files))
+    }
+
+    /// Creates a new [ProgressBarReporter] that updates an existing progress bar.
+    pub fn with_bar(bar: indicatif::ProgressBar) -> ProgressBarReporter {
+        ProgressBarReporter { bar }
+    }
+
+    /// Returns the progress bar, such as for finishing it once the download is complete.
+    pub fn bar(&self) -> &indicatif::ProgressBar {
+        &self.bar
+    }
+}
+
+#[cfg(feature = "progress")]
+impl DownloadReporter for ProgressBarReporter {
+    fn on_event<'p>(&'p self, event: DownloadEvent<'p>) {
+        match event {
+            DownloadEvent::DownloadStarted { path } => self.bar.set_message(path.to_string()),
+            DownloadEvent::DownloadCompleted { .. } => self.bar.inc(1),
+            DownloadEvent::DownloadFailed { path, error } => {
+                self.bar
+                    .println(format!("failed to download {}: {:?}", path, error));
+                self.bar.inc(1);
+            }
+            DownloadEvent::RateLimited { retry_after, .. } => self
+                .bar
+                .set_message(format!("rate limited, retrying in {:?}", retry_after)),
+            DownloadEvent::PathCollision { .. } => {}
+            DownloadEvent::DownloadPlanned { files, .. } => self.bar.set_length(files as u64),
+            DownloadEvent::UnauthenticatedRateLimit { requests, limit } => {
+                self.bar.println(unauthenticated_warning(requests, limit))
+            }
+            DownloadEvent::FileUnchanged { .. } => self.bar.inc(1),
+            DownloadEvent::FetchFallback { from, to, .. } => {
+                self.bar
+                    .println(format!("{:?} failed, trying {:?} instead", from, to));
+                self.bar.set_position(0);
+            }
+            DownloadEvent::FileDeleted { .. }
+            | DownloadEvent::FilteredOut { .. }
+            | DownloadEvent::SyncFinished { .. }
+            | DownloadEvent::FetchStrategyUsed { .. } => {}
+        }
+    }
+}
+
+/// Explains a [DownloadEvent::UnauthenticatedRateLimit].
+#[cfg(any(feature = "log", feature = "progress"))]
+fn unauthenticated_warning(requests: usize, limit: u64) -> String {
+    format!(
+        "downloading without an access token needs about {} requests, but GitHub only allows {} an hour; \
+        set an access token, or download the repository as an archive from github.com instead",
+        requests, limit
+    )
+}
@@ -0,0 +1,47 @@
+use crate::raw::RepositoryModel;
+
+/// Information about a repository, as returned by [GithubClient::get_repo](crate::GithubClient::get_repo).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoInfo {
+    /// The name of the repository including its owner, such as `octocat/Hello-World`.
+    pub full_name: String,
+    /// The name of the branch checked out by default.
+    pub default_branch: String,
+    /// The description of the repository.
+    pub description: Option<String>,
+    /// The SPDX identifier of the repository's license, such as `MIT`.
+    ///
+    /// This is `None` if GitHub didn't find a license, or found one it couldn't identify.
+    pub license_spdx_id: Option<String>,
+    /// The topics the repository is tagged with.
+    pub topics: Vec<String>,
+    /// Who can see the repository: `public`, `private` or `internal`.
+    pub visibility: Option<String>,
+    /// Whether the repository has been archived and is read-only.
+    pub archived: bool,
+    /// Whether the repository has been disabled.
+    pub disabled: bool,
+    /// The size of the repository in kilobytes.
+    pub size_kb: u64,
+}
+
+impl From<RepositoryModel> for RepoInfo {
+    fn from(value: RepositoryModel) -> Self {
+        let license_spdx_id = value
+            .license
+            .and_then(|l| l.spdx_id)
+            .filter(|id| id != "NOASSERTION");
+
+        RepoInfo {
+            full_name: value.full_name,
+            default_branch: value.default_branch,
+            description: value.description,
+            license_spdx_id,
+            topics: value.topics,
+            visibility: value.visibility,
+            archived: value.archived,
+            disabled: value.disabled,
+            size_kb: value.size,
+        }
+    }
+}
@@ -1,25 +1,216 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt, time::Duration};
 
 use const_format::formatcp;
 use reqwest::{header, Client, ClientBuilder};
 
 use crate::Error;
 
-const USER_AGENT: &'static str =
+/// The `User-Agent` sent with every request unless [HttpOptions::user_agent] is set.
+pub const DEFAULT_USER_AGENT: &str =
     formatcp!("azrogers/grab_github version {}", env!("CARGO_PKG_VERSION"));
 
+/// How an access token is sent in the `Authorization` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthScheme {
+    /// [AuthScheme::Bearer] for fine-grained personal access tokens, GitHub App tokens and JSON web tokens,
+    /// which are recognized by their prefixes, and [AuthScheme::Token] for anything else.
+    #[default]
+    Auto,
+    /// `Authorization: token <token>`, which GitHub accepts for classic personal access tokens and OAuth tokens.
+    Token,
+    /// `Authorization: Bearer <token>`, which GitHub accepts for every kind of token and requires for JSON web
+    /// tokens. Some proxies only accept this scheme.
+    Bearer,
+}
+
+impl AuthScheme {
+    /// Returns the scheme `token` will be sent with, resolving [AuthScheme::Auto] to [AuthScheme::Token] or
+    /// [AuthScheme::Bearer].
+    pub fn for_token(self, token: &str) -> AuthScheme {
+        match self {
+            AuthScheme::Auto => {
+                const BEARER_PREFIXES: &[&str] = &["github_pat_", "ghs_", "ghu_", "ghr_"];
+                let is_jwt = token.starts_with("eyJ") && token.split('.').count() == 3;
+                match is_jwt || BEARER_PREFIXES.iter().any(|p| token.starts_with(p)) {
+                    true => AuthScheme::Bearer,
+                    false => AuthScheme::Token,
+                }
+            }
+            scheme => scheme,
+        }
+    }
+
+    /// Returns the value of the `Authorization` header for `token`.
+    pub fn header_value(self, token: &str) -> String {
+        match self.for_token(token) {
+            AuthScheme::Bearer => format!("Bearer {}", token),
+            _ => format!("token {}", token),
+        }
+    }
+}
+
+/// Options for the HTTP requests made to the GitHub API.
+///
+/// Formatting these with `{:?}` redacts the values of credential headers, such as `Authorization`.
+#[derive(Clone, Default)]
+pub struct HttpOptions<'o> {
+    /// The `User-Agent` to identify your application with, in place of [DEFAULT_USER_AGENT].
+    ///
+    /// GitHub asks that applications identify themselves, such as with `my-app/1.0 (+https://example.com)`.
+    /// To extend the default instead of replacing it, include [DEFAULT_USER_AGENT] in the value.
+    pub user_agent: Option<Cow<'o, str>>,
+    /// Additional headers to send with every request, as pairs of a name and a value.
+    /// These replace any default headers with the same name.
+    pub extra_headers: Vec<(Cow<'o, str>, Cow<'o, str>)>,
+    /// How the access token is sent.
+    /// The default is [AuthScheme::Auto].
+    pub auth_scheme: AuthScheme,
+    /// A SOCKS5 proxy to send every request through, such as one opened with `ssh -D` or Tor.
+    ///
+    /// This needs the `socks` feature. Without it, creating a client with a proxy set fails.
+    pub socks_proxy: Option<SocksProxy<'o>>,
+    /// Whether to speak HTTP/2 from the start of every connection, without negotiating it or falling back
+    /// to HTTP/1.1.
+    ///
+    /// HTTP/2 is already used whenever the server offers it over TLS, which api.github.com does, so all of a
+    /// download's requests are multiplexed over one connection. This only skips the negotiation, and is
+    /// needed to use HTTP/2 with servers that support it over plain HTTP. Servers and proxies that only speak
+    /// HTTP/1.1 fail every request with it set.
+    pub http2_prior_knowledge: bool,
+    /// How often to ping HTTP/2 connections to keep them open, including while they're idle, or `None` not
+    /// to. This keeps connections alive through proxies and load balancers that close quiet ones.
+    pub http2_keep_alive_interval: Option<Duration>,
+    /// How long an unused connection is kept open for reuse, or `None` to use reqwest's default of 90
+    /// seconds.
+    pub pool_idle_timeout: Option<Duration>,
+    /// The most unused connections kept open to each host, or `None` for no limit.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How often TCP keepalive probes are sent on open connections, or `None` to use reqwest's default.
+    pub tcp_keepalive: Option<Duration>,
+}
+
+/// A SOCKS5 proxy for [HttpOptions::socks_proxy].
+///
+/// Formatting this with `{:?}` redacts the password.
+#[derive(Clone)]
+pub struct SocksProxy<'o> {
+    /// The `host:port` of the proxy, such as `127.0.0.1:1080`.
+    pub address: Cow<'o, str>,
+    /// The username and password to authenticate with, if the proxy requires them.
+    pub credentials: Option<(Cow<'o, str>, Cow<'o, str>)>,
+    /// Whether host names are resolved by the proxy rather than locally.
+    /// Tor and networks that block DNS lookups need this, so it defaults to `true`.
+    pub remote_dns: bool,
+}
+
+impl<'o> SocksProxy<'o> {
+    /// Creates a [SocksProxy] for the proxy at `address`, without authentication.
+    pub fn new(address: impl Into<Cow<'o, str>>) -> SocksProxy<'o> {
+        SocksProxy {
+            address: address.into(),
+            credentials: None,
+            remote_dns: true,
+        }
+    }
+
+    /// Returns this proxy authenticating with the given username and password.
+    pub fn with_credentials(
+        self,
+        username: impl Into<Cow<'o, str>>,
+        password: impl Into<Cow<'o, str>>,
+    ) -> SocksProxy<'o> {
+        SocksProxy {
+            credentials: Some((username.into(), password.into())),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "socks")]
+    fn to_reqwest(&self) -> Result<reqwest::Proxy, Error> {
+        let scheme = if self.remote_dns { "socks5h" } else { "socks5" };
+        let proxy = reqwest::Proxy::all(format!("{}://{}", scheme, self.address))?;
+        Ok(match &self.credentials {
+            Some((username, password)) => proxy.basic_auth(username, password),
+            None => proxy,
+        })
+    }
+
+    #[cfg(not(feature = "socks"))]
+    fn to_reqwest(&self) -> Result<reqwest::Proxy, Error> {
+        Err(Error::Other(format!(
+            "can't connect through the SOCKS proxy at {} without the `socks` feature",
+            self.address
+        )))
+    }
+}
+
+impl fmt::Debug for HttpOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let extra_headers: Vec<(&str, &str)> = self
+            .extra_headers
+            .iter()
+            .map(|(name, value)| match is_sensitive(name) {
+                true => (name.as_ref(), REDACTED),
+                false => (name.as_ref(), value.as_ref()),
+            })
+            .collect();
+
+        f.debug_struct("HttpOptions")
+            .field("user_agent", &self.user_agent)
+            .field("extra_headers", &extra_headers)
+            .field("auth_scheme", &self.auth_scheme)
+            .field("socks_proxy", &self.socks_proxy)
+            .field("http2_prior_knowledge", &self.http2_prior_knowledge)
+            .field("http2_keep_alive_interval", &self.http2_keep_alive_interval)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .finish()
+    }
+}
+
+impl fmt::Debug for SocksProxy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SocksProxy")
+            .field("address", &self.address)
+            .field(
+                "credentials",
+                &self
+                    .credentials
+                    .as_ref()
+                    .map(|(username, _)| (username, REDACTED)),
+            )
+            .field("remote_dns", &self.remote_dns)
+            .finish()
+    }
+}
+
+/// What credentials are replaced with when formatted with `{:?}`.
+const REDACTED: &str = "<redacted>";
+
+/// Returns whether the header `name` holds credentials, which are kept out of debug output.
+fn is_sensitive(name: &str) -> bool {
+    ["authorization", "proxy-authorization", "cookie"]
+        .iter()
+        .any(|sensitive| name.eq_ignore_ascii_case(sensitive))
+}
+
 pub struct HttpRequest {}
 
 impl HttpRequest {
-    /// Creates a [reqwest::Client] with the default settings.
-    pub fn client<'src>(access_token: &Option<Cow<'src, str>>) -> Result<Client, Error> {
+    /// Creates a [reqwest::Client] with the given settings.
+    pub fn client<'src>(
+        access_token: &Option<Cow<'src, str>>,
+        options: &HttpOptions<'_>,
+    ) -> Result<Client, Error> {
+        let user_agent = options.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+
         let mut headers = header::HeaderMap::new();
-        headers.insert("User-Agent", header::HeaderValue::from_static(USER_AGENT));
+        headers.insert("User-Agent", header_value(user_agent)?);
         if let Some(access_token) = access_token {
             headers.insert(
                 "Authorization",
-                header::HeaderValue::from_str(&format!("token {}", access_token))
-                    .map_err(|e| Error::Other(e.to_string()))?,
+                sensitive_header_value(&options.auth_scheme.header_value(access_token))?,
             );
 
             headers.insert(
@@ -28,6 +219,50 @@ impl HttpRequest {
             );
         }
 
-        Ok(ClientBuilder::new().default_headers(headers).build()?)
+        for (name, value) in &options.extra_headers {
+            let name = header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| Error::Other(e.to_string()))?;
+            let value = match is_sensitive(name.as_str()) {
+                true => sensitive_header_value(value)?,
+                false => header_value(value)?,
+            };
+            headers.insert(name, value);
+        }
+
+        let mut builder = ClientBuilder::new().default_headers(headers);
+        if options.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(interval) = options.http2_keep_alive_interval {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = options.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max_idle) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(keepalive) = options.tcp_keepalive {
+            builder = builder.tcp_keepalive(keepalive);
+        }
+        let builder = match &options.socks_proxy {
+            Some(proxy) => builder.proxy(proxy.to_reqwest()?),
+            None => builder,
+        };
+
+        Ok(builder.build()?)
     }
 }
+
+fn header_value(value: &str) -> Result<header::HeaderValue, Error> {
+    header::HeaderValue::from_str(value).map_err(|e| Error::Other(e.to_string()))
+}
+
+/// Creates a header value that reqwest and hyper leave out of their debug output and traces.
+fn sensitive_header_value(value: &str) -> Result<header::HeaderValue, Error> {
+    let mut value = header_value(value)?;
+    value.set_sensitive(true);
+    Ok(value)
+}
@@ -0,0 +1,212 @@
+//! Generating new projects from template repositories, in the style of `cargo-generate`.
+//!
+//! A template repository declares its variables in [TEMPLATE_FILE_NAME]:
+//!
+//! ```toml
+//! [variables.project_name]
+//! prompt = "Name of the project"
+//!
+//! [variables.use_ci]
+//! prompt = "Include CI workflows?"
+//! default = "false"
+//!
+//! [[conditional]]
+//! when = "use_ci"
+//! paths = [".github/**"]
+//! ```
+//!
+//! Every `{{variable}}` in the contents and paths of the template's files is replaced with its value,
+//! and files matching a [ConditionalFiles] entry are only included when its variable is true.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    path::{Component, Path},
+};
+
+use glob_match::glob_match;
+use serde::{Deserialize, Serialize};
+
+pub use crate::transform::substitute;
+use crate::{
+    Blob, ContentTransform, DownloadConfig, DownloadReporter, DownloadedFile, Downloader, Error,
+    Filter, GithubBranchPath, SourceTree, TreeEntryType,
+};
+
+/// The name of the file, at the root of a template repository, that declares its [Template].
+pub const TEMPLATE_FILE_NAME: &str = "template.toml";
+
+/// A variable declared by a [Template].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct TemplateVariable {
+    /// The text to show when asking for a value.
+    pub prompt: Option<String>,
+    /// The value to use if none is given.
+    pub default: Option<String>,
+}
+
+/// A set of files that are only included when a variable is true.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ConditionalFiles {
+    /// The name of the variable. Its value is true if it's `true`, `yes`, `y` or `1`, ignoring case.
+    pub when: String,
+    /// Globs of the files that depend on the variable.
+    pub paths: Vec<String>,
+}
+
+/// The contents of a [TEMPLATE_FILE_NAME] file.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct Template {
+    /// Each variable, keyed by name.
+    pub variables: BTreeMap<String, TemplateVariable>,
+    /// Files that are only included depending on a variable.
+    pub conditional: Vec<ConditionalFiles>,
+}
+
+impl Template {
+    /// Parses the contents of a [TEMPLATE_FILE_NAME] file.
+    pub fn parse(text: &str) -> Result<Template, Error> {
+        toml::from_str(text).map_err(|e| Error::InvalidTemplate(e.to_string()))
+    }
+
+    /// Determines the value of every variable.
+    ///
+    /// Values in `provided` are used as-is. For any other variable, `prompt` is called with its name and
+    /// declaration, and if that returns `None` its default is used. Returns [Error::MissingTemplateValue] for
+    /// the first variable left without a value.
+    pub fn resolve_values<F>(
+        &self,
+        provided: &BTreeMap<String, String>,
+        mut prompt: F,
+    ) -> Result<BTreeMap<String, String>, Error>
+    where
+        F: FnMut(&str, &TemplateVariable) -> Option<String>,
+    {
+        let mut values = provided.clone();
+        for (name, variable) in &self.variables {
+            if values.contains_key(name) {
+                continue;
+            }
+
+            let value = prompt(name, variable)
+                .or_else(|| variable.default.clone())
+                .ok_or_else(|| Error::MissingTemplateValue {
+                    variable: name.clone(),
+                })?;
+            values.insert(name.clone(), value);
+        }
+
+        Ok(values)
+    }
+
+    /// Returns whether the file at `path` in the template should be generated with the given values.
+    pub fn includes(&self, path: &str, values: &BTreeMap<String, String>) -> bool {
+        path != TEMPLATE_FILE_NAME
+            && self.conditional.iter().all(|c| {
+                !c.paths.iter().any(|glob| glob_match(glob, path))
+                    || values.get(&c.when).is_some_and(|v| is_true(v))
+            })
+    }
+}
+
+/// Contains methods for generating a project from a template repository.
+pub struct Scaffold {}
+
+impl Scaffold {
+    /// Fetches the [Template] declared by the repository at `path`.
+    pub async fn fetch_template<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        path: &GithubBranchPath<'_>,
+    ) -> Result<(Template, SourceTree), Error> {
+        let client = config.client()?;
+        let (_, tree) = SourceTree::get_resolved_with_client(&client, path, &Filter::all()).await?;
+        let template = match tree.resolve_blob(TEMPLATE_FILE_NAME) {
+            Some(entry) => Template::parse(&Blob::fetch_text(&client, entry).await?)?,
+            None => Template::default(),
+        };
+
+        Ok((template, tree))
+    }
+
+    /// Generates a project into the config's output directory from the template `tree`, substituting
+    /// `values` into the contents and paths of each file.
+    ///
+    /// Files are downloaded like [download_tree](Downloader::download_tree), as if the template had been written
+    /// with the values already substituted into its paths, so the config's [inspector](DownloadConfig::inspector),
+    /// [permissions_hook](DownloadConfig::permissions_hook), [collision_policy](DownloadConfig::collision_policy)
+    /// and byte quotas all apply. Values are substituted into the contents before the config's own
+    /// [transform](DownloadConfig::transform) runs, and files that aren't valid UTF-8 are copied without
+    /// substitution. [verified](DownloadedFile::verified) refers to the contents before substitution.
+    ///
+    /// Returns [Error::InvalidTemplatePath] if substituting the values into the path of a file would write it
+    /// outside of the output directory, before anything is written.
+    pub async fn generate<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        template: &Template,
+        tree: &SourceTree,
+        values: &BTreeMap<String, String>,
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        let client = config.client()?;
+        let files: Vec<_> = tree
+            .iter()
+            .filter(|n| {
+                n.entry_type == TreeEntryType::Blob && template.includes(n.path.as_str(), values)
+            })
+            .map(|entry| {
+                let path = substitute(entry.path.as_str(), values);
+                if !is_inside(Path::new(&path)) {
+                    return Err(Error::InvalidTemplatePath { path });
+                }
+
+                let mut entry = entry.clone();
+                entry.path = path.into();
+                Ok(Cow::Owned(entry))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let transform = TemplateTransform {
+            values,
+            then: config.transform,
+        };
+        let config = DownloadConfig {
+            transform: Some(&transform),
+            ..config.clone()
+        };
+        let planned = Downloader::plan(&config, files)?;
+        Downloader::download_planned(&config, &client, planned).await
+    }
+}
+
+/// Substitutes template values into the contents of each file, followed by another transform if there is one.
+struct TemplateTransform<'a> {
+    values: &'a BTreeMap<String, String>,
+    then: Option<&'a dyn ContentTransform>,
+}
+
+impl ContentTransform for TemplateTransform<'_> {
+    fn transform(&self, entry: &SourceTree, bytes: Vec<u8>) -> Vec<u8> {
+        let bytes = match String::from_utf8(bytes) {
+            Ok(text) => substitute(&text, self.values).into_bytes(),
+            Err(e) => e.into_bytes(),
+        };
+
+        match self.then {
+            Some(then) => then.transform(entry, bytes),
+            None => bytes,
+        }
+    }
+}
+
+/// Returns whether the relative path `path` stays inside the directory it's relative to.
+fn is_inside(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+fn is_true(value: &str) -> bool {
+    ["true", "yes", "y", "1"]
+        .iter()
+        .any(|t| value.eq_ignore_ascii_case(t))
+}
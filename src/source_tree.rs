@@ -1,53 +1,289 @@
+use camino::{Utf8Path, Utf8PathBuf};
 use futures::future::{BoxFuture, FutureExt};
 use std::{
-    cell::RefCell,
+    borrow::Cow,
     collections::{HashMap, LinkedList},
-    path::{Component, Path, PathBuf},
-    rc::Rc,
+    fmt,
+    path::{Component, Path},
+    str::FromStr,
 };
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{request::HttpRequest, Error};
+use crate::{
+    raw::{self, TreeEntryModel, TreeModel},
+    EntryCommitInfo, Error, ErrorContext, FileMode, Filter, GithubClient, GithubRef, ObjectFormat,
+    ObjectId, ResolvedRef,
+};
+
+/// The address of the github.com website.
+const GITHUB_URL: &str = "https://github.com";
+
+/// The host that serves the raw contents of files on github.com.
+const RAW_URL: &str = "https://raw.githubusercontent.com";
+
+/// The host that serves archives of repositories on github.com.
+const CODELOAD_URL: &str = "https://codeload.github.com";
+
+/// The kind of archive of a repository that GitHub serves, for [archive_url](GithubBranchPath::archive_url).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GithubArchiveFormat {
+    /// A gzipped tarball.
+    #[default]
+    TarGz,
+    /// A zip archive.
+    Zip,
+}
 
 /// A GitHub branch URL.
-/// The fields should complete the URL `https://github.com/{user}/{repo}/tree/{branch}`.
-#[derive(Debug)]
+/// The fields should complete the URL `https://github.com/{user}/{repo}/tree/{reference}`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct GithubBranchPath<'g> {
     /// The GitHub username of the repository owner.
     pub user: &'g str,
     /// The repository name.
     pub repo: &'g str,
-    /// The branch or SHA1 hash of the commit tree to fetch.
-    pub branch: &'g str,
+    /// The branch, tag, commit or tree to fetch.
+    pub reference: GithubRef<'g>,
 }
 
 impl<'g> GithubBranchPath<'g> {
-    /// Creates a new [GithubBranchPath] to the given user, repo, and branch.
-    pub fn new(user: &'g str, repo: &'g str, branch: &'g str) -> GithubBranchPath<'g> {
-        GithubBranchPath { user, repo, branch }
+    /// Creates a new [GithubBranchPath] to the given user, repo, and reference.
+    ///
+    /// The reference is read by [GithubRef::parse], so it can be a branch name, a commit hash, `tags/{name}` or
+    /// `pull/{number}`. Use [with_ref](GithubBranchPath::with_ref) for a branch whose name would be read as
+    /// something else.
+    pub fn new(user: &'g str, repo: &'g str, reference: &'g str) -> GithubBranchPath<'g> {
+        GithubBranchPath::with_ref(user, repo, GithubRef::parse(reference))
+    }
+
+    /// Creates a new [GithubBranchPath] to the given user, repo, and [GithubRef].
+    pub fn with_ref(
+        user: &'g str,
+        repo: &'g str,
+        reference: GithubRef<'g>,
+    ) -> GithubBranchPath<'g> {
+        GithubBranchPath {
+            user,
+            repo,
+            reference,
+        }
+    }
+
+    /// Interprets a spec of the form `user/repo@ref`, where the reference is read by [GithubRef::parse], such as
+    /// `azrogers/grab_github@main` or `azrogers/grab_github@tags/v1.0`.
+    ///
+    /// Fails with [Error::InvalidSpec] if the spec isn't of that form. The reference can't be left out, since
+    /// finding the default branch takes a request; see [grab](crate::grab) for a spec where it can.
+    pub fn parse(spec: &'g str) -> Result<GithubBranchPath<'g>, Error> {
+        let invalid = |reason: &str| Error::InvalidSpec {
+            spec: spec.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let (repo, reference) = spec
+            .split_once('@')
+            .ok_or_else(|| invalid("there's no @ before the reference"))?;
+        let (user, repo) = repo
+            .split_once('/')
+            .filter(|(user, repo)| !user.is_empty() && !repo.is_empty() && !repo.contains('/'))
+            .ok_or_else(|| invalid("the repository isn't of the form user/repo"))?;
+        if reference.is_empty() {
+            return Err(invalid("the reference is empty"));
+        }
+
+        Ok(GithubBranchPath::with_ref(
+            user,
+            repo,
+            GithubRef::parse(reference),
+        ))
+    }
+
+    /// Returns the name of the reference, which used to be the `branch` field.
+    #[deprecated(
+        note = "use the `reference` field, which says whether this is a branch, tag or commit"
+    )]
+    pub fn branch(&self) -> Cow<'g, str> {
+        self.reference.name()
     }
 
     /// Creates a new [GithubBranchPath] with the given branch and the same user and repo as this path.
     pub fn with_branch(&self, branch: &'g str) -> GithubBranchPath<'g> {
-        GithubBranchPath {
-            user: self.user,
-            repo: self.repo,
-            branch,
+        self.with_reference(GithubRef::Branch(branch))
+    }
+
+    /// Creates a new [GithubBranchPath] with the given [GithubRef] and the same user and repo as this path.
+    pub fn with_reference(&self, reference: GithubRef<'g>) -> GithubBranchPath<'g> {
+        GithubBranchPath::with_ref(self.user, self.repo, reference)
+    }
+
+    /// Returns the URL that github.com serves an archive of this reference from, which doesn't count against the
+    /// API's rate limit.
+    ///
+    /// Fails if the reference is a [GithubRef::Tree], since GitHub only serves archives, raw files and blob
+    /// pages of commits. The same goes for [raw_url](GithubBranchPath::raw_url) and
+    /// [blob_html_url](GithubBranchPath::blob_html_url).
+    pub fn archive_url(&self, format: GithubArchiveFormat) -> Result<String, Error> {
+        let format = match format {
+            GithubArchiveFormat::TarGz => "tar.gz",
+            GithubArchiveFormat::Zip => "zip",
+        };
+        self.public_url(CODELOAD_URL, &[format], &self.url_reference()?, "")
+    }
+
+    /// Returns the URL that github.com serves the raw contents of the file at `path` in this reference from.
+    pub fn raw_url(&self, path: &str) -> Result<String, Error> {
+        self.public_url(RAW_URL, &[], &self.url_reference()?, path)
+    }
+
+    /// Returns the URL of the page on github.com showing the file at `path` in this reference.
+    pub fn blob_html_url(&self, path: &str) -> Result<String, Error> {
+        self.public_url(GITHUB_URL, &["blob"], &self.url_reference()?, path)
+    }
+
+    /// Returns the fully qualified name of the reference, such as `refs/heads/main`, so that branches and tags
+    /// with the same name aren't confused in URLs.
+    fn url_reference(&self) -> Result<String, Error> {
+        match self.reference {
+            GithubRef::Branch(name) => Ok(format!("refs/heads/{}", name)),
+            GithubRef::Tag(name) => Ok(format!("refs/tags/{}", name)),
+            GithubRef::Tree(sha) => Err(Error::Other(format!(
+                "tree {} isn't a commit, so GitHub has no URL for it",
+                sha
+            ))),
+            reference => reference.commit_expression().map(String::from),
         }
     }
 
+    /// Builds `{base}/{user}/{repo}/{segments}/{reference}/{path}`, percent-encoding each component.
+    fn public_url(
+        &self,
+        base: &str,
+        segments: &[&str],
+        reference: &str,
+        path: &str,
+    ) -> Result<String, Error> {
+        let mut url = reqwest::Url::parse(base).map_err(|e| Error::Other(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|_| Error::Other(format!("{} can't have a path", base)))?
+            .extend([self.user, self.repo])
+            .extend(segments)
+            .extend(reference.split('/'))
+            .extend(path.split('/').filter(|segment| !segment.is_empty()));
+        Ok(url.into())
+    }
+
+    /// Returns the URL of this repository in the REST API at `api_url`.
+    pub(crate) fn to_repo_url(self, api_url: &str) -> String {
+        format!("{}/repos/{}/{}", api_url, self.user, self.repo)
+    }
+
+    /// Returns the URL of the given API endpoint within this repository.
+    pub(crate) fn to_api_url(self, api_url: &str, endpoint: &str) -> String {
+        format!("{}/{}", self.to_repo_url(api_url), endpoint)
+    }
+
     /// Returns the URL of the tree API for this branch path.
-    fn to_tree_url(&self) -> String {
-        format!(
-            "https://api.github.com/repos/{}/{}/git/trees/{}",
-            self.user, self.repo, self.branch
-        )
+    ///
+    /// Pull requests are named by their head ref, since the tree API doesn't know about their numbers.
+    pub(crate) fn to_tree_url(self, api_url: &str) -> String {
+        // only a tree has no commit expression, and its name is already the hash of the tree
+        let reference = self
+            .reference
+            .commit_expression()
+            .unwrap_or_else(|_| self.reference.name());
+        self.to_api_url(api_url, &format!("git/trees/{}", reference))
+    }
+}
+
+impl fmt::Display for GithubBranchPath<'_> {
+    /// Writes the path as `user/repo@ref`, which [parse](GithubBranchPath::parse) reads back as the same path.
+    ///
+    /// Tags are written as `tags/{name}`, pull requests as `pull/{number}`, and branches whose names would be
+    /// read as something else as `heads/{name}`. A [GithubRef::Tree] is written as its hash, which is read back
+    /// as a [GithubRef::Commit].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}@", self.user, self.repo)?;
+        match self.reference {
+            GithubRef::Branch(name) if GithubRef::parse(name) != GithubRef::Branch(name) => {
+                write!(f, "heads/{}", name)
+            }
+            GithubRef::Tag(name) => write!(f, "tags/{}", name),
+            GithubRef::PullRequest(number) => write!(f, "pull/{}", number),
+            reference => write!(f, "{}", reference),
+        }
+    }
+}
+
+impl<'g> TryFrom<&'g str> for GithubBranchPath<'g> {
+    type Error = Error;
+
+    /// Interprets a spec of the form `user/repo@ref`, as [parse](GithubBranchPath::parse) does.
+    fn try_from(spec: &'g str) -> Result<Self, Self::Error> {
+        GithubBranchPath::parse(spec)
+    }
+}
+
+impl<'g> TryFrom<&'g reqwest::Url> for GithubBranchPath<'g> {
+    type Error = Error;
+
+    /// Interprets the URL of a reference on the GitHub website:
+    ///
+    /// - `/{user}/{repo}/tree/{reference}`, where the reference is read by [GithubRef::parse].
+    /// - `/{user}/{repo}/commit/{sha}` is a [GithubRef::Commit].
+    /// - `/{user}/{repo}/releases/tag/{name}` is a [GithubRef::Tag].
+    /// - `/{user}/{repo}/pull/{number}`, optionally followed by a tab such as `/files`, is a
+    ///   [GithubRef::PullRequest].
+    ///
+    /// The host isn't checked, so URLs of GitHub Enterprise Server work too. Fails with [Error::InvalidSpec] for
+    /// any other URL, including those with percent-encoded characters, which can't be borrowed decoded.
+    fn try_from(url: &'g reqwest::Url) -> Result<Self, Self::Error> {
+        let invalid = |reason: &str| Error::InvalidSpec {
+            spec: url.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let path = url.path();
+        if path.contains('%') {
+            return Err(invalid("the path has percent-encoded characters"));
+        }
+
+        let mut segments = path.trim_matches('/').splitn(4, '/');
+        let (Some(user), Some(repo), Some(kind), Some(rest)) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(invalid("the path doesn't name a reference of a repository"));
+        };
+        if user.is_empty() || repo.is_empty() || rest.is_empty() {
+            return Err(invalid("the path doesn't name a reference of a repository"));
+        }
+
+        let reference = match kind {
+            "tree" => GithubRef::parse(rest),
+            "commit" => match GithubRef::parse(rest) {
+                GithubRef::Commit(sha) => GithubRef::Commit(sha),
+                _ => return Err(invalid("the commit isn't a full hash")),
+            },
+            "releases" => match rest.strip_prefix("tag/") {
+                Some(name) if !name.is_empty() => GithubRef::Tag(name),
+                _ => return Err(invalid("the release doesn't name a tag")),
+            },
+            "pull" => match rest.split('/').next().map(str::parse) {
+                Some(Ok(number)) => GithubRef::PullRequest(number),
+                _ => return Err(invalid("the pull request number isn't a number")),
+            },
+            _ => return Err(invalid("the path doesn't name a reference of a repository")),
+        };
+
+        Ok(GithubBranchPath::with_ref(user, repo, reference))
     }
 }
 
 /// The type of a single entry in a [SourceTree].
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub enum TreeEntryType {
     /// A blob (file) entry.
     #[serde(rename = "blob")]
@@ -57,20 +293,69 @@ pub enum TreeEntryType {
     Tree,
 }
 
+impl TreeEntryType {
+    /// Returns the name git uses for this type of entry, `blob` or `tree`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TreeEntryType::Blob => "blob",
+            TreeEntryType::Tree => "tree",
+        }
+    }
+}
+
+impl FromStr for TreeEntryType {
+    type Err = Error;
+
+    /// Parses the name git uses for a type of entry, `blob` or `tree`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blob" => Ok(TreeEntryType::Blob),
+            "tree" => Ok(TreeEntryType::Tree),
+            _ => Err(Error::Other(format!("{} is not a tree entry type", s))),
+        }
+    }
+}
+
+impl fmt::Display for TreeEntryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a [GithubClient] obtains the trees it's asked for, set with
+/// [with_tree_fetch_strategy](GithubClient::with_tree_fetch_strategy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TreeFetchStrategy {
+    /// Request the whole tree at once from the REST API, and fall back to requesting each directory on its own
+    /// if GitHub truncates it, which happens past 100,000 entries or 7 MB.
+    #[default]
+    Rest,
+    /// Request the tree through the GraphQL API, several directories and levels at a time, which is never
+    /// truncated and takes fewer round trips for large, deep repositories.
+    ///
+    /// The GraphQL API requires the client to have an access token.
+    #[cfg(feature = "graphql")]
+    GraphQL,
+}
+
 /// A tree representing the directories and files of a GitHub repo.
 #[derive(Debug, Clone, PartialEq)]
 pub struct SourceTree {
-    /// The path of the file relative to the root of the repository.
-    pub path: PathBuf,
-    /// The unix permissions mode of the file, in numeric notation.  
-    pub mode: String,
-    /// The SHA1 hash identifying this blob or tree.
+    /// The path of the entry relative to the root of the repository, which is empty for the root itself.
+    ///
+    /// Paths are always relative and separate their components with `/` on every platform, without any
+    /// `.` or empty components.
+    pub path: Utf8PathBuf,
+    /// The mode of the entry, which says whether it's a file, executable, symlink, submodule or directory.
+    pub mode: FileMode,
+    /// The hash identifying this blob or tree, in the repository's [object format](ObjectFormat).
     ///
-    /// This is NOT the same as the sha1 hash of the contents.
-    pub sha: String,
+    /// This is NOT the same as the hash of the contents; see [git_hash](crate::git_hash) to compute it.
+    pub sha: ObjectId,
     /// The type of the entry.
     pub entry_type: TreeEntryType,
-    /// The size of the entry in bytes, or 0 for blob entries.
+    /// The size of the entry in bytes, or 0 for tree entries and for blobs whose size isn't known, such as
+    /// those from a reconstructed truncated tree.
     pub size: u32,
     /// The API URL to call to get more information on this object.
     ///
@@ -79,66 +364,133 @@ pub struct SourceTree {
     pub url: String,
     /// The children of this entry, if any.
     pub children: Vec<SourceTree>,
-}
-
-/// A type used while building a [SourceTree] from a [TreeModel].
-#[derive(Clone)]
-struct SourceTreeInter {
-    pub path: PathBuf,
-    pub mode: String,
-    pub sha: String,
-    pub entry_type: TreeEntryType,
-    pub size: u32,
-    pub url: String,
-    pub children: Vec<Rc<RefCell<SourceTreeInter>>>,
+    /// The last commit to modify this blob, if filled in by [SourceTree::enrich_with_commits].
+    pub last_commit: Option<EntryCommitInfo>,
 }
 
 impl SourceTree {
     /// Create a new empty [SourceTree] with the given [TreeEntryType].
+    ///
+    /// Its [mode](SourceTree::mode) is [FileMode::Directory] for trees and [FileMode::Regular] for blobs.
     pub fn new(entry_type: TreeEntryType) -> SourceTree {
+        let mode = match entry_type {
+            TreeEntryType::Tree => FileMode::Directory,
+            TreeEntryType::Blob => FileMode::Regular,
+        };
+
         SourceTree {
             url: String::new(),
-            sha: String::new(),
-            path: PathBuf::new(),
-            mode: String::new(),
+            sha: ObjectId::NULL,
+            path: Utf8PathBuf::new(),
+            mode,
             entry_type,
             size: 0,
             children: Vec::new(),
+            last_commit: None,
         }
     }
 
     /// Obtain the entire [SourceTree] for a given [GithubBranchPath].
     pub async fn get<'p>(path: &'p GithubBranchPath<'p>) -> Result<SourceTree, Error> {
-        let tree = TreeModel::get_tree(path).await?;
-        Ok(tree.into())
+        SourceTree::get_filtered(path, &Filter::all()).await
+    }
+
+    /// Obtain the [SourceTree] for a given [GithubBranchPath], only including blobs that pass `filter`.
+    ///
+    /// If the tree is too large to be fetched in one request, directories that can't contain
+    /// any matching blobs (see [may_match_within](Filter::may_match_within)) won't be requested at all.
+    pub async fn get_filtered<'p>(
+        path: &'p GithubBranchPath<'p>,
+        filter: &Filter<'_>,
+    ) -> Result<SourceTree, Error> {
+        let (_, tree) = SourceTree::get_resolved(path, filter).await?;
+        Ok(tree)
+    }
+
+    /// Obtain the [SourceTree] for a given [GithubBranchPath] like [get_filtered](SourceTree::get_filtered),
+    /// along with the commit and tree its reference resolved to.
+    ///
+    /// The tree is fetched by its hash after resolving, so the result is consistent even if a branch moves
+    /// while it's being fetched.
+    ///
+    /// An empty repository resolves to the [empty tree](crate::EMPTY_TREE_SHA) with no commit.
+    pub async fn get_resolved<'p>(
+        path: &'p GithubBranchPath<'p>,
+        filter: &Filter<'_>,
+    ) -> Result<(ResolvedRef, SourceTree), Error> {
+        SourceTree::get_resolved_with_client(&GithubClient::new(None)?, path, filter).await
+    }
+
+    /// Obtain the [SourceTree] for a given [GithubBranchPath] like [get_resolved](SourceTree::get_resolved),
+    /// making every request with `client`.
+    pub async fn get_resolved_with_client<'p>(
+        client: &GithubClient,
+        path: &'p GithubBranchPath<'p>,
+        filter: &Filter<'_>,
+    ) -> Result<(ResolvedRef, SourceTree), Error> {
+        let repo_context = || ErrorContext {
+            repo: Some(format!("{}/{}", path.user, path.repo)),
+            ..ErrorContext::default()
+        };
+
+        let resolved = match ResolvedRef::resolve(client, path).await {
+            Err(e) if matches!(e.root(), Error::EmptyRepository) => {
+                let resolved = ResolvedRef {
+                    commit_sha: None,
+                    tree_sha: ObjectId::empty_tree(ObjectFormat::Sha1),
+                };
+                return Ok((resolved, SourceTree::new(TreeEntryType::Tree)));
+            }
+            resolved => resolved.map_err(|e| e.with_context(repo_context()))?,
+        };
+
+        let tree_sha = resolved.tree_sha.to_string();
+        let tree_path = path.with_reference(GithubRef::Tree(&tree_sha));
+        let tree = TreeModel::get_tree(client, &tree_path, filter)
+            .await
+            .map_err(|e| e.with_context(repo_context()))?;
+        Ok((resolved, tree.into()))
+    }
+
+    /// Returns the final component of this entry's path, such as `lib.rs` for `src/lib.rs`, or an empty
+    /// string for the root.
+    pub fn name(&self) -> &str {
+        self.path.file_name().unwrap_or_default()
+    }
+
+    /// Returns the path of the directory containing this entry, such as `src` for `src/lib.rs`, or an empty
+    /// path for entries at the root and the root itself.
+    pub fn parent_path(&self) -> &Utf8Path {
+        self.path.parent().unwrap_or(Utf8Path::new(""))
     }
 
     /// Walks the tree to find a blob at the given path, if any.
     /// Equivalent to [resolve](SourceTree::resolve) with `find_blob` as `Some(true)`.
-    pub fn resolve_blob(&self, path: &Path) -> Option<&SourceTree> {
+    pub fn resolve_blob(&self, path: impl AsRef<Path>) -> Option<&SourceTree> {
         self.resolve(path, Some(true))
     }
 
     /// Walks the tree to find a tree (directory) at the given path, if any.
     /// Equivalent to [resolve](SourceTree::resolve) with `find_blob` as `Some(false)`.
-    pub fn resolve_tree(&self, path: &Path) -> Option<&SourceTree> {
+    pub fn resolve_tree(&self, path: impl AsRef<Path>) -> Option<&SourceTree> {
         self.resolve(path, Some(false))
     }
 
     /// Walks the tree to find a node at the given path, if any.
     /// Equivalent to [resolve](SourceTree::resolve) with `find_blob` as `None`.
-    pub fn resolve_any(&self, path: &Path) -> Option<&SourceTree> {
+    pub fn resolve_any(&self, path: impl AsRef<Path>) -> Option<&SourceTree> {
         self.resolve(path, Some(false))
     }
 
-    /// Walks the tree to find an entry at the given path, if any.
+    /// Walks the tree to find an entry at the given path, if any. The path can be a [Utf8Path], a [Path] or a
+    /// string, relative to this entry.
     ///
     /// - If `find_blob` is `Some(true)`, only blob entries will be returned.
     /// - If `find_blob` is `Some(false)`, only tree entries will be returned.
     /// - If `find_blob` is `None`, the first type of entry found will be returned.
-    pub fn resolve(&self, path: &Path, find_blob: Option<bool>) -> Option<&SourceTree> {
+    pub fn resolve(&self, path: impl AsRef<Path>, find_blob: Option<bool>) -> Option<&SourceTree> {
         // we reverse the path because going parent->parent->parent is easier
-        let components: Vec<Component> = path.components().into_iter().collect();
+        let components: Vec<Component> = path.as_ref().components().collect();
         self.resolve_inner(&components[..], find_blob)
     }
 
@@ -157,14 +509,12 @@ impl SourceTree {
 
         let file_name = &path[0];
         for c in &self.children {
-            if let Some(name) = c.path.file_name() {
-                if name == file_name.as_os_str() {
-                    return c.resolve_inner(&path[1..], find_blob);
-                }
+            if *file_name.as_os_str() == *c.name() {
+                return c.resolve_inner(&path[1..], find_blob);
             }
         }
 
-        return None;
+        None
     }
 
     /// Creates a SourceTreeIterator that will walk down this tree and return a pointer for each node found.
@@ -174,6 +524,76 @@ impl SourceTree {
         SourceTreeIterator(list)
     }
 
+    /// Creates a [SourceTreeRelativeIterator] that walks down this tree like [iter](SourceTree::iter), returning
+    /// each node along with its path relative to this node.
+    ///
+    /// This node itself is returned first, with an empty path.
+    pub fn iter_relative<'tree>(&'tree self) -> SourceTreeRelativeIterator<'tree> {
+        SourceTreeRelativeIterator {
+            prefix: &self.path,
+            iter: self.iter(),
+        }
+    }
+
+    /// Creates a copy of this tree with `prefix` removed from the start of every path, such as to turn a
+    /// subtree found with [resolve_tree](SourceTree::resolve_tree) into a tree of its own with
+    /// `rebase(&subtree.path)`. Paths that don't start with `prefix` are left alone.
+    pub fn rebase(&self, prefix: impl AsRef<Utf8Path>) -> SourceTree {
+        let mut tree = self.clone();
+        tree.rebase_in_place(prefix.as_ref());
+        tree
+    }
+
+    fn rebase_in_place(&mut self, prefix: &Utf8Path) {
+        if let Ok(relative) = self.path.strip_prefix(prefix) {
+            self.path = relative.to_path_buf();
+        }
+
+        for child in &mut self.children {
+            child.rebase_in_place(prefix);
+        }
+    }
+
+    /// Creates a new [SourceTree] by laying `other` over this tree.
+    ///
+    /// Directories present in both trees are merged. Where both trees have an entry at the same path
+    /// and they aren't both directories, the entry from `other` wins. Since each blob keeps the URL it was
+    /// fetched from, the merged tree can be downloaded as usual even when the trees come from different repos.
+    pub fn overlay(&self, other: &SourceTree) -> SourceTree {
+        if self.entry_type != TreeEntryType::Tree || other.entry_type != TreeEntryType::Tree {
+            return other.clone();
+        }
+
+        let mut children = self.children.clone();
+        for other_child in &other.children {
+            let existing = children.iter_mut().find(|c| c.name() == other_child.name());
+
+            match existing {
+                Some(child) => *child = child.overlay(other_child),
+                None => children.push(other_child.clone()),
+            }
+        }
+
+        SourceTree {
+            children,
+            ..other.clone_without_children()
+        }
+    }
+
+    /// Clones this node without any of its children.
+    fn clone_without_children(&self) -> SourceTree {
+        SourceTree {
+            path: self.path.clone(),
+            mode: self.mode,
+            sha: self.sha,
+            entry_type: self.entry_type.clone(),
+            size: self.size,
+            url: self.url.clone(),
+            children: Vec::new(),
+            last_commit: self.last_commit.clone(),
+        }
+    }
+
     /// Creates a new [SourceTree] from this tree, only including child nodes where `f` returns true.
     pub fn prune(&self, predicate: for<'a> fn(&'a &SourceTree) -> bool) -> SourceTree {
         let new_children: Vec<SourceTree> = self
@@ -184,13 +604,8 @@ impl SourceTree {
             .collect();
 
         SourceTree {
-            path: self.path.clone(),
-            mode: self.mode.clone(),
-            sha: self.sha.clone(),
-            entry_type: self.entry_type.clone(),
-            size: self.size,
-            url: self.url.clone(),
             children: new_children,
+            ..self.clone_without_children()
         }
     }
 }
@@ -202,12 +617,7 @@ impl<'tree> Iterator for SourceTreeIterator<'tree> {
     type Item = &'tree SourceTree;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let state = self.0.pop_back();
-        if state.is_none() {
-            return None;
-        }
-
-        let (node, mut pos) = state.unwrap();
+        let (node, mut pos) = self.0.pop_back()?;
         if pos >= (node.children.len() as isize) && self.0.is_empty() {
             // no children
             return None;
@@ -218,146 +628,368 @@ impl<'tree> Iterator for SourceTreeIterator<'tree> {
             false => &node.children[pos as usize],
         };
 
-        pos = pos + 1;
+        pos += 1;
         if pos < (node.children.len() as isize) {
             self.0.push_back((node, pos));
         }
 
-        if !ptr.children.is_empty() {
+        // the starting node's children were already queued above
+        if !ptr.children.is_empty() && !std::ptr::eq(ptr, node) {
             self.0.push_back((ptr, 0));
         }
 
-        return Some(ptr);
+        Some(ptr)
     }
 }
 
-impl From<SourceTreeInter> for SourceTree {
-    fn from(value: SourceTreeInter) -> Self {
-        SourceTree {
-            path: value.path,
-            mode: value.mode,
-            sha: value.sha,
-            url: value.url,
-            entry_type: value.entry_type,
-            size: value.size,
-            children: value
-                .children
-                .into_iter()
-                .map(|s| s.borrow().clone().into())
-                .collect(),
-        }
+impl<'tree> IntoIterator for &'tree SourceTree {
+    type Item = &'tree SourceTree;
+    type IntoIter = SourceTreeIterator<'tree>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator that takes apart a [SourceTree], returning each node in the same order as [SourceTreeIterator].
+///
+/// Nodes are returned without their [children](SourceTree::children), which are returned after them.
+pub struct SourceTreeIntoIterator(Vec<SourceTree>);
+
+impl Iterator for SourceTreeIntoIterator {
+    type Item = SourceTree;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut node = self.0.pop()?;
+        let children = std::mem::take(&mut node.children);
+        self.0.extend(children.into_iter().rev());
+        Some(node)
+    }
+}
+
+impl IntoIterator for SourceTree {
+    type Item = SourceTree;
+    type IntoIter = SourceTreeIntoIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SourceTreeIntoIterator(vec![self])
+    }
+}
+
+impl<'tree> From<&'tree SourceTree> for Cow<'tree, SourceTree> {
+    fn from(value: &'tree SourceTree) -> Self {
+        Cow::Borrowed(value)
     }
 }
 
-impl From<TreeEntryModel> for SourceTreeInter {
+impl From<SourceTree> for Cow<'_, SourceTree> {
+    fn from(value: SourceTree) -> Self {
+        Cow::Owned(value)
+    }
+}
+
+/// An iterator for a [SourceTree] that walks the tree like [SourceTreeIterator], returning each node along
+/// with its path relative to the node the iterator was created from.
+pub struct SourceTreeRelativeIterator<'tree> {
+    prefix: &'tree Utf8Path,
+    iter: SourceTreeIterator<'tree>,
+}
+
+impl<'tree> Iterator for SourceTreeRelativeIterator<'tree> {
+    type Item = (&'tree Utf8Path, &'tree SourceTree);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.iter.next()?;
+        let relative = node
+            .path
+            .strip_prefix(self.prefix)
+            .unwrap_or(node.path.as_path());
+        Some((relative, node))
+    }
+}
+
+impl From<TreeEntryModel> for SourceTree {
     fn from(value: TreeEntryModel) -> Self {
-        SourceTreeInter {
-            path: PathBuf::from(value.path),
+        SourceTree {
+            path: normalize_path(&value.path),
             mode: value.mode,
             sha: value.sha,
             entry_type: value.entry_type,
             size: value.size,
             url: value.url,
             children: Vec::new(),
+            last_commit: None,
         }
     }
 }
 
 impl From<TreeModel> for SourceTree {
     fn from(value: TreeModel) -> Self {
-        let mut dirs_for_path: HashMap<PathBuf, Rc<RefCell<SourceTreeInter>>> = HashMap::new();
-        let mut nodes: LinkedList<Rc<RefCell<SourceTreeInter>>> = LinkedList::new();
+        TreeArena::from(value).into_tree()
+    }
+}
+
+impl From<TreeModel> for TreeArena {
+    fn from(value: TreeModel) -> Self {
+        let mut root = SourceTree::new(TreeEntryType::Tree);
+        root.url = value.url;
+        root.sha = value.sha;
+
+        let mut arena = TreeArena::new(root);
+        let mut dirs_for_path: HashMap<Utf8PathBuf, NodeId> = HashMap::new();
 
         // read entries into nodes
-        for entry in value.tree {
-            match entry.entry_type {
-                TreeEntryType::Blob => {
-                    nodes.push_back(Rc::new(RefCell::new(entry.into())));
-                }
-                TreeEntryType::Tree => {
-                    let path = entry.path.clone();
-                    let entry = Rc::new(RefCell::new(entry.into()));
-                    nodes.push_back(entry.clone());
-                    dirs_for_path.insert(path.into(), entry);
+        let ids: Vec<NodeId> = value
+            .tree
+            .into_iter()
+            .map(|entry| {
+                let node: SourceTree = entry.into();
+                let is_tree = node.entry_type == TreeEntryType::Tree;
+                let path = node.path.clone();
+                let id = arena.push(node);
+                if is_tree {
+                    dirs_for_path.insert(path, id);
                 }
-            }
+                id
+            })
+            .collect();
+
+        // assign files to dir tree
+        for id in ids {
+            let dir_path = arena
+                .node(id)
+                .path
+                .parent()
+                .unwrap_or(Utf8Path::new(""))
+                .to_owned();
+            let parent = arena.dir(&mut dirs_for_path, &dir_path);
+            arena.add_child(parent, id);
         }
 
-        let root = RefCell::new(SourceTreeInter {
-            url: value.url,
-            sha: value.sha,
-            path: PathBuf::new(),
-            mode: String::new(),
-            entry_type: TreeEntryType::Tree,
-            size: 0,
-            children: Vec::new(),
-        });
+        arena
+    }
+}
 
-        // assign files to dir tree
-        while let Some(node) = nodes.pop_front() {
-            let node_ref = node.borrow();
-            let path = Path::new(&node_ref.path);
-            let dir_path: &Path = path.parent().unwrap_or(Path::new(""));
-            let dir = match dir_path.as_os_str().len() {
-                // use root node for
-                0 => &root,
-                _ => dirs_for_path.get(dir_path).unwrap(),
-            };
+/// Turns a path from the API into the form [SourceTree::path] guarantees: relative, separated by `/`, and
+/// without any `.` or empty components. Backslashes are left alone, since they're valid in file names.
+pub(crate) fn normalize_path(path: &str) -> Utf8PathBuf {
+    let components: Vec<&str> = path
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    Utf8PathBuf::from(components.join("/"))
+}
+
+/// Index of a node within a [TreeArena].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// Flat storage for a tree, with each entry stored once and parent-child links kept as [NodeId]s.
+///
+/// A [TreeModel] converts into an arena in linear time. Subtrees are referred to by their [NodeId], so any
+/// number of them can be looked at, or turned into a [SourceTree] with [to_tree](TreeArena::to_tree), without
+/// converting or copying the rest of the tree. Share an arena between tasks with an [Arc](std::sync::Arc) to
+/// read the same tree with several filters or downloads.
+#[derive(Debug, Clone)]
+pub struct TreeArena {
+    /// Each node without its children, which are only filled in by [into_tree](TreeArena::into_tree).
+    nodes: Vec<Option<SourceTree>>,
+    children: Vec<Vec<NodeId>>,
+}
+
+impl TreeArena {
+    /// The root of the tree, which is always in the arena.
+    pub const ROOT: NodeId = NodeId(0);
 
-            drop(node_ref);
+    fn new(root: SourceTree) -> TreeArena {
+        TreeArena {
+            nodes: vec![Some(root)],
+            children: vec![Vec::new()],
+        }
+    }
+
+    fn push(&mut self, node: SourceTree) -> NodeId {
+        self.nodes.push(Some(node));
+        self.children.push(Vec::new());
+        NodeId(self.nodes.len() - 1)
+    }
+
+    fn add_child(&mut self, parent: NodeId, child: NodeId) {
+        self.children[parent.0].push(child);
+    }
+
+    /// Returns how many nodes are in the arena, including the root.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns true if the arena only holds the root.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// Returns the node with the given id, whose [children](SourceTree::children) are always empty.
+    /// Use [children](TreeArena::children) to find them.
+    pub fn node(&self, id: NodeId) -> &SourceTree {
+        // nodes are only taken out by into_tree, which consumes the arena
+        self.nodes[id.0].as_ref().unwrap()
+    }
+
+    /// Returns the children of the node with the given id, in the order they were listed in.
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.children[id.0]
+    }
+
+    /// Finds the node at `path`, relative to the root of the tree.
+    pub fn find(&self, path: impl AsRef<Utf8Path>) -> Option<NodeId> {
+        let mut id = TreeArena::ROOT;
+        let mut prefix = Utf8PathBuf::new();
+        for component in path.as_ref().components() {
+            prefix.push(component);
+            id = *self
+                .children(id)
+                .iter()
+                .find(|&&child| self.node(child).path == prefix)?;
+        }
+        Some(id)
+    }
 
-            dir.borrow_mut().children.push(node);
+    /// Copies the node with the given id and everything below it into a [SourceTree], leaving the arena as it is.
+    pub fn to_tree(&self, id: NodeId) -> SourceTree {
+        let mut tree = self.node(id).clone();
+        tree.children = self
+            .children(id)
+            .iter()
+            .map(|&child| self.to_tree(child))
+            .collect();
+        tree
+    }
+
+    /// Returns the directory at `path`, creating it and any of its parents that aren't in `dirs_for_path`.
+    /// Filtered and cached trees can leave out the entries of directories while keeping what's inside them.
+    fn dir(&mut self, dirs_for_path: &mut HashMap<Utf8PathBuf, NodeId>, path: &Utf8Path) -> NodeId {
+        // use root node for entries at the top level
+        if path.as_str().is_empty() {
+            return TreeArena::ROOT;
+        }
+        if let Some(id) = dirs_for_path.get(path) {
+            return *id;
         }
 
-        root.into_inner().into()
+        let mut dir = SourceTree::new(TreeEntryType::Tree);
+        dir.path = path.to_owned();
+        let id = self.push(dir);
+        dirs_for_path.insert(path.to_owned(), id);
+
+        let parent = self.dir(dirs_for_path, path.parent().unwrap_or(Utf8Path::new("")));
+        self.add_child(parent, id);
+        id
     }
-}
 
-#[derive(Deserialize)]
-struct TreeEntryModel {
-    pub path: String,
-    pub mode: String,
-    #[serde(rename = "type")]
-    pub entry_type: TreeEntryType,
-    #[serde(default)]
-    pub size: u32,
-    pub sha: String,
-    pub url: String,
-}
+    /// Assembles the nodes into a [SourceTree], moving each node into its parent exactly once.
+    pub fn into_tree(mut self) -> SourceTree {
+        // in reverse pre-order, every node comes after all of its descendants
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![TreeArena::ROOT];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(&self.children[id.0]);
+        }
 
-/// The result of a call to the GitHub `Get a tree` API
-#[derive(Deserialize)]
-struct TreeModel {
-    pub sha: String,
-    pub url: String,
-    pub tree: Vec<TreeEntryModel>,
-    pub truncated: bool,
+        for &id in order.iter().rev() {
+            let children = std::mem::take(&mut self.children[id.0])
+                .into_iter()
+                .filter_map(|c| self.nodes[c.0].take())
+                .collect();
+            if let Some(node) = self.nodes[id.0].as_mut() {
+                node.children = children;
+            }
+        }
+
+        self.nodes[TreeArena::ROOT.0].take().unwrap()
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(untagged)]
-enum TreeOrError {
-    Tree(TreeModel),
-    Error { message: String },
+impl TreeEntryModel {
+    /// Returns whether this entry should be kept in a tree fetched with `filter`.
+    pub(crate) fn passes(&self, filter: &Filter) -> bool {
+        match self.entry_type {
+            TreeEntryType::Blob => filter.check(&self.path),
+            TreeEntryType::Tree => filter.may_match_within(&self.path),
+        }
+    }
 }
 
 impl<'path> TreeModel {
+    /// Obtains a tree like [fetch_tree](TreeModel::fetch_tree), going through the client's [TreeCache](crate::TreeCache)
+    /// if it has one.
+    pub(crate) async fn get_tree(
+        client: &GithubClient,
+        path: &GithubBranchPath<'path>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        let cache = match client.cache() {
+            Some(cache) if cache.policy().cache_trees => cache,
+            _ => return TreeModel::fetch_tree(client, path, filter).await,
+        };
+
+        let scope = client.cache_scope();
+        let mut tree = match cache.get_tree(&scope, path) {
+            Some(tree) => tree,
+            None => {
+                let tree = TreeModel::fetch_tree(client, path, &Filter::all()).await?;
+                cache.put_tree(&scope, path, &tree);
+                tree
+            }
+        };
+
+        tree.tree.retain(|e| e.passes(filter));
+        Ok(tree)
+    }
+
+    /// Obtains a tree with the client's [TreeFetchStrategy].
+    async fn fetch_tree(
+        client: &GithubClient,
+        path: &GithubBranchPath<'path>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        match client.tree_fetch_strategy() {
+            TreeFetchStrategy::Rest => TreeModel::fetch_tree_rest(client, path, filter).await,
+            #[cfg(feature = "graphql")]
+            TreeFetchStrategy::GraphQL => {
+                crate::graphql_tree::fetch_tree_graphql(client, path, filter).await
+            }
+        }
+    }
+
     /// Obtains a tree first recursively, and then non-recursively if truncated.
-    async fn get_tree(path: &GithubBranchPath<'path>) -> Result<TreeModel, Error> {
-        let recursive_tree = TreeModel::get_tree_request(path, true).await?;
+    async fn fetch_tree_rest(
+        client: &GithubClient,
+        path: &GithubBranchPath<'path>,
+        filter: &Filter<'_>,
+    ) -> Result<TreeModel, Error> {
+        let passes = |e: &TreeEntryModel| e.passes(filter);
+        let recursive_tree = raw::get_tree_filtered_raw(client, path, true, passes).await?;
         if !recursive_tree.truncated {
             return Ok(recursive_tree);
         }
 
-        let initial_tree = TreeModel::get_tree_request(path, false).await?;
+        let initial_tree = raw::get_tree_filtered_raw(client, path, false, passes).await?;
         let mut entries: Vec<TreeEntryModel> = Vec::new();
         for entry in &initial_tree.tree {
             if entry.entry_type == TreeEntryType::Tree {
-                TreeModel::get_tree_manual(path, &entry.path, &mut entries).await?;
+                TreeModel::get_tree_manual(
+                    client,
+                    &path.with_reference(GithubRef::Tree(&entry.sha.to_string())),
+                    &entry.path,
+                    filter,
+                    &mut entries,
+                )
+                .await?;
             }
         }
 
-        entries.extend(initial_tree.tree.into_iter());
+        entries.extend(initial_tree.tree);
 
         Ok(TreeModel {
             sha: initial_tree.sha,
@@ -368,9 +1000,14 @@ impl<'path> TreeModel {
     }
 
     /// Recursively fills out the tree using the non-recursive version of the endpoint, collecting entries in `entries`.
+    ///
+    /// `path` should point at the tree being fetched, which lives at `parent_entry_path` in the repository.
+    /// Subtrees that can't contain anything passing `filter` are never requested.
     fn get_tree_manual<'a>(
+        client: &'a GithubClient,
         path: &'a GithubBranchPath<'path>,
         parent_entry_path: &'a str,
+        filter: &'a Filter<'a>,
         entries: &'a mut Vec<TreeEntryModel>,
     ) -> BoxFuture<'a, Result<&'a mut Vec<TreeEntryModel>, Error>>
     where
@@ -378,49 +1015,28 @@ impl<'path> TreeModel {
     {
         // have to use boxed async here because we're calling an async recursively
         async move {
-            let model = TreeModel::get_tree_request(path, false).await?;
+            let mut model = raw::get_tree_raw(client, path, false).await?;
+            for entry in &mut model.tree {
+                entry.path = format!("{}/{}", parent_entry_path, entry.path);
+            }
+
             for entry in &model.tree {
-                if entry.entry_type == TreeEntryType::Tree {
+                if entry.entry_type == TreeEntryType::Tree && entry.passes(filter) {
                     TreeModel::get_tree_manual(
-                        &path.with_branch(&entry.sha),
-                        &format!("{}/{}", parent_entry_path, entry.path),
+                        client,
+                        &path.with_reference(GithubRef::Tree(&entry.sha.to_string())),
+                        &entry.path,
+                        filter,
                         entries,
                     )
                     .await?;
                 }
             }
 
-            entries.extend(model.tree.into_iter());
+            entries.extend(model.tree.into_iter().filter(|e| e.passes(filter)));
 
             Ok(entries)
         }
         .boxed()
     }
-
-    /// Makes a request to the get tree endpoint
-    async fn get_tree_request(
-        path: &GithubBranchPath<'path>,
-        recursive: bool,
-    ) -> Result<TreeModel, Error> {
-        let url = path.to_tree_url();
-
-        let client = HttpRequest::client(&None)?;
-        let request = match recursive {
-            true => client.get(url).query(&[("recursive", true)]),
-            false => client.get(url),
-        };
-
-        let request = request
-            .header("Accept", "application/vnd.github+json")
-            .build()?;
-
-        let response = client.execute(request).await?;
-        let body = response.text().await?;
-
-        let result = serde_json::from_str::<TreeOrError>(&body)?;
-        match result {
-            TreeOrError::Error { message } => Err(Error::GithubError(message)),
-            TreeOrError::Tree(t) => Ok(t),
-        }
-    }
 }
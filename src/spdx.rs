@@ -0,0 +1,93 @@
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::DownloadedFile;
+
+/// How much of the start of each file is searched for `SPDX-License-Identifier` tags, which belong in the
+/// comment at the top of a file.
+const SCAN_BYTES: u64 = 8 * 1024;
+
+/// The tag naming the license of a file, as defined by the SPDX specification.
+const TAG: &str = "SPDX-License-Identifier:";
+
+/// A license expression found in the `SPDX-License-Identifier` tags of downloaded files, as collected with
+/// [collect_spdx](crate::DownloadConfig::collect_spdx).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpdxLicense {
+    /// The license expression, as written in the files, such as `MIT OR Apache-2.0`.
+    pub expression: String,
+    /// The paths in the repository of the files tagged with it, in the order of the report.
+    pub paths: Vec<String>,
+}
+
+/// Searches the start of each of `files` for `SPDX-License-Identifier` tags, returning each expression found,
+/// sorted, with the files tagged with it. Binary files, and files that can't be read, are skipped.
+pub(crate) async fn collect_licenses(files: &[DownloadedFile]) -> Vec<SpdxLicense> {
+    let files: Vec<(String, PathBuf)> = files
+        .iter()
+        .map(|file| (file.entry.path.to_string(), file.output_path.clone()))
+        .collect();
+
+    // only the start of each file is read, which tokio can't do without its io-util feature
+    let licenses = tokio::task::spawn_blocking(move || {
+        let mut licenses: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (path, output_path) in files {
+            for expression in read_expressions(&output_path) {
+                let paths = licenses.entry(expression).or_default();
+                if paths.last() != Some(&path) {
+                    paths.push(path.clone());
+                }
+            }
+        }
+        licenses
+    })
+    .await
+    .unwrap_or_default();
+
+    licenses
+        .into_iter()
+        .map(|(expression, paths)| SpdxLicense { expression, paths })
+        .collect()
+}
+
+/// Reads the license expressions tagged at the start of the file at `path`.
+fn read_expressions(path: &Path) -> Vec<String> {
+    let mut start = Vec::new();
+    let read = std::fs::File::open(path).and_then(|f| f.take(SCAN_BYTES).read_to_end(&mut start));
+    if read.is_err() || start.contains(&0) {
+        return Vec::new();
+    }
+
+    String::from_utf8_lossy(&start)
+        .lines()
+        .filter_map(expression)
+        .collect()
+}
+
+/// Returns the license expression tagged on `line`, without the comment markers around it, or `None` if
+/// it has no tag or anything but an expression follows the tag.
+fn expression(line: &str) -> Option<String> {
+    // a tag starts its comment, so one mentioned in the middle of a sentence or string isn't applied
+    let (before, rest) = line.split_once(TAG)?;
+    if before.chars().any(char::is_alphanumeric) {
+        return None;
+    }
+
+    let mut rest = rest.trim();
+    for end in ["*/", "-->", "--}}", "#}", "*)", "-}"] {
+        rest = rest.strip_suffix(end).unwrap_or(rest).trim_end();
+    }
+
+    let is_expression = rest
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || " .-+:()".contains(c));
+    match !rest.is_empty() && is_expression {
+        true => Some(rest.to_string()),
+        false => None,
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{SourceTree, TreeEntryType};
+
+/// How many of the largest files [stats](SourceTree::stats) keeps.
+pub const DEFAULT_LARGEST_FILES: usize = 10;
+
+/// The upper bounds, exclusive, of every bucket of [TreeStats::histogram] but the last, which has none.
+const BUCKET_LIMITS: [u64; 5] = [1 << 10, 10 << 10, 100 << 10, 1 << 20, 10 << 20];
+
+/// A file of a [SourceTree], as listed in [TreeStats::largest].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LargeFile {
+    /// The path of the file relative to the tree.
+    pub path: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// The files of a [SourceTree] whose sizes fall in a range, as found in [TreeStats::histogram].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SizeBucket {
+    /// The smallest size in bytes of a file in this bucket.
+    pub min: u64,
+    /// The size in bytes that every file in this bucket is smaller than, or `None` for the last bucket.
+    pub max: Option<u64>,
+    /// The number of files in this bucket.
+    pub files: usize,
+    /// The total size in bytes of the files in this bucket.
+    pub bytes: u64,
+}
+
+/// The files of a [SourceTree] with the same extension, as found in [TreeStats::extensions].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct ExtensionStats {
+    /// The number of files with the extension.
+    pub files: usize,
+    /// The total size in bytes of the files with the extension.
+    pub bytes: u64,
+}
+
+/// What a [SourceTree] holds and where its size goes, as found by [stats](SourceTree::stats), for deciding on a
+/// [Filter](crate::Filter) or whether to download an archive before downloading anything.
+///
+/// Files whose size isn't known, such as those from a reconstructed truncated tree, count as 0 bytes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeStats {
+    /// The number of files (blobs) in the tree.
+    pub files: usize,
+    /// The number of directories in the tree, not counting the tree itself.
+    pub directories: usize,
+    /// The total size in bytes of the files in the tree.
+    pub total_bytes: u64,
+    /// The largest files in the tree, largest first, with ties in path order.
+    pub largest: Vec<LargeFile>,
+    /// The files of the tree by size, in buckets of under 1 KiB, 1 KiB to 10 KiB, 10 KiB to 100 KiB, 100 KiB to
+    /// 1 MiB, 1 MiB to 10 MiB and 10 MiB or more.
+    pub histogram: Vec<SizeBucket>,
+    /// The files of the tree by extension, lowercased and without the leading `.`. Files without an extension
+    /// are under the empty string.
+    pub extensions: BTreeMap<String, ExtensionStats>,
+}
+
+impl TreeStats {
+    /// Returns the extensions of the tree, the one whose files take up the most bytes first.
+    pub fn heaviest_extensions(&self) -> Vec<(&str, ExtensionStats)> {
+        let mut extensions: Vec<(&str, ExtensionStats)> = self
+            .extensions
+            .iter()
+            .map(|(extension, stats)| (extension.as_str(), *stats))
+            .collect();
+        extensions.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(b.0)));
+        extensions
+    }
+}
+
+impl SourceTree {
+    /// Counts the files and directories of this tree and where their size goes, keeping the
+    /// [DEFAULT_LARGEST_FILES] largest files. This makes no requests.
+    pub fn stats(&self) -> TreeStats {
+        self.stats_with_largest(DEFAULT_LARGEST_FILES)
+    }
+
+    /// Counts the files and directories of this tree like [stats](SourceTree::stats), keeping the `largest`
+    /// largest files.
+    pub fn stats_with_largest(&self, largest: usize) -> TreeStats {
+        let mut histogram: Vec<SizeBucket> = std::iter::once(0)
+            .chain(BUCKET_LIMITS)
+            .zip(BUCKET_LIMITS.map(Some).into_iter().chain([None]))
+            .map(|(min, max)| SizeBucket {
+                min,
+                max,
+                files: 0,
+                bytes: 0,
+            })
+            .collect();
+
+        let mut stats = TreeStats {
+            files: 0,
+            directories: 0,
+            total_bytes: 0,
+            largest: Vec::new(),
+            histogram: Vec::new(),
+            extensions: BTreeMap::new(),
+        };
+
+        for (path, node) in self.iter_relative().skip(1) {
+            if node.entry_type == TreeEntryType::Tree {
+                stats.directories += 1;
+                continue;
+            }
+
+            let size = node.size as u64;
+            stats.files += 1;
+            stats.total_bytes += size;
+
+            let bucket = BUCKET_LIMITS
+                .iter()
+                .position(|limit| size < *limit)
+                .unwrap_or(BUCKET_LIMITS.len());
+            histogram[bucket].files += 1;
+            histogram[bucket].bytes += size;
+
+            let extension = path.extension().unwrap_or_default().to_lowercase();
+            let extension = stats.extensions.entry(extension).or_default();
+            extension.files += 1;
+            extension.bytes += size;
+
+            stats.largest.push(LargeFile {
+                path: path.to_string(),
+                size,
+            });
+        }
+
+        stats
+            .largest
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        stats.largest.truncate(largest);
+        stats.histogram = histogram;
+        stats
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    git_hash, DownloadConfig, DownloadEvent, DownloadReporter, DownloadedFile, Downloader, Error,
+    ErrorContext, Filter, GithubBranchPath, OutputLayout, ResolvedRef, CONTENT_MANIFEST_FILE_NAME,
+};
+
+/// What a [sync](Downloader::sync) changed in the output directory.
+///
+/// Its [Display](fmt::Display) form is a one-line summary like `12 files updated, 3 deleted`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncSummary {
+    /// The commit and tree that were synced.
+    pub resolved: ResolvedRef,
+    /// Files that weren't on disk before and were downloaded.
+    pub added: Vec<DownloadedFile>,
+    /// Files whose contents on disk didn't match their blob, and were downloaded again.
+    pub updated: Vec<DownloadedFile>,
+    /// Files in the output directory that aren't in the tree anymore, and were deleted.
+    pub deleted: Vec<PathBuf>,
+    /// Files whose contents on disk already matched their blob. These have a
+    /// [bytes_written](DownloadedFile::bytes_written) of 0.
+    pub unchanged: Vec<DownloadedFile>,
+}
+
+impl SyncSummary {
+    /// Returns true if nothing in the output directory was changed.
+    pub fn is_unchanged(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_unchanged() {
+            let count = self.unchanged.len();
+            return write!(f, "{} {} unchanged", count, files(count));
+        }
+
+        let counts = [
+            (self.added.len(), "added"),
+            (self.updated.len(), "updated"),
+            (self.deleted.len(), "deleted"),
+        ];
+        let mut first = true;
+        for (count, change) in counts.into_iter().filter(|(count, _)| *count > 0) {
+            match first {
+                true => write!(f, "{} {} {}", count, files(count), change)?,
+                false => write!(f, ", {} {}", count, change)?,
+            }
+            first = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns `file` or `files` to go with `count`.
+fn files(count: usize) -> &'static str {
+    match count {
+        1 => "file",
+        _ => "files",
+    }
+}
+
+impl<'p> Downloader {
+    /// Makes the output directory mirror the tree specified by `path`, downloading only the files that are missing
+    /// or whose contents differ from their blob, and deleting every other file in the output directory.
+    ///
+    /// Files on disk are compared by hashing them the way git does, so nothing is requested for files that are
+    /// already up to date. Besides the usual events, the reporter is sent [DownloadEvent::FileUnchanged],
+    /// [DownloadEvent::FileDeleted] and [DownloadEvent::SyncFinished].
+    ///
+    /// Everything in the output directory that wasn't planned is deleted, including files that didn't pass
+    /// `filter`, so the output directory should be used for nothing else. The [temp_dir](DownloadConfig::temp_dir)
+    /// the manifest of a content-addressed [layout](DownloadConfig::layout) and the
+    /// [checksum_manifest](DownloadConfig::checksum_manifest) are kept.
+    pub async fn sync<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        path: &GithubBranchPath<'p>,
+        filter: &Filter<'p>,
+    ) -> Result<SyncSummary, Error> {
+        let client = config.client()?;
+        let (resolved, tree) = Downloader::get_resolved(config, &client, path, filter).await?;
+
+        let files = Downloader::select_files(config, tree, filter)?;
+        let files = Downloader::prefetch_sizes(config, &client, files).await;
+        let planned = Downloader::plan(config, files)?;
+
+        let mut summary = SyncSummary {
+            resolved,
+            added: Vec::new(),
+            updated: Vec::new(),
+            deleted: Vec::new(),
+            unchanged: Vec::new(),
+        };
+        let mut kept = HashSet::new();
+        let mut existed = Vec::new();
+        let mut outdated = Vec::new();
+        for planned in planned {
+            kept.insert(planned.output_path.clone());
+            let bytes = match tokio::fs::read(&planned.output_path).await {
+                Ok(bytes) => bytes,
+                Err(_) => {
+                    existed.push(false);
+                    outdated.push(planned);
+                    continue;
+                }
+            };
+
+            if git_hash::blob_id(&bytes, planned.entry.sha.format()) != planned.entry.sha {
+                existed.push(true);
+                outdated.push(planned);
+                continue;
+            }
+
+            if let Some(reporter) = config.reporter {
+                reporter.on_event(DownloadEvent::FileUnchanged {
+                    path: planned.entry.path.as_str(),
+                });
+            }
+            summary.unchanged.push(DownloadedFile {
+                entry: planned.entry,
+                output_path: planned.output_path,
+                bytes_written: 0,
+                bytes_received: 0,
+                verified: true,
+            });
+        }
+
+        let downloaded = Downloader::download_planned(config, &client, outdated)
+            .await
+            .map_err(|e| {
+                e.with_context(ErrorContext {
+                    repo: Some(format!("{}/{}", path.user, path.repo)),
+                    ..ErrorContext::default()
+                })
+            })?;
+        for (file, existed) in downloaded.into_iter().zip(existed) {
+            match existed {
+                true => summary.updated.push(file),
+                false => summary.added.push(file),
+            }
+        }
+
+        let files: Vec<DownloadedFile> = summary
+            .added
+            .iter()
+            .chain(&summary.updated)
+            .chain(&summary.unchanged)
+            .cloned()
+            .collect();
+        if config.layout != OutputLayout::Paths {
+            kept.insert(config.output_path.join(CONTENT_MANIFEST_FILE_NAME));
+            Downloader::write_manifest(config, &files).await?;
+        }
+        if let Some(algorithm) = config.checksum_manifest {
+            kept.insert(config.output_path.join(algorithm.file_name()));
+            Downloader::write_checksums(config, &files, algorithm).await?;
+        }
+
+        if tokio::fs::try_exists(config.output_path).await? {
+            let mut stale = Vec::new();
+            collect_stale(config.output_path, config.temp_dir, &kept, &mut stale)?;
+            stale.sort();
+
+            for path in stale {
+                tokio::fs::remove_file(&path).await?;
+                if let Some(reporter) = config.reporter {
+                    let relative = path.strip_prefix(config.output_path).unwrap_or(&path);
+                    reporter.on_event(DownloadEvent::FileDeleted {
+                        path: &relative.to_string_lossy(),
+                    });
+                }
+                summary.deleted.push(path);
+            }
+        }
+
+        if let Some(reporter) = config.reporter {
+            reporter.on_event(DownloadEvent::SyncFinished {
+                added: summary.added.len(),
+                updated: summary.updated.len(),
+                deleted: summary.deleted.len(),
+                unchanged: summary.unchanged.len(),
+            });
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Adds every file below `dir` that isn't in `kept` to `stale`, skipping `temp_dir`.
+fn collect_stale(
+    dir: &Path,
+    temp_dir: Option<&Path>,
+    kept: &HashSet<PathBuf>,
+    stale: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if Some(path.as_path()) != temp_dir {
+                collect_stale(&path, temp_dir, kept, stale)?;
+            }
+        } else if !kept.contains(&path) {
+            stale.push(path);
+        }
+    }
+
+    Ok(())
+}
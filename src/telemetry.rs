@@ -0,0 +1,55 @@
+//! Metrics emitted through the `metrics` crate when the `metrics` feature is enabled.
+//! Without the feature, every function here does nothing.
+
+#[cfg(feature = "download")]
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::RequestBudget;
+
+/// Records a response from the GitHub API to `url`.
+#[allow(unused_variables)]
+pub(crate) fn record_response(url: &str, status: StatusCode, budget: &RequestBudget) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(
+            "grab_github_requests_total",
+            "endpoint" => endpoint(url),
+            "status" => status.as_u16().to_string()
+        )
+        .increment(1);
+
+        if let Some(remaining) = budget.remaining() {
+            metrics::gauge!("grab_github_rate_limit_remaining").set(remaining as f64);
+        }
+    }
+}
+
+/// Records a file that was downloaded successfully.
+#[cfg(feature = "download")]
+#[allow(unused_variables)]
+pub(crate) fn record_download(bytes: u64, duration: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("grab_github_bytes_downloaded_total").increment(bytes);
+        metrics::histogram!("grab_github_download_duration_seconds").record(duration.as_secs_f64());
+    }
+}
+
+/// Records a download that will be retried after hitting a rate limit.
+#[cfg(feature = "download")]
+pub(crate) fn record_retry() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("grab_github_retries_total").increment(1);
+}
+
+/// Returns the name of the API endpoint `url` points at, such as `git/blobs`, so that metrics
+/// aren't labelled with every distinct URL.
+#[cfg(feature = "metrics")]
+fn endpoint(url: &str) -> String {
+    match url.split_once("/git/") {
+        Some((_, rest)) => format!("git/{}", rest.split(['/', '?']).next().unwrap_or("")),
+        None => String::from("other"),
+    }
+}
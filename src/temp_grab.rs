@@ -0,0 +1,117 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    DownloadConfig, DownloadConfigNoReporting, DownloadReport, DownloadReporter, Downloader, Error,
+    Filter, GithubBranchPath,
+};
+
+/// Tells apart the directories of grabs started in the same nanosecond.
+static NEXT_GRAB: AtomicUsize = AtomicUsize::new(0);
+
+/// Files downloaded into a temporary directory by [download_temp](Downloader::download_temp), which is deleted
+/// along with everything in it when this is dropped.
+///
+/// It derefs to the path of the directory, so files can be found with [join](Path::join).
+#[derive(Debug)]
+pub struct TempGrab {
+    dir: PathBuf,
+    report: DownloadReport,
+}
+
+impl TempGrab {
+    /// The temporary directory the files were downloaded into.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The report of the download.
+    pub fn report(&self) -> &DownloadReport {
+        &self.report
+    }
+
+    /// Keeps the directory instead of deleting it, returning its path.
+    pub fn keep(mut self) -> PathBuf {
+        std::mem::take(&mut self.dir)
+    }
+}
+
+impl Deref for TempGrab {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl AsRef<Path> for TempGrab {
+    fn as_ref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for TempGrab {
+    fn drop(&mut self) {
+        if !self.dir.as_os_str().is_empty() {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+impl<'p> Downloader {
+    /// Downloads the files of `path` that pass `filter` into a new temporary directory, which is deleted when the
+    /// returned [TempGrab] is dropped, for tests and build steps that only need the files briefly.
+    ///
+    /// The download uses the default [DownloadConfig], which makes unauthenticated requests. Use
+    /// [download_temp_with](Downloader::download_temp_with) to download with an access token.
+    pub async fn download_temp(
+        path: &GithubBranchPath<'p>,
+        filter: &Filter<'p>,
+    ) -> Result<TempGrab, Error> {
+        let config = DownloadConfigNoReporting::new(Path::new(""));
+        Downloader::download_temp_with(&config, path, filter).await
+    }
+
+    /// Downloads like [download_temp](Downloader::download_temp), with the settings of `config`. The
+    /// [output_path](DownloadConfig::output_path) of `config` is ignored, and the temporary directory is created
+    /// in its [temp_dir](DownloadConfig::temp_dir) if it has one.
+    pub async fn download_temp_with<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        path: &GithubBranchPath<'_>,
+        filter: &Filter<'_>,
+    ) -> Result<TempGrab, Error> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let dir = config
+            .temp_dir
+            .map(Path::to_path_buf)
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!(
+                "grab_github-{}-{}-{}",
+                std::process::id(),
+                nanos,
+                NEXT_GRAB.fetch_add(1, Ordering::Relaxed)
+            ));
+
+        let result = {
+            let config = DownloadConfig {
+                output_path: &dir,
+                ..config.clone()
+            };
+            Downloader::download_resolved(&config, path, filter).await
+        };
+        match result {
+            Ok(report) => Ok(TempGrab { dir, report }),
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&dir).await;
+                Err(e)
+            }
+        }
+    }
+}
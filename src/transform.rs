@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use glob_match::glob_match;
+use serde::{Deserialize, Serialize};
+
+use crate::SourceTree;
+
+/// Implement this trait to change the contents of each file after it's downloaded, before it's written to disk.
+///
+/// Files are transformed before they're checked by the [ContentInspector](crate::ContentInspector), so the
+/// inspector sees what will be written. [verified](crate::DownloadedFile::verified) still refers to the contents
+/// that were downloaded.
+pub trait ContentTransform: Sync {
+    /// Called with the decoded contents of each file, returning the contents to write instead.
+    fn transform(&self, entry: &SourceTree, bytes: Vec<u8>) -> Vec<u8>;
+}
+
+/// A built-in [ContentTransform], as listed in a [GrabSpec](crate::GrabSpec).
+///
+/// Transforms serialize as an object whose `type` is the name of the variant in snake case:
+///
+/// ```toml
+/// [[transform]]
+/// type = "substitute"
+/// values = { version = "1.2.0" }
+/// paths = ["**/*.md"]
+///
+/// [[transform]]
+/// type = "line_endings"
+/// to = "lf"
+/// ```
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Transform {
+    /// Replaces every `{{name}}` in the contents of a file with the value of `name`, as [substitute] does.
+    /// Files that aren't valid UTF-8 are left as they are.
+    Substitute {
+        /// The value of each variable, keyed by name.
+        values: BTreeMap<String, String>,
+        /// Globs of the paths in the repository to substitute into. The default is every file.
+        #[serde(default)]
+        paths: Vec<String>,
+    },
+    /// Converts the line endings of text files. Files containing a NUL byte are taken to be binary, as git
+    /// does, and left as they are.
+    LineEndings {
+        /// The line ending to convert to.
+        to: LineEnding,
+        /// Globs of the paths in the repository to convert. The default is every file.
+        #[serde(default)]
+        paths: Vec<String>,
+    },
+}
+
+/// A line ending that [Transform::LineEndings] converts to.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineEnding {
+    /// `\n`, as on Unix.
+    Lf,
+    /// `\r\n`, as on Windows.
+    Crlf,
+}
+
+impl ContentTransform for Transform {
+    fn transform(&self, entry: &SourceTree, bytes: Vec<u8>) -> Vec<u8> {
+        let paths = match self {
+            Transform::Substitute { paths, .. } | Transform::LineEndings { paths, .. } => paths,
+        };
+        if !paths.is_empty()
+            && !paths
+                .iter()
+                .any(|glob| glob_match(glob, entry.path.as_str()))
+        {
+            return bytes;
+        }
+
+        match self {
+            Transform::Substitute { values, .. } => match String::from_utf8(bytes) {
+                Ok(text) => substitute(&text, values).into_bytes(),
+                Err(e) => e.into_bytes(),
+            },
+            Transform::LineEndings { .. } if bytes.contains(&0) => bytes,
+            Transform::LineEndings { to, .. } => {
+                let mut converted = Vec::with_capacity(bytes.len());
+                for (i, &byte) in bytes.iter().enumerate() {
+                    match byte {
+                        b'\r' if bytes.get(i + 1) == Some(&b'\n') => {}
+                        b'\n' if *to == LineEnding::Crlf => converted.extend_from_slice(b"\r\n"),
+                        _ => converted.push(byte),
+                    }
+                }
+                converted
+            }
+        }
+    }
+}
+
+/// Applies each transform in order.
+impl<T: ContentTransform> ContentTransform for Vec<T> {
+    fn transform(&self, entry: &SourceTree, bytes: Vec<u8>) -> Vec<u8> {
+        self.iter()
+            .fold(bytes, |bytes, transform| transform.transform(entry, bytes))
+    }
+}
+
+/// Replaces every `{{name}}` in `text` with the value of the variable `name`, allowing whitespace
+/// inside the braces. Unknown variables are left as they are.
+pub fn substitute(text: &str, values: &BTreeMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let replaced = after.find("}}").and_then(|end| {
+            let value = values.get(after[..end].trim())?;
+            Some((value, end))
+        });
+
+        match replaced {
+            Some((value, end)) => {
+                result.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                result.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,144 @@
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{source_tree::normalize_path, Error, FileMode, ObjectId, SourceTree, TreeEntryType};
+
+/// Builds a [SourceTree] from entries given in any order, such as for tests or for tools that list files
+/// from somewhere other than the GitHub API.
+///
+/// Every entry gets a [path](SourceTree::path) in the form [SourceTree] guarantees, and the directories
+/// above it are created as needed, so the tree can be walked and downloaded like one fetched from GitHub.
+/// Children are kept in the order they were added.
+#[derive(Debug, Clone)]
+pub struct SourceTreeBuilder {
+    root: SourceTree,
+}
+
+impl Default for SourceTreeBuilder {
+    fn default() -> Self {
+        SourceTreeBuilder {
+            root: SourceTree::new(TreeEntryType::Tree),
+        }
+    }
+}
+
+impl SourceTreeBuilder {
+    /// Creates a new [SourceTreeBuilder] with an empty root.
+    pub fn new() -> SourceTreeBuilder {
+        SourceTreeBuilder::default()
+    }
+
+    /// Adds a regular file at `path` with the given hash and size, creating its parent directories if needed.
+    pub fn add_blob(
+        &mut self,
+        path: &str,
+        sha: ObjectId,
+        size: u32,
+    ) -> Result<&mut SourceTreeBuilder, Error> {
+        let mut entry = SourceTree::new(TreeEntryType::Blob);
+        entry.path = path.into();
+        entry.sha = sha;
+        entry.size = size;
+        self.add_entry(entry)
+    }
+
+    /// Adds an empty directory at `path`, creating its parent directories if needed. Adding a directory that's
+    /// already in the tree does nothing.
+    pub fn add_dir(&mut self, path: &str) -> Result<&mut SourceTreeBuilder, Error> {
+        let mut entry = SourceTree::new(TreeEntryType::Tree);
+        entry.path = path.into();
+        self.add_entry(entry)
+    }
+
+    /// Adds `entry` at its [path](SourceTree::path), keeping its mode, hash, size and URL, and creating its
+    /// parent directories if needed. Its children are added below it, with paths relative to the root.
+    ///
+    /// A directory that's already in the tree takes the hash and URL of `entry` and keeps its children.
+    /// Fails if the path is empty, absolute or contains `..`, if a file is already at the path, or if one of
+    /// its parents is a file.
+    pub fn add_entry(&mut self, mut entry: SourceTree) -> Result<&mut SourceTreeBuilder, Error> {
+        let children = std::mem::take(&mut entry.children);
+        entry.path = SourceTreeBuilder::check_path(&entry.path)?;
+        if entry.entry_type == TreeEntryType::Tree {
+            entry.mode = FileMode::Directory;
+        }
+
+        let path = entry.path.clone();
+        let mut node = &mut self.root;
+        let dirs: Vec<&Utf8Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_str().is_empty())
+            .collect();
+        for dir in dirs.into_iter().rev() {
+            node = SourceTreeBuilder::child_dir(node, dir)?;
+        }
+
+        match node.children.iter().position(|c| c.path == path) {
+            Some(index)
+                if node.children[index].entry_type == TreeEntryType::Tree
+                    && entry.entry_type == TreeEntryType::Tree =>
+            {
+                let existing = &mut node.children[index];
+                existing.sha = entry.sha;
+                existing.url = entry.url;
+            }
+            Some(_) => return Err(Error::Other(format!("{} is already in the tree", path))),
+            None => node.children.push(entry),
+        }
+
+        for child in children {
+            self.add_entry(child)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Returns the finished tree.
+    pub fn build(self) -> SourceTree {
+        self.root
+    }
+
+    /// Returns `path` in the form [SourceTree::path] guarantees, or an error if it doesn't name an entry
+    /// below the root.
+    fn check_path(path: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+        let invalid = |reason| Error::Other(format!("{} {}", path, reason));
+        if path.as_str().starts_with('/') {
+            return Err(invalid("is an absolute path"));
+        }
+        if path.as_str().split('/').any(|c| c == "..") {
+            return Err(invalid("goes above the root of the tree"));
+        }
+
+        let path = normalize_path(path.as_str());
+        match path.as_str().is_empty() {
+            true => Err(Error::Other(String::from(
+                "the root of the tree can't be added as an entry",
+            ))),
+            false => Ok(path),
+        }
+    }
+
+    /// Returns the directory at `path` among the children of `node`, creating it if it doesn't exist.
+    fn child_dir<'t>(
+        node: &'t mut SourceTree,
+        path: &Utf8Path,
+    ) -> Result<&'t mut SourceTree, Error> {
+        let index = match node.children.iter().position(|c| c.path == path) {
+            Some(index) => index,
+            None => {
+                let mut dir = SourceTree::new(TreeEntryType::Tree);
+                dir.path = path.to_path_buf();
+                node.children.push(dir);
+                node.children.len() - 1
+            }
+        };
+
+        let dir = &mut node.children[index];
+        match dir.entry_type {
+            TreeEntryType::Tree => Ok(dir),
+            TreeEntryType::Blob => {
+                Err(Error::Other(format!("{} is a file, not a directory", path)))
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use crate::{
+    raw, DownloadConfig, DownloadReporter, Downloader, Error, Filter, GithubBranchPath,
+    GithubClient, GithubRef, SyncSummary,
+};
+
+/// Contains methods for keeping a directory in sync with a branch or tag as it moves.
+pub struct Watcher {}
+
+/// The last version of the reference that was synced.
+struct Synced {
+    /// The hash of the object the reference pointed to.
+    sha: String,
+    /// The `ETag` of the response the reference was read from.
+    etag: Option<String>,
+}
+
+impl<'p> Watcher {
+    /// Polls the branch or tag of `path` every `interval`, and [syncs](Downloader::sync) the output directory
+    /// with it right away and again whenever it moves, passing the summary of each sync to `on_sync`.
+    ///
+    /// Each poll is a conditional request for the reference, which GitHub doesn't count against the rate limit
+    /// while the reference hasn't changed. When GitHub rate limits a poll or a sync, watching waits as long as
+    /// GitHub asks before polling again. Any other error stops watching and is returned.
+    ///
+    /// Watching goes on until the [handle](DownloadConfig::handle) is aborted, even in the middle of a sync, and
+    /// then returns `Ok`. Without a handle, it only stops on an error. Set [client](DownloadConfig::client) to
+    /// make every poll and sync with the same client.
+    pub async fn watch<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        path: &GithubBranchPath<'p>,
+        filter: &Filter<'p>,
+        interval: Duration,
+        mut on_sync: impl FnMut(SyncSummary),
+    ) -> Result<(), Error> {
+        let name = match path.reference {
+            GithubRef::Branch(name) => format!("heads/{}", name),
+            GithubRef::Tag(name) => format!("tags/{}", name),
+            reference => {
+                return Err(Error::Other(format!(
+                    "{} isn't a branch or tag, so it can't be watched",
+                    reference
+                )))
+            }
+        };
+
+        let client = config.client()?;
+        let mut synced = None;
+        loop {
+            let result = Watcher::poll(
+                config,
+                &client,
+                path,
+                &name,
+                filter,
+                &mut synced,
+                &mut on_sync,
+            )
+            .await;
+
+            let wait = match result {
+                Ok(()) => interval,
+                Err(e) => match e.root() {
+                    Error::Aborted => return Ok(()),
+                    Error::RateLimited { retry_after, .. } => interval.max(*retry_after),
+                    _ => return Err(e),
+                },
+            };
+
+            match &config.handle {
+                Some(handle) => tokio::select! {
+                    _ = handle.aborted() => return Ok(()),
+                    _ = tokio::time::sleep(wait) => {}
+                },
+                None => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    /// Reads the reference named `name`, and syncs the output directory with it if it has moved since `synced`.
+    async fn poll<Reporter: DownloadReporter>(
+        config: &'p DownloadConfig<'p, Reporter>,
+        client: &GithubClient,
+        path: &GithubBranchPath<'p>,
+        name: &str,
+        filter: &Filter<'p>,
+        synced: &mut Option<Synced>,
+        on_sync: &mut impl FnMut(SyncSummary),
+    ) -> Result<(), Error> {
+        let etag = synced.as_ref().and_then(|synced| synced.etag.as_deref());
+        let Some((git_ref, etag)) = raw::get_ref_if_changed_raw(client, path, name, etag).await?
+        else {
+            return Ok(());
+        };
+
+        let object = git_ref.object;
+        if let Some(synced) = synced.as_mut().filter(|synced| synced.sha == object.sha) {
+            synced.etag = etag;
+            return Ok(());
+        }
+
+        // annotated tags point at a tag object, which sync resolves on its own
+        let reference = match object.object_type.as_str() {
+            "commit" => GithubRef::Commit(&object.sha),
+            _ => path.reference,
+        };
+        let commit_path = GithubBranchPath::with_ref(path.user, path.repo, reference);
+        on_sync(Downloader::sync(config, &commit_path, filter).await?);
+
+        *synced = Some(Synced {
+            sha: object.sha,
+            etag,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Downloading the pages of a repository's wiki.
+//!
+//! GitHub stores a wiki as a separate git repository named `{repo}.wiki`, which isn't part of the
+//! repository's own tree and isn't served by the REST API at all. The functions here clone that repository
+//! from `https://github.com/{user}/{repo}.wiki.git`, or the same path on a GitHub Enterprise Server, like
+//! [FetchStrategy::Clone] does, so its pages can be filtered and downloaded like any other tree.
+//!
+//! Wikis are kept on whichever branch the `HEAD` of the wiki repository points to, which is read from the
+//! repository each time rather than assumed. Requests for a wiki that hasn't been created fail when cloning.
+
+use crate::{
+    fetch_strategy::CloneTarget, DownloadConfig, DownloadReporter, DownloadedFile, Downloader,
+    Error, FetchStrategy, Filter, GithubBranchPath, GithubRef, SourceTree,
+};
+
+/// Returns the name of the repository holding the wiki of `repo`.
+pub fn wiki_repo_name(repo: &str) -> String {
+    format!("{}.wiki", repo)
+}
+
+/// Fetches and downloads the pages of repository wikis.
+pub struct Wiki {}
+
+impl Wiki {
+    /// Returns the branch the wiki of `user/repo` is kept on, by asking the wiki repository where its `HEAD`
+    /// points. Uses the API URL and access token of `config`.
+    pub async fn default_branch<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        user: &str,
+        repo: &str,
+    ) -> Result<String, Error> {
+        let client = config.client()?;
+        let wiki_repo = wiki_repo_name(repo);
+        CloneTarget::with_refspec(config, &client, user, &wiki_repo, "HEAD".to_string())
+            .default_branch()
+            .await
+    }
+
+    /// Fetches the tree of the wiki of `user/repo`, keeping entries that pass `filter`. The wiki is cloned into
+    /// the [temp_dir](DownloadConfig::temp_dir) of `config`, and deleted again before this returns.
+    pub async fn get_tree<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        user: &str,
+        repo: &str,
+        filter: &Filter<'_>,
+    ) -> Result<SourceTree, Error> {
+        let client = config.client()?;
+        let branch = Wiki::default_branch(config, user, repo).await?;
+        let wiki_repo = wiki_repo_name(repo);
+        let path = GithubBranchPath::with_ref(user, &wiki_repo, GithubRef::Branch(&branch));
+        Downloader::get_cloned_tree(config, &client, &path, filter).await
+    }
+
+    /// Downloads the pages of the wiki of `user/repo` that pass `filter`, as configured by `config`.
+    /// The wiki is always cloned, whatever the [fetch_strategy](DownloadConfig::fetch_strategy) or
+    /// [forge](DownloadConfig::forge) of `config`.
+    pub async fn download<Reporter: DownloadReporter>(
+        config: &DownloadConfig<'_, Reporter>,
+        user: &str,
+        repo: &str,
+        filter: &Filter<'_>,
+    ) -> Result<Vec<DownloadedFile>, Error> {
+        let branch = Wiki::default_branch(config, user, repo).await?;
+        let wiki_repo = wiki_repo_name(repo);
+        let path = GithubBranchPath::with_ref(user, &wiki_repo, GithubRef::Branch(&branch));
+        let config = DownloadConfig {
+            fetch_strategy: FetchStrategy::Clone,
+            forge: None,
+            ..config.clone()
+        };
+        Downloader::download(&config, &path, filter).await
+    }
+}
@@ -0,0 +1,322 @@
+#![cfg(feature = "archive")]
+
+mod common;
+
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use common::{MockResponse, MockServer};
+use grab_github::{
+    ArchiveFormat, ArchiveOptions, CollectedEvent, CollectingReporter, DownloadConfig,
+    DownloadConfigNoReporting, DownloadedFile, Downloader, Error, FetchStrategy, FileMode, Filter,
+    GithubBranchPath, SourceTree, TreeEntryType, Utf8PathBuf,
+};
+
+/// Writes files as if they had been downloaded, with the given modification time.
+fn write_files(output_path: &Path, modified: SystemTime) -> Result<Vec<DownloadedFile>, Error> {
+    let files = [
+        ("src/main.rs", "100644"),
+        ("build.sh", "100755"),
+        ("README.md", "100644"),
+    ];
+    files
+        .iter()
+        .map(|(path, mode)| {
+            let output = output_path.join(path);
+            std::fs::create_dir_all(output.parent().unwrap())?;
+            std::fs::write(&output, path.as_bytes())?;
+            File::options()
+                .write(true)
+                .open(&output)?
+                .set_modified(modified)?;
+
+            let mut entry = SourceTree::new(TreeEntryType::Blob);
+            entry.path = Utf8PathBuf::from(*path);
+            entry.mode = mode.parse()?;
+            Ok(DownloadedFile {
+                entry: Arc::new(entry),
+                output_path: output,
+                bytes_written: path.len() as u64,
+                bytes_received: 0,
+                verified: true,
+            })
+        })
+        .collect()
+}
+
+fn package_twice(format: ArchiveFormat, extension: &str) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let output_path = PathBuf::from(format!("./tests/test_output_dir_archive_{:?}/", format));
+    let archive_path = PathBuf::from(format!(
+        "./tests/test_output_archive_{:?}.{}",
+        format, extension
+    ));
+    let config = DownloadConfigNoReporting::new(&output_path);
+    let options = ArchiveOptions::default();
+
+    let result = (|| {
+        let mut archives = Vec::new();
+        for seconds in [1_000_000_000, 1_700_000_000] {
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+            let files = write_files(&output_path, modified)?;
+            Downloader::package(&config, &files, &archive_path, format, &options)?;
+            archives.push(std::fs::read(&archive_path)?);
+        }
+        Ok((archives.remove(0), archives.remove(0)))
+    })();
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(&output_path)?;
+    }
+    let _ = std::fs::remove_file(&archive_path);
+
+    result
+}
+
+#[test]
+pub fn deterministic_zip() -> Result<(), Error> {
+    let (first, second) = package_twice(ArchiveFormat::Zip, "zip")?;
+    assert_eq!(first, second);
+
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(first)).unwrap();
+    let names: Vec<&str> = zip.file_names().collect();
+    assert_eq!(names.len(), 3);
+    let entries: Vec<(String, Option<u32>)> = (0..zip.len())
+        .map(|i| {
+            let file = zip.by_index(i).unwrap();
+            (file.name().to_string(), file.unix_mode().map(|m| m & 0o777))
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (String::from("README.md"), Some(0o644)),
+            (String::from("build.sh"), Some(0o755)),
+            (String::from("src/main.rs"), Some(0o644)),
+        ]
+    );
+
+    let mut contents = String::new();
+    zip.by_name("src/main.rs")
+        .unwrap()
+        .read_to_string(&mut contents)?;
+    assert_eq!(contents, "src/main.rs");
+
+    Ok(())
+}
+
+#[test]
+pub fn deterministic_tar_zst() -> Result<(), Error> {
+    let (first, second) = package_twice(ArchiveFormat::TarZst, "tar.zst")?;
+    assert_eq!(first, second);
+
+    let tar = zstd::decode_all(first.as_slice())?;
+    let mut archive = tar::Archive::new(tar.as_slice());
+    let entries: Vec<(String, u32, u64)> = archive
+        .entries()?
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let header = entry.header();
+            (
+                entry.path().unwrap().to_string_lossy().into_owned(),
+                header.mode().unwrap(),
+                header.mtime().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (String::from("README.md"), 0o644, 315_532_800),
+            (String::from("build.sh"), 0o755, 315_532_800),
+            (String::from("src/main.rs"), 0o644, 315_532_800),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn archive_format_from_path() {
+    let format = |path: &str| ArchiveFormat::from_path(Path::new(path));
+    assert_eq!(format("out/repo.tar.zst"), Some(ArchiveFormat::TarZst));
+    assert_eq!(format("repo.TZST"), Some(ArchiveFormat::TarZst));
+    assert_eq!(format("repo.zip"), Some(ArchiveFormat::Zip));
+    assert_eq!(format("repo.tar.gz"), None);
+}
+
+/// Builds a gzipped tarball laid out like the ones GitHub serves for `user/repo` at `main`.
+fn github_tarball(commit: &str) -> Result<Vec<u8>, Error> {
+    github_tarball_of(
+        commit,
+        &[
+            ("README.md", 0o664, b"hello".to_vec()),
+            ("run.sh", 0o775, b"hello".to_vec()),
+            ("docs/guide.md", 0o664, b"hello".to_vec()),
+        ],
+    )
+}
+
+/// Builds a tarball like [github_tarball] holding the given paths, modes and contents.
+fn github_tarball_of(commit: &str, files: &[(&str, u32, Vec<u8>)]) -> Result<Vec<u8>, Error> {
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+
+    let comment = format!("52 comment={}\n", commit);
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XGlobalHeader);
+    header.set_path("pax_global_header")?;
+    header.set_size(comment.len() as u64);
+    header.set_mode(0o666);
+    header.set_cksum();
+    builder.append(&header, comment.as_bytes())?;
+
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o775);
+    builder.append_data(&mut header, "repo-main/", std::io::empty())?;
+
+    for (path, mode, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(*mode);
+        builder.append_data(&mut header, format!("repo-main/{}", path), &contents[..])?;
+    }
+
+    Ok(builder.into_inner()?.finish()?)
+}
+
+#[tokio::test]
+async fn auto_fetch_falls_back_to_archive() -> Result<(), Error> {
+    const COMMIT: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+    let tarball = github_tarball(COMMIT)?;
+    let server = MockServer::start(move |r| match r.path.as_str() {
+        "/repos/user/repo/tarball/main" => MockResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: tarball.clone(),
+            raw_body: None,
+        },
+        _ => MockResponse::json(
+            403,
+            r#"{"message":"API rate limit exceeded for 127.0.0.1."}"#,
+        )
+        .with_header("X-RateLimit-Remaining", "0")
+        .with_header("Retry-After", "60"),
+    })
+    .await;
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_auto_fetch/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+    config.api_url = server.url.clone().into();
+    config.fetch_strategy = FetchStrategy::Auto;
+
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let filter = Filter::new(vec![], vec!["docs/**"]);
+    let report = Downloader::download_resolved(&config, &path, &filter).await;
+    let readme = std::fs::read(output_path.join("README.md"));
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let report = report?;
+    assert_eq!(
+        report.resolved.commit_sha.map(|sha| sha.to_string()),
+        Some(String::from(COMMIT))
+    );
+    assert_eq!(report.files.len(), 2);
+    assert!(report.all_verified());
+    assert_eq!(readme?, b"hello");
+    let run = report.files.iter().find(|f| f.entry.path == "run.sh");
+    assert_eq!(run.map(|f| f.entry.mode), Some(FileMode::Executable));
+
+    let events = reporter.events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        CollectedEvent::FetchFallback {
+            from: FetchStrategy::Api,
+            to: FetchStrategy::Archive,
+            ..
+        }
+    )));
+    assert!(matches!(
+        events.last(),
+        Some(CollectedEvent::FetchStrategyUsed {
+            strategy: FetchStrategy::Archive
+        })
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn archive_quotas() -> Result<(), Error> {
+    const COMMIT: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+    // compresses to a few kilobytes, and would take 16 MiB to hold in memory
+    let tarball = github_tarball_of(
+        COMMIT,
+        &[
+            ("huge.bin", 0o664, vec![0; 16 * 1024 * 1024]),
+            ("README.md", 0o664, b"hello".to_vec()),
+            ("LICENSE", 0o664, b"hello".to_vec()),
+        ],
+    )?;
+    let server = MockServer::start(move |_| MockResponse {
+        status: 200,
+        headers: Vec::new(),
+        body: tarball.clone(),
+        raw_body: None,
+    })
+    .await;
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_archive_quotas/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+    config.api_url = server.url.clone().into();
+    config.fetch_strategy = FetchStrategy::Archive;
+    config.max_file_bytes = Some(1024);
+    let path = GithubBranchPath::new("user", "repo", "main");
+
+    // the huge file is listed, but skipped without being read
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    let huge = output_path.join("huge.bin").exists();
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+    assert_eq!(report?.files.len(), 2);
+    assert!(!huge);
+    assert!(reporter.events().iter().any(|e| matches!(
+        e,
+        CollectedEvent::DownloadFailed { path, .. } if path == "huge.bin"
+    )));
+
+    // unpacking stops once the files kept add up to more than the total
+    config.max_file_bytes = None;
+    config.max_total_bytes = Some(1024);
+    let result = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    assert!(matches!(
+        result.map_err(|e| e.root().clone()),
+        Err(Error::QuotaExceeded {
+            path: None,
+            limit: 1024,
+            ..
+        })
+    ));
+
+    config.max_total_bytes = None;
+    config.max_files = Some(2);
+    let result = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    assert!(matches!(
+        result.map_err(|e| e.root().clone()),
+        Err(Error::TooManyFiles { limit: 2 })
+    ));
+    assert!(!output_path.exists());
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "bitbucket")]
+
+mod common;
+
+use std::path::Path;
+
+use common::{MockResponse, MockServer};
+use grab_github::{
+    BitbucketForge, DownloadConfigNoReporting, Downloader, Error, FileMode, Filter,
+    GithubBranchPath, GithubClient,
+};
+
+#[tokio::test]
+pub async fn bitbucket_forge() -> Result<(), Error> {
+    const COMMIT: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let src = format!("/repositories/team/project/src/{}", COMMIT);
+        match r.path.as_str() {
+            "/repositories/team/project/refs/branches/main" => MockResponse::json(
+                200,
+                &format!(r#"{{"name":"main","target":{{"hash":"{}"}}}}"#, COMMIT),
+            ),
+            p if p == format!("{}/?pagelen=100", src) => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"values":[
+                        {{"type":"commit_file","path":"README.md","size":5,"attributes":[]}},
+                        {{"type":"commit_directory","path":"docs"}},
+                        {{"type":"commit_directory","path":"bin"}}
+                    ],"next":"http://{}{}/?pagelen=100&page=2"}}"#,
+                    host, src
+                ),
+            ),
+            p if p == format!("{}/?pagelen=100&page=2", src) => MockResponse::json(
+                200,
+                r#"{"values":[{"type":"commit_file","path":"lib","size":0,"attributes":["subrepository"]}]}"#,
+            ),
+            p if p == format!("{}/bin/?pagelen=100", src) => MockResponse::json(
+                200,
+                r#"{"values":[{"type":"commit_file","path":"bin/run.sh","size":5,"attributes":["executable"]}]}"#,
+            ),
+            p if p.starts_with(&src) => MockResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: b"hello".to_vec(),
+                raw_body: None,
+            },
+            _ => MockResponse::json(404, r#"{"type":"error","error":{"message":"Not found"}}"#),
+        }
+    })
+    .await;
+
+    let forge = BitbucketForge::with_client(GithubClient::new(None)?.with_api_url(&server.url));
+    let output_path = Path::new("./tests/test_output_dir_bitbucket_forge/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.forge = Some(&forge);
+
+    // docs can't hold anything passing the filter, so it's never listed
+    let path = GithubBranchPath::new("team", "project", "main");
+    let filter = Filter::new(vec![], vec!["docs/**"]);
+    let report = Downloader::download_resolved(&config, &path, &filter).await;
+    let script = std::fs::read(output_path.join("bin/run.sh"));
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let report = report?;
+    let files: Vec<(&str, FileMode)> = report
+        .files
+        .iter()
+        .map(|f| (f.entry.path.as_str(), f.entry.mode))
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            ("README.md", FileMode::Regular),
+            ("bin/run.sh", FileMode::Executable)
+        ]
+    );
+    assert_eq!(script?, b"hello");
+    assert!(!server.requests().iter().any(|r| r.path.contains("/docs/")));
+    assert_eq!(
+        report.files[1].entry.url,
+        format!(
+            "{}/repositories/team/project/src/{}/bin/run.sh",
+            server.url, COMMIT
+        )
+    );
+
+    Ok(())
+}
@@ -0,0 +1,256 @@
+#![cfg(feature = "download")]
+
+mod common;
+
+use common::{MockResponse, MockServer};
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use grab_github::{
+    raw, ApiObserver, ApiRequest, ApiResponse, AuthScheme, Blob, CachePolicy, Error, GithubClient,
+    HttpOptions, TreeCache, DEFAULT_USER_AGENT,
+};
+
+#[test]
+pub fn decode_text() {
+    assert_eq!(Blob::decode_text(b"plain text"), "plain text");
+    assert_eq!(Blob::decode_text("caf\u{e9}".as_bytes()), "caf\u{e9}");
+    assert_eq!(Blob::decode_text(b"\xEF\xBB\xBFwith bom"), "with bom");
+    assert_eq!(Blob::decode_text(b"\xFF\xFEh\x00i\x00"), "hi");
+    assert_eq!(Blob::decode_text(b"\xFE\xFF\x00h\x00i"), "hi");
+    assert_eq!(Blob::decode_text(b"caf\xE9"), "caf\u{e9}");
+}
+
+#[tokio::test]
+pub async fn request_budget() -> Result<(), Error> {
+    let server = MockServer::start(|_| {
+        MockResponse::blob(b"hello")
+            .with_header("X-RateLimit-Limit", "5000")
+            .with_header("X-RateLimit-Remaining", "4998")
+    })
+    .await;
+
+    let client = GithubClient::new(None)?;
+    assert_eq!(client.budget().requests_made(), 0);
+    assert_eq!(client.budget().remaining(), None);
+
+    let entry = server.blob_entry("hello.txt", 5);
+    assert_eq!(Blob::fetch(&client, &entry).await?, b"hello");
+    assert_eq!(Blob::fetch_text(&client.clone(), &entry).await?, "hello");
+
+    assert_eq!(client.budget().requests_made(), 2);
+    assert_eq!(client.budget().limit(), Some(5000));
+    assert_eq!(client.budget().remaining(), Some(4998));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn large_blob() -> Result<(), Error> {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/blobs/large.bin" => MockResponse {
+            status: 200,
+            headers: vec![],
+            body: b"raw contents".to_vec(),
+            raw_body: None,
+        },
+        _ => MockResponse::json(
+            403,
+            r#"{"message":"This blob is too large to be returned by the API"}"#,
+        ),
+    })
+    .await;
+
+    let client = GithubClient::new(None)?;
+    let size = (raw::BLOB_API_SIZE_LIMIT + 1) as u32;
+
+    let entry = server.blob_entry("large.bin", size);
+    assert_eq!(Blob::fetch(&client, &entry).await?, b"raw contents");
+    assert_eq!(
+        server.requests()[0].header("Accept"),
+        Some(raw::MEDIA_TYPE_RAW)
+    );
+
+    let entry = server.blob_entry("huge.bin", size);
+    let error = Blob::fetch(&client, &entry).await.unwrap_err();
+    assert!(matches!(error.root(), Error::BlobTooLargeForApi { .. }));
+    assert_eq!(error.context().and_then(|c| c.status), Some(403));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn blob_cache() -> Result<(), Error> {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/blobs/missing.txt" => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+        _ => MockResponse::blob(b"hello"),
+    })
+    .await;
+
+    let policy = CachePolicy {
+        cache_blobs: true,
+        ..CachePolicy::default()
+    };
+    let client = GithubClient::new(None)?.with_cache(Arc::new(TreeCache::new(policy)));
+
+    let entry = server.blob_entry("hello.txt", 5);
+    assert_eq!(Blob::fetch(&client, &entry).await?, b"hello");
+    assert_eq!(Blob::fetch(&client.clone(), &entry).await?, b"hello");
+
+    let missing = server.blob_entry("missing.txt", 5);
+    assert!(Blob::fetch(&client, &missing).await.is_err());
+    let error = Blob::fetch(&client, &missing).await.unwrap_err();
+    assert_eq!(error.context().and_then(|c| c.status), Some(404));
+
+    assert_eq!(server.requests().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn http_options() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"hello")).await;
+    let entry = server.blob_entry("hello.txt", 5);
+
+    Blob::fetch(&GithubClient::new(None)?, &entry).await?;
+
+    let options = HttpOptions {
+        user_agent: Some(Cow::from("my-app/1.0")),
+        extra_headers: vec![(Cow::from("X-Request-Source"), Cow::from("tests"))],
+        ..HttpOptions::default()
+    };
+    Blob::fetch(&GithubClient::with_options(None, &options)?, &entry).await?;
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("User-Agent"), Some(DEFAULT_USER_AGENT));
+    assert_eq!(requests[0].header("X-Request-Source"), None);
+    assert_eq!(requests[1].header("User-Agent"), Some("my-app/1.0"));
+    assert_eq!(requests[1].header("X-Request-Source"), Some("tests"));
+
+    // proxies can be configured whatever the features, but only used with `socks`
+    #[cfg(not(feature = "socks"))]
+    {
+        let options = HttpOptions {
+            socks_proxy: Some(grab_github::SocksProxy::new("127.0.0.1:1080")),
+            ..HttpOptions::default()
+        };
+        assert!(GithubClient::with_options(None, &options).is_err());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn auth_scheme() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"hello")).await;
+    let entry = server.blob_entry("hello.txt", 5);
+
+    let fetch = |token: &'static str, auth_scheme: AuthScheme| {
+        let entry = entry.clone();
+        async move {
+            let options = HttpOptions {
+                auth_scheme,
+                ..HttpOptions::default()
+            };
+            let client = GithubClient::with_options(Some(token), &options)?;
+            assert!(!format!("{:?}", client).contains(token));
+            Blob::fetch(&client, &entry).await
+        }
+    };
+    fetch("ghp_classic", AuthScheme::Auto).await?;
+    fetch("github_pat_fine", AuthScheme::Auto).await?;
+    fetch("ghp_classic", AuthScheme::Bearer).await?;
+
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].header("Authorization"),
+        Some("token ghp_classic")
+    );
+    assert_eq!(
+        requests[1].header("Authorization"),
+        Some("Bearer github_pat_fine")
+    );
+    assert_eq!(
+        requests[2].header("Authorization"),
+        Some("Bearer ghp_classic")
+    );
+
+    assert_eq!(
+        AuthScheme::Auto.for_token("eyJhbGciOiJSUzI1NiJ9.eyJpc3MiOjF9.c2ln"),
+        AuthScheme::Bearer
+    );
+
+    let options = HttpOptions {
+        extra_headers: vec![(Cow::from("authorization"), Cow::from("Bearer secret"))],
+        ..HttpOptions::default()
+    };
+    assert!(!format!("{:?}", options).contains("secret"));
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct CollectingObserver(Mutex<Vec<String>>);
+
+impl ApiObserver for CollectingObserver {
+    fn on_request(&self, request: &ApiRequest<'_>) {
+        let path = request.url.rsplit('/').next().unwrap();
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("request {} {}", request.method, path));
+    }
+
+    fn on_response(&self, response: &ApiResponse<'_>) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("response {}", response.status));
+    }
+
+    fn on_rate_limit(&self, _url: &str, retry_after: Duration) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("rate limit {}", retry_after.as_secs()));
+    }
+}
+
+#[tokio::test]
+pub async fn api_observer() -> Result<(), Error> {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/blobs/limited.txt" => {
+            MockResponse::json(429, r#"{"message":"slow down"}"#).with_header("Retry-After", "30")
+        }
+        _ => MockResponse::blob(b"hello"),
+    })
+    .await;
+
+    let observer = Arc::new(CollectingObserver::default());
+    let client = GithubClient::new(None)?.with_observer(observer.clone());
+
+    Blob::fetch(&client, &server.blob_entry("hello.txt", 5)).await?;
+    let limited = Blob::fetch(&client, &server.blob_entry("limited.txt", 5)).await;
+    let limited = limited.unwrap_err();
+    assert!(matches!(limited.root(), Error::RateLimited { .. }));
+    assert_eq!(
+        limited.context().and_then(|c| c.url.as_deref()),
+        Some(format!("{}/blobs/limited.txt", server.url).as_str())
+    );
+
+    assert_eq!(
+        *observer.0.lock().unwrap(),
+        vec![
+            "request GET hello.txt",
+            "response 200",
+            "request GET limited.txt",
+            "response 429",
+            "rate limit 30",
+        ]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,285 @@
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use grab_github::{FileMode, ObjectId, SourceTree, TreeEntryType, Utf8PathBuf};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request received by a [MockServer].
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// A canned response returned by a [MockServer].
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// The body to send instead when the request asks for the raw media type, as GitHub does for blobs.
+    pub raw_body: Option<Vec<u8>>,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: &str) -> MockResponse {
+        MockResponse {
+            status,
+            headers: vec![("Content-Type".into(), "application/json".into())],
+            body: body.as_bytes().to_vec(),
+            raw_body: None,
+        }
+    }
+
+    /// A `Get a blob` response with the given contents, sent as-is to requests for the raw media type.
+    pub fn blob(contents: &[u8]) -> MockResponse {
+        let mut response = MockResponse::json(
+            200,
+            &format!(
+                r#"{{"content":"{}","encoding":"base64","url":"","sha":"","size":{}}}"#,
+                BASE64_STANDARD.encode(contents),
+                contents.len()
+            ),
+        );
+        response.raw_body = Some(contents.to_vec());
+        response
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> MockResponse {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&MockRequest) -> MockResponse + Send + Sync;
+
+/// A minimal HTTP server standing in for the GitHub API.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub async fn start<F>(handler: F) -> MockServer
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let server_requests = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let requests = server_requests.clone();
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, requests, handler).await;
+                });
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// Returns every request received so far.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Creates a blob entry at `path` whose URL points at `/blobs/{path}` on this server.
+    pub fn blob_entry(&self, path: &str, size: u32) -> SourceTree {
+        SourceTree {
+            path: Utf8PathBuf::from(path),
+            mode: FileMode::Regular,
+            sha: ObjectId::NULL,
+            entry_type: TreeEntryType::Blob,
+            size,
+            url: format!("{}/blobs/{}", self.url, path),
+            children: Vec::new(),
+            last_commit: None,
+        }
+    }
+
+    /// Creates a root tree containing the given entries.
+    pub fn tree(&self, children: Vec<SourceTree>) -> SourceTree {
+        let mut tree = SourceTree::new(TreeEntryType::Tree);
+        tree.children = children;
+        tree
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+    handler: Arc<Handler>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let head_len = buf.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&buf[..head_len]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or("").split(' ');
+    let mut request = MockRequest {
+        method: request_line.next().unwrap_or("").to_string(),
+        path: request_line.next().unwrap_or("").to_string(),
+        headers: lines
+            .take_while(|l| !l.is_empty())
+            .filter_map(|l| l.split_once(": "))
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect(),
+        body: buf.split_off(head_len),
+    };
+
+    let content_length: usize = request
+        .header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    while request.body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        request.body.extend_from_slice(&chunk[..n]);
+    }
+
+    let response = handler(&request);
+    let is_raw = request
+        .header("accept")
+        .is_some_and(|accept| accept.contains("vnd.github.raw"));
+    let (body, content_type) = match response.raw_body {
+        Some(raw_body) if is_raw => (raw_body, "application/vnd.github.raw"),
+        _ => (response.body, "application/json"),
+    };
+    let is_head = request.method == "HEAD";
+    requests.lock().unwrap().push(request);
+
+    let mut out = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        body.len()
+    );
+    for (name, value) in &response.headers {
+        let value = match name.eq_ignore_ascii_case("content-type") {
+            true => content_type,
+            false => value,
+        };
+        out.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    out.push_str("\r\n");
+
+    stream.write_all(out.as_bytes()).await?;
+    // like any HTTP server, only the headers are sent in response to a HEAD request
+    if !is_head {
+        stream.write_all(&body).await?;
+    }
+    stream.shutdown().await
+}
+
+/// A minimal SOCKS5 proxy that requires username and password authentication and forwards each
+/// connection to its target.
+pub struct MockSocksProxy {
+    pub address: String,
+    targets: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockSocksProxy {
+    pub async fn start(username: &str, password: &str) -> MockSocksProxy {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let credentials = Arc::new((username.to_string(), password.to_string()));
+
+        let proxy_targets = targets.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let targets = proxy_targets.clone();
+                let credentials = credentials.clone();
+                tokio::spawn(async move {
+                    let _ = handle_socks_connection(stream, targets, credentials).await;
+                });
+            }
+        });
+
+        MockSocksProxy { address, targets }
+    }
+
+    /// Returns the `host:port` of every connection forwarded so far.
+    pub fn targets(&self) -> Vec<String> {
+        self.targets.lock().unwrap().clone()
+    }
+}
+
+async fn read_string(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut value = vec![0u8; stream.read_u8().await? as usize];
+    stream.read_exact(&mut value).await?;
+    Ok(String::from_utf8_lossy(&value).into_owned())
+}
+
+async fn handle_socks_connection(
+    mut stream: TcpStream,
+    targets: Arc<Mutex<Vec<String>>>,
+    credentials: Arc<(String, String)>,
+) -> std::io::Result<()> {
+    // The greeting lists the authentication methods the client supports; 2 is username/password.
+    let mut greeting = [0u8; 2];
+    stream.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if greeting[0] != 5 || !methods.contains(&2) {
+        return stream.write_all(&[5, 0xff]).await;
+    }
+    stream.write_all(&[5, 2]).await?;
+
+    // Username and password authentication, from RFC 1929.
+    stream.read_u8().await?;
+    let username = read_string(&mut stream).await?;
+    let password = read_string(&mut stream).await?;
+    if (username, password) != *credentials {
+        return stream.write_all(&[1, 1]).await;
+    }
+    stream.write_all(&[1, 0]).await?;
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request).await?;
+    let host = match request[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            stream.read_exact(&mut ip).await?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        3 => read_string(&mut stream).await?,
+        _ => return stream.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0]).await,
+    };
+    let target = format!("{}:{}", host, stream.read_u16().await?);
+    targets.lock().unwrap().push(target.clone());
+
+    let mut upstream = TcpStream::connect(&target).await?;
+    stream.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    tokio::io::copy_bidirectional(&mut stream, &mut upstream).await?;
+    Ok(())
+}
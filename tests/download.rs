@@ -1,15 +1,36 @@
-use std::path::Path;
+#![cfg(feature = "download")]
 
+mod common;
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::{MockResponse, MockServer};
+
+use futures::{
+    future::{BoxFuture, FutureExt},
+    TryStreamExt,
+};
 use grab_github::{
-    DownloadConfig, DownloadEvent, DownloadReporter, Downloader, Error, Filter, GithubBranchPath,
-    SourceTree,
+    ChecksumAlgorithm, ClientPool, CollectedEvent, CollectingReporter, CollisionPolicy,
+    DownloadConfig, DownloadConfigNoReporting, DownloadEvent, DownloadHandle, DownloadHooks,
+    DownloadInterceptor, DownloadOrdering, DownloadReport, DownloadReporter, DownloadedFile,
+    Downloader, Error, FilePermissions, Filter, GitLabForge, GiteaHost, GithubBranchPath,
+    GithubClient, GithubRef, HttpOptions, InterceptDecision, OutputLayout, RefLayout, SourceTree,
+    SourceTreeBuilder, TokenSource, UnrepresentablePathPolicy, Watcher, CONTENT_MANIFEST_FILE_NAME,
 };
 use sha1::{Digest, Sha1};
 
 struct TestReporter;
 
 impl DownloadReporter for TestReporter {
-    fn on_event<'p>(&'p self, event: DownloadEvent<'p>) -> () {
+    fn on_event<'p>(&'p self, event: DownloadEvent<'p>) {
         eprintln!("download reported event {:?}", event);
     }
 }
@@ -17,7 +38,7 @@ impl DownloadReporter for TestReporter {
 pub async fn download_and_test<'p>(
     path: GithubBranchPath<'p>,
     filter: Filter<'p>,
-    test: fn(&Path, &Vec<SourceTree>) -> Result<(), Error>,
+    test: fn(&Path, &Vec<DownloadedFile>) -> Result<(), Error>,
 ) -> Result<(), Error> {
     let reporter = TestReporter {};
     let output_path = Path::new("./tests/test_output_dir/");
@@ -25,9 +46,10 @@ pub async fn download_and_test<'p>(
         std::fs::remove_dir_all(output_path)?;
     }
     let result = async {
-        let config = DownloadConfig::new_with_reporter(&output_path, &reporter);
+        let config = DownloadConfig::new_with_reporter(output_path, &reporter)
+            .with_token_source(TokenSource::Env);
         let files = Downloader::download(&config, &path, &filter).await?;
-        test(&config.output_path, &files)
+        test(config.output_path, &files)
     }
     .await;
 
@@ -62,7 +84,7 @@ fn check_hash(dir: &Path, path: &Path, expected_hash: &'static str) -> Result<()
         )));
     }
 
-    return Ok(());
+    Ok(())
 }
 
 #[tokio::test]
@@ -82,3 +104,2241 @@ pub async fn hello_git_world() -> Result<(), Error> {
 
     Ok(())
 }
+
+#[tokio::test]
+pub async fn hello_git_world_stream() -> Result<(), Error> {
+    let output_path = Path::new("./tests/test_output_dir_stream/");
+    let repo = GithubBranchPath::new("githubtraining", "hellogitworld", "master");
+    let filter = Filter::new(vec!["build.gradle", "pom.xml"], vec![]);
+    let config = DownloadConfigNoReporting::new(output_path).with_token_source(TokenSource::Env);
+
+    let files: Result<Vec<DownloadedFile>, Error> =
+        Downloader::download_stream(&config, &repo, &filter)
+            .try_collect()
+            .await;
+    let result = files.and_then(|files| {
+        assert_eq!(files.len(), 2);
+        for file in &files {
+            assert_eq!(file.output_path, output_path.join(&file.entry.path));
+        }
+
+        check_hash(
+            output_path,
+            Path::new("build.gradle"),
+            "d8a738144623ca437e35d781992cc75e1ee3b79c",
+        )
+    });
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    result
+}
+
+#[tokio::test]
+pub async fn rate_limited_download() -> Result<(), Error> {
+    let attempts = AtomicUsize::new(0);
+    let server = MockServer::start(move |_| match attempts.fetch_add(1, Ordering::SeqCst) {
+        0 => MockResponse::json(
+            403,
+            r#"{"message":"You have exceeded a secondary rate limit."}"#,
+        )
+        .with_header("Retry-After", "1"),
+        _ => MockResponse::blob(b"hello"),
+    })
+    .await;
+
+    let mut entry = server.blob_entry("a.txt", 5);
+    entry.sha = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0".parse()?;
+    let tree = server.tree(vec![entry]);
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_rate_limit/");
+    let config = DownloadConfig::new_with_reporter(output_path, &reporter);
+
+    let result = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    let contents = std::fs::read(output_path.join("a.txt"));
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let files = result?;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].output_path, output_path.join("a.txt"));
+    assert_eq!(files[0].bytes_written, 5);
+    assert!(files[0].verified);
+    assert_eq!(contents?, b"hello");
+    assert_eq!(server.requests().len(), 2);
+    let events = reporter.events();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        CollectedEvent::RateLimited { path, retry_after } if path == "a.txt" && retry_after.as_secs() == 1
+    )));
+    assert!(matches!(
+        events.last(),
+        Some(CollectedEvent::DownloadCompleted { .. })
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn rate_limit_guidance() -> Result<(), Error> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let server = MockServer::start(|r| match r.path.as_str() {
+        "/blobs/b.txt" => MockResponse::json(403, r#"{"message":"API rate limit exceeded."}"#)
+            .with_header("Retry-After", "60"),
+        _ => MockResponse::blob(b"hello"),
+    })
+    .await;
+
+    let tree = server.tree(vec![
+        server.blob_entry("a.txt", 5),
+        server.blob_entry("b.txt", 5),
+        server.blob_entry("c.txt", 5),
+    ]);
+    let output_path = Path::new("./tests/test_output_dir_rate_limit_guidance/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.max_rate_limit_retries = 0;
+    config.max_simultaneous_downloads = 1;
+
+    let result = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let error = result.unwrap_err();
+    let guidance = error.rate_limit_guidance().unwrap();
+    assert_eq!(guidance.requests_made, 2);
+    assert_eq!(guidance.files_downloaded, 1);
+    assert_eq!(guidance.files_remaining, 2);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    assert!(guidance.reset_at >= now.as_secs() + 59);
+    assert_eq!(guidance.archive_would_succeed, cfg!(feature = "archive"));
+    assert_eq!(
+        serde_json::to_value(&error)?["source"]["guidance"]["files_remaining"],
+        serde_json::json!(2)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn estimate_requests() {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("Cargo.toml", 0),
+        server.blob_entry("src/lib.rs", 0),
+        server.blob_entry("src/main.rs", 0),
+    ]);
+
+    assert_eq!(Downloader::estimate_requests(&tree, &Filter::all()), 3);
+    assert_eq!(
+        Downloader::estimate_requests(&tree, &Filter::new(vec!["src/*"], vec![])),
+        2
+    );
+}
+
+#[tokio::test]
+pub async fn shared_client() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("Cargo.toml", 0),
+        server.blob_entry("src/lib.rs", 0),
+        server.blob_entry("src/main.rs", 0),
+    ]);
+
+    let client = GithubClient::new(None)?;
+    let output_path = Path::new("./tests/test_output_dir_shared_client/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.client = Some(client.clone());
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(files?.len(), 3);
+    assert_eq!(client.budget().requests_made(), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn bytes_received() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"hello")).await;
+    let mut entry = server.blob_entry("a.txt", 5);
+    entry.sha = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0".parse()?;
+    let tree = server.tree(vec![entry]);
+
+    let client = GithubClient::new(None)?;
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_bytes_received/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+    config.client = Some(client.clone());
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let files = files?;
+    let blob_response = MockResponse::blob(b"hello").body.len() as u64;
+    assert_eq!(files[0].bytes_written, 5);
+    assert_eq!(files[0].bytes_received, blob_response);
+    assert_eq!(client.budget().bytes_received(), blob_response);
+    assert!(reporter.events().iter().any(|e| matches!(
+        e,
+        CollectedEvent::DownloadCompleted { path, bytes_received, bytes_written: 5 }
+            if path == "a.txt" && *bytes_received == blob_response
+    )));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn subtree() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let mut builder = SourceTreeBuilder::new();
+    builder
+        .add_entry(server.blob_entry("README.md", 0))?
+        .add_entry(server.blob_entry("src/lib.rs", 0))?
+        .add_entry(server.blob_entry("src/bin/main.rs", 0))?;
+    let tree = builder.build();
+    let src = tree.resolve_tree("src").unwrap();
+
+    let relative: Vec<&str> = src
+        .iter_relative()
+        .skip(1)
+        .map(|(p, _)| p.as_str())
+        .collect();
+    assert_eq!(relative, vec!["lib.rs", "bin", "bin/main.rs"]);
+    assert_eq!(
+        src.rebase("src").children[1].children[0].path.as_str(),
+        "bin/main.rs"
+    );
+
+    let output_path = Path::new("./tests/test_output_dir_subtree/");
+    let config = DownloadConfigNoReporting::new(output_path);
+    let files = Downloader::download_tree(&config, src, &Filter::new(vec!["bin/**"], vec![])).await;
+    let written = (
+        output_path.join("bin/main.rs").is_file(),
+        output_path.join("src").exists(),
+    );
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(files?[0].entry.path.as_str(), "bin/main.rs");
+    assert_eq!(written, (true, false));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn owned_tree() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("keep.txt", 0),
+        server.blob_entry("skip.txt", 0),
+    ]);
+
+    let output_path = Path::new("./tests/test_output_dir_owned_tree/");
+    let config = DownloadConfigNoReporting::new(output_path);
+    let files = Downloader::download_tree_iter(
+        &config,
+        tree.prune(|n| n.name() != "skip.txt"),
+        &Filter::all(),
+    )
+    .await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let files = files?;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].entry.path.as_str(), "keep.txt");
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn disk_space() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"hello")).await;
+    let output_path = Path::new("./tests/test_output_dir_disk_space/");
+    let temp_dir = output_path.join("staging");
+
+    // thousands of 4 GB files won't fit anywhere, and nothing is requested before that's found out
+    let huge = server.tree(
+        (0..5000)
+            .map(|i| server.blob_entry(&format!("{}.bin", i), u32::MAX))
+            .collect(),
+    );
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    let error = Downloader::download_tree(&config, &huge, &Filter::all()).await;
+    assert!(matches!(
+        error.map_err(|e| e.root().clone()),
+        Err(Error::InsufficientSpace { required, .. }) if required == 5000 * u32::MAX as u64
+    ));
+    assert!(server.requests().is_empty());
+
+    // a relative path is checked against the current directory when none of it exists yet
+    let relative = DownloadConfigNoReporting::new(Path::new("test_output_dir_disk_space_relative"));
+    let error = Downloader::download_tree(&relative, &huge, &Filter::all()).await;
+    assert!(matches!(
+        error.map_err(|e| e.root().clone()),
+        Err(Error::InsufficientSpace { .. })
+    ));
+    assert!(server.requests().is_empty());
+
+    config.temp_dir = Some(&temp_dir);
+    let tree = server.tree(vec![server.blob_entry("hello.txt", 5)]);
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    let written = std::fs::read(output_path.join("hello.txt"));
+    let staged = std::fs::read_dir(&temp_dir).map(|dir| dir.count());
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    files?;
+    assert_eq!(written?, b"hello");
+    assert_eq!(staged?, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn download_refs() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str, sha: &str| {
+            format!(
+                r#"{{"path":"{}","mode":"100644","type":"blob","size":5,"sha":"{}","url":"http://{}/blobs/{}"}}"#,
+                path, sha, host, path
+            )
+        };
+        let tree_entry = |path: &str| {
+            format!(
+                r#"{{"path":"{}","mode":"040000","type":"tree","sha":"4b825dc642cb6eb9a060e54bf8d69288fbee4904","url":""}}"#,
+                path
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/0000000000000000000000000000000000000001") => {
+                MockResponse::json(
+                    200,
+                    &format!(
+                        r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{}]}}"#,
+                        entry("a.txt", "1111111111111111111111111111111111111111"),
+                        entry("b.txt", "2222222222222222222222222222222222222222")
+                    ),
+                )
+            }
+            p if p.contains("/git/trees/0000000000000000000000000000000000000002") => {
+                MockResponse::json(
+                    200,
+                    &format!(
+                        r#"{{"sha":"0000000000000000000000000000000000000002","url":"","truncated":false,"tree":[{},{},{}]}}"#,
+                        entry("a.txt", "1111111111111111111111111111111111111111"),
+                        tree_entry("c"),
+                        entry("c/d.txt", "3333333333333333333333333333333333333333")
+                    ),
+                )
+            }
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_download_refs/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+
+    let repo = GithubBranchPath::new("user", "repo", "main");
+    let refs = [
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+        GithubRef::Tree("0000000000000000000000000000000000000002"),
+    ];
+    let reports =
+        Downloader::download_refs(&config, &repo, &refs, RefLayout::Names, &Filter::all()).await;
+    let shared = std::fs::read(
+        output_path
+            .join("0000000000000000000000000000000000000002")
+            .join("a.txt"),
+    );
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let reports = reports?;
+    assert_eq!(reports.len(), 2);
+    let paths: Vec<&str> = reports[1]
+        .files
+        .iter()
+        .map(|f| f.entry.path.as_str())
+        .collect();
+    assert_eq!(paths, vec!["a.txt", "c/d.txt"]);
+    assert_eq!(shared?, b"hello");
+
+    // a.txt is the same blob in both trees, so it's only fetched once
+    let blobs = server
+        .requests()
+        .iter()
+        .filter(|r| r.path.starts_with("/blobs/"))
+        .count();
+    assert_eq!(blobs, 3);
+
+    Ok(())
+}
+
+/// Fails for `b.txt`, and writes the path of every file to `INDEX` once they've all been downloaded.
+struct IndexHooks;
+
+impl DownloadHooks for IndexHooks {
+    fn after_file<'h>(&'h self, file: &'h DownloadedFile) -> BoxFuture<'h, Result<(), Error>> {
+        async move {
+            match file.entry.path.as_str() {
+                "b.txt" => Err(Error::Other("chmod failed".into())),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+
+    fn after_run<'h>(&'h self, report: &'h DownloadReport) -> BoxFuture<'h, Result<(), Error>> {
+        async move {
+            let paths: Vec<&str> = report.files.iter().map(|f| f.entry.path.as_str()).collect();
+            let dir = report.files[0].output_path.parent().unwrap();
+            std::fs::write(dir.join("INDEX"), paths.join("\n"))?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[tokio::test]
+pub async fn hooks() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str, sha: &str| {
+            format!(
+                r#"{{"path":"{}","mode":"100644","type":"blob","size":5,"sha":"{}","url":"http://{}/blobs/{}"}}"#,
+                path, sha, host, path
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{}]}}"#,
+                    entry("a.txt", "1111111111111111111111111111111111111111"),
+                    entry("b.txt", "2222222222222222222222222222222222222222")
+                ),
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_hooks/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.hooks = Some(&IndexHooks);
+
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    let index = std::fs::read_to_string(output_path.join("INDEX"));
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    // a failing hook doesn't fail the download
+    let report = report?;
+    assert_eq!(report.files.len(), 2);
+    assert!(report.merged_paths.is_empty());
+    assert_eq!(report.hook_failures.len(), 1);
+    assert_eq!(report.hook_failures[0].path.as_deref(), Some("b.txt"));
+    assert!(matches!(report.hook_failures[0].error, Error::Other(_)));
+    assert_eq!(index?, "a.txt\nb.txt");
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn report_filtered() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str, entry_type: &str| {
+            format!(
+                r#"{{"path":"{0}","mode":"100644","type":"{2}","size":5,"sha":"1111111111111111111111111111111111111111","url":"http://{1}/blobs/{0}"}}"#,
+                path, host, entry_type
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{},{},{}]}}"#,
+                    entry("a.txt", "blob"),
+                    entry("docs", "tree"),
+                    entry("docs/b.txt", "blob"),
+                    entry("c.log", "blob")
+                ),
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_report_filtered/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+    config.api_url = server.url.clone().into();
+    config.report_filtered = true;
+
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let filter = Filter::new(vec!["*.txt", "docs/*"], vec!["docs/*"]);
+    let report = Downloader::download_resolved(&config, &path, &filter).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(report?.files.len(), 1);
+    let filtered: Vec<(String, Option<String>)> = reporter
+        .events()
+        .into_iter()
+        .filter_map(|e| match e {
+            CollectedEvent::FilteredOut {
+                path,
+                matched_exclude,
+            } => Some((path, matched_exclude)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        filtered,
+        vec![
+            (String::from("docs/b.txt"), Some(String::from("docs/*"))),
+            (String::from("c.log"), None),
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn collect_spdx() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str| {
+            format!(
+                r#"{{"path":"{0}","mode":"100644","type":"blob","size":5,"sha":"1111111111111111111111111111111111111111","url":"http://{1}/blobs/{0}"}}"#,
+                path, host
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{},{},{},{}]}}"#,
+                    entry("a.rs"),
+                    entry("b.c"),
+                    entry("c.rs"),
+                    entry("d.bin"),
+                    entry("e.md")
+                ),
+            ),
+            "/blobs/a.rs" | "/blobs/c.rs" => {
+                MockResponse::blob(b"// SPDX-License-Identifier: MIT OR Apache-2.0\nfn main() {}\n")
+            }
+            "/blobs/b.c" => MockResponse::blob(b"/* SPDX-License-Identifier: GPL-2.0-only */\n"),
+            "/blobs/d.bin" => MockResponse::blob(b"\0SPDX-License-Identifier: MIT\n"),
+            _ => MockResponse::blob(b"Files are tagged with SPDX-License-Identifier: headers.\n"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_collect_spdx/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.collect_spdx = true;
+
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let licenses: Vec<(String, Vec<String>)> = report?
+        .spdx_licenses
+        .into_iter()
+        .map(|license| (license.expression, license.paths))
+        .collect();
+    assert_eq!(
+        licenses,
+        vec![
+            (String::from("GPL-2.0-only"), vec![String::from("b.c")]),
+            (
+                String::from("MIT OR Apache-2.0"),
+                vec![String::from("a.rs"), String::from("c.rs")]
+            ),
+        ]
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+pub async fn permissions_hook() -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str| {
+            format!(
+                r#"{{"path":"{0}","mode":"100644","type":"blob","size":5,"sha":"1111111111111111111111111111111111111111","url":"http://{1}/blobs/{0}"}}"#,
+                path, host
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{}]}}"#,
+                    entry("secret.env"),
+                    entry("README.md")
+                ),
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let hook = |entry: &SourceTree| match entry.path.as_str() {
+        "secret.env" => Some(FilePermissions::mode(0o600)),
+        _ => None,
+    };
+    let output_path = Path::new("./tests/test_output_dir_permissions_hook/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.permissions_hook = Some(&hook);
+
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    let mode = |name: &str| {
+        std::fs::metadata(output_path.join(name)).map(|m| m.permissions().mode() & 0o777)
+    };
+    let secret = mode("secret.env");
+    let readme = mode("README.md");
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(report?.files.len(), 2);
+    assert_eq!(secret?, 0o600);
+    assert_ne!(readme?, 0o600);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn download_temp() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{{"path":"a.txt","mode":"100644","type":"blob","size":5,"sha":"1111111111111111111111111111111111111111","url":"http://{}/blobs/a.txt"}}]}}"#,
+                    host
+                ),
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let mut config = DownloadConfigNoReporting::new(Path::new("./tests/unused_output_dir/"));
+    config.api_url = server.url.clone().into();
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+
+    let grab = Downloader::download_temp_with(&config, &path, &Filter::all()).await?;
+    let dir = grab.path().to_path_buf();
+    assert_eq!(grab.report().files.len(), 1);
+    assert_eq!(std::fs::read(grab.join("a.txt"))?, b"hello");
+    assert!(!config.output_path.exists());
+    drop(grab);
+    assert!(!dir.exists());
+
+    let kept = Downloader::download_temp_with(&config, &path, &Filter::all())
+        .await?
+        .keep();
+    assert_ne!(kept, dir);
+    assert!(kept.join("a.txt").is_file());
+    std::fs::remove_dir_all(kept)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn checksum_manifest() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str, kind: &str| {
+            format!(
+                r#"{{"path":"{0}","mode":"100644","type":"{2}","size":5,"sha":"1111111111111111111111111111111111111111","url":"http://{1}/blobs/{0}"}}"#,
+                path, host, kind
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{},{}]}}"#,
+                    entry("src", "tree"),
+                    entry("src/lib.rs", "blob"),
+                    entry("README.md", "blob")
+                ),
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_checksum_manifest/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.checksum_manifest = Some(ChecksumAlgorithm::Sha256);
+
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    let manifest = std::fs::read_to_string(output_path.join("SHA256SUMS"));
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(report?.files.len(), 2);
+    let hello = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+    assert_eq!(
+        manifest?,
+        format!("{0}  README.md\n{0}  src/lib.rs\n", hello)
+    );
+    assert_eq!(ChecksumAlgorithm::Sha1.file_name(), "SHA1SUMS");
+    assert_eq!(
+        ChecksumAlgorithm::Sha1.hex_digest(b"hello"),
+        "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn empty_dirs() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str, sha: &str| {
+            format!(
+                r#"{{"path":"{}","mode":"100644","type":"blob","size":5,"sha":"{}","url":"http://{}/blobs/{}"}}"#,
+                path, sha, host, path
+            )
+        };
+        let tree_entry = |path: &str| {
+            format!(
+                r#"{{"path":"{}","mode":"040000","type":"tree","sha":"4b825dc642cb6eb9a060e54bf8d69288fbee4904","url":""}}"#,
+                path
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{},{},{},{},{}]}}"#,
+                    tree_entry("a"),
+                    entry("a/kept.txt", "1111111111111111111111111111111111111111"),
+                    tree_entry("b"),
+                    entry("b/excluded.txt", "2222222222222222222222222222222222222222"),
+                    tree_entry("c"),
+                    entry("c/missing.txt", "3333333333333333333333333333333333333333")
+                ),
+            ),
+            "/blobs/c/missing.txt" => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_empty_dirs/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.max_simultaneous_downloads = 1;
+
+    // directories are only created for files that are written
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let filter = Filter::new(vec![], vec!["b/**"]);
+    let result = Downloader::download_resolved(&config, &path, &filter).await;
+    let kept = output_path.join("a/kept.txt").exists();
+    let excluded_dir = output_path.join("b").exists();
+    let missing_dir = output_path.join("c").exists();
+
+    std::fs::create_dir_all(output_path.join("d/e/f"))?;
+    let removed = Downloader::cleanup_empty_dirs(output_path).await;
+    let d = output_path.join("d").exists();
+    let a = output_path.join("a").exists();
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert!(result.is_err());
+    assert!(kept);
+    assert!(!excluded_dir);
+    assert!(!missing_dir);
+    assert_eq!(removed?, 3);
+    assert!(!d);
+    assert!(a);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn sync() -> Result<(), Error> {
+    const HELLO_SHA: &str = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0";
+    let server = MockServer::start(|r| {
+        if !r.path.contains("/git/trees/") {
+            return MockResponse::blob(b"hello");
+        }
+
+        let host = r.header("host").unwrap_or_default().to_string();
+        let mut entries: Vec<String> = ["a.txt", "b.txt", "c/d.txt"]
+            .iter()
+            .map(|path| {
+                format!(
+                    r#"{{"path":"{}","mode":"100644","type":"blob","size":5,"sha":"{}","url":"http://{}/blobs/{}"}}"#,
+                    path, HELLO_SHA, host, path
+                )
+            })
+            .collect();
+        entries.insert(
+            2,
+            String::from(
+                r#"{"path":"c","mode":"040000","type":"tree","sha":"4b825dc642cb6eb9a060e54bf8d69288fbee4904","url":""}"#,
+            ),
+        );
+        MockResponse::json(
+            200,
+            &format!(
+                r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{}]}}"#,
+                entries.join(",")
+            ),
+        )
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_sync/");
+    std::fs::create_dir_all(output_path)?;
+    std::fs::write(output_path.join("a.txt"), "hello")?;
+    std::fs::write(output_path.join("b.txt"), "goodbye")?;
+    std::fs::write(output_path.join("stale.txt"), "stale")?;
+
+    let reporter = CollectingReporter::new();
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+    config.api_url = server.url.clone().into();
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+
+    let summary = Downloader::sync(&config, &path, &Filter::all()).await;
+    let stale_exists = output_path.join("stale.txt").exists();
+    let updated = std::fs::read(output_path.join("b.txt"));
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let summary = summary?;
+    assert_eq!(summary.unchanged[0].entry.path.as_str(), "a.txt");
+    assert_eq!(summary.updated[0].entry.path.as_str(), "b.txt");
+    assert_eq!(summary.added[0].entry.path.as_str(), "c/d.txt");
+    assert_eq!(summary.deleted, vec![output_path.join("stale.txt")]);
+    assert_eq!(summary.to_string(), "1 file added, 1 updated, 1 deleted");
+    assert!(!stale_exists);
+    assert_eq!(updated?, b"hello");
+
+    // a.txt was already up to date, so only two blobs were requested
+    let blobs = server
+        .requests()
+        .iter()
+        .filter(|r| r.path.starts_with("/blobs/"))
+        .count();
+    assert_eq!(blobs, 2);
+
+    let events = reporter.events();
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, CollectedEvent::FileUnchanged { path } if path == "a.txt")));
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, CollectedEvent::FileDeleted { path } if path == "stale.txt")));
+    assert!(matches!(
+        events.last(),
+        Some(CollectedEvent::SyncFinished {
+            added: 1,
+            updated: 1,
+            deleted: 1,
+            unchanged: 1
+        })
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn watch() -> Result<(), Error> {
+    const HELLO_SHA: &str = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0";
+    let ref_requests = Arc::new(AtomicUsize::new(0));
+    let server_ref_requests = ref_requests.clone();
+    let server = MockServer::start(move |r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str| {
+            format!(
+                r#"{{"path":"{}","mode":"100644","type":"blob","size":5,"sha":"{}","url":"http://{}/blobs/{}"}}"#,
+                path, HELLO_SHA, host, path
+            )
+        };
+        let git_ref = |sha: &str| {
+            MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"ref":"refs/heads/main","url":"","object":{{"sha":"{}","type":"commit","url":""}}}}"#,
+                    sha
+                ),
+            )
+            .with_header("ETag", &format!("\"{}\"", &sha[..1]))
+        };
+        let commit = |sha: &str, tree: &str| {
+            MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"{}","url":"","message":"","tree":{{"sha":"{}","url":""}}}}"#,
+                    sha, tree
+                ),
+            )
+        };
+        let tree = |sha: &str, entries: &[String]| {
+            MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"{}","url":"","truncated":false,"tree":[{}]}}"#,
+                    sha,
+                    entries.join(",")
+                ),
+            )
+        };
+
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        match r.path.as_str() {
+            "/repos/user/repo/git/ref/heads/main" => {
+                match server_ref_requests.fetch_add(1, Ordering::SeqCst) {
+                    0 => git_ref(&a),
+                    1 if r.header("if-none-match") == Some("\"a\"") => {
+                        MockResponse::json(304, "")
+                    }
+                    _ => git_ref(&b),
+                }
+            }
+            p if p.ends_with(&format!("/git/commits/{}", a)) => {
+                commit(&a, "0000000000000000000000000000000000000001")
+            }
+            p if p.ends_with(&format!("/git/commits/{}", b)) => {
+                commit(&b, "0000000000000000000000000000000000000002")
+            }
+            p if p.contains("/git/trees/0000000000000000000000000000000000000001") => tree(
+                "0000000000000000000000000000000000000001",
+                &[entry("a.txt")],
+            ),
+            p if p.contains("/git/trees/0000000000000000000000000000000000000002") => tree(
+                "0000000000000000000000000000000000000002",
+                &[entry("a.txt"), entry("b.txt")],
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_watch/");
+    let handle = DownloadHandle::new();
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.handle = Some(handle.clone());
+
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let mut summaries = Vec::new();
+    let watched = tokio::time::timeout(
+        Duration::from_secs(10),
+        Watcher::watch(
+            &config,
+            &path,
+            &Filter::all(),
+            Duration::from_millis(10),
+            |summary| {
+                summaries.push(summary);
+                if summaries.len() == 2 {
+                    handle.abort();
+                }
+            },
+        ),
+    )
+    .await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    watched.expect("watching didn't stop when aborted")?;
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].added[0].entry.path.as_str(), "a.txt");
+    assert_eq!(
+        summaries[1].resolved.commit_sha,
+        Some("b".repeat(40).parse()?)
+    );
+    assert_eq!(summaries[1].added[0].entry.path.as_str(), "b.txt");
+    assert_eq!(summaries[1].unchanged[0].entry.path.as_str(), "a.txt");
+
+    // the unchanged reference was answered with 304 rather than a sync
+    assert_eq!(ref_requests.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+/// Starts a server for a GitLab project with two files, `README.md` and `src/lib.rs`, which GitLab lists without
+/// their sizes.
+async fn start_gitlab_server() -> MockServer {
+    MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let project = "/projects/group%2Fsubgroup%2Fproject/repository";
+        match r.path.as_str() {
+            p if p == format!("{}/commits/main", project) => MockResponse::json(
+                200,
+                r#"{"id":"6dcb09b5b57875f334f61aebed695e2e4193db5e","short_id":"6dcb09b5"}"#,
+            ),
+            p if p.contains("page_token=2") => MockResponse::json(
+                200,
+                r#"[{"id":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","name":"lib.rs","type":"blob","path":"src/lib.rs","mode":"100644"}]"#,
+            ),
+            p if p.starts_with(&format!("{}/tree", project)) => MockResponse::json(
+                200,
+                r#"[
+                    {"id":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","name":"README.md","type":"blob","path":"README.md","mode":"100644"},
+                    {"id":"0000000000000000000000000000000000000002","name":"src","type":"tree","path":"src","mode":"040000"},
+                    {"id":"0000000000000000000000000000000000000003","name":"vendor","type":"commit","path":"vendor","mode":"160000"}
+                ]"#,
+            )
+            .with_header(
+                "Link",
+                &format!(
+                    "<http://{}{}/tree?page_token=2>; rel=\"next\", <http://{}/first>; rel=\"first\"",
+                    host, project, host
+                ),
+            ),
+            p if p.ends_with("/raw") => MockResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: b"hello".to_vec(),
+                raw_body: None,
+            },
+            _ => MockResponse::json(404, r#"{"message":"404 Not Found"}"#),
+        }
+    })
+    .await
+}
+
+#[tokio::test]
+pub async fn gitlab_forge() -> Result<(), Error> {
+    let server = start_gitlab_server().await;
+    let forge = GitLabForge::with_client(GithubClient::new(None)?.with_api_url(&server.url));
+    let output_path = Path::new("./tests/test_output_dir_gitlab_forge/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.forge = Some(&forge);
+
+    let path = GithubBranchPath::new("group/subgroup", "project", "main");
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    let readme = std::fs::read(output_path.join("README.md"));
+    let lib = std::fs::read(output_path.join("src/lib.rs"));
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let report = report?;
+    assert_eq!(
+        report.resolved.commit_sha.map(|sha| sha.to_string()),
+        Some(String::from("6dcb09b5b57875f334f61aebed695e2e4193db5e"))
+    );
+    assert_eq!(report.files.len(), 2);
+    assert!(report.all_verified());
+    assert_eq!(readme?, b"hello");
+    assert_eq!(lib?, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn gitea_host() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let repo = "/api/v1/repos/user/repo";
+        let tree = "0000000000000000000000000000000000000001";
+        match r.path.as_str() {
+            p if p == format!("{}/branches/main", repo) => MockResponse::json(
+                200,
+                r#"{"name":"main","commit":{"id":"6dcb09b5b57875f334f61aebed695e2e4193db5e"}}"#,
+            ),
+            p if p == format!("{}/git/commits/6dcb09b5b57875f334f61aebed695e2e4193db5e", repo) => {
+                MockResponse::json(
+                    200,
+                    &format!(r#"{{"sha":"6dcb09b5b57875f334f61aebed695e2e4193db5e","commit":{{"tree":{{"sha":"{}"}}}}}}"#, tree),
+                )
+            }
+            p if p.starts_with(&format!("{}/git/trees/{}", repo, tree)) => {
+                let (entries, truncated) = match p.contains("page=1&") {
+                    true => (
+                        format!(
+                            r#"{{"path":"README.md","mode":"100644","type":"blob","sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","size":5,"url":"http://{}{}/git/blobs/b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0"}},
+                            {{"path":"src","mode":"040000","type":"tree","sha":"0000000000000000000000000000000000000002","url":"http://{}{}/git/trees/0000000000000000000000000000000000000002"}}"#,
+                            host, repo, host, repo
+                        ),
+                        true,
+                    ),
+                    false => (
+                        format!(
+                            r#"{{"path":"src/lib.rs","mode":"100644","type":"blob","sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","size":5,"url":"http://{}{}/git/blobs/b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0"}}"#,
+                            host, repo
+                        ),
+                        false,
+                    ),
+                };
+                MockResponse::json(
+                    200,
+                    &format!(
+                        r#"{{"sha":"{}","url":"","tree":[{}],"truncated":{}}}"#,
+                        tree, entries, truncated
+                    ),
+                )
+            }
+            p if p.contains("/git/blobs/") => MockResponse::blob(b"hello"),
+            _ => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+        }
+    })
+    .await;
+
+    let client = GithubClient::new(None)?.with_api_url(&format!("{}/api/v1", server.url));
+    let host = GiteaHost::with_client(client);
+    let output_path = Path::new("./tests/test_output_dir_gitea_host/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.forge = Some(&host);
+
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let report = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    let lib = std::fs::read(output_path.join("src/lib.rs"));
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let report = report?;
+    assert_eq!(
+        report.resolved.tree_sha.to_string(),
+        "0000000000000000000000000000000000000001"
+    );
+    assert_eq!(report.files.len(), 2);
+    assert!(report.all_verified());
+    assert_eq!(lib?, b"hello");
+    assert!(server
+        .requests()
+        .iter()
+        .any(|r| r.path.contains("page=2&per_page=1000")));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn tree_limits() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let output_path = Path::new("./tests/test_output_dir_tree_limits/");
+    let tree = server.tree(vec![
+        server.blob_entry("a.txt", 0),
+        server.blob_entry("b/c.txt", 0),
+        server.blob_entry("b/d/e/f.txt", 0),
+    ]);
+
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.max_files = Some(2);
+    let error = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    assert!(matches!(
+        error.map_err(|e| e.root().clone()),
+        Err(Error::TooManyFiles { limit: 2 })
+    ));
+
+    config.max_files = None;
+    config.max_depth = Some(2);
+    let error = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    assert!(matches!(
+        error.map_err(|e| e.root().clone()),
+        Err(Error::TooDeep { path, depth: 3, limit: 2 }) if path == "b/d/e/f.txt"
+    ));
+    assert!(server.requests().is_empty());
+
+    // only files that pass the filter count towards the limits
+    config.max_files = Some(2);
+    let files = Downloader::download_tree(&config, &tree, &Filter::new(vec!["b/d/**"], vec![]))
+        .await
+        .map(|files| files.len());
+    let filtered = Downloader::download_tree(&config, &tree, &Filter::new(vec![], vec!["b/d/**"]))
+        .await
+        .map(|files| files.len());
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert!(matches!(
+        files.map_err(|e| e.root().clone()),
+        Err(Error::TooDeep { .. })
+    ));
+    assert_eq!(filtered?, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn nested_directories() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("a/b/c/d.txt", 0),
+        server.blob_entry("a/b/e.txt", 0),
+        server.blob_entry("a/b-c/f.txt", 0),
+        server.blob_entry("g.txt", 0),
+    ]);
+
+    let output_path = Path::new("./tests/test_output_dir_nested/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.write_workers = 2;
+    config.rename_rules = vec![("g.txt".into(), "h/i/g.txt".into())];
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    let exists = ["a/b/c/d.txt", "a/b/e.txt", "a/b-c/f.txt", "h/i/g.txt"]
+        .map(|p| output_path.join(p).is_file());
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(files?.len(), 4);
+    assert_eq!(exists, [true; 4]);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn content_addressed() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let (lib_sha, readme_sha) = ("a".repeat(40), "b".repeat(40));
+    let mut lib = server.blob_entry("src/lib.rs", 0);
+    lib.sha = lib_sha.parse()?;
+    let mut readme = server.blob_entry("README.md", 0);
+    readme.sha = readme_sha.parse()?;
+    let tree = server.tree(vec![lib, readme]);
+
+    let output_path = Path::new("./tests/test_output_dir_content_addressed/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.layout = OutputLayout::ContentAddressed;
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    let lib_exists = output_path.join(&lib_sha).join("lib.rs").is_file();
+    let manifest = std::fs::read_to_string(output_path.join(CONTENT_MANIFEST_FILE_NAME));
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(
+        files?[1].output_path,
+        output_path.join(&readme_sha).join("README.md")
+    );
+    assert!(lib_exists);
+    let manifest: serde_json::Value = serde_json::from_str(&manifest?)?;
+    assert_eq!(
+        manifest,
+        serde_json::json!({ "src/lib.rs": lib_sha, "README.md": readme_sha })
+    );
+
+    config.layout = OutputLayout::ContentAddressedFlat;
+    assert_eq!(
+        config.output_path_for_entry(&tree.children[0]),
+        output_path.join(&lib_sha)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn client_pool() -> Result<(), Error> {
+    let server = MockServer::start(|_| {
+        MockResponse::json(
+            200,
+            r#"{"full_name":"user/repo","url":"","default_branch":"main"}"#,
+        )
+    })
+    .await;
+
+    let pool = Arc::new(ClientPool::new());
+    let mut config =
+        DownloadConfigNoReporting::new(Path::new("./tests/test_output_dir_client_pool/"));
+    config.api_url = format!("{}/", server.url).into();
+    config.access_token = Some("ghp_first".into());
+    config.client_pool = Some(pool.clone());
+
+    let first = config.client()?;
+    assert_eq!(first.api_url(), server.url);
+    let repo = first
+        .get_repo(&GithubBranchPath::new("user", "repo", ""))
+        .await?;
+    assert_eq!(repo.default_branch, "main");
+    assert_eq!(config.client()?.budget().requests_made(), 1);
+    assert_eq!(pool.len(), 1);
+
+    config.access_token = Some("ghp_second".into());
+    assert_eq!(config.client()?.budget().requests_made(), 0);
+    assert_eq!(pool.len(), 2);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/repos/user/repo");
+    assert_eq!(requests[0].header("Authorization"), Some("token ghp_first"));
+
+    Ok(())
+}
+
+#[test]
+pub fn token_source() {
+    let output_path = Path::new("./tests/test_output_dir_token_source/");
+    std::env::set_var("GRAB_GITHUB_TEST_TOKEN", "ghp_from_env");
+
+    assert_eq!(
+        DownloadConfigNoReporting::new(output_path).access_token,
+        None
+    );
+    assert_eq!(
+        DownloadConfigNoReporting::unauthenticated(output_path).access_token,
+        None
+    );
+
+    let config = DownloadConfigNoReporting::new(output_path)
+        .with_token_source(TokenSource::EnvVar("GRAB_GITHUB_TEST_TOKEN".into()));
+    assert_eq!(config.access_token.as_deref(), Some("ghp_from_env"));
+
+    let config = config.with_token_source(TokenSource::EnvVar("GRAB_GITHUB_NO_SUCH_TOKEN".into()));
+    assert_eq!(config.access_token, None);
+
+    let source = TokenSource::Token("ghp_secret".into());
+    assert_eq!(source.resolve().as_deref(), Some("ghp_secret"));
+    assert!(!format!("{:?}", source).contains("ghp_secret"));
+}
+
+#[tokio::test]
+pub async fn preflight() -> Result<(), Error> {
+    const COMMIT: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+    const TREE: &str = "0000000000000000000000000000000000000001";
+    let server = MockServer::start(|r| {
+        // the token picks how much of the rate limit is left
+        let (limit, remaining) = match r.header("authorization") {
+            Some("token ghp_rejected") => {
+                return MockResponse::json(401, r#"{"message":"Bad credentials"}"#)
+            }
+            Some("token ghp_spent") => (5000, 0),
+            Some("token ghp_low") => (5000, 2),
+            Some(_) => (5000, 4999),
+            None => (60, 60),
+        };
+        match r.path.as_str() {
+            "/rate_limit" => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"rate":{{"limit":{},"remaining":{},"used":{},"reset":0}}}}"#,
+                    limit,
+                    remaining,
+                    limit - remaining
+                ),
+            )
+            .with_header("X-OAuth-Scopes", "repo, workflow"),
+            "/repos/user/repo" => MockResponse::json(
+                200,
+                r#"{"full_name":"user/repo","url":"","default_branch":"main"}"#,
+            ),
+            "/repos/user/repo/git/ref/heads/main" => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"ref":"refs/heads/main","url":"","object":{{"type":"commit","sha":"{}","url":""}}}}"#,
+                    COMMIT
+                ),
+            ),
+            p if p == format!("/repos/user/repo/git/commits/{}", COMMIT) => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"{}","url":"","message":"","tree":{{"sha":"{}","url":""}},"parents":[]}}"#,
+                    COMMIT, TREE
+                ),
+            ),
+            p if p.starts_with(&format!("/repos/user/repo/git/trees/{}", TREE)) => {
+                MockResponse::json(
+                    200,
+                    &format!(
+                        r#"{{"sha":"{}","url":"","tree":[
+                            {{"path":"build.gradle","mode":"100644","type":"blob","size":5,"sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","url":""}},
+                            {{"path":"README.md","mode":"100644","type":"blob","size":5,"sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","url":""}}
+                        ],"truncated":false}}"#,
+                        TREE
+                    ),
+                )
+            }
+            _ => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_preflight/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.access_token = Some("ghp_valid".into());
+    let filter = Filter::new(vec!["build.gradle"], vec![]);
+
+    // resolving the branch, its commit and the tree, then downloading the one file
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let report = Downloader::preflight(&config, &path, &filter).await?;
+    assert_eq!(report.repo.full_name, "user/repo");
+    assert_eq!(
+        report.resolved.commit_sha.map(|sha| sha.to_string()),
+        Some(String::from(COMMIT))
+    );
+    assert!(report.authenticated);
+    assert_eq!(
+        report.token_scopes,
+        Some(vec![String::from("repo"), String::from("workflow")])
+    );
+    assert_eq!(report.files, 1);
+    assert_eq!(report.estimated_requests, 4);
+    assert_eq!(report.rate_limit_remaining, 4999);
+    assert!(!output_path.exists());
+
+    let preflight_error = |token: Option<&'static str>, path: GithubBranchPath<'static>| {
+        let mut config = DownloadConfigNoReporting::new(output_path);
+        config.api_url = server.url.clone().into();
+        config.access_token = token.map(Into::into);
+        async move {
+            let filter = Filter::new(vec!["build.gradle"], vec![]);
+            match Downloader::preflight(&config, &path, &filter).await {
+                Err(Error::PreflightFailed(message)) => message,
+                result => panic!("expected a failed preflight, got {:?}", result),
+            }
+        }
+    };
+
+    let message = preflight_error(
+        Some("ghp_valid"),
+        GithubBranchPath::new("user", "repo", "no-such-branch"),
+    )
+    .await;
+    assert!(message.contains("branch no-such-branch wasn't found"));
+    assert!(message.contains("default branch is main"));
+
+    let message = preflight_error(Some("ghp_rejected"), path).await;
+    assert!(message.contains("rejected the access token"));
+
+    let missing = GithubBranchPath::new("user", "missing", "main");
+    let message = preflight_error(Some("ghp_valid"), missing).await;
+    assert!(message.contains("user/missing wasn't found"));
+    let message = preflight_error(None, missing).await;
+    assert!(message.contains("set an access token that can read it"));
+
+    let message = preflight_error(Some("ghp_spent"), path).await;
+    assert!(message.contains("rate limit has been used up"));
+
+    let message = preflight_error(Some("ghp_low"), path).await;
+    assert!(message.contains("needs about 4 API requests, but only 2 are left"));
+
+    Ok(())
+}
+
+#[cfg(feature = "cli")]
+#[tokio::test]
+pub async fn grab_invalid_spec() {
+    for spec in ["grab_github", "/grab_github", "azrogers/", "a/b/c@main"] {
+        assert!(matches!(
+            grab_github::grab(spec, "out").await,
+            Err(Error::Other(_))
+        ));
+    }
+}
+
+#[test]
+pub fn rename_rules() {
+    let mut config = DownloadConfigNoReporting::new(Path::new("out"));
+    config.rename_rules = vec![
+        ("docs/**/*.md".into(), "site/content/**/*.markdown".into()),
+        ("docs/**".into(), "site/static/**".into()),
+        ("{src,lib}/*.rs".into(), "rust/{}/*.rs".into()),
+        ("LICENSE".into(), "legal/LICENSE.txt".into()),
+    ];
+
+    assert_eq!(
+        config.output_path_for("docs/guide/intro.md"),
+        Path::new("out/site/content/guide/intro.markdown")
+    );
+    assert_eq!(
+        config.output_path_for("docs/index.md"),
+        Path::new("out/site/content/index.markdown")
+    );
+    assert_eq!(
+        config.output_path_for("docs/images/logo.png"),
+        Path::new("out/site/static/images/logo.png")
+    );
+    assert_eq!(
+        config.output_path_for("lib/main.rs"),
+        Path::new("out/rust/lib/main.rs")
+    );
+    assert_eq!(
+        config.output_path_for("LICENSE"),
+        Path::new("out/legal/LICENSE.txt")
+    );
+    assert_eq!(
+        config.output_path_for("README.md"),
+        Path::new("out/README.md")
+    );
+}
+
+#[tokio::test]
+pub async fn path_collisions() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("README.md", 0),
+        server.blob_entry("Readme.md", 0),
+        SourceTree {
+            // the hash of an empty blob, so it isn't taken for a copy of README.md
+            sha: "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391".parse().unwrap(),
+            ..server.blob_entry("docs/README.md", 0)
+        },
+        server.blob_entry("src/lib.rs", 0),
+    ]);
+
+    // paths that only differ in case only collide where the filesystem ignores case
+    let output_path = Path::new("./tests/test_output_dir_path_collisions/");
+    std::fs::create_dir_all(output_path)?;
+    std::fs::write(output_path.join("CASE"), "")?;
+    let ignores_case = output_path.join("case").exists();
+    std::fs::remove_file(output_path.join("CASE"))?;
+
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.rename_rules = vec![("docs/README.md".into(), "README.md".into())];
+
+    let error = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    config.collision_policy = CollisionPolicy::FirstWins;
+    let first_wins = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    config.collision_policy = CollisionPolicy::Rename;
+    let renamed = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert!(matches!(
+        error,
+        Err(Error::PathCollision { existing, .. }) if existing == "README.md"
+    ));
+    let renamed = renamed?;
+    assert_eq!(renamed.len(), 4);
+    let renamed_paths: Vec<_> = renamed.iter().map(|f| f.output_path.clone()).collect();
+    assert!(renamed_paths.contains(&output_path.join("README~1.md")));
+    match ignores_case {
+        true => {
+            assert_eq!(first_wins?.len(), 2);
+            assert!(renamed_paths.contains(&output_path.join("README~2.md")));
+        }
+        false => {
+            assert_eq!(first_wins?.len(), 3);
+            assert!(renamed_paths.contains(&output_path.join("Readme.md")));
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn unrepresentable_paths() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("ok.txt", 0),
+        server.blob_entry("100%\u{FFFD}.txt", 0),
+    ]);
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_unrepresentable_paths/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+
+    let skipped = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    config.unrepresentable_paths = UnrepresentablePathPolicy::PercentEncode;
+    let encoded = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    config.unrepresentable_paths = UnrepresentablePathPolicy::Error;
+    let error = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(skipped?.len(), 1);
+    assert!(matches!(
+        &reporter.events()[0],
+        CollectedEvent::DownloadFailed {
+            error: Error::UnrepresentablePath { .. },
+            ..
+        }
+    ));
+    assert_eq!(
+        encoded?[1].output_path,
+        output_path.join("100%25%EF%BF%BD.txt")
+    );
+    assert!(matches!(
+        error,
+        Err(Error::UnrepresentablePath { path, .. }) if path == "100%\u{FFFD}.txt"
+    ));
+
+    Ok(())
+}
+
+struct SkipTests;
+
+impl DownloadInterceptor for SkipTests {
+    fn before_download(&self, entry: &SourceTree, output_path: &Path) -> InterceptDecision {
+        if entry.path.starts_with("tests") {
+            InterceptDecision::Skip
+        } else if entry.path.ends_with("README.md") {
+            InterceptDecision::Redirect(output_path.with_file_name("index.md"))
+        } else {
+            InterceptDecision::Download
+        }
+    }
+}
+
+#[tokio::test]
+pub async fn interceptor() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("README.md", 0),
+        server.blob_entry("src/lib.rs", 0),
+        server.blob_entry("tests/lib.rs", 0),
+    ]);
+
+    let output_path = Path::new("./tests/test_output_dir_interceptor/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.interceptor = Some(&SkipTests);
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    let index_exists = output_path.join("index.md").is_file();
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let files = files?;
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].output_path, output_path.join("index.md"));
+    assert!(index_exists);
+    assert_eq!(server.requests().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn ordering() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("b.txt", 20),
+        server.blob_entry("c.txt", 10),
+        server.blob_entry("a.txt", 30),
+    ]);
+
+    let output_path = Path::new("./tests/test_output_dir_ordering/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.max_simultaneous_downloads = 1;
+
+    let mut orders = Vec::new();
+    for ordering in [
+        DownloadOrdering::Tree,
+        DownloadOrdering::SmallestFirst,
+        DownloadOrdering::LargestFirst,
+        DownloadOrdering::Path,
+    ] {
+        config.ordering = ordering;
+        orders.push(Downloader::download_tree(&config, &tree, &Filter::all()).await);
+    }
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let requested: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(
+        requested[3..6],
+        ["/blobs/c.txt", "/blobs/b.txt", "/blobs/a.txt"]
+    );
+    for (files, expected) in orders.into_iter().zip(["bca", "cba", "abc", "abc"]) {
+        let names: String = files?
+            .iter()
+            .map(|f| f.entry.path.as_str().chars().next().unwrap())
+            .collect();
+        assert_eq!(names, expected);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn quotas_unknown_sizes() -> Result<(), Error> {
+    // GitLab doesn't list sizes, so the quotas can only be enforced as the files arrive
+    let server = start_gitlab_server().await;
+    let forge = GitLabForge::with_client(GithubClient::new(None)?.with_api_url(&server.url));
+    let output_path = Path::new("./tests/test_output_dir_quotas_unknown_sizes/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.forge = Some(&forge);
+    config.max_simultaneous_downloads = 1;
+    let path = GithubBranchPath::new("group/subgroup", "project", "main");
+
+    config.max_file_bytes = Some(4);
+    let over_file = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+    config.max_file_bytes = None;
+    config.max_total_bytes = Some(8);
+    let over_total = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+
+    // blobs fetched from the API without a size are counted as they stream in
+    let blobs = MockServer::start(|_| MockResponse::blob(&[0; 1000])).await;
+    let tree = blobs.tree(vec![blobs.blob_entry("large.bin", 0)]);
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.max_file_bytes = Some(100);
+    let over_api_file = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert!(matches!(
+        over_file.map_err(|e| e.root().clone()),
+        Err(Error::QuotaExceeded {
+            path: Some(_),
+            size: 5,
+            limit: 4
+        })
+    ));
+    assert!(matches!(
+        over_total.map_err(|e| e.root().clone()),
+        Err(Error::QuotaExceeded {
+            path: None,
+            size: 10,
+            limit: 8
+        })
+    ));
+    assert!(matches!(
+        over_api_file.map_err(|e| e.root().clone()),
+        Err(Error::QuotaExceeded { path: Some(path), limit: 100, .. }) if path == "large.bin"
+    ));
+    assert!(blobs
+        .requests()
+        .iter()
+        .all(|r| r.header("accept") == Some("application/vnd.github.raw+json")));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn quotas() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("small.txt", 10),
+        server.blob_entry("medium.txt", 100),
+        server.blob_entry("large.bin", 1000),
+    ]);
+
+    let output_path = Path::new("./tests/test_output_dir_quotas/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.max_total_bytes = Some(100);
+
+    let over_total = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    config.max_file_bytes = Some(100);
+    config.max_total_bytes = Some(110);
+    let skipped_large = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert!(matches!(
+        over_total,
+        Err(Error::QuotaExceeded {
+            path: None,
+            size: 1110,
+            limit: 100
+        })
+    ));
+    assert_eq!(skipped_large?.len(), 2);
+    assert_eq!(server.requests().len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn serialize_report() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"hello")).await;
+    let mut entry = server.blob_entry("a.txt", 5);
+    entry.sha = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0".parse()?;
+    let tree = server.tree(vec![entry]);
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_serialize_report/");
+    let config = DownloadConfig::new_with_reporter(output_path, &reporter);
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    let files = serde_json::to_value(files?)?;
+    assert_eq!(
+        files,
+        serde_json::json!([{
+            "entry": {
+                "path": "a.txt",
+                "type": "blob",
+                "mode": "100644",
+                "size": 5,
+                "sha": "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0"
+            },
+            "output_path": output_path.join("a.txt"),
+            "bytes_received": 69,
+            "bytes_written": 5,
+            "verified": true
+        }])
+    );
+
+    let events = serde_json::to_value(reporter.events())?;
+    assert_eq!(
+        events,
+        serde_json::json!([
+            { "type": "download_planned", "files": 1, "total_bytes": 5 },
+            { "type": "download_started", "path": "a.txt" },
+            {
+                "type": "download_completed",
+                "path": "a.txt",
+                "bytes_received": 69,
+                "bytes_written": 5
+            }
+        ])
+    );
+
+    let event = DownloadEvent::RateLimited {
+        path: "a.txt",
+        retry_after: Duration::from_secs(2),
+    };
+    assert_eq!(
+        serde_json::to_value(&event)?,
+        serde_json::json!({ "type": "rate_limited", "path": "a.txt", "retry_after_ms": 2000 })
+    );
+
+    let error = Error::WithContext {
+        context: grab_github::ErrorContext {
+            path: Some(String::from("a.txt")),
+            status: Some(403),
+            ..Default::default()
+        },
+        source: Box::new(Error::QuotaExceeded {
+            path: None,
+            size: 10,
+            limit: 5,
+        }),
+    };
+    assert_eq!(
+        serde_json::to_value(&error)?,
+        serde_json::json!({
+            "type": "with_context",
+            "context": { "repo": null, "path": "a.txt", "url": null, "status": 403 },
+            "source": { "type": "quota_exceeded", "path": null, "size": 10, "limit": 5 }
+        })
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn unauthenticated() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(
+        (0..61)
+            .map(|i| server.blob_entry(&format!("{}.txt", i), 0))
+            .collect(),
+    );
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_unauthenticated/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    config.require_auth = true;
+    let required = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(files?.len(), 61);
+    assert!(reporter.events().iter().any(|e| matches!(
+        e,
+        CollectedEvent::UnauthenticatedRateLimit {
+            requests: 61,
+            limit: 60
+        }
+    )));
+    assert!(matches!(required, Err(Error::AuthenticationRequired)));
+    assert_eq!(server.requests().len(), 61);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn prefetch_sizes() -> Result<(), Error> {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/blobs/known.txt" => MockResponse::blob(b"hello"),
+        _ => MockResponse::blob(b"hello world"),
+    })
+    .await;
+    let tree = server.tree(vec![
+        server.blob_entry("known.txt", 5),
+        server.blob_entry("unknown.txt", 0),
+    ]);
+
+    let reporter = CollectingReporter::new();
+    let output_path = Path::new("./tests/test_output_dir_prefetch_sizes/");
+    let mut config = DownloadConfig::new_with_reporter(output_path, &reporter);
+
+    let unknown = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    config.prefetch_sizes = true;
+    let sized = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(unknown?[1].entry.size, 0);
+    assert_eq!(sized?[1].entry.size, 11);
+    let planned: Vec<u64> = reporter
+        .events()
+        .iter()
+        .filter_map(|e| match e {
+            CollectedEvent::DownloadPlanned {
+                files: 2,
+                total_bytes,
+            } => Some(*total_bytes),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(planned, vec![5, 16]);
+
+    let heads: Vec<_> = server
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "HEAD")
+        .collect();
+    assert_eq!(heads.len(), 1);
+    assert_eq!(heads[0].path, "/blobs/unknown.txt");
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn pause_resume_abort() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"")).await;
+    let tree = server.tree(vec![
+        server.blob_entry("a.txt", 0),
+        server.blob_entry("b.txt", 0),
+    ]);
+
+    let output_path = Path::new("./tests/test_output_dir_pause_resume_abort/");
+    let handle = DownloadHandle::new();
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.handle = Some(handle.clone());
+
+    let filter = Filter::all();
+    handle.pause();
+    let (resumed, requests_while_paused) =
+        tokio::join!(Downloader::download_tree(&config, &tree, &filter), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let requests = server.requests().len();
+            handle.resume();
+            requests
+        });
+
+    handle.abort();
+    handle.resume();
+    let aborted = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    let leftover = std::fs::read_dir(output_path)?.count();
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(requests_while_paused, 0);
+    assert_eq!(resumed?.len(), 2);
+    assert!(handle.is_aborted());
+    let aborted = aborted.unwrap_err();
+    assert!(matches!(aborted.root(), Error::Aborted));
+    assert!(aborted
+        .context()
+        .and_then(|c| c.path.as_deref())
+        .is_some_and(|path| path.ends_with(".txt")));
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(leftover, 2);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+pub async fn abort_while_writing() -> Result<(), Error> {
+    let contents = vec![b'x'; 2 << 20];
+    let server = MockServer::start(move |_| MockResponse::blob(&contents)).await;
+    let tree = server.tree(
+        (0..16)
+            .map(|i| server.blob_entry(&format!("{}.bin", i), 0))
+            .collect(),
+    );
+
+    // aborts as the fourth file is about to be written, while others are being written
+    let output_path = Path::new("./tests/test_output_dir_abort_while_writing/");
+    let handle = DownloadHandle::new();
+    let hooked = AtomicUsize::new(0);
+    let aborting = handle.clone();
+    let hook = move |_: &SourceTree| {
+        if hooked.fetch_add(1, Ordering::SeqCst) == 3 {
+            aborting.abort();
+        }
+        None
+    };
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.handle = Some(handle.clone());
+    config.permissions_hook = Some(&hook);
+    config.max_simultaneous_downloads = 16;
+
+    let result = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+
+    // writes that were already running when the download was dropped finish in the background
+    let count_partial = || match output_path.is_dir() {
+        true => std::fs::read_dir(output_path)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "part"))
+            .count(),
+        false => 0,
+    };
+    let mut partial = count_partial();
+    for _ in 0..50 {
+        if partial == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        partial = count_partial();
+    }
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert!(matches!(
+        result.map_err(|e| e.root().clone()),
+        Err(Error::Aborted)
+    ));
+    assert_eq!(partial, 0);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+pub async fn host_limits() -> Result<(), Error> {
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most_in_flight = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let (in_flight, most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+        MockServer::start(move |_| {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            most_in_flight.fetch_max(current, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            MockResponse::blob(b"")
+        })
+        .await
+    };
+    let tree = server.tree(
+        (0..6)
+            .map(|i| server.blob_entry(&format!("{}.txt", i), 0))
+            .collect(),
+    );
+
+    let output_path = Path::new("./tests/test_output_dir_host_limits/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.host_limits = vec![("127.0.0.1".into(), 1)];
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(files?.len(), 6);
+    assert_eq!(most_in_flight.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+pub async fn adaptive_concurrency() -> Result<(), Error> {
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let most_in_flight = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let (in_flight, most_in_flight) = (in_flight.clone(), most_in_flight.clone());
+        MockServer::start(move |_| {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            most_in_flight.fetch_max(current, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            MockResponse::blob(b"")
+        })
+        .await
+    };
+    let tree = server.tree(
+        (0..16)
+            .map(|i| server.blob_entry(&format!("{}.txt", i), 0))
+            .collect(),
+    );
+
+    let output_path = Path::new("./tests/test_output_dir_adaptive/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.max_simultaneous_downloads = 3;
+    config.adaptive_concurrency = true;
+
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+
+    assert_eq!(files?.len(), 16);
+    // starts with one download at a time, and grows as they succeed without going over the maximum
+    let most_in_flight = most_in_flight.load(Ordering::SeqCst);
+    assert!((2..=3).contains(&most_in_flight), "{}", most_in_flight);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn bench_profile() -> Result<(), Error> {
+    let server = MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str| {
+            format!(
+                r#"{{"path":"{0}","mode":"100644","type":"blob","size":5,"sha":"1111111111111111111111111111111111111111","url":"http://{1}/blobs/{0}"}}"#,
+                path, host
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[{},{},{}]}}"#,
+                    entry("a.txt"),
+                    entry("b.txt"),
+                    entry("c.txt")
+                ),
+            ),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await;
+
+    let output_path = Path::new("./tests/test_output_dir_bench_profile/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = server.url.clone().into();
+    config.max_simultaneous_downloads = 2;
+    config.http_options = HttpOptions {
+        pool_idle_timeout: Some(Duration::from_secs(30)),
+        pool_max_idle_per_host: Some(4),
+        tcp_keepalive: Some(Duration::from_secs(60)),
+        ..HttpOptions::default()
+    };
+
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+    let profile = Downloader::bench_profile(&config, &path, &Filter::all(), 2).await?;
+
+    // nothing is written, and only the sampled blobs are fetched
+    assert!(!output_path.exists());
+    assert_eq!(server.requests().len(), 3);
+    assert_eq!(profile.requests, 2);
+    assert_eq!(profile.concurrency, 2);
+    assert_eq!(profile.bytes, 10);
+    assert!(profile.percentile(50.0) <= profile.percentile(99.0));
+    assert_eq!(profile.percentile(100.0), profile.latencies[1]);
+    assert!(profile
+        .to_string()
+        .starts_with("2 requests at concurrency 2"));
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use grab_github::Filter;
+use grab_github::{Error, Filter, FilterOptions};
 
 #[test]
 pub fn only_included() {
@@ -82,3 +82,149 @@ pub fn neither() {
     assert!(filter.check("other/one/two"));
     assert!(filter.check("one/any/other"));
 }
+
+#[test]
+pub fn parse() {
+    let filter = Filter::parse("src/**, *.{toml,md},!src/tests/**,, !**/*.bak").unwrap();
+
+    assert!(filter.check("src/lib.rs"));
+    assert!(filter.check("Cargo.toml"));
+    assert!(filter.check("README.md"));
+    assert!(!filter.check("src/tests/filter.rs"));
+    assert!(!filter.check("src/lib.rs.bak"));
+    assert!(!filter.check("tests/filter.rs"));
+}
+
+#[test]
+pub fn from_patterns_file() {
+    let path = std::env::temp_dir().join("grab_github_from_patterns_file.txt");
+    std::fs::write(
+        &path,
+        "# docs and sources\nsrc/**\n\n  docs/*.md\n!src/generated/**\n",
+    )
+    .unwrap();
+
+    let filter = Filter::from_patterns_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    let filter = filter.unwrap();
+
+    assert!(filter.check("src/lib.rs"));
+    assert!(filter.check("docs/index.md"));
+    assert!(!filter.check("src/generated/bindings.rs"));
+    assert!(!filter.check("# docs and sources"));
+    assert!(!filter.check("docs/nested/index.md"));
+}
+
+#[test]
+pub fn invalid_patterns() {
+    for pattern in ["src/[ab", "src/{a,b", "src/a}", "src/**a", "a**/b", "src\\"] {
+        match Filter::parse(pattern) {
+            Err(Error::InvalidPattern { pattern: p, .. }) => assert_eq!(p, pattern),
+            _ => panic!("expected {} to be rejected", pattern),
+        }
+    }
+
+    assert!(Filter::parse("src/[]a],{a,{b,c}}/**,\\{literal\\}").is_ok());
+}
+
+#[test]
+pub fn try_new() {
+    assert!(Filter::try_new(vec!["src/**", "*.{rs,toml}"], vec!["target/*"]).is_ok());
+
+    match Filter::try_new(vec!["src/**"], vec!["target/[ab"]) {
+        Err(Error::InvalidPattern { pattern, .. }) => assert_eq!(pattern, "target/[ab"),
+        _ => panic!("expected target/[ab to be rejected"),
+    }
+
+    // a trailing backslash is an unfinished escape, unless backslashes are separators
+    let backslashes = FilterOptions {
+        match_backslashes: true,
+        ..Default::default()
+    };
+    assert!(Filter::try_new(vec!["src\\"], vec![]).is_err());
+    let filter = Filter::new(vec!["src\\"], vec!["src\\*\\"])
+        .try_with_options(backslashes)
+        .unwrap();
+    assert!(filter.check("src/"));
+    assert!(!filter.check("src\\tests\\"));
+    assert!(Filter::new(vec!["src\\[ab"], vec![])
+        .try_with_options(backslashes)
+        .is_err());
+}
+
+#[test]
+pub fn explain() {
+    let filter = Filter::new(vec!["src/**", "*.rs"], vec!["**/tests/**"]);
+
+    let explanation = filter.explain("src/lib.rs");
+    assert_eq!(explanation.included_by, Some("src/**"));
+    assert_eq!(explanation.excluded_by, None);
+    assert!(explanation.passes);
+
+    let explanation = filter.explain("src/tests/filter.rs");
+    assert_eq!(explanation.included_by, Some("src/**"));
+    assert_eq!(explanation.excluded_by, Some("**/tests/**"));
+    assert!(!explanation.passes);
+
+    let explanation = filter.explain("README.md");
+    assert_eq!(explanation.included_by, None);
+    assert!(!explanation.passes);
+
+    let all = Filter::all();
+    let explanation = all.explain("README.md");
+    assert_eq!(explanation.included_by, None);
+    assert!(explanation.passes);
+}
+
+#[test]
+pub fn options() {
+    let filter = Filter::new(vec!["SRC/**/*.RS"], vec![]).with_options(FilterOptions {
+        case_insensitive: true,
+        ..Default::default()
+    });
+    assert!(filter.check("src/lib.rs"));
+    assert!(filter.check("Src/Lib.Rs"));
+    assert!(!filter.check("src/lib.toml"));
+
+    let filter = Filter::new(vec!["src\\**"], vec!["src\\tests\\*"]).with_options(FilterOptions {
+        match_backslashes: true,
+        ..Default::default()
+    });
+    assert!(filter.check("src/lib.rs"));
+    assert!(filter.check("src\\filter.rs"));
+    assert!(!filter.check("src/tests/filter.rs"));
+
+    let filter = Filter::new(vec!["**", ".github/*"], vec![]);
+    assert!(filter.check(".gitignore"));
+    assert!(filter.check("src/.hidden"));
+
+    let filter = filter.with_options(FilterOptions {
+        require_literal_leading_dot: true,
+        ..Default::default()
+    });
+    assert!(filter.check("src/lib.rs"));
+    assert!(filter.check(".github/workflows.yml"));
+    assert!(!filter.check(".gitignore"));
+    assert!(!filter.check("src/.hidden"));
+    assert!(!filter.check(".github/.hidden"));
+}
+
+#[test]
+pub fn may_match_within() {
+    let filter = Filter::new(vec!["src/**", "docs/*.md", "*.toml"], vec![]);
+    assert!(filter.may_match_within("src"));
+    assert!(filter.may_match_within("src/nested/deeper"));
+    assert!(filter.may_match_within("docs"));
+    assert!(!filter.may_match_within("docs/images"));
+    assert!(!filter.may_match_within("node_modules"));
+
+    let filter = Filter::new(vec![], vec!["**/node_modules/**", "target/**"]);
+    assert!(filter.may_match_within("src"));
+    assert!(!filter.may_match_within("node_modules"));
+    assert!(!filter.may_match_within("web/node_modules"));
+    assert!(!filter.may_match_within("target/debug"));
+    assert!(filter.may_match_within("web"));
+
+    let filter = Filter::new(vec!["{src,docs/api}/**"], vec![]);
+    assert!(filter.may_match_within("docs"));
+}
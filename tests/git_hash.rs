@@ -0,0 +1,137 @@
+#![cfg(feature = "download")]
+
+use grab_github::{
+    git_hash::{self, TreeHashEntry},
+    FileMode, ObjectFormat, ObjectId, SourceTree, TreeEntryType, Utf8PathBuf, EMPTY_TREE_SHA,
+    EMPTY_TREE_SHA256,
+};
+
+const HELLO_SHA: &str = "ce013625030ba8dba906f756967f9e9ca394464a";
+const HELLO_SHA256: &str = "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4";
+
+fn id(sha: &str) -> ObjectId {
+    sha.parse().unwrap()
+}
+
+#[test]
+pub fn blob_sha1() -> Result<(), std::io::Error> {
+    assert_eq!(
+        git_hash::blob_sha1(b""),
+        "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391"
+    );
+    assert_eq!(git_hash::blob_sha1(b"hello\n"), HELLO_SHA);
+    assert_eq!(git_hash::blob_sha1_reader(&b"hello\n"[..], 6)?, HELLO_SHA);
+    assert!(git_hash::blob_sha1_reader(&b"hello\n"[..], 7).is_err());
+
+    Ok(())
+}
+
+#[test]
+pub fn blob_sha256() {
+    assert_eq!(
+        git_hash::blob_sha256(b""),
+        "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813"
+    );
+    assert_eq!(git_hash::blob_sha256(b"hello\n"), HELLO_SHA256);
+    assert_eq!(
+        git_hash::blob_id(b"hello\n", ObjectFormat::Sha256),
+        id(HELLO_SHA256)
+    );
+    assert_eq!(
+        git_hash::blob_id(b"hello\n", ObjectFormat::Sha1),
+        id(HELLO_SHA)
+    );
+}
+
+#[test]
+pub fn object_id() {
+    let sha1 = id(&HELLO_SHA.to_uppercase());
+    assert_eq!(sha1.format(), ObjectFormat::Sha1);
+    assert_eq!(sha1.to_string(), HELLO_SHA);
+    assert_eq!(sha1, HELLO_SHA);
+    assert_eq!(id(HELLO_SHA256).format(), ObjectFormat::Sha256);
+    assert_eq!(id(HELLO_SHA256).as_bytes().len(), 32);
+
+    assert!(ObjectId::default().is_null());
+    assert!("abc123".parse::<ObjectId>().is_err());
+    assert!("z".repeat(40).parse::<ObjectId>().is_err());
+
+    assert_eq!(
+        serde_json::to_string(&sha1).unwrap(),
+        format!("\"{}\"", HELLO_SHA)
+    );
+    assert_eq!(
+        serde_json::from_str::<ObjectId>(&format!("\"{}\"", HELLO_SHA256)).unwrap(),
+        id(HELLO_SHA256)
+    );
+}
+
+#[test]
+pub fn tree_id() -> Result<(), grab_github::Error> {
+    assert_eq!(git_hash::tree_sha1(&[])?, EMPTY_TREE_SHA);
+    assert_eq!(
+        git_hash::tree_id(&[], ObjectFormat::Sha256)?,
+        id(EMPTY_TREE_SHA256)
+    );
+
+    let files = [
+        TreeHashEntry {
+            mode: FileMode::Executable,
+            name: "run.sh",
+            sha: id(HELLO_SHA),
+        },
+        TreeHashEntry {
+            mode: FileMode::Regular,
+            name: "hello.txt",
+            sha: id(HELLO_SHA),
+        },
+    ];
+    let files_sha = git_hash::tree_sha1(&files)?;
+    assert_eq!(files_sha, "e1ba437b7929ebe074c43902ecc945a482603ab8");
+
+    // directories sort as if their names ended with a slash, so `src` comes after `src.txt`
+    let root = [
+        TreeHashEntry {
+            mode: FileMode::Directory,
+            name: "src",
+            sha: id(&files_sha),
+        },
+        TreeHashEntry {
+            mode: FileMode::Regular,
+            name: "src.txt",
+            sha: id(HELLO_SHA),
+        },
+    ];
+    assert_eq!(
+        git_hash::tree_sha1(&root)?,
+        "9db626b402da7d3efa319c8acf6ae4dbebd9e67e"
+    );
+
+    // a tree can't mix object formats
+    assert!(git_hash::tree_id(&files, ObjectFormat::Sha256).is_err());
+
+    let files_sha256 = files.map(|entry| TreeHashEntry {
+        sha: id(HELLO_SHA256),
+        ..entry
+    });
+    assert_eq!(
+        git_hash::tree_id(&files_sha256, ObjectFormat::Sha256)?,
+        id("f70fd3b5a87ead9b927eaf86f89f33c102c86e3b90d0d2fc4b49648dfac52c98")
+    );
+
+    let node = |path: &str, mode| {
+        let mut node = SourceTree::new(TreeEntryType::Blob);
+        node.path = Utf8PathBuf::from(path);
+        node.mode = mode;
+        node.sha = id(HELLO_SHA);
+        node
+    };
+    let mut tree = SourceTree::new(TreeEntryType::Tree);
+    tree.children = vec![
+        node("src/hello.txt", FileMode::Regular),
+        node("src/run.sh", FileMode::Executable),
+    ];
+    assert_eq!(git_hash::source_tree_id(&tree)?, files_sha.as_str());
+
+    Ok(())
+}
@@ -0,0 +1,230 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use std::path::Path;
+
+use common::{MockResponse, MockServer};
+use grab_github::{
+    ContentTransform, Downloader, Error, GrabSpec, GrabWorkspace, LineEnding, OutputLayout,
+    SourceTree, SpecAuth, Transform, TreeEntryType,
+};
+
+const COMMIT: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+/// Another commit with the same tree as [COMMIT].
+const OTHER_COMMIT: &str = "0000000000000000000000000000000000000002";
+const TREE: &str = "0000000000000000000000000000000000000001";
+
+/// Starts a server for `user/repo` at [COMMIT], whose tree has three files. The license holds `{{y}}`,
+/// and the others hold `hello`.
+async fn start_server() -> MockServer {
+    MockServer::start(|r| {
+        let host = r.header("host").unwrap_or_default().to_string();
+        let entry = |path: &str| {
+            let sha = match path.ends_with("LICENSE") {
+                true => "2222222222222222222222222222222222222222",
+                false => "1111111111111111111111111111111111111111",
+            };
+            format!(
+                r#"{{"path":"{0}","mode":"100644","type":"blob","size":5,"sha":"{2}","url":"http://{1}/blobs/{0}"}}"#,
+                path, host, sha
+            )
+        };
+
+        match r.path.as_str() {
+            p if p.contains("/git/commits/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"{}","url":"","message":"","tree":{{"sha":"{}","url":""}}}}"#,
+                    COMMIT, TREE
+                ),
+            ),
+            p if p.contains("/git/trees/") => MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"{}","url":"","truncated":false,"tree":[{},{},{}]}}"#,
+                    TREE,
+                    entry("template/src/lib.rs"),
+                    entry("template/LICENSE"),
+                    entry("README.md")
+                ),
+            ),
+            p if p.ends_with("/LICENSE") => MockResponse::blob(b"{{y}}"),
+            _ => MockResponse::blob(b"hello"),
+        }
+    })
+    .await
+}
+
+#[test]
+pub fn parse() -> Result<(), Error> {
+    let spec = GrabSpec::parse(
+        r#"
+        repo = "user/repo"
+        ref = "tags/v1.0"
+        include = ["template/**"]
+        output = "vendor"
+        layout = "content_addressed"
+        auth = { env_var = "VENDOR_TOKEN" }
+        "#,
+    )?;
+    assert_eq!(spec.reference.as_deref(), Some("tags/v1.0"));
+    assert_eq!(spec.layout, OutputLayout::ContentAddressed);
+    assert_eq!(spec.auth, SpecAuth::EnvVar("VENDOR_TOKEN".into()));
+    assert!(spec.filter()?.check("template/a.txt"));
+    assert!(!spec.filter()?.check("b.txt"));
+
+    let spec = GrabSpec::parse(
+        r#"
+        repo = "user/repo"
+        output = "vendor"
+
+        [[transform]]
+        type = "substitute"
+        values = { version = "1.2" }
+        paths = ["*.md"]
+
+        [[transform]]
+        type = "line_endings"
+        to = "crlf"
+        "#,
+    )?;
+    assert_eq!(spec.transforms.len(), 2);
+    assert_eq!(
+        spec.transforms[1],
+        Transform::LineEndings {
+            to: LineEnding::Crlf,
+            paths: vec![]
+        }
+    );
+    let mut entry = SourceTree::new(TreeEntryType::Blob);
+    entry.path = "README.md".into();
+    let text = b"v{{version}}\r\nnotes\n".to_vec();
+    assert_eq!(
+        spec.transforms.transform(&entry, text.clone()),
+        b"v1.2\r\nnotes\r\n"
+    );
+    entry.path = "src/lib.rs".into();
+    assert_eq!(
+        spec.transforms.transform(&entry, text),
+        b"v{{version}}\r\nnotes\r\n"
+    );
+    assert_eq!(spec.transforms.transform(&entry, b"\0\n".to_vec()), b"\0\n");
+
+    let defaults = GrabSpec::parse("repo = \"user/repo\"\noutput = \"vendor\"")?;
+    assert_eq!(defaults.reference, None);
+    assert_eq!(defaults.layout, OutputLayout::Paths);
+    assert_eq!(defaults.auth, SpecAuth::Env);
+
+    for invalid in [
+        "repo = \"user\"\noutput = \"vendor\"",
+        "repo = \"user/repo\"",
+        "repo = \"user/repo\"\noutput = \"vendor\"\nincldue = []",
+        "repo = \"user/repo\"\noutput = \"vendor\"\n[[transform]]\ntype = \"upper\"",
+        "repo = \"user/repo\"\noutput = \"vendor\"\n[[transform]]\ntype = \"line_endings\"\nto = \"lf\"\nwhen = 1",
+    ] {
+        assert!(matches!(GrabSpec::parse(invalid), Err(Error::Other(_))));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn run_spec() -> Result<(), Error> {
+    let server = start_server().await;
+
+    let dir = Path::new("./tests/test_output_dir_run_spec/");
+    std::fs::create_dir_all(dir)?;
+    let spec_file = dir.join("grab.toml");
+    std::fs::write(
+        &spec_file,
+        format!(
+            r#"
+            repo = "user/repo"
+            ref = "{}"
+            include = ["template/**"]
+            output = "vendor"
+            strip_prefix = "template"
+            auth = "none"
+            api_url = "{}"
+
+            [[rename]]
+            from = "template/LICENSE"
+            to = "LICENSE.txt"
+
+            [[transform]]
+            type = "substitute"
+            values = {{ y = "2026" }}
+            "#,
+            COMMIT, server.url
+        ),
+    )?;
+
+    let spec = GrabSpec::from_toml(&spec_file)?;
+    assert_eq!(spec.output_path(), dir.join("vendor"));
+    let report = Downloader::run_spec(&spec).await;
+    let lib = std::fs::read(dir.join("vendor/src/lib.rs"));
+    let license = std::fs::read(dir.join("vendor/LICENSE.txt"));
+    let readme = dir.join("vendor/README.md").exists();
+    std::fs::remove_dir_all(dir)?;
+
+    let report = report?;
+    assert_eq!(report.files.len(), 2);
+    assert_eq!(lib?, b"hello");
+    assert_eq!(license?, b"2026");
+    assert!(!readme);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn run_workspace() -> Result<(), Error> {
+    let server = start_server().await;
+    let dir = Path::new("./tests/test_output_dir_run_workspace/");
+    std::fs::create_dir_all(dir)?;
+    let workspace_file = dir.join("grab-workspace.toml");
+    let grab = |output: &str, commit: &str| {
+        format!(
+            "[[grab]]\nrepo = \"user/repo\"\nref = \"{}\"\ninclude = [\"template/**\"]\noutput = \"{}\"\nauth = \"none\"\napi_url = \"{}\"\n",
+            commit, output, server.url
+        )
+    };
+    let transform = "[[grab.transform]]\ntype = \"substitute\"\nvalues = { y = \"2026\" }\n";
+    std::fs::write(
+        &workspace_file,
+        format!(
+            "max_requests = 5\n{}{}{}{}{}",
+            grab("first", COMMIT),
+            grab("second", COMMIT),
+            transform,
+            grab("third", OTHER_COMMIT),
+            grab("fourth", COMMIT)
+        ),
+    )?;
+
+    let workspace = GrabWorkspace::from_toml(&workspace_file)?;
+    assert_eq!(workspace.grabs[1].output_path(), dir.join("second"));
+    let report = Downloader::run_workspace(&workspace).await;
+    let second = std::fs::read(dir.join("second/template/src/lib.rs"));
+    let first_license = std::fs::read(dir.join("first/template/LICENSE"));
+    let second_license = std::fs::read(dir.join("second/template/LICENSE"));
+    std::fs::remove_dir_all(dir)?;
+
+    // the second grab finds the commit, tree and blobs in the shared cache, and the third only has to
+    // resolve its commit, which uses up the budget before the fourth
+    assert_eq!(report.requests_made, 5);
+    assert_eq!(server.requests().len(), 5);
+    assert!(report.grabs[0].result.is_ok());
+    assert!(report.grabs[1].result.is_ok());
+    assert!(report.grabs[2].result.is_ok());
+    assert_eq!(second?, b"hello");
+    // only the second grab substitutes into the license it shares with the first through the cache
+    assert_eq!(first_license?, b"{{y}}");
+    assert_eq!(second_license?, b"2026");
+    let failures: Vec<_> = report.failures().collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].output_path, dir.join("fourth"));
+    assert_eq!(report.bytes_written(), 29);
+
+    Ok(())
+}
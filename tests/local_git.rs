@@ -0,0 +1,130 @@
+#![cfg(feature = "git2")]
+
+use std::{path::Path, process::Command};
+
+use grab_github::{
+    DownloadConfigNoReporting, Downloader, Error, FetchStrategy, Filter, GithubBranchPath,
+    GithubRef, LocalGitForge,
+};
+
+/// Runs git with the given arguments in `dir`, with an identity so commits can be made anywhere.
+fn git(dir: &Path, args: &[&str]) -> Result<(), Error> {
+    let status = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .status()?;
+    assert!(status.success(), "git {:?} failed", args);
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn local_git_forge() -> Result<(), Error> {
+    let repo_path = Path::new("./tests/test_repo_local_git_forge/");
+    let output_path = Path::new("./tests/test_output_dir_local_git_forge/");
+    std::fs::create_dir_all(repo_path.join("src"))?;
+    std::fs::write(repo_path.join("README.md"), "hello")?;
+    std::fs::write(repo_path.join("src/lib.rs"), "hello")?;
+    std::fs::write(repo_path.join("notes.txt"), "notes")?;
+    git(repo_path, &["-c", "init.defaultBranch=main", "init", "-q"])?;
+    git(repo_path, &["add", "-A"])?;
+    git(repo_path, &["commit", "-q", "-m", "initial"])?;
+
+    let forge = LocalGitForge::open(repo_path)?;
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.forge = Some(&forge);
+
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let filter = Filter::new(vec!["README.md", "src/**"], vec![]);
+    let report = Downloader::download_resolved(&config, &path, &filter).await;
+    let readme = std::fs::read(output_path.join("README.md"));
+    let lib = std::fs::read(output_path.join("src/lib.rs"));
+    let notes = output_path.join("notes.txt").exists();
+
+    for dir in [repo_path, output_path] {
+        if dir.is_dir() {
+            std::fs::remove_dir_all(dir)?;
+        }
+    }
+
+    let report = report?;
+    assert!(report.resolved.commit_sha.is_some());
+    assert_eq!(report.files.len(), 2);
+    assert!(report.all_verified());
+    assert_eq!(readme?, b"hello");
+    assert_eq!(lib?, b"hello");
+    assert!(!notes);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn clone_strategy() -> Result<(), Error> {
+    // the clone is made from `{web_url}/user/repo.git`, where the web URL is the API URL without `/api/v3`
+    let host_path = Path::new("./tests/test_repo_clone_strategy/");
+    let source_path = host_path.join("source");
+    let output_path = Path::new("./tests/test_output_dir_clone_strategy/");
+    std::fs::create_dir_all(source_path.join("src"))?;
+    std::fs::write(source_path.join("README.md"), "hello")?;
+    std::fs::write(source_path.join("src/lib.rs"), "hello")?;
+    std::fs::write(source_path.join("notes.txt"), "notes")?;
+    git(
+        &source_path,
+        &["-c", "init.defaultBranch=main", "init", "-q"],
+    )?;
+    git(&source_path, &["add", "-A"])?;
+    git(&source_path, &["commit", "-q", "-m", "initial"])?;
+    git(
+        host_path,
+        &["clone", "-q", "--bare", "source", "user/repo.git"],
+    )?;
+
+    let api_url = format!("file://{}/api/v3", host_path.canonicalize()?.display());
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = api_url.into();
+    config.fetch_strategy = FetchStrategy::Clone;
+
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let filter = Filter::new(vec!["README.md", "src/**"], vec![]);
+    let report = Downloader::download_resolved(&config, &path, &filter).await;
+    let readme = std::fs::read(output_path.join("README.md"));
+    let lib = std::fs::read(output_path.join("src/lib.rs"));
+    let notes = output_path.join("notes.txt").exists();
+
+    for dir in [host_path, output_path] {
+        if dir.is_dir() {
+            std::fs::remove_dir_all(dir)?;
+        }
+    }
+
+    let report = report?;
+    assert!(report.resolved.commit_sha.is_some());
+    assert_eq!(report.files.len(), 2);
+    assert!(report.all_verified());
+    assert_eq!(readme?, b"hello");
+    assert_eq!(lib?, b"hello");
+    assert!(!notes);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn clone_rejects_invalid_commit() -> Result<(), Error> {
+    let output_path = Path::new("./tests/test_output_dir_clone_invalid_commit/");
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.fetch_strategy = FetchStrategy::Clone;
+
+    // would be read by git as an option if it were passed along
+    let reference = GithubRef::Commit("--upload-pack=touch tests/test_clone_injected");
+    let path = GithubBranchPath::with_ref("user", "repo", reference);
+    let result = Downloader::download_resolved(&config, &path, &Filter::all()).await;
+
+    let injected = Path::new("./tests/test_clone_injected").exists();
+    assert!(
+        matches!(result, Err(Error::Other(message)) if message.contains("isn't a commit hash"))
+    );
+    assert!(!injected);
+    assert!(!output_path.exists());
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+#![cfg(all(feature = "metrics", feature = "download"))]
+
+mod common;
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use common::{MockResponse, MockServer};
+use grab_github::{DownloadConfigNoReporting, Downloader, Error, Filter};
+use metrics::{
+    Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+};
+
+/// Records the name and labels of every counter incremented, with the amount.
+#[derive(Default)]
+struct CountingRecorder(Arc<Mutex<Vec<(String, u64)>>>);
+
+struct CountingCounter(String, Arc<Mutex<Vec<(String, u64)>>>);
+
+impl CounterFn for CountingCounter {
+    fn increment(&self, value: u64) {
+        self.1.lock().unwrap().push((self.0.clone(), value));
+    }
+
+    fn absolute(&self, _value: u64) {}
+}
+
+impl Recorder for CountingRecorder {
+    fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+    fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        let labels: Vec<String> = key
+            .labels()
+            .map(|l| format!("{}={}", l.key(), l.value()))
+            .collect();
+        let name = format!("{}{{{}}}", key.name(), labels.join(","));
+        Counter::from_arc(Arc::new(CountingCounter(name, self.0.clone())))
+    }
+
+    fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::noop()
+    }
+
+    fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::noop()
+    }
+}
+
+#[tokio::test]
+pub async fn download_metrics() -> Result<(), Error> {
+    let recorder = CountingRecorder::default();
+    let counters = recorder.0.clone();
+    metrics::set_global_recorder(recorder).unwrap();
+
+    let server = MockServer::start(|_| MockResponse::blob(b"hello")).await;
+    let tree = server.tree(vec![server.blob_entry("a.txt", 5)]);
+
+    let output_path = Path::new("./tests/test_output_dir_metrics/");
+    let config = DownloadConfigNoReporting::new(output_path);
+    let files = Downloader::download_tree(&config, &tree, &Filter::all()).await;
+    if output_path.is_dir() {
+        std::fs::remove_dir_all(output_path)?;
+    }
+    files?;
+
+    let counters = counters.lock().unwrap();
+    assert!(counters.contains(&(String::from("grab_github_bytes_downloaded_total{}"), 5)));
+    assert!(counters.contains(&(
+        String::from("grab_github_requests_total{endpoint=other,status=200}"),
+        1
+    )));
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "cli")]
+
+use std::path::Path;
+
+use grab_github::{DownloadConfigNoReporting, Error, GrabProfiles};
+
+#[test]
+pub fn parse() -> Result<(), Error> {
+    let profiles = GrabProfiles::parse(
+        r#"
+        [profiles.minimal]
+        include = ["template/**"]
+        exclude = ["**/*.md"]
+        strip_prefix = "template/"
+
+        [profiles.everything]
+        "#,
+    )?;
+
+    let minimal = profiles.get("minimal")?;
+    let filter = minimal.filter()?;
+    assert!(filter.check("template/src/main.rs"));
+    assert!(!filter.check("template/README.md"));
+    assert!(!filter.check("ci/build.sh"));
+
+    let mut config = DownloadConfigNoReporting::new(Path::new("out"));
+    config.rename_rules.push(minimal.rename_rule().unwrap());
+    assert_eq!(
+        config.output_path_for("template/src/main.rs"),
+        Path::new("out/src/main.rs")
+    );
+
+    let everything = profiles.get("everything")?;
+    assert!(everything.filter()?.check("ci/build.sh"));
+    assert_eq!(everything.rename_rule(), None);
+
+    assert!(matches!(
+        profiles.get("missing"),
+        Err(Error::ProfileError(_))
+    ));
+    assert!(matches!(
+        GrabProfiles::parse("[profiles.bad]\ninclude = 5"),
+        Err(Error::ProfileError(_))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,456 @@
+mod common;
+
+use common::{MockResponse, MockServer};
+use grab_github::{
+    raw::{
+        self, CommitModel, CommitSummaryModel, LicenseContentModel, PullRequestModel, RefModel,
+        RepositoryModel, TreeModel,
+    },
+    Error, FileMode, GithubApiErrorDetail, GithubBranchPath, GithubClient, GithubRef, RepoInfo,
+    TreeEntryType,
+};
+
+#[test]
+pub fn tree_model() -> Result<(), Error> {
+    let tree: TreeModel = serde_json::from_str(
+        r#"{
+            "sha": "9fb037999f264ba9a7fc6274d15fa3ae2ab98312",
+            "url": "https://api.github.com/repos/octocat/Hello-World/trees/9fb037999f264ba9a7fc6274d15fa3ae2ab98312",
+            "tree": [
+                {
+                    "path": "file.rb",
+                    "mode": "100644",
+                    "type": "blob",
+                    "size": 30,
+                    "sha": "44b4fc6d56897b048c772eb4087f854f46256132",
+                    "url": "https://api.github.com/repos/octocat/Hello-World/git/blobs/44b4fc6d56897b048c772eb4087f854f46256132"
+                },
+                {
+                    "path": "subdir",
+                    "mode": "040000",
+                    "type": "tree",
+                    "sha": "f484d249c660418515fb01c2b9662073663c242e",
+                    "url": "https://api.github.com/repos/octocat/Hello-World/git/blobs/f484d249c660418515fb01c2b9662073663c242e"
+                }
+            ],
+            "truncated": false
+        }"#,
+    )?;
+
+    assert_eq!(tree.tree.len(), 2);
+    assert_eq!(tree.tree[0].entry_type, TreeEntryType::Blob);
+    assert_eq!(tree.tree[0].size, 30);
+    assert_eq!(tree.tree[0].mode, FileMode::Regular);
+    assert_eq!(tree.tree[1].entry_type, TreeEntryType::Tree);
+    assert_eq!(tree.tree[1].size, 0);
+    assert_eq!(tree.tree[1].mode, FileMode::Directory);
+    assert_eq!(tree.tree[0].sha, "44b4fc6d56897b048c772eb4087f854f46256132");
+
+    // a malformed hash is an error rather than a null hash
+    let malformed = serde_json::from_str::<TreeModel>(
+        r#"{
+            "sha": "9fb037999f264ba9a7fc6274d15fa3ae2ab98312",
+            "url": "",
+            "tree": [
+                { "path": "a", "mode": "100644", "type": "blob", "sha": "44b4fc6d", "url": "" }
+            ],
+            "truncated": false
+        }"#,
+    );
+    assert!(malformed.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn tree_model_streamed() -> Result<(), Error> {
+    // large enough to arrive in several chunks, with strings that look like the structure of the response
+    let entries: Vec<String> = (0..2000)
+        .map(|i| {
+            format!(
+                r#"{{"path":"dir/{{{}}}]\"[tree].txt","mode":"100644","type":"blob","size":{},"sha":"44b4fc6d56897b048c772eb4087f854f46256132","url":"u","extra":{{"a":[1,{{}}]}}}}"#,
+                i, i
+            )
+        })
+        .collect();
+    let body = format!(
+        r#"{{"sha":"9fb037999f264ba9a7fc6274d15fa3ae2ab98312","url":"{{\"tree\":[","tree" : [{}],"truncated":true}}"#,
+        entries.join(",\n ")
+    );
+    let server = MockServer::start(move |_| MockResponse::json(200, &body)).await;
+
+    let client = GithubClient::new(None)?.with_api_url(&server.url);
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let tree = raw::get_tree_raw(&client, &path, true).await?;
+
+    assert_eq!(tree.sha, "9fb037999f264ba9a7fc6274d15fa3ae2ab98312");
+    assert_eq!(tree.url, "{\"tree\":[");
+    assert!(tree.truncated);
+    assert_eq!(tree.tree.len(), 2000);
+    assert_eq!(tree.tree[1999].path, "dir/{1999}]\"[tree].txt");
+    assert_eq!(tree.tree[1999].size, 1999);
+    assert_eq!(tree.tree[1999].mode, FileMode::Regular);
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn pull_request_tree() -> Result<(), Error> {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/repos/user/repo/git/trees/refs/pull/42/head" => MockResponse::json(
+            200,
+            r#"{"sha":"9fb037999f264ba9a7fc6274d15fa3ae2ab98312","url":"","tree":[],"truncated":false}"#,
+        ),
+        _ => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+    })
+    .await;
+
+    let client = GithubClient::new(None)?.with_api_url(&server.url);
+    let path = GithubBranchPath::with_ref("user", "repo", GithubRef::PullRequest(42));
+    let tree = raw::get_tree_raw(&client, &path, false).await?;
+
+    assert_eq!(tree.sha, "9fb037999f264ba9a7fc6274d15fa3ae2ab98312");
+    assert_eq!(server.requests().len(), 1);
+
+    Ok(())
+}
+
+#[cfg(feature = "download")]
+#[test]
+pub fn blob_model_decode() -> Result<(), Error> {
+    let blob: raw::BlobModel = serde_json::from_str(
+        r#"{
+            "content": "aGVsbG8g\nd29ybGQK\n",
+            "encoding": "base64",
+            "url": "https://api.github.com/repos/octocat/example/git/blobs/3a0f86fb8db8eea7ccbb9a95f325ddbedfb25e15",
+            "sha": "3a0f86fb8db8eea7ccbb9a95f325ddbedfb25e15",
+            "size": 12
+        }"#,
+    )?;
+
+    assert_eq!(blob.decode()?, b"hello world\n");
+
+    // line breaks from anywhere, and missing padding, don't get in the way of decoding
+    let lenient = raw::BlobModel {
+        content: String::from("aGVsbG8g\r\nd29y bGQK"),
+        ..blob.clone()
+    };
+    assert_eq!(lenient.decode()?, b"hello world\n");
+
+    let unsupported: raw::BlobModel =
+        serde_json::from_str(r#"{"content":"","encoding":"rot13","url":"","sha":"","size":12}"#)?;
+    assert_eq!(
+        unsupported.encoding,
+        raw::ContentEncoding::Other(String::from("rot13"))
+    );
+    assert!(matches!(
+        unsupported.decode(),
+        Err(Error::UnsupportedEncoding { encoding }) if encoding == "rot13"
+    ));
+    assert_eq!(
+        serde_json::to_value(&unsupported)?["encoding"],
+        serde_json::json!("rot13")
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "download")]
+#[tokio::test]
+pub async fn blob_encoding_none() -> Result<(), Error> {
+    // like the contents API for large files, the JSON response leaves the content out
+    let server = MockServer::start(|request| match request.header("accept") {
+        Some(raw::MEDIA_TYPE_RAW) => MockResponse {
+            status: 200,
+            headers: vec![],
+            body: b"hello world\n".to_vec(),
+            raw_body: None,
+        },
+        _ => MockResponse::json(
+            200,
+            r#"{"content":"","encoding":"none","url":"","sha":"","size":12}"#,
+        ),
+    })
+    .await;
+
+    let client = GithubClient::new(None)?;
+    let url = format!("{}/blobs/large", server.url);
+    let blob = raw::get_blob_raw(&client, &url).await?;
+    assert_eq!(blob.encoding, raw::ContentEncoding::None);
+    assert!(matches!(
+        blob.decode(),
+        Err(Error::UnsupportedEncoding { .. })
+    ));
+
+    let contents = raw::get_blob_contents(&client, &url, 12).await?;
+    assert_eq!(contents, b"hello world\n");
+    assert_eq!(server.requests().len(), 3);
+
+    Ok(())
+}
+
+#[test]
+pub fn ref_and_commit_models() -> Result<(), Error> {
+    let git_ref: RefModel = serde_json::from_str(
+        r#"{
+            "ref": "refs/tags/v1.0",
+            "node_id": "MDM6UmVmcmVmcy90YWdzL3YxLjA=",
+            "url": "https://api.github.com/repos/octocat/Hello-World/git/refs/tags/v1.0",
+            "object": {
+                "type": "tag",
+                "sha": "940bd336248efae0f9ee5bc7b2d5c985887b16ac",
+                "url": "https://api.github.com/repos/octocat/Hello-World/git/tags/940bd336248efae0f9ee5bc7b2d5c985887b16ac"
+            }
+        }"#,
+    )?;
+
+    assert_eq!(git_ref.name, "refs/tags/v1.0");
+    assert_eq!(git_ref.object.object_type, "tag");
+
+    let commit: CommitModel = serde_json::from_str(
+        r#"{
+            "sha": "7638417db6d59f3c431d3e1f261cc637155684cd",
+            "url": "https://api.github.com/repos/octocat/Hello-World/git/commits/7638417db6d59f3c431d3e1f261cc637155684cd",
+            "message": "added readme, because im a good github citizen",
+            "tree": {
+                "url": "https://api.github.com/repos/octocat/Hello-World/git/trees/691272480426f78a0138979dd3ce63b77f706feb",
+                "sha": "691272480426f78a0138979dd3ce63b77f706feb"
+            },
+            "parents": []
+        }"#,
+    )?;
+
+    assert_eq!(commit.tree.sha, "691272480426f78a0138979dd3ce63b77f706feb");
+
+    let pull_request: PullRequestModel = serde_json::from_str(
+        r#"{
+            "url": "https://api.github.com/repos/octocat/Hello-World/pulls/1347",
+            "number": 1347,
+            "state": "open",
+            "head": {
+                "label": "octocat:new-topic",
+                "ref": "new-topic",
+                "sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+            }
+        }"#,
+    )?;
+
+    assert_eq!(pull_request.head.name, "new-topic");
+
+    let commits: Vec<CommitSummaryModel> = serde_json::from_str(
+        r#"[{
+            "sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e",
+            "url": "https://api.github.com/repos/octocat/Hello-World/commits/6dcb09b5b57875f334f61aebed695e2e4193db5e",
+            "commit": {
+                "url": "https://api.github.com/repos/octocat/Hello-World/git/commits/6dcb09b5b57875f334f61aebed695e2e4193db5e",
+                "author": { "name": "Monalisa Octocat", "email": "support@github.com", "date": "2011-04-14T16:00:49Z" },
+                "committer": { "name": "Monalisa Octocat", "email": "support@github.com", "date": "2011-04-14T16:00:49Z" },
+                "message": "Fix all the bugs",
+                "tree": {
+                    "url": "https://api.github.com/repos/octocat/Hello-World/tree/6dcb09b5b57875f334f61aebed695e2e4193db5e",
+                    "sha": "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+                }
+            },
+            "parents": []
+        }]"#,
+    )?;
+
+    assert_eq!(commits[0].commit.message, "Fix all the bugs");
+    assert_eq!(commits[0].commit.author.date, "2011-04-14T16:00:49Z");
+    assert_eq!(
+        pull_request.head.sha,
+        "6dcb09b5b57875f334f61aebed695e2e4193db5e"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn repository_model() -> Result<(), Error> {
+    let repository: RepositoryModel = serde_json::from_str(
+        r#"{
+            "id": 1296269,
+            "full_name": "octocat/Hello-World",
+            "url": "https://api.github.com/repos/octocat/Hello-World",
+            "description": "This your first repo!",
+            "default_branch": "master",
+            "license": {
+                "key": "mit",
+                "name": "MIT License",
+                "spdx_id": "MIT",
+                "url": "https://api.github.com/licenses/mit"
+            },
+            "topics": ["octocat", "atom"],
+            "visibility": "public",
+            "archived": false,
+            "disabled": false,
+            "size": 108
+        }"#,
+    )?;
+
+    let info = RepoInfo::from(repository.clone());
+    assert_eq!(info.default_branch, "master");
+    assert_eq!(info.license_spdx_id.as_deref(), Some("MIT"));
+    assert_eq!(info.topics, ["octocat", "atom"]);
+    assert_eq!(info.size_kb, 108);
+
+    let unidentified = RepositoryModel {
+        license: repository.license.map(|l| raw::LicenseModel {
+            spdx_id: Some(String::from("NOASSERTION")),
+            ..l
+        }),
+        ..repository
+    };
+    assert_eq!(RepoInfo::from(unidentified).license_spdx_id, None);
+
+    let license: LicenseContentModel = serde_json::from_str(
+        r#"{
+            "name": "LICENSE",
+            "path": "LICENSE",
+            "sha": "401c59dcc4570b954dd6d345e76199e1f4e76266",
+            "size": 11,
+            "url": "https://api.github.com/repos/benbalter/gman/contents/LICENSE?ref=master",
+            "git_url": "https://api.github.com/repos/benbalter/gman/git/blobs/401c59dcc4570b954dd6d345e76199e1f4e76266",
+            "type": "file",
+            "content": "TUlUIExpY2Vu\nc2UK\n",
+            "encoding": "base64",
+            "license": {
+                "key": "mit",
+                "name": "MIT License",
+                "spdx_id": "MIT",
+                "url": "https://api.github.com/licenses/mit"
+            }
+        }"#,
+    )?;
+
+    #[cfg(feature = "download")]
+    assert_eq!(license.decode()?, b"MIT License\n");
+    assert_eq!(license.license.spdx_id.as_deref(), Some("MIT"));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn empty_repository() -> Result<(), Error> {
+    let server =
+        MockServer::start(|_| MockResponse::json(409, r#"{"message":"Git Repository is empty."}"#))
+            .await;
+
+    let client = GithubClient::new(None)?;
+    let result: Result<RefModel, Error> =
+        raw::get_with_accept(&client, &server.url, raw::MEDIA_TYPE_JSON).await;
+    assert!(matches!(result.unwrap_err().root(), Error::EmptyRepository));
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn unexpected_response() -> Result<(), Error> {
+    let page = format!("<html><body>{}</body></html>", "x".repeat(1000));
+    let server = MockServer::start(move |request| {
+        let status = match request.path.as_str() {
+            "/ok" => 200,
+            _ => 502,
+        };
+        MockResponse {
+            status,
+            headers: vec![],
+            body: page.clone().into_bytes(),
+            raw_body: None,
+        }
+    })
+    .await;
+
+    let client = GithubClient::new(None)?;
+    for (path, expected_status) in [("/ok", 200), ("/bad-gateway", 502)] {
+        let url = format!("{}{}", server.url, path);
+        let result: Result<RefModel, Error> =
+            raw::get_with_accept(&client, &url, raw::MEDIA_TYPE_JSON).await;
+
+        match result.unwrap_err().root() {
+            Error::UnexpectedResponse {
+                status,
+                body_preview,
+            } => {
+                assert_eq!(*status, expected_status);
+                assert!(body_preview.starts_with("<html><body>xxx"));
+                assert!(body_preview.len() < 600);
+            }
+            e => panic!("expected an unexpected response, got {:?}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn github_api_error() -> Result<(), Error> {
+    let server = MockServer::start(|_| {
+        MockResponse::json(
+            422,
+            r#"{
+                "message": "Validation Failed",
+                "errors": [
+                    { "resource": "Issue", "field": "title", "code": "missing_field" },
+                    "title can't be blank"
+                ],
+                "documentation_url": "https://docs.github.com/rest"
+            }"#,
+        )
+    })
+    .await;
+
+    let client = GithubClient::new(None)?;
+    let url = format!("{}/validation", server.url);
+    let result: Result<RefModel, Error> =
+        raw::get_with_accept(&client, &url, raw::MEDIA_TYPE_JSON).await;
+    let error = result.unwrap_err();
+
+    assert_eq!(error.context().and_then(|c| c.status), Some(422));
+    match error.root() {
+        Error::GithubError(error) => {
+            assert_eq!(error.message, "Validation Failed");
+            assert_eq!(
+                error.documentation_url.as_deref(),
+                Some("https://docs.github.com/rest")
+            );
+            assert_eq!(
+                error.errors,
+                vec![
+                    GithubApiErrorDetail {
+                        resource: Some("Issue".to_string()),
+                        field: Some("title".to_string()),
+                        code: Some("missing_field".to_string()),
+                        message: None,
+                    },
+                    GithubApiErrorDetail {
+                        message: Some("title can't be blank".to_string()),
+                        ..GithubApiErrorDetail::default()
+                    },
+                ]
+            );
+        }
+        e => panic!("expected a GitHub error, got {:?}", e),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn blob_size() -> Result<(), Error> {
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/blobs/compressed" => {
+            MockResponse::blob(b"hello world").with_header("Content-Encoding", "gzip")
+        }
+        _ => MockResponse::blob(b"hello world"),
+    })
+    .await;
+
+    let client = GithubClient::new(None)?;
+    let url = format!("{}/blobs/plain", server.url);
+    assert_eq!(raw::get_blob_size_raw(&client, &url).await?, Some(11));
+    let url = format!("{}/blobs/compressed", server.url);
+    assert_eq!(raw::get_blob_size_raw(&client, &url).await?, None);
+
+    Ok(())
+}
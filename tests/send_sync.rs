@@ -0,0 +1,64 @@
+//! Compile-time checks that the public API can be used from multi-threaded executors.
+#![cfg(all(feature = "download", feature = "graphql"))]
+
+use std::path::Path;
+
+use futures::StreamExt;
+#[cfg(any(feature = "git2", feature = "gix"))]
+use grab_github::wiki::Wiki;
+use grab_github::{
+    Blob, DownloadConfigNoReporting, DownloadHandle, Downloader, Error, Filter, GithubBranchPath,
+    GithubClient, RequestBudget, ResolvedRef, SourceTree,
+};
+
+fn assert_send<T: Send>(_: &T) {}
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+pub fn types_are_send_sync() {
+    assert_send_sync::<SourceTree>();
+    assert_send_sync::<Error>();
+    assert_send_sync::<GithubClient>();
+    assert_send_sync::<RequestBudget>();
+    assert_send_sync::<Filter>();
+    assert_send_sync::<GithubBranchPath>();
+    assert_send_sync::<ResolvedRef>();
+    assert_send_sync::<DownloadConfigNoReporting>();
+    assert_send_sync::<DownloadHandle>();
+}
+
+#[test]
+pub fn futures_are_send() -> Result<(), Error> {
+    let path = GithubBranchPath::new("user", "repo", "main");
+    let filter = Filter::all();
+    let client = GithubClient::new(None)?;
+    let tree = SourceTree::new(grab_github::TreeEntryType::Tree);
+    let config = DownloadConfigNoReporting::new(Path::new("out"));
+
+    assert_send(&SourceTree::get(&path));
+    assert_send(&SourceTree::get_filtered(&path, &filter));
+    assert_send(&SourceTree::get_resolved_with_client(
+        &client, &path, &filter,
+    ));
+    assert_send(&ResolvedRef::resolve(&client, &path));
+    assert_send(&client.list_commits(&path, Some("README.md"), None, None, Default::default()));
+    assert_send(&tree.clone().enrich_with_commits(&client, &path));
+    assert_send(&Blob::fetch(&client, &tree));
+    assert_send(&Blob::fetch_text(&client, &tree));
+    assert_send(&Downloader::download(&config, &path, &filter));
+    assert_send(&Downloader::download_tree(&config, &tree, &filter));
+    assert_send(&Downloader::download_tree_iter(
+        &config,
+        tree.clone(),
+        &filter,
+    ));
+    assert_send(&Downloader::download_stream(&config, &path, &filter).boxed());
+    assert_send(&Downloader::preflight(&config, &path, &filter));
+    #[cfg(any(feature = "git2", feature = "gix"))]
+    {
+        assert_send(&Wiki::get_tree(&config, "user", "repo", &filter));
+        assert_send(&Wiki::download(&config, "user", "repo", &filter));
+    }
+
+    Ok(())
+}
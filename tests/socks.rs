@@ -0,0 +1,49 @@
+#![cfg(all(feature = "socks", feature = "download"))]
+
+mod common;
+
+use common::{MockResponse, MockServer, MockSocksProxy};
+use grab_github::{Blob, Error, GithubClient, HttpOptions, SocksProxy};
+
+#[tokio::test]
+pub async fn socks_proxy() -> Result<(), Error> {
+    let server = MockServer::start(|_| MockResponse::blob(b"proxied")).await;
+    let proxy = MockSocksProxy::start("grab", "hunter2").await;
+    let entry = server.blob_entry("hello.txt", 7);
+    let server_address = server.url.trim_start_matches("http://").to_string();
+
+    let options = HttpOptions {
+        socks_proxy: Some(
+            SocksProxy::new(proxy.address.as_str()).with_credentials("grab", "hunter2"),
+        ),
+        ..HttpOptions::default()
+    };
+    let client = GithubClient::with_options(None, &options)?;
+    assert_eq!(Blob::fetch(&client, &entry).await?, b"proxied");
+    assert_eq!(proxy.targets(), vec![server_address.clone()]);
+
+    // Resolving locally sends the address instead of the host name, which is the same here.
+    let options = HttpOptions {
+        socks_proxy: Some(SocksProxy {
+            remote_dns: false,
+            ..SocksProxy::new(proxy.address.as_str()).with_credentials("grab", "hunter2")
+        }),
+        ..HttpOptions::default()
+    };
+    let client = GithubClient::with_options(None, &options)?;
+    assert_eq!(Blob::fetch(&client, &entry).await?, b"proxied");
+    assert_eq!(proxy.targets().len(), 2);
+    assert_eq!(proxy.targets()[1], server_address);
+
+    let options = HttpOptions {
+        socks_proxy: Some(
+            SocksProxy::new(proxy.address.as_str()).with_credentials("grab", "wrong"),
+        ),
+        ..HttpOptions::default()
+    };
+    let client = GithubClient::with_options(None, &options)?;
+    assert!(Blob::fetch(&client, &entry).await.is_err());
+    assert_eq!(proxy.targets().len(), 2);
+
+    Ok(())
+}
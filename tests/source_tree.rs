@@ -1,6 +1,17 @@
-use std::path::{Path, PathBuf};
+mod common;
 
-use grab_github::{Error, GithubBranchPath, SourceTree, TreeEntryType};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use grab_github::{
+    raw::{TreeEntryModel, TreeModel},
+    CachePolicy, Error, FileMode, Filter, GithubArchiveFormat, GithubBranchPath, GithubClient,
+    GithubRef, LazySourceTree, ObjectId, ResolvedRef, SourceTree, SourceTreeBuilder, TreeArena,
+    TreeCache, TreeEntryType, Utf8Path, Utf8PathBuf, EMPTY_TREE_SHA,
+};
 
 #[tokio::test]
 pub async fn hello_git_world() -> Result<(), Error> {
@@ -14,27 +25,1005 @@ pub async fn hello_git_world() -> Result<(), Error> {
     assert_eq!(
     tree.resolve_blob(Path::new("build.gradle")),
     Some(&SourceTree {
-        path: PathBuf::from("build.gradle"),
-        mode: String::from("100644"),
+        path: Utf8PathBuf::from("build.gradle"),
+        mode: FileMode::Regular,
         entry_type: TreeEntryType::Blob,
         size: 112,
-        sha: String::from("6058be211566308428ca6dcab3f08cf270cd9568"),
+        sha: "6058be211566308428ca6dcab3f08cf270cd9568".parse()?,
         url: String::from("https://api.github.com/repos/githubtraining/hellogitworld/git/blobs/6058be211566308428ca6dcab3f08cf270cd9568"),
-        children: Vec::new()
+        children: Vec::new(),
+        last_commit: None
     }));
 
     let dir = tree
         .resolve_tree(Path::new("src/test/java/com/github"))
         .unwrap();
     assert_eq!(dir.children[0], SourceTree {
-        path: PathBuf::from("src/test/java/com/github/AppTest.java"),
-        mode: String::from("100644"),
+        path: Utf8PathBuf::from("src/test/java/com/github/AppTest.java"),
+        mode: FileMode::Regular,
         entry_type: TreeEntryType::Blob,
         size: 750,
-        sha: String::from("43767197a768385d97ce751c421ee9e7ceeda5a7"),
+        sha: "43767197a768385d97ce751c421ee9e7ceeda5a7".parse()?,
         url: String::from("https://api.github.com/repos/githubtraining/hellogitworld/git/blobs/43767197a768385d97ce751c421ee9e7ceeda5a7"),
-        children: Vec::new()
+        children: Vec::new(),
+        last_commit: None
 	});
 
     Ok(())
 }
+
+#[test]
+pub fn iter() {
+    let blob = |path: &str| {
+        let mut node = SourceTree::new(TreeEntryType::Blob);
+        node.path = Utf8PathBuf::from(path);
+        node
+    };
+
+    let mut dir = SourceTree::new(TreeEntryType::Tree);
+    dir.path = Utf8PathBuf::from("src");
+    dir.children = vec![blob("src/lib.rs"), blob("src/main.rs")];
+
+    let mut root = SourceTree::new(TreeEntryType::Tree);
+    root.children = vec![blob("Cargo.toml"), dir, blob("README.md")];
+
+    let paths: Vec<&Utf8Path> = root.iter().map(|n| n.path.as_path()).collect();
+    assert_eq!(
+        paths,
+        vec![
+            Utf8Path::new(""),
+            Utf8Path::new("Cargo.toml"),
+            Utf8Path::new("src"),
+            Utf8Path::new("src/lib.rs"),
+            Utf8Path::new("src/main.rs"),
+            Utf8Path::new("README.md"),
+        ]
+    );
+}
+
+#[test]
+pub fn overlay() {
+    let base: ObjectId = "b".repeat(40).parse().unwrap();
+    let over: ObjectId = "c".repeat(40).parse().unwrap();
+    let node = |entry_type: TreeEntryType, path: &str, sha, children: Vec<SourceTree>| {
+        let mut node = SourceTree::new(entry_type);
+        node.path = Utf8PathBuf::from(path);
+        node.sha = sha;
+        node.children = children;
+        node
+    };
+    let blob = |path: &str, sha| node(TreeEntryType::Blob, path, sha, Vec::new());
+    let dir = |path: &str, children| node(TreeEntryType::Tree, path, ObjectId::NULL, children);
+
+    let base_tree = dir(
+        "",
+        vec![
+            blob("Cargo.toml", base),
+            dir(
+                "src",
+                vec![blob("src/lib.rs", base), blob("src/main.rs", base)],
+            ),
+            blob("docs", base),
+        ],
+    );
+    let overrides = dir(
+        "",
+        vec![
+            dir("src", vec![blob("src/lib.rs", over)]),
+            dir("docs", vec![blob("docs/index.md", over)]),
+            blob("LICENSE", over),
+        ],
+    );
+
+    let merged = base_tree.overlay(&overrides);
+    let entries: Vec<(&Utf8Path, ObjectId)> =
+        merged.iter().map(|n| (n.path.as_path(), n.sha)).collect();
+    assert_eq!(
+        entries,
+        vec![
+            (Utf8Path::new(""), ObjectId::NULL),
+            (Utf8Path::new("Cargo.toml"), base),
+            (Utf8Path::new("src"), ObjectId::NULL),
+            (Utf8Path::new("src/lib.rs"), over),
+            (Utf8Path::new("src/main.rs"), base),
+            (Utf8Path::new("docs"), ObjectId::NULL),
+            (Utf8Path::new("docs/index.md"), over),
+            (Utf8Path::new("LICENSE"), over),
+        ]
+    );
+}
+
+#[tokio::test]
+pub async fn resolve_tree_ref() -> Result<(), Error> {
+    let client = GithubClient::new(None)?;
+    let sha = "7638417db6d59f3c431d3e1f261cc637155684cd";
+    let path = GithubBranchPath::with_ref("user", "repo", GithubRef::Tree(sha));
+    let resolved = ResolvedRef::resolve(&client, &path).await?;
+
+    assert_eq!(
+        resolved,
+        ResolvedRef {
+            commit_sha: None,
+            tree_sha: sha.parse()?,
+        }
+    );
+    assert_eq!(client.budget().requests_made(), 0);
+
+    let invalid = GithubBranchPath::with_ref("user", "repo", GithubRef::Tree("abc123"));
+    assert!(ResolvedRef::resolve(&client, &invalid).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn tree_cache() -> Result<(), Error> {
+    use common::{MockResponse, MockServer};
+
+    const COMMIT: &str = "6dcb09b5b57875f334f61aebed695e2e4193db5e";
+    const TREE: &str = "0000000000000000000000000000000000000001";
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/repos/user/repo/git/ref/heads/main" => MockResponse::json(
+            200,
+            &format!(
+                r#"{{"ref":"refs/heads/main","url":"","object":{{"type":"commit","sha":"{}","url":""}}}}"#,
+                COMMIT
+            ),
+        ),
+        p if p == format!("/repos/user/repo/git/commits/{}", COMMIT) => MockResponse::json(
+            200,
+            &format!(
+                r#"{{"sha":"{}","url":"","message":"","tree":{{"sha":"{}","url":""}},"parents":[]}}"#,
+                COMMIT, TREE
+            ),
+        ),
+        p if p.starts_with(&format!("/repos/user/repo/git/trees/{}", TREE)) => MockResponse::json(
+            200,
+            &format!(
+                r#"{{"sha":"{}","url":"","truncated":false,"tree":[
+                    {{"path":"build.gradle","mode":"100644","type":"blob","size":5,"sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","url":""}},
+                    {{"path":"README.txt","mode":"100644","type":"blob","size":5,"sha":"b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0","url":""}}
+                ]}}"#,
+                TREE
+            ),
+        ),
+        _ => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+    })
+    .await;
+
+    let disk_path = PathBuf::from("./tests/test_output_dir_tree_cache/");
+    let policy = CachePolicy {
+        disk_path: Some(disk_path.clone()),
+        ref_ttl: Duration::from_millis(200),
+        ..CachePolicy::default()
+    };
+    let cached_client = |policy: CachePolicy| -> Result<GithubClient, Error> {
+        Ok(GithubClient::new(None)?
+            .with_api_url(&server.url)
+            .with_cache(Arc::new(TreeCache::new(policy))))
+    };
+    let path = GithubBranchPath::new("user", "repo", "main");
+
+    let result = async {
+        // the branch, its commit and the tree
+        let client = cached_client(policy.clone())?;
+        let (resolved, tree) =
+            SourceTree::get_resolved_with_client(&client, &path, &Filter::all()).await?;
+        assert_eq!(server.requests().len(), 3);
+
+        // the branch is still fresh, and the tree is cached by its SHA
+        let filter = Filter::new(vec!["build.gradle"], vec![]);
+        let (_, filtered) = SourceTree::get_resolved_with_client(&client, &path, &filter).await?;
+        assert_eq!(server.requests().len(), 3);
+        assert!(filtered.resolve_blob("build.gradle").is_some());
+        assert!(filtered.resolve_blob("README.txt").is_none());
+
+        // once the branch expires it's resolved again, but its tree is still cached
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        SourceTree::get_resolved_with_client(&client, &path, &Filter::all()).await?;
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[3].path, "/repos/user/repo/git/ref/heads/main");
+
+        // a new cache finds the tree on disk, so only the commit needs resolving
+        let client = cached_client(policy)?;
+        let commit = resolved.commit_sha.unwrap().to_string();
+        let commit_path = path.with_reference(GithubRef::Commit(&commit));
+        let (_, cached) =
+            SourceTree::get_resolved_with_client(&client, &commit_path, &Filter::all()).await?;
+        assert_eq!(cached, tree);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 6);
+        assert_eq!(
+            requests[5].path,
+            format!("/repos/user/repo/git/commits/{}", COMMIT)
+        );
+
+        // now the commit is cached too
+        SourceTree::get_resolved_with_client(&client, &commit_path, &Filter::all()).await?;
+        assert_eq!(server.requests().len(), 6);
+
+        Ok(())
+    }
+    .await;
+
+    if disk_path.is_dir() {
+        std::fs::remove_dir_all(&disk_path)?;
+    }
+
+    result
+}
+
+#[tokio::test]
+pub async fn tree_cache_scopes() -> Result<(), Error> {
+    use common::{MockResponse, MockServer};
+
+    let tree = "0000000000000000000000000000000000000001";
+    let respond = move |request: &common::MockRequest| match request.path.as_str() {
+        p if p.starts_with(&format!("/repos/user/repo/git/trees/{}", tree)) => MockResponse::json(
+            200,
+            &format!(
+                r#"{{"sha":"{}","url":"","truncated":false,"tree":[]}}"#,
+                tree
+            ),
+        ),
+        _ => MockResponse::json(404, r#"{"message":"Not Found"}"#),
+    };
+    let first = MockServer::start(respond).await;
+    let second = MockServer::start(respond).await;
+
+    let cache = Arc::new(TreeCache::default());
+    let client = |token: Option<&str>, url: &str| -> Result<GithubClient, Error> {
+        Ok(GithubClient::new(token)?
+            .with_api_url(url)
+            .with_cache(cache.clone()))
+    };
+    let path = GithubBranchPath::with_ref("user", "repo", GithubRef::Tree(tree));
+    let get = |client: GithubClient| async move {
+        SourceTree::get_resolved_with_client(&client, &path, &Filter::all()).await
+    };
+
+    get(client(Some("ghp_first"), &first.url)?).await?;
+    assert_eq!(first.requests().len(), 1);
+
+    // the same token on the same host shares what's cached
+    get(client(Some("ghp_first"), &first.url)?).await?;
+    assert_eq!(first.requests().len(), 1);
+
+    // another token, no token or another host with the same owner and repo don't
+    get(client(Some("ghp_second"), &first.url)?).await?;
+    get(client(None, &first.url)?).await?;
+    assert_eq!(first.requests().len(), 3);
+    get(client(Some("ghp_first"), &second.url)?).await?;
+    assert_eq!(second.requests().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+pub fn from_tree_model() {
+    let entry = |path: &str, entry_type| TreeEntryModel {
+        path: path.to_string(),
+        mode: FileMode::Regular,
+        entry_type,
+        size: 0,
+        sha: ObjectId::NULL,
+        url: String::new(),
+    };
+
+    // subtrees fetched separately are listed before the entries of their parent
+    let tree: SourceTree = TreeModel {
+        sha: EMPTY_TREE_SHA.parse().unwrap(),
+        url: String::new(),
+        tree: vec![
+            entry("src/bin/main.rs", TreeEntryType::Blob),
+            entry("src/bin", TreeEntryType::Tree),
+            entry("./src//lib.rs", TreeEntryType::Blob),
+            entry("src", TreeEntryType::Tree),
+            entry("Cargo.toml", TreeEntryType::Blob),
+        ],
+        truncated: false,
+    }
+    .into();
+
+    assert_eq!(tree.sha, EMPTY_TREE_SHA);
+    assert_eq!(tree.children.len(), 2);
+    assert_eq!(
+        tree.resolve_tree(Path::new("src")).unwrap().children.len(),
+        2
+    );
+    assert!(tree.resolve_blob(Path::new("src/bin/main.rs")).is_some());
+    assert!(tree.resolve_blob(Path::new("Cargo.toml")).is_some());
+    assert_eq!(tree.iter().count(), 6);
+
+    // paths are normalized, and can be resolved from strings
+    let lib = tree.resolve_blob("src/lib.rs").unwrap();
+    assert_eq!(lib.path.as_str(), "src/lib.rs");
+    assert_eq!(lib.name(), "lib.rs");
+    assert_eq!(lib.parent_path(), Utf8Path::new("src"));
+    let cargo = tree.resolve_blob(Utf8Path::new("Cargo.toml")).unwrap();
+    assert_eq!(cargo.parent_path(), Utf8Path::new(""));
+    assert_eq!(tree.name(), "");
+}
+
+#[test]
+pub fn from_tree_model_missing_dirs() {
+    let entry = |path: &str| TreeEntryModel {
+        path: path.to_string(),
+        mode: FileMode::Regular,
+        entry_type: TreeEntryType::Blob,
+        size: 0,
+        sha: ObjectId::NULL,
+        url: String::new(),
+    };
+
+    // filtered and cached trees can keep files without the entries of their directories
+    let tree: SourceTree = TreeModel {
+        sha: ObjectId::NULL,
+        url: String::new(),
+        tree: vec![entry("src/bin/main.rs"), entry("src/lib.rs")],
+        truncated: false,
+    }
+    .into();
+
+    let src = tree.resolve_tree("src").unwrap();
+    assert_eq!(src.mode, FileMode::Directory);
+    assert_eq!(src.children.len(), 2);
+    assert!(tree.resolve_tree("src/bin").is_some());
+    assert!(tree.resolve_blob("src/bin/main.rs").is_some());
+    assert_eq!(tree.iter().count(), 5);
+}
+
+#[test]
+pub fn tree_arena() {
+    let entry = |path: &str, entry_type| TreeEntryModel {
+        path: path.to_string(),
+        mode: FileMode::Regular,
+        entry_type,
+        size: 0,
+        sha: ObjectId::NULL,
+        url: String::new(),
+    };
+
+    let arena = TreeArena::from(TreeModel {
+        sha: ObjectId::NULL,
+        url: String::new(),
+        tree: vec![
+            entry("src", TreeEntryType::Tree),
+            entry("src/lib.rs", TreeEntryType::Blob),
+            entry("src/bin/main.rs", TreeEntryType::Blob),
+            entry("Cargo.toml", TreeEntryType::Blob),
+        ],
+        truncated: false,
+    });
+
+    assert_eq!(arena.len(), 6);
+    assert_eq!(arena.children(TreeArena::ROOT).len(), 2);
+    let src = arena.find("src").unwrap();
+    assert_eq!(arena.children(src).len(), 2);
+    assert!(arena.node(src).children.is_empty());
+    let bin = arena.find("src/bin").unwrap();
+    assert_eq!(arena.node(bin).mode, FileMode::Directory);
+    assert!(arena.find("src/missing.rs").is_none());
+
+    // subtrees are copied out without touching the arena
+    let src_tree = arena.to_tree(src);
+    assert_eq!(src_tree.iter().count(), 4);
+    assert!(src_tree.iter().any(|e| e.path == "src/bin/main.rs"));
+    assert_eq!(arena.to_tree(TreeArena::ROOT), arena.clone().into_tree());
+    assert_eq!(arena.into_tree().iter().count(), 6);
+}
+
+#[cfg(feature = "download")]
+#[test]
+pub fn diff_local() -> Result<(), Error> {
+    let blob = |path: &str, sha: &str| SourceTree {
+        path: Utf8PathBuf::from(path),
+        sha: sha.parse().unwrap(),
+        ..SourceTree::new(TreeEntryType::Blob)
+    };
+    let hello = "b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0";
+
+    let mut src = SourceTree::new(TreeEntryType::Tree);
+    src.path = Utf8PathBuf::from("src");
+    src.children = vec![
+        blob("src/same.txt", hello),
+        blob("src/changed.txt", hello),
+        blob("src/gone.txt", hello),
+    ];
+    let mut tree = SourceTree::new(TreeEntryType::Tree);
+    tree.children = vec![src];
+
+    let dir = Path::new("./tests/test_output_dir_diff_local/");
+    std::fs::create_dir_all(dir.join("src/new"))?;
+    std::fs::write(dir.join("src/same.txt"), "hello")?;
+    std::fs::write(dir.join("src/changed.txt"), "goodbye")?;
+    std::fs::write(dir.join("src/new/extra.txt"), "hello")?;
+
+    let diff = tree.diff_local(dir);
+    let subtree_diff = tree.children[0].diff_local(&dir.join("src"));
+    std::fs::remove_dir_all(dir)?;
+
+    let diff = diff?;
+    assert_eq!(diff.modified, vec![PathBuf::from("src/changed.txt")]);
+    assert_eq!(diff.missing, vec![PathBuf::from("src/gone.txt")]);
+    assert_eq!(diff.extra, vec![PathBuf::from("src/new/extra.txt")]);
+    assert!(!diff.is_empty());
+
+    assert_eq!(subtree_diff?.missing, vec![PathBuf::from("gone.txt")]);
+
+    Ok(())
+}
+
+#[test]
+pub fn export() -> Result<(), Error> {
+    let blob = |path: &str, size| SourceTree {
+        path: Utf8PathBuf::from(path),
+        size,
+        sha: "a".repeat(40).parse().unwrap(),
+        ..SourceTree::new(TreeEntryType::Blob)
+    };
+
+    let mut dir = SourceTree::new(TreeEntryType::Tree);
+    dir.path = Utf8PathBuf::from("src");
+    dir.children = vec![blob("src/lib.rs", 2048), blob("src/a,b.rs", 1)];
+
+    let mut root = SourceTree::new(TreeEntryType::Tree);
+    root.children = vec![blob("Cargo.toml", 112), dir];
+
+    let mut json = Vec::new();
+    root.to_json_lines(&mut json)?;
+    let json = String::from_utf8(json).unwrap();
+    assert_eq!(json.lines().count(), 4);
+    assert_eq!(
+        json.lines().next().unwrap(),
+        r#"{"path":"Cargo.toml","type":"blob","mode":"100644","size":112,"sha":"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"}"#
+    );
+
+    let mut csv = Vec::new();
+    root.to_csv(&mut csv)?;
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "path,type,mode,size,sha\n\
+         Cargo.toml,blob,100644,112,aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n\
+         src,tree,040000,0,0000000000000000000000000000000000000000\n\
+         src/lib.rs,blob,100644,2048,aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n\
+         \"src/a,b.rs\",blob,100644,1,aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n"
+    );
+
+    assert_eq!(
+        root.to_text_tree(),
+        ".\n\
+         ├── Cargo.toml (112 bytes)\n\
+         └── src\n    \
+         ├── lib.rs (2048 bytes)\n    \
+         └── a,b.rs (1 bytes)\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn parse_ref() {
+    let sha = "7638417db6d59f3c431d3e1f261cc637155684cd";
+    assert_eq!(GithubRef::parse("main"), GithubRef::Branch("main"));
+    assert_eq!(
+        GithubRef::parse("refs/heads/feature/x"),
+        GithubRef::Branch("feature/x")
+    );
+    assert_eq!(GithubRef::parse("tags/v1.0"), GithubRef::Tag("v1.0"));
+    assert_eq!(GithubRef::parse("refs/tags/v1.0"), GithubRef::Tag("v1.0"));
+    assert_eq!(GithubRef::parse(sha), GithubRef::Commit(sha));
+    let sha256 = "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4";
+    assert_eq!(GithubRef::parse(sha256), GithubRef::Commit(sha256));
+    assert_eq!(GithubRef::parse("pull/42"), GithubRef::PullRequest(42));
+    assert_eq!(
+        GithubRef::parse("refs/pull/42/head"),
+        GithubRef::PullRequest(42)
+    );
+    assert_eq!(GithubRef::parse("pull/x"), GithubRef::Branch("pull/x"));
+    assert_eq!(GithubRef::PullRequest(42).name(), "42");
+    assert_eq!(GithubRef::PullRequest(42).to_string(), "pull/42/head");
+    assert_eq!(
+        GithubRef::parse(&GithubRef::PullRequest(42).to_string()),
+        GithubRef::PullRequest(42)
+    );
+}
+
+#[test]
+pub fn parse_branch_path() -> Result<(), Error> {
+    let sha = "7638417db6d59f3c431d3e1f261cc637155684cd";
+    let specs = [
+        (
+            "user/repo@main",
+            GithubBranchPath::new("user", "repo", "main"),
+        ),
+        (
+            "user/repo@heads/tags/x",
+            GithubBranchPath::with_ref("user", "repo", GithubRef::Branch("tags/x")),
+        ),
+        (
+            "user/repo@tags/v1.0",
+            GithubBranchPath::with_ref("user", "repo", GithubRef::Tag("v1.0")),
+        ),
+        (
+            "user/repo@pull/42",
+            GithubBranchPath::with_ref("user", "repo", GithubRef::PullRequest(42)),
+        ),
+    ];
+    for (spec, path) in specs {
+        assert_eq!(GithubBranchPath::parse(spec)?, path);
+        assert_eq!(path.to_string(), spec);
+    }
+    assert_eq!(
+        GithubBranchPath::new("user", "repo", sha),
+        GithubBranchPath::with_ref("user", "repo", GithubRef::Commit(sha))
+    );
+    assert_eq!(
+        GithubBranchPath::new("user", "repo", sha).to_string(),
+        format!("user/repo@{}", sha)
+    );
+    assert_eq!(
+        GithubBranchPath::new("user", "repo", "tags/v1.0").reference,
+        GithubRef::Tag("v1.0")
+    );
+    assert_eq!(
+        GithubBranchPath::try_from("user/repo@feature/x")?,
+        GithubBranchPath::new("user", "repo", "feature/x")
+    );
+
+    for spec in ["user/repo", "user@main", "user/repo/x@main", "user/repo@"] {
+        assert!(matches!(
+            GithubBranchPath::parse(spec),
+            Err(Error::InvalidSpec { .. })
+        ));
+    }
+
+    let parse_url = |url: &str| {
+        let url = reqwest::Url::parse(url).unwrap();
+        GithubBranchPath::try_from(&url).map(|path| path.to_string())
+    };
+    assert_eq!(
+        parse_url("https://github.com/user/repo/tree/feature/x")?,
+        "user/repo@feature/x"
+    );
+    assert_eq!(
+        parse_url(&format!("https://github.com/user/repo/commit/{}", sha))?,
+        format!("user/repo@{}", sha)
+    );
+    assert_eq!(
+        parse_url("https://github.com/user/repo/releases/tag/v1.0")?,
+        "user/repo@tags/v1.0"
+    );
+    assert_eq!(
+        parse_url("https://github.com/user/repo/pull/42/files")?,
+        "user/repo@pull/42"
+    );
+    for url in [
+        "https://github.com/user/repo",
+        "https://github.com/user/repo/commit/main",
+        "https://github.com/user/repo/tree/read%20me",
+        "https://github.com/user/repo/issues/42",
+    ] {
+        assert!(matches!(parse_url(url), Err(Error::InvalidSpec { .. })));
+    }
+
+    assert_eq!("blob".parse::<TreeEntryType>()?, TreeEntryType::Blob);
+    assert_eq!(TreeEntryType::Tree.to_string(), "tree");
+    assert!("commit".parse::<TreeEntryType>().is_err());
+
+    Ok(())
+}
+
+#[test]
+pub fn public_urls() -> Result<(), Error> {
+    let path = GithubBranchPath::new("user", "repo", "feature/x");
+    assert_eq!(
+        path.archive_url(GithubArchiveFormat::TarGz)?,
+        "https://codeload.github.com/user/repo/tar.gz/refs/heads/feature/x"
+    );
+    assert_eq!(
+        path.with_reference(GithubRef::Tag("v1.0"))
+            .archive_url(GithubArchiveFormat::Zip)?,
+        "https://codeload.github.com/user/repo/zip/refs/tags/v1.0"
+    );
+    assert_eq!(
+        path.raw_url("docs/read me.md")?,
+        "https://raw.githubusercontent.com/user/repo/refs/heads/feature/x/docs/read%20me.md"
+    );
+
+    let sha = "7638417db6d59f3c431d3e1f261cc637155684cd";
+    assert_eq!(
+        path.with_reference(GithubRef::Commit(sha))
+            .blob_html_url("/src/lib#1.rs")?,
+        format!("https://github.com/user/repo/blob/{}/src/lib%231.rs", sha)
+    );
+    assert_eq!(
+        path.with_reference(GithubRef::PullRequest(42))
+            .blob_html_url("a.txt")?,
+        "https://github.com/user/repo/blob/refs/pull/42/head/a.txt"
+    );
+    assert!(path
+        .with_reference(GithubRef::Tree(sha))
+        .raw_url("a.txt")
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+pub fn file_mode() {
+    let parse = |mode: &str| mode.parse::<FileMode>().ok();
+    assert_eq!(parse("100644"), Some(FileMode::Regular));
+    assert_eq!(parse("100755"), Some(FileMode::Executable));
+    assert_eq!(parse("100664"), Some(FileMode::Regular));
+    assert_eq!(parse("120000"), Some(FileMode::Symlink));
+    assert_eq!(parse("160000"), Some(FileMode::Submodule));
+    assert_eq!(parse("040000"), Some(FileMode::Directory));
+    assert_eq!(parse("40000"), Some(FileMode::Directory));
+    assert_eq!(parse("777"), None);
+    assert_eq!(parse("blob"), None);
+
+    assert_eq!(FileMode::Executable.raw(), "100755");
+    assert!(FileMode::Symlink.is_blob());
+    assert!(!FileMode::Submodule.is_blob());
+    assert_eq!(
+        SourceTree::new(TreeEntryType::Tree).mode,
+        FileMode::Directory
+    );
+}
+
+#[cfg(feature = "graphql")]
+#[tokio::test]
+pub async fn graphql_tree() -> Result<(), Error> {
+    use common::{MockResponse, MockServer};
+    use grab_github::TreeFetchStrategy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // the first query comes back with `deep` unexpanded, as if it were past the nesting limit
+    let queries = AtomicUsize::new(0);
+    let server = MockServer::start(move |_| match queries.fetch_add(1, Ordering::SeqCst) {
+        0 => MockResponse::json(
+            200,
+            r#"{"data":{"repository":{"t0":{"entries":[
+                {"name":"README.md","mode":33188,"type":"blob","oid":"1111111111111111111111111111111111111111","object":{"byteSize":5}},
+                {"name":"build.sh","mode":33261,"type":"blob","oid":"2222222222222222222222222222222222222222","object":{"byteSize":9}},
+                {"name":"vendor","mode":57344,"type":"commit","oid":"3333333333333333333333333333333333333333","object":null},
+                {"name":"src","mode":16384,"type":"tree","oid":"4444444444444444444444444444444444444444","object":{"entries":[
+                    {"name":"lib.rs","mode":33188,"type":"blob","oid":"5555555555555555555555555555555555555555","object":{"byteSize":12}}
+                ]}},
+                {"name":"deep","mode":16384,"type":"tree","oid":"6666666666666666666666666666666666666666","object":{}}
+            ]}}}}"#,
+        ),
+        _ => MockResponse::json(
+            200,
+            r#"{"data":{"repository":{"t0":{"entries":[
+                {"name":"notes.txt","mode":33188,"type":"blob","oid":"7777777777777777777777777777777777777777","object":{"byteSize":3}}
+            ]}}}}"#,
+        ),
+    })
+    .await;
+
+    let client = GithubClient::new(Some("ghp_test"))?
+        .with_api_url(&server.url)
+        .with_tree_fetch_strategy(TreeFetchStrategy::GraphQL);
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+
+    let (_, tree) = SourceTree::get_resolved_with_client(&client, &path, &Filter::all()).await?;
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests
+        .iter()
+        .all(|r| r.method == "POST" && r.path == "/graphql"));
+
+    let lib = tree.resolve_blob("src/lib.rs").unwrap();
+    assert_eq!(lib.size, 12);
+    assert_eq!(
+        lib.url,
+        format!(
+            "{}/repos/user/repo/git/blobs/5555555555555555555555555555555555555555",
+            server.url
+        )
+    );
+    assert_eq!(
+        tree.resolve_blob("build.sh").map(|b| b.mode),
+        Some(FileMode::Executable)
+    );
+    assert!(tree.resolve_blob("deep/notes.txt").is_some());
+    assert!(tree.resolve_any("vendor").is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "graphql")]
+#[tokio::test]
+pub async fn enrich_with_commits() -> Result<(), Error> {
+    use common::{MockResponse, MockServer};
+
+    // answers each aliased history with a commit naming the path it was asked for under that alias
+    let server = MockServer::start(|request| {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let histories: serde_json::Map<String, serde_json::Value> = body["variables"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter_map(|(name, path)| {
+                let alias = format!("f{}", name.strip_prefix('p')?);
+                let path = path.as_str().unwrap();
+                let nodes = match path {
+                    "untracked.txt" => serde_json::json!([]),
+                    _ => serde_json::json!([{
+                        "oid": format!("{:0>40}", path.len()),
+                        "author": {"name": path, "email": "a@example.com", "date": "2024-01-01T00:00:00Z"}
+                    }]),
+                };
+                Some((alias, serde_json::json!({ "nodes": nodes })))
+            })
+            .collect();
+        let response = serde_json::json!({ "data": { "repository": { "object": histories } } });
+        MockResponse::json(200, &response.to_string())
+    })
+    .await;
+
+    // more files than fit in a single query
+    let blob = |path: String| SourceTree {
+        path: Utf8PathBuf::from(path),
+        ..SourceTree::new(TreeEntryType::Blob)
+    };
+    let mut src = SourceTree::new(TreeEntryType::Tree);
+    src.path = Utf8PathBuf::from("src");
+    src.children = (0..60).map(|i| blob(format!("src/{}.rs", i))).collect();
+    let mut tree = SourceTree::new(TreeEntryType::Tree);
+    tree.children = vec![blob(String::from("untracked.txt")), src];
+
+    let client = GithubClient::new(Some("ghp_test"))?.with_api_url(&server.url);
+    let path = GithubBranchPath::new("user", "repo", "main");
+    tree.enrich_with_commits(&client, &path).await?;
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests
+        .iter()
+        .all(|r| r.method == "POST" && r.path == "/graphql"));
+
+    assert!(tree
+        .resolve_blob("untracked.txt")
+        .unwrap()
+        .last_commit
+        .is_none());
+    for i in 0..60 {
+        let path = format!("src/{}.rs", i);
+        let commit = tree.resolve_blob(&path).unwrap().last_commit.as_ref();
+        assert_eq!(commit.map(|c| c.author_name.as_str()), Some(path.as_str()));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn lazy_tree() -> Result<(), Error> {
+    use common::{MockResponse, MockServer};
+
+    let server = MockServer::start(|request| match request.path.as_str() {
+        "/repos/user/repo/git/trees/0000000000000000000000000000000000000001" => MockResponse::json(
+            200,
+            r#"{"sha":"0000000000000000000000000000000000000001","url":"","truncated":false,"tree":[
+                {"path":"README.md","mode":"100644","type":"blob","size":5,"sha":"1111111111111111111111111111111111111111","url":""},
+                {"path":"src","mode":"040000","type":"tree","sha":"2222222222222222222222222222222222222222","url":""}
+            ]}"#,
+        ),
+        _ => MockResponse::json(
+            200,
+            r#"{"sha":"2222222222222222222222222222222222222222","url":"","truncated":false,"tree":[
+                {"path":"lib.rs","mode":"100644","type":"blob","size":12,"sha":"3333333333333333333333333333333333333333","url":""}
+            ]}"#,
+        ),
+    })
+    .await;
+
+    let client = GithubClient::new(None)?.with_api_url(&server.url);
+    let path = GithubBranchPath::with_ref(
+        "user",
+        "repo",
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+    );
+
+    let mut root = LazySourceTree::root(&client, &path).await?;
+    assert!(!root.is_expanded());
+    assert!(server.requests().is_empty());
+
+    let lib = root.expand_path("src/lib.rs").await?.unwrap();
+    assert_eq!(lib.entry().path, Utf8PathBuf::from("src/lib.rs"));
+    assert_eq!(lib.entry().size, 12);
+    assert_eq!(server.requests().len(), 2);
+
+    // everything is expanded already, so nothing more is fetched
+    assert!(root.expand_path("src/main.rs").await?.is_none());
+    let tree = root.materialize().await?;
+    assert_eq!(server.requests().len(), 2);
+    assert!(tree.resolve_blob("README.md").is_some());
+    assert!(tree.resolve_blob("src/lib.rs").is_some());
+
+    Ok(())
+}
+
+#[test]
+pub fn stats() -> Result<(), Error> {
+    let sha: ObjectId = "6058be211566308428ca6dcab3f08cf270cd9568".parse()?;
+    let mut builder = SourceTreeBuilder::new();
+    builder
+        .add_blob("src/lib.rs", sha, 2_000)?
+        .add_blob("src/main.RS", sha, 500)?
+        .add_blob("assets/logo.png", sha, 3 << 20)?
+        .add_blob("LICENSE", sha, 1_000)?
+        .add_dir("empty")?;
+    let tree = builder.build();
+
+    let stats = tree.stats_with_largest(2);
+    assert_eq!(stats.files, 4);
+    assert_eq!(stats.directories, 3);
+    assert_eq!(stats.total_bytes, 3_500 + (3 << 20));
+    let largest: Vec<(&str, u64)> = stats
+        .largest
+        .iter()
+        .map(|file| (file.path.as_str(), file.size))
+        .collect();
+    assert_eq!(
+        largest,
+        vec![("assets/logo.png", 3 << 20), ("src/lib.rs", 2_000)]
+    );
+
+    let histogram: Vec<usize> = stats.histogram.iter().map(|b| b.files).collect();
+    assert_eq!(histogram, vec![2, 1, 0, 0, 1, 0]);
+    assert_eq!(stats.histogram[0].bytes, 1_500);
+    assert_eq!(stats.histogram[5].max, None);
+
+    assert_eq!(stats.extensions["rs"].files, 2);
+    assert_eq!(stats.extensions["rs"].bytes, 2_500);
+    assert_eq!(stats.extensions[""].files, 1);
+    let heaviest: Vec<&str> = stats
+        .heaviest_extensions()
+        .into_iter()
+        .map(|(extension, _)| extension)
+        .collect();
+    assert_eq!(heaviest, vec!["png", "rs", ""]);
+
+    let subtree = tree.resolve_tree("src").unwrap().stats();
+    assert_eq!(subtree.files, 2);
+    assert_eq!(subtree.largest[0].path, "lib.rs");
+
+    Ok(())
+}
+
+#[test]
+pub fn tree_builder() -> Result<(), Error> {
+    let sha: ObjectId = "6058be211566308428ca6dcab3f08cf270cd9568".parse()?;
+    let mut builder = SourceTreeBuilder::new();
+    builder
+        .add_blob("src/test/AppTest.java", sha, 112)?
+        .add_blob("./README.md", sha, 5)?
+        .add_dir("src/test")?
+        .add_dir("docs/")?;
+
+    assert!(builder.add_blob("../outside.txt", sha, 1).is_err());
+    assert!(builder.add_blob("/absolute.txt", sha, 1).is_err());
+    assert!(builder.add_blob("README.md", sha, 1).is_err());
+    assert!(builder.add_dir("README.md/nested").is_err());
+
+    let tree = builder.build();
+    let names: Vec<&str> = tree.iter().skip(1).map(|n| n.path.as_str()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "src",
+            "src/test",
+            "src/test/AppTest.java",
+            "README.md",
+            "docs"
+        ]
+    );
+
+    let blob = tree.resolve_blob("src/test/AppTest.java").unwrap();
+    assert_eq!(blob.sha, sha);
+    assert_eq!(blob.size, 112);
+    assert_eq!(blob.mode, FileMode::Regular);
+    assert_eq!(
+        tree.resolve_tree("docs").map(|d| d.mode),
+        Some(FileMode::Directory)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn compare() -> Result<(), Error> {
+    use common::{MockResponse, MockServer};
+    use grab_github::ChangeKind;
+
+    let server = MockServer::start(|request| {
+        let entry = |path: &str, mode: &str, sha: char, size: u32| {
+            format!(
+                r#"{{"path":"{}","mode":"{}","type":"blob","size":{},"sha":"{}","url":""}}"#,
+                path,
+                mode,
+                size,
+                sha.to_string().repeat(40)
+            )
+        };
+        // the trees of `src` differ too, but only blobs are compared
+        let tree = |sha: &str, entries: [String; 5]| {
+            MockResponse::json(
+                200,
+                &format!(
+                    r#"{{"sha":"{0}","url":"","truncated":false,"tree":[{{"path":"src","mode":"040000","type":"tree","sha":"{0}","url":""}},{1}]}}"#,
+                    sha,
+                    entries.join(",")
+                ),
+            )
+        };
+
+        match request.path.as_str() {
+            p if p.contains("/git/trees/0000000000000000000000000000000000000001") => tree(
+                "0000000000000000000000000000000000000001",
+                [
+                    entry("README.md", "100644", '1', 5),
+                    entry("src/lib.rs", "100644", '3', 12),
+                    entry("run.sh", "100644", '4', 3),
+                    entry("old.txt", "100644", '5', 4),
+                    entry("ignored.txt", "100644", '8', 1),
+                ],
+            ),
+            _ => tree(
+                "0000000000000000000000000000000000000002",
+                [
+                    entry("README.md", "100644", '1', 5),
+                    entry("src/lib.rs", "100644", '6', 20),
+                    entry("run.sh", "100755", '4', 3),
+                    entry("new.txt", "100644", '7', 7),
+                    entry("ignored.txt", "100644", '9', 2),
+                ],
+            ),
+        }
+    })
+    .await;
+
+    let client = GithubClient::new(None)?.with_api_url(&server.url);
+    let repo = GithubBranchPath::new("user", "repo", "main");
+    let filter = Filter::new(vec![], vec!["ignored.txt"]);
+    let comparison = SourceTree::compare(
+        &client,
+        &repo,
+        GithubRef::Tree("0000000000000000000000000000000000000001"),
+        GithubRef::Tree("0000000000000000000000000000000000000002"),
+        &filter,
+    )
+    .await?;
+
+    // one tree per reference, and no blobs
+    assert_eq!(server.requests().len(), 2);
+    let paths: Vec<(&str, ChangeKind)> = comparison
+        .changes
+        .iter()
+        .map(|change| (change.path.as_str(), change.kind))
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            ("new.txt", ChangeKind::Added),
+            ("old.txt", ChangeKind::Removed),
+            ("run.sh", ChangeKind::Modified),
+            ("src/lib.rs", ChangeKind::Modified),
+        ]
+    );
+    assert_eq!(comparison.of_kind(ChangeKind::Modified).count(), 2);
+    assert_eq!(comparison.size_delta(), 11);
+    assert_eq!(
+        comparison.to_string(),
+        "A new.txt (+7 bytes)\nD old.txt (-4 bytes)\nM run.sh (mode 100644 -> 100755)\nM src/lib.rs (+8 bytes)\n"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+#![cfg(any(feature = "git2", feature = "gix"))]
+
+use std::{path::Path, process::Command};
+
+use grab_github::{wiki::Wiki, DownloadConfigNoReporting, Error, Filter, TreeEntryType};
+
+/// Runs git with the given arguments in `dir`, with an identity so commits can be made anywhere.
+fn git(dir: &Path, args: &[&str]) -> Result<(), Error> {
+    let status = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .status()?;
+    assert!(status.success(), "git {:?} failed", args);
+    Ok(())
+}
+
+#[tokio::test]
+pub async fn wiki_clone() -> Result<(), Error> {
+    // served from a file URL laid out like github.com, with the wiki on `main` rather than `master`
+    let server_path = std::env::current_dir()?.join("tests/test_server_wiki");
+    let work_path = Path::new("./tests/test_repo_wiki/");
+    let output_path = Path::new("./tests/test_output_dir_wiki/");
+    std::fs::create_dir_all(work_path.join("images"))?;
+    std::fs::create_dir_all(server_path.join("user"))?;
+    std::fs::write(work_path.join("Home.md"), "home")?;
+    std::fs::write(work_path.join("Setup.md"), "setup")?;
+    std::fs::write(work_path.join("images/logo.png"), "logo")?;
+    git(work_path, &["-c", "init.defaultBranch=main", "init", "-q"])?;
+    git(work_path, &["add", "-A"])?;
+    git(work_path, &["commit", "-q", "-m", "initial"])?;
+    git(
+        &server_path.join("user"),
+        &[
+            "clone",
+            "-q",
+            "--bare",
+            &std::env::current_dir()?.join(work_path).to_string_lossy(),
+            "repo.wiki.git",
+        ],
+    )?;
+
+    let mut config = DownloadConfigNoReporting::new(output_path);
+    config.api_url = format!("file://{}/api/v3", server_path.display()).into();
+    let filter = Filter::new(vec!["*.md"], vec![]);
+    let branch = Wiki::default_branch(&config, "user", "repo").await;
+    let tree = Wiki::get_tree(&config, "user", "repo", &filter).await;
+    let files = Wiki::download(&config, "user", "repo", &filter).await;
+    let home = std::fs::read(output_path.join("Home.md"));
+    let logo = output_path.join("images/logo.png").exists();
+    let missing = Wiki::download(&config, "user", "other", &filter).await;
+
+    for dir in [work_path, &server_path, output_path] {
+        if dir.is_dir() {
+            std::fs::remove_dir_all(dir)?;
+        }
+    }
+
+    assert_eq!(branch?, "main");
+    let mut pages: Vec<_> = tree?
+        .iter()
+        .filter(|entry| entry.entry_type == TreeEntryType::Blob)
+        .map(|entry| entry.path.to_string())
+        .collect();
+    pages.sort();
+    assert_eq!(pages, ["Home.md", "Setup.md"]);
+    assert_eq!(files?.len(), 2);
+    assert_eq!(home?, b"home");
+    assert!(!logo);
+    assert!(missing.is_err());
+
+    Ok(())
+}